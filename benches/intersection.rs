@@ -0,0 +1,103 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use std::sync::Arc;
+
+use raytracer::camera::Camera;
+use raytracer::geometry::{
+	load_mesh, Aabb3d, Float, Hittable, Interval, MeshOptions, Ray, Sphere, Triangle, Uv, Vec3f,
+};
+use raytracer::material::{Diffuse, Material, SolidColor};
+
+fn material() -> Arc<dyn Material> {
+	Arc::new(Diffuse::new(Arc::new(SolidColor::new(Vec3f::new(
+		0.5, 0.5, 0.5,
+	)))))
+}
+
+fn ray(direction: Vec3f) -> Ray {
+	Ray {
+		origin: Vec3f::new(0.0, 0.0, 0.0),
+		direction: direction.unit(),
+		time: 0.0,
+	}
+}
+
+fn primitives(c: &mut Criterion) {
+	let hit = ray(Vec3f::new(0.0, 0.0, -1.0));
+	let miss = ray(Vec3f::new(0.0, 1.0, -1.0));
+
+	let sphere = Sphere::new(Vec3f::new(0.0, 0.0, -2.0), 0.5, material());
+	c.bench_function("sphere hit", |b| {
+		b.iter(|| sphere.hit(&Interval::new_ray(), black_box(&hit)))
+	});
+	c.bench_function("sphere miss", |b| {
+		b.iter(|| sphere.hit(&Interval::new_ray(), black_box(&miss)))
+	});
+
+	let triangle = Triangle::new(
+		Vec3f::new(-1.0, -1.0, -2.0),
+		Vec3f::new(1.0, -1.0, -2.0),
+		Vec3f::new(0.0, 1.0, -2.0),
+		Uv::new(0.0, 0.0),
+		Uv::new(1.0, 0.0),
+		Uv::new(0.5, 1.0),
+		material(),
+	);
+	c.bench_function("triangle hit", |b| {
+		b.iter(|| triangle.hit(&Interval::new_ray(), black_box(&hit)))
+	});
+	c.bench_function("triangle miss", |b| {
+		b.iter(|| triangle.hit(&Interval::new_ray(), black_box(&miss)))
+	});
+
+	let aabb = Aabb3d::from_corners(Vec3f::new(-1.0, -1.0, -3.0), Vec3f::new(1.0, 1.0, -2.0));
+	c.bench_function("aabb hit", |b| {
+		b.iter(|| aabb.hit(black_box(&hit), &Interval::new_ray()))
+	});
+	c.bench_function("aabb miss", |b| {
+		b.iter(|| aabb.hit(black_box(&miss), &Interval::new_ray()))
+	});
+}
+
+fn bvh(c: &mut Criterion) {
+	let mesh = load_mesh("models/IS.obj", material(), &MeshOptions::default()).unwrap();
+
+	// Primary rays from a camera looking at the middle of the mesh, about half of them hit
+	let bounds = mesh.bounds();
+	let center = Vec3f::new(
+		(bounds.axis(0).min + bounds.axis(0).max) / 2.0,
+		(bounds.axis(1).min + bounds.axis(1).max) / 2.0,
+		(bounds.axis(2).min + bounds.axis(2).max) / 2.0,
+	);
+	let size = bounds.axis(0).size().max(bounds.axis(1).size());
+
+	const SIZE: u32 = 64;
+	let camera = Camera::new(
+		Vec3f::new(0.0, 0.0, 0.0),
+		center + Vec3f::new(0.0, 0.0, size as Float * 1.5),
+		center,
+		40.0,
+		SIZE,
+		SIZE,
+	);
+	let rays: Vec<Ray> = (0..SIZE * SIZE)
+		.filter_map(|i| camera.intial_ray(i % SIZE, i / SIZE))
+		.collect();
+
+	c.bench_function("bvh closest hit", |b| {
+		b.iter(|| {
+			rays.iter()
+				.filter(|ray| mesh.hit(&Interval::new_ray(), ray).is_some())
+				.count()
+		})
+	});
+	c.bench_function("bvh any hit", |b| {
+		b.iter(|| {
+			rays.iter()
+				.filter(|ray| mesh.hit_any(&Interval::new_ray(), ray))
+				.count()
+		})
+	});
+}
+
+criterion_group!(benches, primitives, bvh);
+criterion_main!(benches);
@@ -0,0 +1,44 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::sync::Arc;
+
+use raytracer::geometry::HittableGroup;
+use raytracer::integrator::PathTracer;
+use raytracer::render::{render, PostProcess, RenderSettings};
+use raytracer::scenes::scene_tank;
+
+fn frame(c: &mut Criterion) {
+	const SIZE: u32 = 64;
+
+	let mut world = HittableGroup::new();
+	let camera = scene_tank(&mut world, SIZE, SIZE).unwrap();
+	let world = Arc::new(world);
+
+	let mut group = c.benchmark_group("render");
+	group.sample_size(10);
+	group.bench_function("tank 64x64 4spp", |b| {
+		b.iter(|| {
+			render(
+				Arc::clone(&world),
+				Arc::clone(&camera),
+				Arc::new(PathTracer::default()),
+				&RenderSettings {
+					width: SIZE,
+					height: SIZE,
+					samples: 4,
+					threads: 4,
+					..RenderSettings::default()
+				},
+				&PostProcess::default(),
+				Arc::new(|_| {}),
+				None,
+				None,
+			)
+			.unwrap()
+			.image
+		})
+	});
+	group.finish();
+}
+
+criterion_group!(benches, frame);
+criterion_main!(benches);
@@ -0,0 +1,272 @@
+use std::ops::{Add, Mul, Sub};
+
+use crate::camera::Camera;
+use crate::geometry::{Float, Mat4, Quat, Vec3f};
+
+// Where the camera is in every frame of an animation, as a variation of the camera of the scene
+pub trait CameraAnimation {
+	fn frames(&self) -> u32;
+	fn camera(&self, base: &Camera, frame: u32) -> Camera;
+}
+
+// Orbits the camera around a target at a fixed radius and elevation
+pub struct Turntable {
+	pub target: Vec3f,
+	pub radius: Float,
+	// Degrees above the horizontal plane through the target
+	pub elevation: Float,
+	// Degrees around the vertical axis of the first frame
+	pub start_angle: Float,
+	pub frames: u32,
+}
+
+impl Turntable {
+	// Starts the orbit at the camera's current position
+	pub fn from_camera(camera: &Camera, frames: u32) -> Turntable {
+		let offset = camera.position - camera.look_at;
+		let radius = offset.length();
+
+		Turntable {
+			target: camera.look_at,
+			radius,
+			elevation: (offset.y / radius).asin().to_degrees(),
+			start_angle: offset.x.atan2(offset.z).to_degrees(),
+			frames,
+		}
+	}
+
+	pub fn position(&self, frame: u32) -> Vec3f {
+		let angle = (self.start_angle + 360.0 * frame as Float / self.frames as Float).to_radians();
+		let elevation = self.elevation.to_radians();
+		let horizontal = self.radius * elevation.cos();
+
+		self.target
+			+ Vec3f::new(
+				horizontal * angle.sin(),
+				self.radius * elevation.sin(),
+				horizontal * angle.cos(),
+			)
+	}
+}
+
+impl CameraAnimation for Turntable {
+	fn frames(&self) -> u32 {
+		self.frames
+	}
+
+	fn camera(&self, base: &Camera, frame: u32) -> Camera {
+		base.to_builder()
+			.position(self.position(frame))
+			.look_at(self.target)
+			.build()
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interpolation {
+	// Straight from key to key, the speed jumps at every key
+	Linear,
+	// Catmull-Rom spline through the keys, the speed changes smoothly
+	Spline,
+}
+
+// Values at keyframes, sorted by frame with no frame twice
+#[derive(Debug, Clone)]
+pub struct Track<T> {
+	pub keys: Vec<(Float, T)>,
+	pub interpolation: Interpolation,
+}
+
+impl<T> Track<T> {
+	// The key after `frame` and how far `frame` is from the one before it to that key, 0 to 1.
+	// None before the first and after the last key, where the track holds.
+	pub fn segment(&self, frame: Float) -> Option<(usize, Float)> {
+		let (first, last) = (self.keys.first()?, self.keys.last()?);
+		if frame <= first.0 || frame >= last.0 {
+			return None;
+		}
+
+		let next = self.keys.partition_point(|&(key, _)| key <= frame);
+		let (t0, t1) = (self.keys[next - 1].0, self.keys[next].0);
+		Some((next, (frame - t0) / (t1 - t0)))
+	}
+}
+
+impl<T> Track<T>
+where
+	T: Copy + Add<Output = T> + Sub<Output = T> + Mul<Float, Output = T>,
+{
+	// The value at `frame`, held before the first and after the last key. None without keys.
+	pub fn at(&self, frame: Float) -> Option<T> {
+		let keys = &self.keys;
+		let (first, last) = (keys.first()?, keys.last()?);
+		let Some((next, t)) = self.segment(frame) else {
+			return Some(if frame <= first.0 { first.1 } else { last.1 });
+		};
+
+		let ((t0, p0), (t1, p1)) = (keys[next - 1], keys[next]);
+		let duration = t1 - t0;
+
+		match self.interpolation {
+			Interpolation::Linear => Some(p0 + (p1 - p0) * t),
+			Interpolation::Spline => {
+				// Tangents through the neighboring keys, which may be unevenly spaced, and the
+				// slope of the segment at either end
+				let tangent = |index: usize| {
+					let before = keys[index.saturating_sub(1)];
+					let after = keys[(index + 1).min(keys.len() - 1)];
+					(after.1 - before.1) * (1.0 / (after.0 - before.0))
+				};
+				let (m0, m1) = (tangent(next - 1), tangent(next));
+
+				let (t2, t3) = (t * t, t * t * t);
+				Some(
+					p0 * (2.0 * t3 - 3.0 * t2 + 1.0)
+						+ m0 * ((t3 - 2.0 * t2 + t) * duration)
+						+ p1 * (3.0 * t2 - 2.0 * t3)
+						+ m1 * ((t3 - t2) * duration),
+				)
+			}
+		}
+	}
+}
+
+// A camera move authored as keyframes. Each of position, look-at point and vertical field of
+// view has its own keys and keeps the value of the scene camera without any.
+#[derive(Debug, Clone)]
+pub struct CameraPath {
+	pub position: Track<Vec3f>,
+	pub look_at: Track<Vec3f>,
+	pub fov: Track<Float>,
+	pub frames: u32,
+}
+
+impl CameraAnimation for CameraPath {
+	fn frames(&self) -> u32 {
+		self.frames
+	}
+
+	fn camera(&self, base: &Camera, frame: u32) -> Camera {
+		let frame = frame as Float;
+		base.to_builder()
+			.position(self.position.at(frame).unwrap_or(base.position))
+			.look_at(self.look_at.at(frame).unwrap_or(base.look_at))
+			.fov(self.fov.at(frame).unwrap_or(base.fov))
+			.build()
+	}
+}
+
+// Placement of an object over the frames of an animation: it is scaled, then rotated by degrees
+// around x, y and z in that order, both around `pivot`, and then moved by the translation.
+// Rotation keys are turned into quaternions and slerped at a steady speed, which takes the
+// shortest way from one key to the next without the wobble of blending angles. Where any angle changes by more than
+// 180 degrees between two keys the angles are interpolated instead, so a key can deliberately
+// turn an object several times over.
+#[derive(Debug, Clone)]
+pub struct TransformPath {
+	pub translate: Track<Vec3f>,
+	pub rotate: Track<Vec3f>,
+	pub scale: Track<Vec3f>,
+	pub pivot: Vec3f,
+}
+
+impl TransformPath {
+	// Tracks without keys leave the object as it is
+	pub fn matrix(&self, frame: Float) -> Mat4 {
+		let zero = Vec3f::new(0.0, 0.0, 0.0);
+		let translate = self.translate.at(frame).unwrap_or(zero);
+		let scale = self.scale.at(frame).unwrap_or(Vec3f::new(1.0, 1.0, 1.0));
+
+		let keys = &self.rotate.keys;
+		let rotation = match self.rotate.segment(frame) {
+			Some((next, t)) if !spins(keys[next - 1].1, keys[next].1) => {
+				Quat::slerp(&euler(keys[next - 1].1), &euler(keys[next].1), t)
+			}
+			_ => euler(self.rotate.at(frame).unwrap_or(zero)),
+		};
+
+		Mat4::translate(translate + self.pivot)
+			* rotation.to_mat4()
+			* Mat4::scale(scale)
+			* Mat4::translate(-self.pivot)
+	}
+
+	// Whether the object moves at all, otherwise one placement serves every frame
+	pub fn is_animated(&self) -> bool {
+		self.translate.keys.len() > 1 || self.rotate.keys.len() > 1 || self.scale.keys.len() > 1
+	}
+}
+
+// Degrees around x, y and z in that order
+fn euler(degrees: Vec3f) -> Quat {
+	Quat::from_axis_angle(Vec3f::new(0.0, 0.0, 1.0), degrees.z)
+		* Quat::from_axis_angle(Vec3f::new(0.0, 1.0, 0.0), degrees.y)
+		* Quat::from_axis_angle(Vec3f::new(1.0, 0.0, 0.0), degrees.x)
+}
+
+// Whether two rotation keys are more than half a turn apart around any axis, which slerp would
+// take the short way around
+fn spins(from: Vec3f, to: Vec3f) -> bool {
+	let turn = to - from;
+	turn.x.abs() > 180.0 || turn.y.abs() > 180.0 || turn.z.abs() > 180.0
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn rotation(keys: &[(Float, [Float; 3])]) -> TransformPath {
+		let zero = Vec3f::new(0.0, 0.0, 0.0);
+		let fixed = |value| Track {
+			keys: vec![(0.0, value)],
+			interpolation: Interpolation::Linear,
+		};
+		TransformPath {
+			translate: fixed(zero),
+			rotate: Track {
+				keys: keys
+					.iter()
+					.map(|&(frame, [x, y, z])| (frame, Vec3f::new(x, y, z)))
+					.collect(),
+				interpolation: Interpolation::Linear,
+			},
+			scale: fixed(Vec3f::new(1.0, 1.0, 1.0)),
+			pivot: zero,
+		}
+	}
+
+	// Where the path turns the x axis in `frame`
+	fn x_axis(path: &TransformPath, frame: Float) -> Vec3f {
+		path.matrix(frame)
+			.transform_vector(Vec3f::new(1.0, 0.0, 0.0))
+	}
+
+	fn assert_near(a: Vec3f, b: Vec3f) {
+		assert!((a - b).length() < 1e-4, "{:?} != {:?}", a, b);
+	}
+
+	#[test]
+	fn keys_are_held_at_the_ends() {
+		let path = rotation(&[(0.0, [0.0, 90.0, 0.0]), (10.0, [0.0, 0.0, 90.0])]);
+		assert_near(x_axis(&path, -5.0), Vec3f::new(0.0, 0.0, -1.0));
+		assert_near(x_axis(&path, 0.0), Vec3f::new(0.0, 0.0, -1.0));
+		assert_near(x_axis(&path, 10.0), Vec3f::new(0.0, 1.0, 0.0));
+		assert_near(x_axis(&path, 15.0), Vec3f::new(0.0, 1.0, 0.0));
+	}
+
+	#[test]
+	fn keys_within_half_a_turn_are_slerped() {
+		// Halfway along the single turn slerp makes from one key to the next, where blending the
+		// angles would give (0.5, 0.5, -0.71)
+		let path = rotation(&[(0.0, [0.0, 90.0, 0.0]), (10.0, [0.0, 0.0, 90.0])]);
+		assert_near(x_axis(&path, 5.0), Vec3f::new(1.0, 2.0, -2.0) * (1.0 / 3.0));
+	}
+
+	#[test]
+	fn keys_past_half_a_turn_spin() {
+		// Slerp would not move at all between these, the angles turn the object around once
+		let path = rotation(&[(0.0, [0.0, 0.0, 0.0]), (10.0, [0.0, 360.0, 0.0])]);
+		assert_near(x_axis(&path, 5.0), Vec3f::new(-1.0, 0.0, 0.0));
+		assert_near(x_axis(&path, 2.5), Vec3f::new(0.0, 0.0, -1.0));
+	}
+}
@@ -0,0 +1,105 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::error::{RendererError, Result};
+use crate::geometry::{Float, Vec3f};
+use crate::random;
+
+// Shape of the opening of a lens, which out of focus highlights take on
+#[derive(Debug, Clone, PartialEq)]
+pub enum Aperture {
+	Circle,
+	// A regular polygon of `blades` straight blades, turned by `rotation` degrees from the one
+	// with a corner at the top
+	Polygon { blades: u32, rotation: Float },
+	// An image over the square around the opening, letting light through in proportion to its
+	// brightness
+	Mask(Arc<ApertureMask>),
+}
+
+// The brightness of a mask image as a distribution to pick pixels from
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApertureMask {
+	width: u32,
+	height: u32,
+	// Running sum of the brightness in row order, the last entry is the total
+	cdf: Vec<Float>,
+}
+
+impl Aperture {
+	// A random point of the opening with y pointing down the image, within 1 of the center for a
+	// circle or polygon and in the square around that for a mask
+	pub fn sample(&self) -> (Float, Float) {
+		match self {
+			Aperture::Circle => {
+				let point = Vec3f::random_in_unit_disk();
+				(point.x, point.y)
+			}
+			Aperture::Polygon { blades, rotation } => {
+				// Uniform over the triangle from the center to the edge of one blade
+				let blades = (*blades).max(3);
+				let step = 2.0 * PI as Float / blades as Float;
+				let start = rotation.to_radians() - PI as Float / 2.0
+					+ step * random::index(blades as usize) as Float;
+				let corner = |angle: Float| (angle.cos(), angle.sin());
+				let ((ax, ay), (bx, by)) = (corner(start), corner(start + step));
+
+				let (mut u, mut v) = (random::float(), random::float());
+				if u + v > 1.0 {
+					(u, v) = (1.0 - u, 1.0 - v);
+				}
+				(ax * u + bx * v, ay * u + by * v)
+			}
+			Aperture::Mask(mask) => mask.sample(),
+		}
+	}
+}
+
+impl ApertureMask {
+	pub fn load(path: &str) -> Result<ApertureMask> {
+		let image = image::open(path)
+			.map_err(|source| RendererError::Image {
+				path: path.to_string(),
+				source,
+			})?
+			.into_luma8();
+
+		ApertureMask::new(image.width(), image.height(), image.as_raw()).ok_or_else(|| {
+			RendererError::Scene {
+				path: Some(path.to_string()),
+				message: "an aperture mask needs to let some light through".to_string(),
+			}
+		})
+	}
+
+	// From the brightness of `width` by `height` pixels in row order, None if all are black
+	pub fn new(width: u32, height: u32, pixels: &[u8]) -> Option<ApertureMask> {
+		let mut total = 0.0;
+		let cdf: Vec<Float> = pixels
+			.iter()
+			.map(|&pixel| {
+				total += pixel as Float / 255.0;
+				total
+			})
+			.collect();
+
+		if total == 0.0 || cdf.len() != (width * height) as usize {
+			return None;
+		}
+
+		Some(ApertureMask { width, height, cdf })
+	}
+
+	fn sample(&self) -> (Float, Float) {
+		let total = self.cdf[self.cdf.len() - 1];
+		let target = random::float() * total;
+		let pixel = self
+			.cdf
+			.partition_point(|&sum| sum <= target)
+			.min(self.cdf.len() - 1) as u32;
+
+		let x = ((pixel % self.width) as Float + random::float()) / self.width as Float;
+		let y = ((pixel / self.width) as Float + random::float()) / self.height as Float;
+		(2.0 * x - 1.0, 2.0 * y - 1.0)
+	}
+}
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::error::Result;
+use crate::geometry::{
+	load_mesh, load_mesh_with_materials, load_moving_mesh, load_moving_mesh_with_materials, Aabb3d,
+	Bvh, HitResult, Hittable, Interval, MeshOptions, Ray, Triangle,
+};
+use crate::material::{ImageTexture, Material};
+
+// Images and meshes loaded while building a scene, so that a file referred to by several
+// materials or objects is read and kept in memory once. Files are told apart by their canonical
+// path. Kept around between builds, only the files that changed on disk since (see refresh)
+// are loaded again.
+#[derive(Default)]
+pub struct Assets {
+	images: Mutex<HashMap<PathBuf, Arc<ImageTexture>>>,
+	// Keyed by the settings they were loaded with as well, displaced and moving meshes are never
+	// shared
+	meshes: Mutex<HashMap<(PathBuf, String), CachedMesh>>,
+	// Modification times of every file read, None if it could not be found out
+	modified: Mutex<HashMap<PathBuf, Option<SystemTime>>>,
+}
+
+struct CachedMesh {
+	mesh: Arc<Bvh<Triangle>>,
+	// The OBJ and its MTL files
+	files: Vec<PathBuf>,
+	// Meshes with the materials of their MTL files also depend on the images they use
+	uses_images: bool,
+}
+
+fn canonical(path: &Path) -> PathBuf {
+	path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+	std::fs::metadata(path)
+		.and_then(|metadata| metadata.modified())
+		.ok()
+}
+
+// The MTL files an OBJ refers to with `mtllib`, relative to the OBJ
+fn material_libraries(path: &Path) -> Vec<PathBuf> {
+	let Ok(obj) = std::fs::read_to_string(path) else {
+		return Vec::new();
+	};
+	let directory = path.parent().unwrap_or(Path::new(""));
+
+	obj.lines()
+		.filter_map(|line| line.trim().strip_prefix("mtllib"))
+		.flat_map(|names| names.split_whitespace())
+		.map(|name| canonical(&directory.join(name)))
+		.collect()
+}
+
+impl Assets {
+	pub fn image(&self, path: &Path) -> Result<Arc<ImageTexture>> {
+		let key = canonical(path);
+		if let Some(image) = self.images.lock().unwrap().get(&key) {
+			return Ok(Arc::clone(image));
+		}
+
+		let image = Arc::new(ImageTexture::new(&path.to_string_lossy())?);
+		self.watch(&key);
+		self.images.lock().unwrap().insert(key, Arc::clone(&image));
+		Ok(image)
+	}
+
+	// Remembers when `path` was last modified, for changed
+	fn watch(&self, path: &Path) {
+		self.modified
+			.lock()
+			.unwrap()
+			.entry(path.to_path_buf())
+			.or_insert_with(|| modified(path));
+	}
+
+	fn mesh(
+		&self,
+		path: &str,
+		settings: String,
+		options: &MeshOptions,
+		uses_images: bool,
+		load: impl FnOnce() -> Result<Bvh<Triangle>>,
+	) -> Result<Arc<Bvh<Triangle>>> {
+		let obj = canonical(Path::new(path));
+		let mut files = vec![obj.clone()];
+		if uses_images {
+			files.extend(material_libraries(&obj));
+		}
+		for file in &files {
+			self.watch(file);
+		}
+
+		if options.displacement.is_some() {
+			return Ok(Arc::new(load()?));
+		}
+
+		let key = (obj, settings);
+		if let Some(cached) = self.meshes.lock().unwrap().get(&key) {
+			log::info!("Reusing {}", path);
+			return Ok(Arc::clone(&cached.mesh));
+		}
+
+		let mesh = Arc::new(load()?);
+		self.meshes.lock().unwrap().insert(
+			key,
+			CachedMesh {
+				mesh: Arc::clone(&mesh),
+				files,
+				uses_images,
+			},
+		);
+		Ok(mesh)
+	}
+
+	// Every object gets its own material, on triangles that are shared between all objects with
+	// the same OBJ file and options
+	pub fn mesh_with_material(
+		&self,
+		path: &str,
+		material: Arc<dyn Material>,
+		options: &MeshOptions,
+	) -> Result<Box<dyn Hittable>> {
+		let mesh = self.mesh(path, options_key(options), options, false, || {
+			load_mesh(path, Arc::clone(&material), options)
+		})?;
+		Ok(Box::new(Rematerial { mesh, material }))
+	}
+
+	pub fn mesh_with_materials(
+		&self,
+		path: &str,
+		fallback: Arc<dyn Material>,
+		texture_paths: &[PathBuf],
+		options: &MeshOptions,
+	) -> Result<Box<dyn Hittable>> {
+		let settings = format!("{} mtl {:?}", options_key(options), texture_paths);
+		let mesh = self.mesh(path, settings, options, true, || {
+			load_mesh_with_materials(path, fallback, texture_paths, options, self)
+		})?;
+		Ok(Box::new(mesh))
+	}
+
+	// A mesh deforming through the OBJ files of `samples` over the shutter interval, loaded
+	// every time like displaced meshes
+	pub fn moving_mesh_with_material(
+		&self,
+		path: &str,
+		samples: &[String],
+		material: Arc<dyn Material>,
+		options: &MeshOptions,
+	) -> Result<Box<dyn Hittable>> {
+		self.watch_meshes(path, samples, false);
+		Ok(Box::new(load_moving_mesh(
+			path, samples, material, options,
+		)?))
+	}
+
+	pub fn moving_mesh_with_materials(
+		&self,
+		path: &str,
+		samples: &[String],
+		fallback: Arc<dyn Material>,
+		texture_paths: &[PathBuf],
+		options: &MeshOptions,
+	) -> Result<Box<dyn Hittable>> {
+		self.watch_meshes(path, samples, true);
+		Ok(Box::new(load_moving_mesh_with_materials(
+			path,
+			samples,
+			fallback,
+			texture_paths,
+			options,
+			self,
+		)?))
+	}
+
+	fn watch_meshes(&self, path: &str, samples: &[String], uses_images: bool) {
+		let obj = canonical(Path::new(path));
+		if uses_images {
+			for library in material_libraries(&obj) {
+				self.watch(&library);
+			}
+		}
+		self.watch(&obj);
+		for sample in samples {
+			self.watch(&canonical(Path::new(sample)));
+		}
+	}
+
+	// Files read so far that were modified on disk since, or that appeared or disappeared
+	pub fn changed(&self) -> Vec<PathBuf> {
+		self.modified
+			.lock()
+			.unwrap()
+			.iter()
+			.filter(|(path, &time)| modified(path) != time)
+			.map(|(path, _)| path.clone())
+			.collect()
+	}
+
+	// Forgets the images and meshes whose files changed, so that the next build loads them
+	// again, and returns the files. Meshes with MTL materials are dropped whenever an image
+	// changed, since it may be one of their textures.
+	pub fn refresh(&self) -> Vec<PathBuf> {
+		let changed = self.changed();
+		let mut modified = self.modified.lock().unwrap();
+		for path in &changed {
+			modified.remove(path);
+		}
+
+		let mut images = self.images.lock().unwrap();
+		let image_changed = changed.iter().any(|path| images.contains_key(path));
+		images.retain(|path, _| !changed.contains(path));
+
+		self.meshes.lock().unwrap().retain(|_, cached| {
+			!(cached.uses_images && image_changed
+				|| cached.files.iter().any(|file| changed.contains(file)))
+		});
+
+		changed
+	}
+}
+
+fn options_key(options: &MeshOptions) -> String {
+	format!(
+		"{:?} {} {:?}",
+		options.weld, options.subdivisions, options.smooth_normals
+	)
+}
+
+// Shared triangles seen with the material of one object
+struct Rematerial {
+	mesh: Arc<Bvh<Triangle>>,
+	material: Arc<dyn Material>,
+}
+
+impl Hittable for Rematerial {
+	fn hit(&self, interval: &Interval, ray: &Ray) -> Option<HitResult<'_>> {
+		let mut hit_result = self.mesh.hit(interval, ray)?;
+		hit_result.material = self.material.as_ref();
+		Some(hit_result)
+	}
+
+	fn hit_any(&self, interval: &Interval, ray: &Ray) -> bool {
+		self.mesh.hit_any(interval, ray)
+	}
+
+	fn bounds(&self) -> &Aabb3d {
+		self.mesh.bounds()
+	}
+}
@@ -1,34 +1,213 @@
-use rand::Rng;
+use crate::aperture::Aperture;
+use crate::geometry::{Float, Ray, RayDifferentials, Vec3f};
+use crate::random;
 
-use crate::geometry::{Hittable, HittableGroup, Interval, Ray, Vec3f};
+use std::f64::consts::PI;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FisheyeMapping {
+	// Image radius proportional to the angle from the view direction
+	Equidistant,
+	// Equal areas on the image cover equal solid angles
+	Equisolid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+	// Pinhole camera with the vertical field of view of the camera
+	Perspective,
+	// Circular image inscribed in the frame, covering `fov` degrees across its diameter
+	Fisheye { mapping: FisheyeMapping, fov: Float },
+	// Full 360x180 degree panorama, longitude across and latitude down the image
+	Equirectangular,
+}
+
+// Photographic camera settings, lengths are in millimeters and the shutter time in seconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lens {
+	pub focal_length: Float,
+	// 24mm for a full frame (35mm) sensor, the image is square so only the height counts
+	pub sensor_height: Float,
+	pub f_stop: Float,
+	pub shutter: Float,
+	pub iso: Float,
+}
+
+impl Lens {
+	pub fn fov(&self) -> Float {
+		(2.0 * (self.sensor_height / (2.0 * self.focal_length)).atan()).to_degrees()
+	}
+
+	// Exposure in stops relative to f/16, 1/100s and ISO 100 (the sunny 16 rule), at which the
+	// rendered radiance is left unchanged
+	pub fn exposure(&self) -> Float {
+		let relative = (self.shutter / 0.01) * (self.iso / 100.0) * (16.0 * 16.0)
+			/ (self.f_stop * self.f_stop);
+		relative.log2()
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StereoLayout {
+	SideBySide,
+	// The left eye above the right one, as VR players expect 360 degree panoramas
+	TopBottom,
+}
+
+// Views from a left and a right eye `interocular` scene units apart, sharing the image as laid
+// out by `layout`. Both look at the same point at `convergence` distance, which appears at the
+// depth of the screen, and look straight ahead without one. Wide projections move the eyes
+// around with the view direction (omni-directional stereo), to be seen in a VR headset.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stereo {
+	pub interocular: Float,
+	pub convergence: Option<Float>,
+	pub layout: StereoLayout,
+}
+
+impl Stereo {
+	// Size of the view of one eye in an image of `width` by `height`
+	pub fn eye_size(&self, width: u32, height: u32) -> (u32, u32) {
+		match self.layout {
+			StereoLayout::SideBySide => ((width / 2).max(1), height),
+			StereoLayout::TopBottom => (width, (height / 2).max(1)),
+		}
+	}
+}
+
+// A thin lens instead of a pinhole, sharp at `focus_distance` from the camera along the view
+// direction and blurring what is in front of or behind it more the wider the opening is. The
+// `aperture` diameter is in scene units.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DepthOfField {
+	pub focus_distance: Float,
+	pub aperture: Float,
+	pub shape: Aperture,
+}
 
 pub struct Camera {
 	pub background: Vec3f,
 	pub position: Vec3f,
-	pub dir: Vec3f,
+	pub look_at: Vec3f,
+	pub fov: Float,
+	// Width over height of the view, which need not be that of the image
+	pub aspect: Float,
+	pub image_width: u32,
+	pub image_height: u32,
+	pub projection: Projection,
+	pub depth_of_field: Option<DepthOfField>,
+	pub stereo: Option<Stereo>,
+	// Rays are traced at random times while the shutter is open, for objects that move during it
+	pub motion_blur: bool,
+
+	// Size of the view, that of the image or of one eye in it
+	eye_width: u32,
+	eye_height: u32,
+
+	// Orthonormal basis, `down` follows the image rows
+	right: Vec3f,
+	down: Vec3f,
+	forward: Vec3f,
 
 	pixel_dx: Vec3f,
 	pixel_dy: Vec3f,
 	pixel_corner: Vec3f,
 }
 
-impl Camera {
-	pub fn new(
-		background: Vec3f,
-		position: Vec3f,
-		lookat: Vec3f,
-		fov: f64,
-		image_width: u32,
-		image_height: u32,
-	) -> Camera {
-		let theta = fov.to_radians();
+// Camera settings by name, see Camera::builder. Without an `aspect` the view takes the shape of
+// the image.
+#[derive(Debug, Clone)]
+pub struct CameraBuilder {
+	background: Vec3f,
+	position: Vec3f,
+	look_at: Vec3f,
+	fov: Float,
+	aspect: Option<Float>,
+	image_width: u32,
+	image_height: u32,
+	projection: Projection,
+	depth_of_field: Option<DepthOfField>,
+	stereo: Option<Stereo>,
+	motion_blur: bool,
+}
+
+impl CameraBuilder {
+	pub fn background(mut self, background: Vec3f) -> CameraBuilder {
+		self.background = background;
+		self
+	}
+
+	pub fn position(mut self, position: Vec3f) -> CameraBuilder {
+		self.position = position;
+		self
+	}
+
+	pub fn look_at(mut self, look_at: Vec3f) -> CameraBuilder {
+		self.look_at = look_at;
+		self
+	}
+
+	// Vertical field of view in degrees
+	pub fn fov(mut self, fov: Float) -> CameraBuilder {
+		self.fov = fov;
+		self
+	}
+
+	// Field of view from the focal length and sensor size, the exposure of the lens has to be
+	// passed on to the post-processing separately
+	pub fn lens(mut self, lens: &Lens) -> CameraBuilder {
+		self.fov = lens.fov();
+		self
+	}
+
+	pub fn aspect(mut self, aspect: Float) -> CameraBuilder {
+		self.aspect = Some(aspect);
+		self
+	}
+
+	pub fn size(mut self, image_width: u32, image_height: u32) -> CameraBuilder {
+		self.image_width = image_width;
+		self.image_height = image_height;
+		self
+	}
+
+	pub fn projection(mut self, projection: Projection) -> CameraBuilder {
+		self.projection = projection;
+		self
+	}
+
+	pub fn depth_of_field(mut self, depth_of_field: DepthOfField) -> CameraBuilder {
+		self.depth_of_field = Some(depth_of_field);
+		self
+	}
+
+	pub fn stereo(mut self, stereo: Stereo) -> CameraBuilder {
+		self.stereo = Some(stereo);
+		self
+	}
+
+	pub fn motion_blur(mut self, motion_blur: bool) -> CameraBuilder {
+		self.motion_blur = motion_blur;
+		self
+	}
+
+	pub fn build(&self) -> Camera {
+		let (eye_width, eye_height) = match self.stereo {
+			Some(stereo) => stereo.eye_size(self.image_width, self.image_height),
+			None => (self.image_width, self.image_height),
+		};
+		let aspect = self
+			.aspect
+			.unwrap_or(eye_width as Float / eye_height as Float);
+
+		let theta = self.fov.to_radians();
 		let h = (theta / 2.0).tan();
 		let viewport_height = 2.0 * h;
-		let viewport_width = viewport_height; // Assuming 1:1 aspect ratio
+		let viewport_width = viewport_height * aspect;
 
 		// Calculate basis vectors for the camera
 		let up = Vec3f::new(0.0, 1.0, 0.0);
-		let cz = (position - lookat).unit();
+		let cz = (self.position - self.look_at).unit();
 		let cx = Vec3f::cross(&up, &cz).unit();
 		let cy = Vec3f::cross(&cx, &cz);
 
@@ -36,71 +215,286 @@ impl Camera {
 		let vx = cx * viewport_width;
 		let vy = cy * viewport_height;
 
-		let pixel_dx = vx / image_width as f64;
-		let pixel_dy = vy / image_height as f64;
+		let pixel_dx = vx / eye_width as Float;
+		let pixel_dy = vy / eye_height as Float;
 
-		let viewport_corner = (position - cz) - vx / 2.0 - vy / 2.0;
+		let viewport_corner = (self.position - cz) - vx / 2.0 - vy / 2.0;
 		let pixel_corner = viewport_corner + pixel_dx / 2.0 + pixel_dy / 2.0;
 
-		let dir = lookat - position;
-
 		Camera {
-			background,
-			dir,
-			position,
+			background: self.background,
+			position: self.position,
+			look_at: self.look_at,
+			fov: self.fov,
+			aspect,
+			image_width: self.image_width,
+			image_height: self.image_height,
+			projection: self.projection,
+			depth_of_field: self.depth_of_field.clone(),
+			stereo: self.stereo,
+			motion_blur: self.motion_blur,
+			eye_width,
+			eye_height,
+			right: cx,
+			down: cy,
+			forward: -cz,
 			pixel_dx,
 			pixel_dy,
 			pixel_corner,
 		}
 	}
+}
 
-	fn sample_square(&self) -> Vec3f {
-		let rx = -0.5 + rand::thread_rng().gen::<f64>();
-		let ry = -0.5 + rand::thread_rng().gen::<f64>();
+impl Camera {
+	// A camera at the origin looking down -z with a black background, a field of view of 90
+	// degrees and a 512x512 image, to be changed setting by setting
+	pub fn builder() -> CameraBuilder {
+		CameraBuilder {
+			background: Vec3f::new(0.0, 0.0, 0.0),
+			position: Vec3f::new(0.0, 0.0, 0.0),
+			look_at: Vec3f::new(0.0, 0.0, -1.0),
+			fov: 90.0,
+			aspect: None,
+			image_width: 512,
+			image_height: 512,
+			projection: Projection::Perspective,
+			depth_of_field: None,
+			stereo: None,
+			motion_blur: false,
+		}
+	}
 
-		self.pixel_dx * rx + self.pixel_dy * ry
+	// The view is square whatever the size of the image, see CameraBuilder::aspect
+	pub fn new(
+		background: Vec3f,
+		position: Vec3f,
+		lookat: Vec3f,
+		fov: Float,
+		image_width: u32,
+		image_height: u32,
+	) -> Camera {
+		Camera::builder()
+			.background(background)
+			.position(position)
+			.look_at(lookat)
+			.fov(fov)
+			.aspect(1.0)
+			.size(image_width, image_height)
+			.build()
 	}
 
-	pub fn intial_ray(&self, pixel_x: u32, pixel_y: u32) -> Ray {
-		let point =
-			self.pixel_corner + (self.pixel_dx * pixel_x as f64) + (self.pixel_dy * pixel_y as f64);
+	// Field of view from the focal length and sensor size, the exposure of the lens has to be
+	// passed on to the post-processing separately
+	pub fn from_lens(
+		background: Vec3f,
+		position: Vec3f,
+		lookat: Vec3f,
+		lens: &Lens,
+		image_width: u32,
+		image_height: u32,
+	) -> Camera {
+		Camera::new(
+			background,
+			position,
+			lookat,
+			lens.fov(),
+			image_width,
+			image_height,
+		)
+	}
 
-		let sample = self.sample_square();
+	// The settings of this camera, for building variations of it
+	pub fn to_builder(&self) -> CameraBuilder {
+		let mut builder = Camera::builder()
+			.background(self.background)
+			.position(self.position)
+			.look_at(self.look_at)
+			.fov(self.fov)
+			.aspect(self.aspect)
+			.size(self.image_width, self.image_height)
+			.projection(self.projection)
+			.motion_blur(self.motion_blur);
 
-		Ray {
-			origin: self.position,
-			direction: ((point - self.position) + sample).unit(),
+		if let Some(depth_of_field) = &self.depth_of_field {
+			builder = builder.depth_of_field(depth_of_field.clone());
+		}
+		if let Some(stereo) = self.stereo {
+			builder = builder.stereo(stereo);
 		}
+		builder
 	}
 
-	#[allow(unreachable_code)]
-	pub fn raycast(&self, ray: &Ray, world: &HittableGroup, depth: u32) -> Vec3f {
-		if depth == 0 {
-			//return Vec3f::new(2.0, 2.0, 2.0);
-			return self.background;
-		}
+	// The same view at another resolution, also when the shapes differ
+	pub fn resized(&self, width: u32, height: u32) -> Camera {
+		self.to_builder().size(width, height).build()
+	}
 
-		if let Some(hit_result) = world.hit(&Interval::new_ray(), ray) {
-			let emitted = hit_result.material.emit(hit_result.u, hit_result.v);
+	pub fn with_projection(mut self, projection: Projection) -> Camera {
+		self.projection = projection;
+		self
+	}
+
+	pub fn with_motion_blur(mut self) -> Camera {
+		self.motion_blur = true;
+		self
+	}
+
+	pub fn with_depth_of_field(mut self, depth_of_field: DepthOfField) -> Camera {
+		self.depth_of_field = Some(depth_of_field);
+		self
+	}
+
+	// The view of each eye gets its own part of the image, so the camera is built again for its
+	// size
+	pub fn with_stereo(self, stereo: Stereo) -> Camera {
+		self.to_builder().stereo(stereo).build()
+	}
 
-			if let Some((attenuation, scattered)) = hit_result.material.scatter(ray, &hit_result) {
-				let scatter = attenuation * self.raycast(&scattered, world, depth - 1);
-				return emitted + scatter;
-			} else {
-				return emitted;
+	// Angle between the rays through neighboring pixels at the center of the image, which the
+	// ray cones of the integrators widen by
+	pub fn spread(&self) -> Float {
+		match self.projection {
+			Projection::Perspective => self.pixel_dy.length(),
+			Projection::Fisheye { fov, .. } => {
+				fov.to_radians() / self.eye_width.min(self.eye_height) as Float
 			}
+			Projection::Equirectangular => PI as Float / self.eye_height as Float,
 		}
+	}
+
+	// The rays through the neighbors of a camera ray's pixel, for the perspective projection.
+	// The others fall back on ray cones of the spread.
+	pub fn differentials(&self, ray: &Ray) -> Option<RayDifferentials> {
+		if self.projection != Projection::Perspective {
+			return None;
+		}
+
+		// Pixels are laid out on the plane one unit in front of the camera
+		let through = ray.direction / Vec3f::dot(&ray.direction, &self.forward);
+		let length = ray.direction.length();
+		let offset = |pixel: Vec3f| (through + pixel).unit() * length - ray.direction;
+
+		Some(RayDifferentials {
+			origin: [Vec3f::new(0.0, 0.0, 0.0); 2],
+			direction: [offset(self.pixel_dx), offset(self.pixel_dy)],
+		})
+	}
+
+	// Through a random point of the pixel, None for pixels that the projection does not cover,
+	// they stay black
+	pub fn intial_ray(&self, pixel_x: u32, pixel_y: u32) -> Option<Ray> {
+		let x = pixel_x as Float + random::float();
+		let y = pixel_y as Float + random::float();
+		self.ray_at(x, y)
+	}
+
+	// Through a point of the image in pixels, pixel (x, y) spans x to x + 1 and y to y + 1, and
+	// a random point of the lens with depth of field, at a random time with motion blur. None
+	// outside of the projection.
+	pub fn ray_at(&self, image_x: Float, image_y: Float) -> Option<Ray> {
+		// -1 for the left eye and 1 for the right one, and the point in the view of that eye
+		let (eye, image_x, image_y) = match self.stereo.map(|stereo| stereo.layout) {
+			None => (0.0, image_x, image_y),
+			Some(StereoLayout::SideBySide) if image_x < self.eye_width as Float => {
+				(-1.0, image_x, image_y)
+			}
+			Some(StereoLayout::SideBySide) => (1.0, image_x - self.eye_width as Float, image_y),
+			Some(StereoLayout::TopBottom) if image_y < self.eye_height as Float => {
+				(-1.0, image_x, image_y)
+			}
+			Some(StereoLayout::TopBottom) => (1.0, image_x, image_y - self.eye_height as Float),
+		};
+
+		let direction = match self.projection {
+			Projection::Perspective => {
+				let point = self.pixel_corner
+					+ (self.pixel_dx * (image_x - 0.5))
+					+ (self.pixel_dy * (image_y - 0.5));
+
+				point - self.position
+			}
+			Projection::Fisheye { mapping, fov } => {
+				// Offset from the image center, 1 at the edge of the image circle
+				let radius = self.eye_width.min(self.eye_height) as Float / 2.0;
+				let x = (image_x - self.eye_width as Float / 2.0) / radius;
+				let y = (image_y - self.eye_height as Float / 2.0) / radius;
 
-		return self.background;
+				let r = (x * x + y * y).sqrt();
+				if r > 1.0 {
+					return None;
+				}
 
-		let blue: Vec3f = Vec3f {
-			x: 0.5,
-			y: 0.7,
-			z: 1.0,
+				let half_fov = fov.to_radians() / 2.0;
+				let theta = match mapping {
+					FisheyeMapping::Equidistant => r * half_fov,
+					FisheyeMapping::Equisolid => 2.0 * (r * (half_fov / 2.0).sin()).asin(),
+				};
+				let phi = y.atan2(x);
+
+				self.forward * theta.cos()
+					+ (self.right * phi.cos() + self.down * phi.sin()) * theta.sin()
+			}
+			Projection::Equirectangular => {
+				let u = image_x / self.eye_width as Float;
+				let v = image_y / self.eye_height as Float;
+
+				let longitude = (u - 0.5) * 2.0 * PI as Float;
+				let latitude = (0.5 - v) * PI as Float;
+
+				(self.forward * longitude.cos() + self.right * longitude.sin()) * latitude.cos()
+					- self.down * latitude.sin()
+			}
 		};
 
-		let a = 0.5 * (ray.direction.y + 1.0);
+		let mut direction = direction.unit();
+		let mut origin = self.position;
+		let time = if self.motion_blur {
+			random::float()
+		} else {
+			0.0
+		};
 
-		blue * a + (1.0 - a)
+		if let Some(stereo) = self.stereo {
+			// Wide projections put the eyes to the sides of every direction, fading to the center
+			// towards the poles where there is no side
+			let baseline = match self.projection {
+				Projection::Perspective => self.right,
+				_ => Vec3f::cross(&direction, &Vec3f::new(0.0, 1.0, 0.0)),
+			};
+			origin = self.position + baseline * (eye * stereo.interocular / 2.0);
+			if let Some(convergence) = stereo.convergence {
+				let target = self.position + direction * self.along(direction, convergence);
+				direction = (target - origin).unit();
+			}
+		}
+
+		let Some(depth_of_field) = &self.depth_of_field else {
+			return Some(Ray {
+				origin,
+				direction,
+				time,
+			});
+		};
+
+		// The pinhole ray meets what is in focus, which every point of the lens sees sharply
+		let focus = origin + direction * self.along(direction, depth_of_field.focus_distance);
+		let (u, v) = depth_of_field.shape.sample();
+		let lens = origin + (self.right * u + self.down * v) * (depth_of_field.aperture / 2.0);
+
+		Some(Ray {
+			origin: lens,
+			direction: (focus - lens).unit(),
+			time,
+		})
+	}
+
+	// How far to go along `direction` to reach `distance` from the camera, measured along the
+	// view direction for the perspective projection. Wide projections measure it along the ray,
+	// as the view direction is far off towards the edges.
+	fn along(&self, direction: Vec3f, distance: Float) -> Float {
+		match self.projection {
+			Projection::Perspective => distance / Vec3f::dot(&direction, &self.forward),
+			_ => distance,
+		}
 	}
 }
@@ -0,0 +1,260 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use raytracer::dither::Dither;
+use raytracer::filter::Filter;
+use raytracer::geometry::Float;
+use raytracer::integrator::{self, DebugMode, DebugView, Integrator};
+use raytracer::material::MaterialOverride;
+use raytracer::render::{OutputFormat, Preset, Region, SampleOffset};
+use raytracer::scenes::{self, Scene};
+
+pub const USAGE: &str = "Usage:
+  raytracer [SCENE.toml] [OPTIONS]   render a built-in scene (tank by default) or a scene file
+  raytracer serve [ADDRESS]          start the headless render server
+
+Options:
+  --scene NAME         built-in scene: tank, ant, cube, spheres, cornell, random_spheres
+  --integrator NAME    path (default), ao or direct, overrides the scene file
+  --mode MODE          false color view of the first hit: normals, depth, uv, wireframe
+                       or heatmap
+  --override-material NAME
+                       render every material but the lights as clay or chrome
+  --preset NAME        quality: draft, medium or final (default), replaces the samples
+                       and preset of a scene file
+  --scale X            render at X times the resolution with the same framing, e.g. 0.25
+                       for a quick check of the composition
+  --scale-samples      also scale the samples by the same factor
+  --region X,Y,W,H     only render the given sub-rectangle, the rest stays black
+  --threads N          worker threads, one per core by default and at most two per core
+  --seed N             seed the random numbers for a reproducible image
+  --clamp X            limit the brightness of single samples to get rid of fireflies
+  --filter NAME        reconstruction filter: box (default), tent, gaussian or mitchell
+  --sample-offset X,Y  shift the sample pattern by X,Y pixels, or by a new Halton offset for
+                       every frame with 'halton', for accumulating frames TAA style
+  --format FORMAT      png (default) or exr
+  --dither MODE        ordered or blue_noise noise against banding in the 8 bit image
+  --lut FILE           grade the 8 bit image with a 3D LUT from a .cube file
+  --time DURATION      keep adding samples until the time budget (e.g. 90s, 10m, 1h) runs out
+  --turntable FRAMES   orbit the camera around its look-at point over FRAMES frames
+  --radius R           turntable orbit radius, defaults to the camera distance
+  --elevation DEG      turntable elevation, defaults to the camera elevation
+  --video FILE         pipe animation frames to ffmpeg and write FILE (.mp4 or .webm)
+  --fps N              video frame rate, defaults to 30
+  --stats              write render statistics as JSON next to the output
+  --depth-aov          also write the average bounce count of every pixel as a heat map
+  --id-aov             also write the objects seen in every pixel in colors made from their IDs
+  --exposure-check     also write a false color image of the exposure and a luminance histogram
+  --tev ADDRESS        stream finished rows to the tev image viewer, e.g. 127.0.0.1:14158
+  --watch              render the scene file again whenever it or its assets change
+  --export FILE        write the scene with all generated objects as a scene file instead
+                       of rendering it
+
+Set RUST_LOG (e.g. RUST_LOG=debug) to control how much is logged.";
+
+pub enum Command {
+	Render(Box<Options>),
+	Serve(String),
+}
+
+#[derive(Default)]
+pub struct Options {
+	pub scene_file: Option<String>,
+	pub scene: Option<Scene>,
+	pub scene_name: Option<String>,
+	pub integrator: Option<Arc<dyn Integrator>>,
+	pub material_override: Option<MaterialOverride>,
+	pub preset: Option<Preset>,
+	pub scale: Option<Float>,
+	pub scale_samples: bool,
+	pub region: Option<Region>,
+	pub threads: Option<usize>,
+	pub seed: Option<u64>,
+	pub clamp: Option<Float>,
+	pub format: Option<OutputFormat>,
+	pub filter: Option<Filter>,
+	pub sample_offset: Option<SampleOffset>,
+	pub dither: Option<Dither>,
+	pub lut: Option<String>,
+	pub time: Option<Duration>,
+	pub turntable: Option<u32>,
+	pub radius: Option<Float>,
+	pub elevation: Option<Float>,
+	pub video: Option<String>,
+	pub fps: Option<u32>,
+	pub stats: bool,
+	pub depth_aov: bool,
+	pub id_aov: bool,
+	pub exposure_check: bool,
+	pub tev: Option<String>,
+	pub watch: bool,
+	pub export: Option<String>,
+}
+
+fn value<T: std::str::FromStr>(
+	args: &mut impl Iterator<Item = String>,
+	flag: &str,
+) -> Result<T, String> {
+	let value = args
+		.next()
+		.ok_or_else(|| format!("{} expects a value", flag))?;
+
+	value
+		.parse()
+		.map_err(|_| format!("invalid value '{}' for {}", value, flag))
+}
+
+// Seconds, optionally suffixed with s, m or h
+fn duration(value: &str) -> Result<Duration, String> {
+	let (number, unit) = match value.char_indices().last() {
+		Some((index, 's')) => (&value[..index], 1.0),
+		Some((index, 'm')) => (&value[..index], 60.0),
+		Some((index, 'h')) => (&value[..index], 3600.0),
+		_ => (value, 1.0),
+	};
+
+	number
+		.parse::<f64>()
+		.ok()
+		.and_then(|number| Duration::try_from_secs_f64(number * unit).ok())
+		.ok_or_else(|| format!("invalid duration '{}'", value))
+}
+
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+	let mut args = args.into_iter().peekable();
+
+	if args.peek().map(String::as_str) == Some("serve") {
+		args.next();
+		let address = args
+			.next()
+			.unwrap_or_else(|| String::from("127.0.0.1:8080"));
+		return Ok(Command::Serve(address));
+	}
+
+	let mut options = Options::default();
+
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--scene" => {
+				let name: String = value(&mut args, &arg)?;
+				let scene = scenes::find(&name).ok_or_else(|| {
+					let names: Vec<&str> = scenes::SCENES.iter().map(|(name, _)| *name).collect();
+					format!(
+						"unknown scene '{}', expected one of {}",
+						name,
+						names.join(", ")
+					)
+				})?;
+				options.scene = Some(scene);
+				options.scene_name = Some(name);
+			}
+			"--integrator" => {
+				let name: String = value(&mut args, &arg)?;
+				let integrator = integrator::find(&name).ok_or_else(|| {
+					format!(
+						"unknown integrator '{}', expected one of {}",
+						name,
+						integrator::NAMES.join(", ")
+					)
+				})?;
+				if options.integrator.replace(integrator).is_some() {
+					return Err(String::from("--integrator cannot be combined with --mode"));
+				}
+			}
+			"--mode" => {
+				let mode: DebugMode = value::<String>(&mut args, &arg)?.parse()?;
+				if options
+					.integrator
+					.replace(Arc::new(DebugView::new(mode)))
+					.is_some()
+				{
+					return Err(String::from("--mode cannot be combined with --integrator"));
+				}
+			}
+			"--override-material" => {
+				options.material_override = Some(value::<String>(&mut args, &arg)?.parse()?)
+			}
+			"--preset" => options.preset = Some(value::<String>(&mut args, &arg)?.parse()?),
+			"--scale" => options.scale = Some(value(&mut args, &arg)?),
+			"--scale-samples" => options.scale_samples = true,
+			"--region" => options.region = Some(value(&mut args, &arg)?),
+			"--threads" => options.threads = Some(value(&mut args, &arg)?),
+			"--seed" => options.seed = Some(value(&mut args, &arg)?),
+			"--clamp" => options.clamp = Some(value(&mut args, &arg)?),
+			"--format" => options.format = Some(value::<String>(&mut args, &arg)?.parse()?),
+			"--sample-offset" => options.sample_offset = Some(value(&mut args, &arg)?),
+			"--filter" => options.filter = Some(value::<String>(&mut args, &arg)?.parse()?),
+			"--dither" => options.dither = Some(value::<String>(&mut args, &arg)?.parse()?),
+			"--lut" => options.lut = Some(value(&mut args, &arg)?),
+			"--time" => options.time = Some(duration(&value::<String>(&mut args, &arg)?)?),
+			"--turntable" => options.turntable = Some(value(&mut args, &arg)?),
+			"--radius" => options.radius = Some(value(&mut args, &arg)?),
+			"--elevation" => options.elevation = Some(value(&mut args, &arg)?),
+			"--video" => options.video = Some(value(&mut args, &arg)?),
+			"--fps" => options.fps = Some(value(&mut args, &arg)?),
+			"--stats" => options.stats = true,
+			"--depth-aov" => options.depth_aov = true,
+			"--id-aov" => options.id_aov = true,
+			"--exposure-check" => options.exposure_check = true,
+			"--tev" => options.tev = Some(value(&mut args, &arg)?),
+			"--watch" => options.watch = true,
+			"--export" => options.export = Some(value(&mut args, &arg)?),
+			flag if flag.starts_with("--") => return Err(format!("unknown option {}", flag)),
+			_ if options.scene_file.is_none() => options.scene_file = Some(arg),
+			_ => return Err(format!("unexpected argument {}", arg)),
+		}
+	}
+
+	if options.scene.is_some() && options.scene_file.is_some() {
+		return Err(String::from("--scene cannot be combined with a scene file"));
+	}
+
+	if options.depth_aov && options.video.is_some() {
+		return Err(String::from("--depth-aov cannot be combined with --video"));
+	}
+
+	if options.id_aov && options.video.is_some() {
+		return Err(String::from("--id-aov cannot be combined with --video"));
+	}
+
+	if options.exposure_check && options.video.is_some() {
+		return Err(String::from(
+			"--exposure-check cannot be combined with --video",
+		));
+	}
+
+	if options
+		.scale
+		.is_some_and(|scale| !(scale.is_finite() && scale > 0.0))
+	{
+		return Err(String::from("--scale expects a positive factor"));
+	}
+
+	if options.threads == Some(0) {
+		return Err(String::from("--threads expects at least one thread"));
+	}
+
+	// Infinity is allowed and clamps nothing
+	if options
+		.clamp
+		.is_some_and(|clamp| clamp.is_nan() || clamp <= 0.0)
+	{
+		return Err(String::from("--clamp expects a positive brightness"));
+	}
+
+	if options.format.is_some() && options.video.is_some() {
+		return Err(String::from("--format cannot be combined with --video"));
+	}
+
+	if options.watch && options.scene_file.is_none() {
+		return Err(String::from("--watch needs a scene file"));
+	}
+
+	if options.watch && (options.turntable.is_some() || options.time.is_some()) {
+		return Err(String::from(
+			"--watch cannot be combined with --turntable or --time",
+		));
+	}
+
+	Ok(Command::Render(Box::new(options)))
+}
@@ -0,0 +1,154 @@
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::geometry::Float;
+
+// Noise added before colors are cut down to 8 bit, so that smooth gradients come out as fine
+// grain instead of bands. The same threshold is used for all three channels, which keeps grays
+// gray.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dither {
+	// 8x8 Bayer matrix, a regular cross-hatch pattern
+	Ordered,
+	// 64x64 void-and-cluster mask, grain without visible structure
+	BlueNoise,
+}
+
+pub const DITHERS: &[&str] = &["ordered", "blue_noise"];
+
+const BAYER_SIZE: usize = 8;
+const MASK_SIZE: usize = 64;
+// Width of the Gaussian that void-and-cluster measures clusters with, in pixels
+const SIGMA: Float = 1.5;
+
+impl FromStr for Dither {
+	type Err = String;
+
+	fn from_str(value: &str) -> Result<Self, Self::Err> {
+		match value {
+			"ordered" => Ok(Dither::Ordered),
+			"blue_noise" => Ok(Dither::BlueNoise),
+			_ => Err(format!(
+				"unknown dither '{}', expected one of {}",
+				value,
+				DITHERS.join(", ")
+			)),
+		}
+	}
+}
+
+impl Dither {
+	// Offset in [0, 1) to add to a value in 8 bit steps before it is truncated, the pattern
+	// repeats across the image
+	pub fn threshold(self, x: u32, y: u32) -> Float {
+		match self {
+			Dither::Ordered => {
+				let (x, y) = (x as usize % BAYER_SIZE, y as usize % BAYER_SIZE);
+				(bayer(x, y) as Float + 0.5) / (BAYER_SIZE * BAYER_SIZE) as Float
+			}
+			Dither::BlueNoise => {
+				let (x, y) = (x as usize % MASK_SIZE, y as usize % MASK_SIZE);
+				(blue_noise()[y * MASK_SIZE + x] as Float + 0.5) / (MASK_SIZE * MASK_SIZE) as Float
+			}
+		}
+	}
+}
+
+// Rank of a cell of the Bayer matrix, from the bits of x and y interleaved in reverse
+fn bayer(x: usize, y: usize) -> usize {
+	let mut rank = 0;
+	for bit in 0..BAYER_SIZE.trailing_zeros() {
+		let (x, y) = ((x >> bit) & 1, (y >> bit) & 1);
+		rank = (rank << 2) | ((x ^ y) << 1) | y;
+	}
+	rank
+}
+
+// Ranks of the cells of the mask, made once with Ulichney's void-and-cluster method from a
+// fixed seed so that every image gets the same one
+fn blue_noise() -> &'static [u16] {
+	static MASK: OnceLock<Vec<u16>> = OnceLock::new();
+	MASK.get_or_init(void_and_cluster)
+}
+
+fn void_and_cluster() -> Vec<u16> {
+	let cells = MASK_SIZE * MASK_SIZE;
+
+	// The Gaussian on the torus, by the offset between two cells
+	let wrap = |d: usize| d.min(MASK_SIZE - d) as Float;
+	let kernel: Vec<Float> = (0..cells)
+		.map(|offset| {
+			let (dx, dy) = (wrap(offset % MASK_SIZE), wrap(offset / MASK_SIZE));
+			(-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp()
+		})
+		.collect();
+
+	// Energy of every cell is the sum of the kernel around every set cell
+	let mut energy = vec![0.0; cells];
+	let mut set = vec![false; cells];
+	let toggle = |energy: &mut [Float], set: &mut [bool], cell: usize| {
+		set[cell] = !set[cell];
+		let sign = if set[cell] { 1.0 } else { -1.0 };
+		let (cx, cy) = (cell % MASK_SIZE, cell / MASK_SIZE);
+		for (other, energy) in energy.iter_mut().enumerate() {
+			let dx = (other % MASK_SIZE + MASK_SIZE - cx) % MASK_SIZE;
+			let dy = (other / MASK_SIZE + MASK_SIZE - cy) % MASK_SIZE;
+			*energy += sign * kernel[dy * MASK_SIZE + dx];
+		}
+	};
+	// The set cell in the tightest cluster, or the free cell in the largest void
+	let extreme = |energy: &[Float], set: &[bool], tightest: bool| {
+		(0..cells)
+			.filter(|&cell| set[cell] == tightest)
+			.max_by(|&a, &b| {
+				let (a, b) = if tightest {
+					(energy[a], energy[b])
+				} else {
+					(energy[b], energy[a])
+				};
+				a.total_cmp(&b)
+			})
+			.unwrap()
+	};
+
+	// Start from a tenth of the cells picked by a hash and spread them out evenly by moving the
+	// tightest cluster into the largest void until that changes nothing
+	let initial = cells / 10;
+	let mut state = 0x2545_f491_4f6c_dd1du64;
+	while set.iter().filter(|&&set| set).count() < initial {
+		state ^= state << 13;
+		state ^= state >> 7;
+		state ^= state << 17;
+		let cell = (state % cells as u64) as usize;
+		if !set[cell] {
+			toggle(&mut energy, &mut set, cell);
+		}
+	}
+	loop {
+		let cluster = extreme(&energy, &set, true);
+		toggle(&mut energy, &mut set, cluster);
+		let void = extreme(&energy, &set, false);
+		if void == cluster {
+			toggle(&mut energy, &mut set, cluster);
+			break;
+		}
+		toggle(&mut energy, &mut set, void);
+	}
+
+	// The initial cells are ranked by taking out the tightest cluster one at a time, the rest by
+	// filling the largest void
+	let mut ranks = vec![0u16; cells];
+	let (mut removed_energy, mut removed_set) = (energy.clone(), set.clone());
+	for rank in (0..initial).rev() {
+		let cluster = extreme(&removed_energy, &removed_set, true);
+		toggle(&mut removed_energy, &mut removed_set, cluster);
+		ranks[cluster] = rank as u16;
+	}
+	for rank in initial..cells {
+		let void = extreme(&energy, &set, false);
+		toggle(&mut energy, &mut set, void);
+		ranks[void] = rank as u16;
+	}
+
+	ranks
+}
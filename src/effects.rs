@@ -0,0 +1,155 @@
+use crate::geometry::{Float, Vec3f};
+
+// Lens effects for a finished look, applied to the exposed colors of the whole image before they
+// are quantized. Sizes are relative to the height of the image so that a draft and the final
+// render look the same.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Effects {
+	pub bloom: Option<Bloom>,
+	// Fraction of the light lost in the corners, falling off with the squared distance from the
+	// center
+	pub vignette: Float,
+	// How much larger the red image is and the blue image is smaller than the green one at the
+	// edges, as a fraction, e.g. 0.003
+	pub chromatic_aberration: Float,
+}
+
+// Glow around everything brighter than `threshold`, whose excess is blurred over `radius` and
+// added back scaled by `intensity`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bloom {
+	pub threshold: Float,
+	pub intensity: Float,
+	pub radius: Float,
+}
+
+// Three box blurs in a row come close to a Gaussian at a cost independent of its size
+const BOX_PASSES: usize = 3;
+
+impl Effects {
+	pub fn is_empty(&self) -> bool {
+		self.bloom.is_none() && self.vignette == 0.0 && self.chromatic_aberration == 0.0
+	}
+
+	// Applies chromatic aberration, bloom and vignette in the order light meets them in a lens
+	pub fn apply(&self, colors: &mut [Vec3f], width: u32, height: u32) {
+		if self.is_empty() || colors.is_empty() {
+			return;
+		}
+		let (width, height) = (width as usize, height as usize);
+
+		if self.chromatic_aberration != 0.0 {
+			aberrate(colors, width, height, self.chromatic_aberration);
+		}
+		if let Some(bloom) = self.bloom {
+			bloom.apply(colors, width, height);
+		}
+		if self.vignette != 0.0 {
+			vignette(colors, width, height, self.vignette.clamp(0.0, 1.0));
+		}
+	}
+}
+
+impl Bloom {
+	fn apply(&self, colors: &mut [Vec3f], width: usize, height: usize) {
+		// Only the part of the luminance above the threshold glows, in the color of the pixel
+		let mut glow: Vec<Vec3f> = colors
+			.iter()
+			.map(|&color| {
+				let luminance = 0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z;
+				if luminance > self.threshold {
+					color * ((luminance - self.threshold) / luminance)
+				} else {
+					Vec3f::new(0.0, 0.0, 0.0)
+				}
+			})
+			.collect();
+
+		// Boxes of 2r + 1 pixels have a variance of r(r + 1) / 3 each
+		let sigma = self.radius * height as Float;
+		let variance = sigma * sigma / BOX_PASSES as Float;
+		let radius = ((0.25 + 3.0 * variance).sqrt() - 0.5).round() as usize;
+		if radius == 0 {
+			return;
+		}
+
+		let mut line = Vec::with_capacity(width.max(height));
+		for _ in 0..BOX_PASSES {
+			for row in glow.chunks_mut(width) {
+				line.clear();
+				line.extend_from_slice(row);
+				box_blur(&line, row.iter_mut(), radius);
+			}
+			for x in 0..width {
+				line.clear();
+				line.extend(glow.iter().skip(x).step_by(width));
+				box_blur(&line, glow.iter_mut().skip(x).step_by(width), radius);
+			}
+		}
+
+		for (color, glow) in colors.iter_mut().zip(glow) {
+			*color += glow * self.intensity;
+		}
+	}
+}
+
+// Average over 2r + 1 neighbors with the edge pixels repeated, kept up as a running sum
+fn box_blur<'a>(line: &[Vec3f], output: impl Iterator<Item = &'a mut Vec3f>, radius: usize) {
+	let last = line.len() as isize - 1;
+	let at = |index: isize| line[index.clamp(0, last) as usize];
+	let radius = radius as isize;
+	let scale = 1.0 / (2 * radius + 1) as Float;
+
+	let mut sum = Vec3f::new(0.0, 0.0, 0.0);
+	for index in -radius..=radius {
+		sum += at(index);
+	}
+	for (index, output) in (0..).zip(output) {
+		*output = sum * scale;
+		sum += at(index + radius + 1) - at(index - radius);
+	}
+}
+
+// Scales the red channel up and the blue one down around the center of the image, read back
+// with bilinear filtering
+fn aberrate(colors: &mut [Vec3f], width: usize, height: usize, amount: Float) {
+	let source = colors.to_vec();
+	let (cx, cy) = (width as Float / 2.0, height as Float / 2.0);
+
+	let sample = |x: Float, y: Float, channel: usize| {
+		let x = (x - 0.5).clamp(0.0, (width - 1) as Float);
+		let y = (y - 0.5).clamp(0.0, (height - 1) as Float);
+		let (x0, y0) = (x as usize, y as usize);
+		let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+		let (fx, fy) = (x - x0 as Float, y - y0 as Float);
+		let at = |x: usize, y: usize| source[y * width + x][channel];
+
+		let top = at(x0, y0) * (1.0 - fx) + at(x1, y0) * fx;
+		let bottom = at(x0, y1) * (1.0 - fx) + at(x1, y1) * fx;
+		top * (1.0 - fy) + bottom * fy
+	};
+
+	for (pixel, color) in colors.iter_mut().enumerate() {
+		let (dx, dy) = (
+			(pixel % width) as Float + 0.5 - cx,
+			(pixel / width) as Float + 0.5 - cy,
+		);
+		let red = 1.0 / (1.0 + amount);
+		let blue = 1.0 / (1.0 - amount);
+		color.x = sample(cx + dx * red, cy + dy * red, 0);
+		color.z = sample(cx + dx * blue, cy + dy * blue, 2);
+	}
+}
+
+fn vignette(colors: &mut [Vec3f], width: usize, height: usize, strength: Float) {
+	let (cx, cy) = (width as Float / 2.0, height as Float / 2.0);
+	let corner = cx * cx + cy * cy;
+
+	for (pixel, color) in colors.iter_mut().enumerate() {
+		let (dx, dy) = (
+			(pixel % width) as Float + 0.5 - cx,
+			(pixel / width) as Float + 0.5 - cy,
+		);
+		*color *= 1.0 - strength * (dx * dx + dy * dy) / corner;
+	}
+}
@@ -0,0 +1,76 @@
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum RendererError {
+	// Reading or writing a file failed
+	Io {
+		path: String,
+		source: io::Error,
+	},
+	// An OBJ/MTL file could not be loaded
+	Mesh {
+		path: String,
+		source: tobj::LoadError,
+	},
+	// A texture could not be decoded or a render could not be encoded
+	Image {
+		path: String,
+		source: image::ImageError,
+	},
+	// A color lookup table is not a valid .cube file
+	Lut {
+		path: String,
+		message: String,
+	},
+	// A scene file is not valid TOML or does not describe a scene
+	Scene {
+		path: Option<String>,
+		message: String,
+	},
+	// A render worker thread panicked
+	WorkerPanicked,
+	// The render was stopped through its CancelToken
+	Cancelled,
+}
+
+pub type Result<T> = std::result::Result<T, RendererError>;
+
+impl fmt::Display for RendererError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			RendererError::Io { path, source } => write!(f, "{}: {}", path, source),
+			RendererError::Mesh { path, source } => {
+				write!(f, "failed to load mesh {}: {}", path, source)
+			}
+			RendererError::Image { path, source } => write!(f, "{}: {}", path, source),
+			RendererError::Lut { path, message } => {
+				write!(f, "failed to load LUT {}: {}", path, message)
+			}
+			RendererError::Scene {
+				path: Some(path),
+				message,
+			} => write!(f, "invalid scene file {}: {}", path, message),
+			RendererError::Scene {
+				path: None,
+				message,
+			} => write!(f, "invalid scene file: {}", message),
+			RendererError::WorkerPanicked => write!(f, "a render worker thread panicked"),
+			RendererError::Cancelled => write!(f, "the render was cancelled"),
+		}
+	}
+}
+
+impl std::error::Error for RendererError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			RendererError::Io { source, .. } => Some(source),
+			RendererError::Mesh { source, .. } => Some(source),
+			RendererError::Image { source, .. } => Some(source),
+			RendererError::Lut { .. }
+			| RendererError::Scene { .. }
+			| RendererError::WorkerPanicked
+			| RendererError::Cancelled => None,
+		}
+	}
+}
@@ -0,0 +1,135 @@
+use image::{ImageBuffer, Rgb, RgbImage};
+
+use crate::geometry::{Float, Vec3f};
+use crate::render::RenderOutput;
+
+// The histogram spans the stops below white that 8 bit can still tell apart, plus some headroom
+// above it for what was clipped
+const HISTOGRAM_MIN_STOPS: Float = -8.0;
+const HISTOGRAM_MAX_STOPS: Float = 2.0;
+const HISTOGRAM_BINS: usize = 160;
+const HISTOGRAM_BAR_WIDTH: u32 = 3;
+const HISTOGRAM_HEIGHT: u32 = 200;
+
+// 18% gray, where a well exposed subject sits, and the band around it shown green
+const MIDDLE_GRAY: Float = 0.18;
+const MIDDLE_GRAY_BAND: Float = 1.0 / 3.0;
+// Within a stop below white highlights are about to clip
+const NEAR_WHITE: Float = 0.5;
+// Darker than half an 8 bit step rounds to black, below 2% 8 bit only has a few steps left
+const BLACK: Float = 0.5 / 255.0;
+const DEEP_SHADOW: Float = 0.02;
+// Width of the zebra stripes over clipped pixels
+const ZEBRA_WIDTH: u32 = 4;
+
+fn luminance(color: Vec3f) -> Float {
+	0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+fn is_clipped(color: Vec3f) -> bool {
+	color.x >= 1.0 || color.y >= 1.0 || color.z >= 1.0
+}
+
+impl RenderOutput {
+	// The image in gray by luminance, with the exposure zones a lighter looks for in color:
+	// red and white zebra stripes where a channel clips, yellow within a stop of white, green
+	// around middle gray, blue in the deepest shadows and purple where they are crushed to black
+	pub fn false_color_image(&self) -> RgbImage {
+		let (width, height) = (self.image.width(), self.image.height());
+
+		ImageBuffer::from_fn(width, height, |x, y| {
+			let color = self.colors[(y * width + x) as usize];
+			let luminance = luminance(color);
+			let stops_from_gray = (luminance / MIDDLE_GRAY).log2();
+
+			let false_color = if is_clipped(color) {
+				if ((x + y) / ZEBRA_WIDTH).is_multiple_of(2) {
+					Vec3f::new(1.0, 0.0, 0.0)
+				} else {
+					Vec3f::new(1.0, 1.0, 1.0)
+				}
+			} else if luminance >= NEAR_WHITE {
+				Vec3f::new(1.0, 0.9, 0.0)
+			} else if stops_from_gray.abs() <= MIDDLE_GRAY_BAND {
+				Vec3f::new(0.1, 0.8, 0.1)
+			} else if luminance < BLACK {
+				Vec3f::new(0.5, 0.0, 0.6)
+			} else if luminance < DEEP_SHADOW {
+				Vec3f::new(0.0, 0.3, 1.0)
+			} else {
+				Vec3f::new(luminance, luminance, luminance)
+			};
+
+			Rgb([
+				(false_color.x * 255.0) as u8,
+				(false_color.y * 255.0) as u8,
+				(false_color.z * 255.0) as u8,
+			])
+		})
+	}
+
+	// Pixels per bin of luminance, in even steps of stops from HISTOGRAM_MIN_STOPS below white,
+	// which also holds black, to HISTOGRAM_MAX_STOPS above it
+	pub fn luminance_histogram(&self) -> Vec<u32> {
+		let mut bins = vec![0; HISTOGRAM_BINS];
+		let range = HISTOGRAM_MAX_STOPS - HISTOGRAM_MIN_STOPS;
+
+		for &color in &self.colors {
+			let stops = luminance(color).max(Float::MIN_POSITIVE).log2();
+			let bin = ((stops - HISTOGRAM_MIN_STOPS) / range * HISTOGRAM_BINS as Float)
+				.clamp(0.0, (HISTOGRAM_BINS - 1) as Float);
+			bins[bin as usize] += 1;
+		}
+
+		bins
+	}
+
+	// The luminance histogram as bars scaled to the fullest bin, with a white line at white
+	// (where 8 bit clips, the bins to its right are red) and a green one at middle gray. The
+	// first bin, which also counts the background, is left out of the scale and may overflow.
+	pub fn histogram_image(&self) -> RgbImage {
+		let bins = self.luminance_histogram();
+		let fullest = bins[1..].iter().copied().max().unwrap_or(0).max(1);
+		let range = HISTOGRAM_MAX_STOPS - HISTOGRAM_MIN_STOPS;
+		let column = |stops: Float| {
+			((stops - HISTOGRAM_MIN_STOPS) / range * HISTOGRAM_BINS as Float) as u32
+				* HISTOGRAM_BAR_WIDTH
+		};
+		let (white, gray) = (column(0.0), column(MIDDLE_GRAY.log2()));
+
+		let width = HISTOGRAM_BINS as u32 * HISTOGRAM_BAR_WIDTH;
+		ImageBuffer::from_fn(width, HISTOGRAM_HEIGHT, |x, y| {
+			if x == white {
+				return Rgb([255, 255, 255]);
+			}
+			if x == gray {
+				return Rgb([25, 200, 25]);
+			}
+
+			let count = bins[(x / HISTOGRAM_BAR_WIDTH) as usize];
+			let bar = (count as u64 * HISTOGRAM_HEIGHT as u64).div_ceil(fullest as u64);
+			match ((HISTOGRAM_HEIGHT - y) as u64 <= bar, x > white) {
+				(true, true) => Rgb([230, 40, 40]),
+				(true, false) => Rgb([200, 200, 200]),
+				(false, _) => Rgb([30, 30, 30]),
+			}
+		})
+	}
+
+	// Fractions of the pixels with a clipped channel and of those that come out black
+	pub fn clipping(&self) -> (Float, Float) {
+		let count = self.colors.len().max(1) as Float;
+		let clipped = self
+			.colors
+			.iter()
+			.filter(|&&color| is_clipped(color))
+			.count();
+		let crushed = self
+			.colors
+			.iter()
+			.filter(|&&color| luminance(color) < BLACK)
+			.count();
+
+		(clipped as Float / count, crushed as Float / count)
+	}
+}
@@ -0,0 +1,391 @@
+use image::{ImageBuffer, Rgb, RgbImage};
+
+use crate::filter::Filter;
+use crate::geometry::{Float, Vec3f};
+use crate::integrator::Sample;
+use crate::render::{LightGroup, PostProcess, Region, RenderOutput, ID_RANKS};
+
+// Object IDs seen in a pixel with their sample counts, empty slots have a count of 0
+type IdCounts = [(u32, u32); ID_RANKS];
+
+// Adds `count` samples of object `id`. Once all slots are taken the object with the fewest
+// samples is replaced, if that has fewer than `count`.
+fn count_id(ids: &mut IdCounts, id: u32, count: u32) {
+	if let Some(slot) = ids.iter_mut().find(|(other, _)| *other == id) {
+		slot.1 += count;
+		return;
+	}
+
+	if let Some(slot) = ids.iter_mut().min_by_key(|(_, other)| *other) {
+		if slot.1 < count {
+			*slot = (id, count);
+		}
+	}
+}
+
+// Colors are summed in double precision whatever Float is, so that the samples of a pixel that
+// already has thousands still count in full
+#[derive(Debug, Clone, Copy, Default)]
+struct ColorSum {
+	r: f64,
+	g: f64,
+	b: f64,
+}
+
+impl ColorSum {
+	#[allow(clippy::unnecessary_cast)]
+	fn add(&mut self, color: Vec3f, weight: f64) {
+		self.r += color.x as f64 * weight;
+		self.g += color.y as f64 * weight;
+		self.b += color.z as f64 * weight;
+	}
+
+	fn merge(&mut self, other: &ColorSum) {
+		self.r += other.r;
+		self.g += other.g;
+		self.b += other.b;
+	}
+
+	// Black without any weight
+	#[allow(clippy::unnecessary_cast)]
+	fn average(&self, weight: f64) -> Vec3f {
+		if weight <= 0.0 {
+			return Vec3f::new(0.0, 0.0, 0.0);
+		}
+
+		Vec3f::new(
+			(self.r / weight) as Float,
+			(self.g / weight) as Float,
+			(self.b / weight) as Float,
+		)
+	}
+}
+
+// The accumulation buffer: per pixel sums over all samples of the color, the coverage (one for
+// every sample that did not hit a holdout) and the light groups, weighted by the filter and
+// along with the sum of the weights, as well as the bounce count and the object IDs of the
+// samples taken in the pixel itself, along with their number. Samples with a NaN or an infinity
+// anywhere are left out of every sum and only counted as rejected, so a single one does not ruin
+// its pixel. The light groups of a pixel are stored next to each other.
+struct Sums {
+	color: Vec<ColorSum>,
+	weights: Vec<f64>,
+	alpha: Vec<f64>,
+	groups: Vec<ColorSum>,
+	bounces: Vec<u64>,
+	ids: Vec<IdCounts>,
+	counts: Vec<u32>,
+	rejected: u64,
+	group_count: usize,
+}
+
+impl Sums {
+	fn new(pixel_count: u32, group_count: usize) -> Self {
+		let pixel_count = pixel_count as usize;
+
+		Sums {
+			color: vec![ColorSum::default(); pixel_count],
+			weights: vec![0.0; pixel_count],
+			alpha: vec![0.0; pixel_count],
+			groups: vec![ColorSum::default(); pixel_count * group_count],
+			bounces: vec![0; pixel_count],
+			ids: vec![[(0, 0); ID_RANKS]; pixel_count],
+			counts: vec![0; pixel_count],
+			rejected: 0,
+			group_count,
+		}
+	}
+
+	// Adds a sample taken in `pixel` to each of the pixels of `splats` with their weights
+	#[allow(clippy::unnecessary_cast)]
+	fn add_sample(&mut self, pixel: usize, sample: &Sample, splats: &[(usize, Float)]) {
+		let groups = &sample.groups[..self.group_count];
+		if !sample.color.is_finite()
+			|| !sample.alpha.is_finite()
+			|| !groups.iter().all(Vec3f::is_finite)
+		{
+			self.rejected += 1;
+			return;
+		}
+
+		for &(to, weight) in splats {
+			let weight = weight as f64;
+			self.color[to].add(sample.color, weight);
+			self.weights[to] += weight;
+			self.alpha[to] += sample.alpha as f64 * weight;
+			for (group, &color) in groups.iter().enumerate() {
+				self.groups[to * self.group_count + group].add(color, weight);
+			}
+		}
+		if sample.id != 0 {
+			count_id(&mut self.ids[pixel], sample.id, 1);
+		}
+		self.counts[pixel] += 1;
+	}
+
+	// A sum of the pixel over its own samples, 0 without any
+	#[allow(clippy::unnecessary_cast)]
+	fn average(&self, pixel: usize, sum: f64) -> Float {
+		match self.counts[pixel] {
+			0 => 0.0,
+			count => (sum / count as f64) as Float,
+		}
+	}
+
+	// A weighted sum of the pixel over the weights, 0 without any
+	#[allow(clippy::unnecessary_cast)]
+	fn filtered(&self, pixel: usize, sum: f64) -> Float {
+		match self.weights[pixel] {
+			weight if weight <= 0.0 => 0.0,
+			weight => (sum / weight) as Float,
+		}
+	}
+
+	fn average_color(&self, pixel: usize) -> Vec3f {
+		self.color[pixel].average(self.weights[pixel])
+	}
+
+	// Adds pixel `from` of `other` to pixel `to`
+	fn add(&mut self, to: usize, other: &Sums, from: usize) {
+		self.color[to].merge(&other.color[from]);
+		self.weights[to] += other.weights[from];
+		self.alpha[to] += other.alpha[from];
+		self.bounces[to] += other.bounces[from];
+		self.counts[to] += other.counts[from];
+
+		for &(id, count) in &other.ids[from] {
+			if count > 0 {
+				count_id(&mut self.ids[to], id, count);
+			}
+		}
+
+		for group in 0..self.group_count {
+			self.groups[to * self.group_count + group]
+				.merge(&other.groups[from * self.group_count + group]);
+		}
+	}
+}
+
+// The sums of the samples of a whole image and the way they are developed into a RenderOutput.
+// Camera samples are weighted into the pixels around them by the filter, in FilmTiles that
+// workers fill on their own and merge when they are done.
+pub struct Film {
+	width: u32,
+	height: u32,
+	filter: Filter,
+	sums: Sums,
+}
+
+// The part of a film that one worker renders: the rows of its tile and those that the filter
+// reaches into around them, across the columns of the region
+pub struct FilmTile {
+	region: Region,
+	first_row: u32,
+	rows: u32,
+	filter: Filter,
+	sums: Sums,
+	// Reused for every sample, the pixels it counts for with their weights
+	splats: Vec<(usize, Float)>,
+	columns: Vec<(u32, Float)>,
+}
+
+impl Film {
+	pub fn new(width: u32, height: u32, filter: Filter, group_count: usize) -> Film {
+		Film {
+			width,
+			height,
+			filter,
+			sums: Sums::new(width * height, group_count),
+		}
+	}
+
+	pub fn width(&self) -> u32 {
+		self.width
+	}
+
+	pub fn height(&self) -> u32 {
+		self.height
+	}
+
+	pub fn filter(&self) -> Filter {
+		self.filter
+	}
+
+	// Adds the samples of a finished tile
+	pub fn merge(&mut self, tile: &FilmTile) {
+		self.sums.rejected += tile.sums.rejected;
+		for from in 0..tile.sums.counts.len() {
+			let x = tile.region.x + from as u32 % tile.region.width;
+			let y = tile.first_row + from as u32 / tile.region.width;
+			self.sums
+				.add((y * self.width + x) as usize, &tile.sums, from);
+		}
+	}
+
+	// The average color of a pixel so far
+	pub fn color(&self, x: u32, y: u32) -> Vec3f {
+		self.sums.average_color((y * self.width + x) as usize)
+	}
+
+	// Normalizes the sums, exposes them and applies the effects of `post`, and quantizes the
+	// result to 8 bit. `samples` is the number of samples per pixel that the output reports.
+	pub fn develop(&self, groups: &[String], samples: usize, post: &PostProcess) -> RenderOutput {
+		let (width, height) = (self.width, self.height);
+		let sums = &self.sums;
+		let pixels = 0..sums.counts.len();
+
+		let light_groups = groups
+			.iter()
+			.take(sums.group_count)
+			.enumerate()
+			.map(|(group, name)| {
+				let colors = expose(
+					pixels.clone().map(|pixel| {
+						sums.groups[pixel * sums.group_count + group].average(sums.weights[pixel])
+					}),
+					post,
+				);
+
+				LightGroup {
+					name: name.clone(),
+					image: quantize(&colors, width, height, post),
+					colors,
+				}
+			})
+			.collect();
+		let mut colors = expose(
+			(0..height).flat_map(|y| (0..width).map(move |x| self.color(x, y))),
+			post,
+		);
+		post.effects.apply(&mut colors, width, height);
+
+		RenderOutput {
+			image: quantize(&colors, width, height, post),
+			colors,
+			light_groups,
+			samples,
+			rejected: sums.rejected,
+			alpha: pixels
+				.clone()
+				.map(|pixel| sums.filtered(pixel, sums.alpha[pixel]))
+				.collect(),
+			depth: pixels
+				.clone()
+				.map(|pixel| sums.average(pixel, sums.bounces[pixel] as f64))
+				.collect(),
+			ids: pixels
+				.map(|pixel| {
+					let mut ids = sums.ids[pixel];
+					ids.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+					ids.map(|(id, count)| (id, sums.average(pixel, count as f64)))
+				})
+				.collect(),
+		}
+	}
+}
+
+impl FilmTile {
+	// A tile for the rows from `row_start` to `row_end` of `region`, with the filter and the
+	// light group count of its film
+	pub fn new(
+		region: Region,
+		row_start: u32,
+		row_end: u32,
+		filter: Filter,
+		group_count: usize,
+	) -> FilmTile {
+		let margin = filter.margin();
+		let first_row = row_start.saturating_sub(margin).max(region.y);
+		let last_row = (row_end + margin).min(region.y + region.height);
+		let rows = last_row - first_row;
+
+		FilmTile {
+			region,
+			first_row,
+			rows,
+			filter,
+			sums: Sums::new(rows * region.width, group_count),
+			splats: Vec::new(),
+			columns: Vec::new(),
+		}
+	}
+
+	// Adds a sample taken at `x`, `y` in the image to the pixels around it. Its bounces and
+	// object ID count for the pixel it is in.
+	pub fn add_sample(&mut self, x: Float, y: Float, sample: &Sample) {
+		self.splat(x, y);
+		let pixel = self.index(x as u32, y as u32);
+		self.sums.add_sample(pixel, sample, &self.splats);
+	}
+
+	// Sets the number of bounces that the samples of a pixel took in all
+	pub fn set_bounces(&mut self, x: u32, y: u32, bounces: u64) {
+		let pixel = self.index(x, y);
+		self.sums.bounces[pixel] = bounces;
+	}
+
+	// The average color of a pixel so far. Rows further down the image may still add to it
+	// through the filter.
+	pub fn color(&self, x: u32, y: u32) -> Vec3f {
+		self.sums.average_color(self.index(x, y))
+	}
+
+	fn index(&self, x: u32, y: u32) -> usize {
+		((y - self.first_row) * self.region.width + (x - self.region.x)) as usize
+	}
+
+	// Finds the pixels of the tile that a sample at `x`, `y` counts for, with their weights.
+	// These are the pixels whose center is less than the radius to the left or up to it to the
+	// right, which for the box filter is only the one the sample is in. The filters are
+	// separable, so the weights of the columns and the rows are found once.
+	fn splat(&mut self, x: Float, y: Float) {
+		let filter = self.filter;
+		let radius = filter.radius();
+		let weights = |position: Float, start: u32, count: u32| {
+			let first = ((position - 0.5 - radius).floor() + 1.0).max(start as Float) as u32;
+			let last = ((position - 0.5 + radius).floor() as i64).min((start + count) as i64 - 1);
+			(first as i64..=last).map(move |pixel| {
+				let weight = filter.weight_1d(pixel as Float + 0.5 - position);
+				(pixel as u32 - start, weight)
+			})
+		};
+
+		self.columns.clear();
+		self.columns
+			.extend(weights(x, self.region.x, self.region.width));
+		self.splats.clear();
+		for (row, row_weight) in weights(y, self.first_row, self.rows) {
+			for &(column, column_weight) in &self.columns {
+				let weight = row_weight * column_weight;
+				if weight != 0.0 {
+					let pixel = row * self.region.width + column;
+					self.splats.push((pixel as usize, weight));
+				}
+			}
+		}
+	}
+}
+
+fn expose(averages: impl Iterator<Item = Vec3f>, post: &PostProcess) -> Vec<Vec3f> {
+	let gain = post.gain();
+	averages.map(|average| average * gain).collect()
+}
+
+fn quantize(colors: &[Vec3f], width: u32, height: u32, post: &PostProcess) -> RgbImage {
+	let mut buffer: RgbImage = ImageBuffer::new(width, height);
+
+	for ((x, y, pixel), &color) in buffer.enumerate_pixels_mut().zip(colors) {
+		let color = match &post.lut {
+			Some(lut) => lut.apply(color.clamp(0.0, 1.0)),
+			None => color,
+		};
+		let threshold = post.dither.map_or(0.0, |dither| dither.threshold(x, y));
+		*pixel = Rgb([
+			(color.x * 255.0 + threshold) as u8,
+			(color.y * 255.0 + threshold) as u8,
+			(color.z * 255.0 + threshold) as u8,
+		]);
+	}
+
+	buffer
+}
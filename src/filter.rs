@@ -0,0 +1,97 @@
+use std::str::FromStr;
+
+use crate::geometry::Float;
+
+// How the samples of the image are weighted into the pixels around them. Every filter is
+// separable, the weight of a sample is that of its horizontal times its vertical offset from
+// the center of the pixel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+	// Each sample counts fully for its own pixel only, sharpest but with the most aliasing
+	Box,
+	// Falls off linearly to the centers of the neighbors
+	Tent,
+	// A Gaussian with a standard deviation of half a pixel, soft but free of ringing
+	Gaussian,
+	// Mitchell-Netravali with B = C = 1/3, keeps edges crisp with slight ringing
+	Mitchell,
+}
+
+pub const FILTERS: &[&str] = &["box", "tent", "gaussian", "mitchell"];
+
+const GAUSSIAN_SIGMA: Float = 0.5;
+const MITCHELL_B: Float = 1.0 / 3.0;
+const MITCHELL_C: Float = 1.0 / 3.0;
+
+impl FromStr for Filter {
+	type Err = String;
+
+	fn from_str(value: &str) -> Result<Self, Self::Err> {
+		match value {
+			"box" => Ok(Filter::Box),
+			"tent" => Ok(Filter::Tent),
+			"gaussian" => Ok(Filter::Gaussian),
+			"mitchell" => Ok(Filter::Mitchell),
+			_ => Err(format!(
+				"unknown filter '{}', expected one of {}",
+				value,
+				FILTERS.join(", ")
+			)),
+		}
+	}
+}
+
+impl Filter {
+	// Offset in pixels beyond which samples get no weight
+	pub fn radius(self) -> Float {
+		match self {
+			Filter::Box => 0.5,
+			Filter::Tent => 1.0,
+			Filter::Gaussian => 3.0 * GAUSSIAN_SIGMA,
+			Filter::Mitchell => 2.0,
+		}
+	}
+
+	// Rows and columns of neighbors on each side that a sample in a pixel can reach
+	pub fn margin(self) -> u32 {
+		(self.radius() - 0.5).ceil() as u32
+	}
+
+	// Weight of a sample `dx` and `dy` pixels away from the center of a pixel, Mitchell's is
+	// negative in its outer lobe
+	pub fn weight(self, dx: Float, dy: Float) -> Float {
+		self.weight_1d(dx) * self.weight_1d(dy)
+	}
+
+	// Weight along one axis, the product of both is that of the sample
+	pub fn weight_1d(self, offset: Float) -> Float {
+		let x = offset.abs();
+		if x > self.radius() {
+			return 0.0;
+		}
+
+		match self {
+			Filter::Box => 1.0,
+			Filter::Tent => 1.0 - x,
+			Filter::Gaussian => {
+				// Shifted down to reach 0 at the radius instead of stopping abruptly
+				let gaussian = |x: Float| (-x * x / (2.0 * GAUSSIAN_SIGMA * GAUSSIAN_SIGMA)).exp();
+				gaussian(x) - gaussian(self.radius())
+			}
+			Filter::Mitchell => {
+				let (b, c) = (MITCHELL_B, MITCHELL_C);
+				let weight = if x < 1.0 {
+					(12.0 - 9.0 * b - 6.0 * c) * x * x * x
+						+ (-18.0 + 12.0 * b + 6.0 * c) * x * x
+						+ (6.0 - 2.0 * b)
+				} else {
+					(-b - 6.0 * c) * x * x * x
+						+ (6.0 * b + 30.0 * c) * x * x
+						+ (-12.0 * b - 48.0 * c) * x
+						+ (8.0 * b + 24.0 * c)
+				};
+				weight / 6.0
+			}
+		}
+	}
+}
@@ -1,48 +1,76 @@
-use crate::geometry::{Interval, Ray, Vec3f};
+#[cfg(feature = "simd")]
+use crate::geometry::FloatX4;
+use crate::geometry::{Float, Interval, Ray, Vec3f};
 
+#[cfg(not(feature = "simd"))]
 use std::mem::swap;
 
+#[derive(Clone, Copy)]
 pub struct Aabb3d {
 	bounds: [Interval; 3],
+
+	// Packed copies of the bounds for the SIMD slab test
+	#[cfg(feature = "simd")]
+	min_lanes: FloatX4,
+	#[cfg(feature = "simd")]
+	max_lanes: FloatX4,
 }
 
+// Far slab distances are pushed out by this factor, a few times the rounding error of computing
+// them. Otherwise a flat box, such as the padded bounds of a wall, can vanish between two
+// neighboring floats when it is far from the ray origin, which happens easily in single
+// precision.
+pub const SLAB_ROUNDING: Float = 1.0 + 8.0 * Float::EPSILON;
+
 impl Aabb3d {
-	pub fn from_intervals(x_bound: Interval, y_bound: Interval, z_bound: Interval) -> Aabb3d {
+	fn new(bounds: [Interval; 3]) -> Aabb3d {
 		Aabb3d {
-			bounds: [x_bound, y_bound, z_bound],
+			bounds,
+			#[cfg(feature = "simd")]
+			min_lanes: FloatX4::new([bounds[0].min, bounds[1].min, bounds[2].min, 0.0]),
+			#[cfg(feature = "simd")]
+			max_lanes: FloatX4::new([bounds[0].max, bounds[1].max, bounds[2].max, 0.0]),
 		}
 	}
 
+	pub fn from_intervals(x_bound: Interval, y_bound: Interval, z_bound: Interval) -> Aabb3d {
+		Aabb3d::new([x_bound, y_bound, z_bound])
+	}
+
 	pub fn from_corners(corner0: Vec3f, corner1: Vec3f) -> Aabb3d {
 		let ix = Interval::new(
-			f64::min(corner0.x, corner1.x),
-			f64::max(corner0.x, corner1.x),
+			Float::min(corner0.x, corner1.x),
+			Float::max(corner0.x, corner1.x),
 		);
 		let iy = Interval::new(
-			f64::min(corner0.y, corner1.y),
-			f64::max(corner0.y, corner1.y),
+			Float::min(corner0.y, corner1.y),
+			Float::max(corner0.y, corner1.y),
 		);
 		let iz = Interval::new(
-			f64::min(corner0.z, corner1.z),
-			f64::max(corner0.z, corner1.z),
+			Float::min(corner0.z, corner1.z),
+			Float::max(corner0.z, corner1.z),
 		);
 
 		Aabb3d::from_intervals(ix, iy, iz)
 	}
 
+	// Contains nothing, so it can be the starting point for growing bounds
+	pub fn empty() -> Aabb3d {
+		Aabb3d::new([Interval::EMPTY; 3])
+	}
+
 	pub fn from_bounds(bounds0: &Aabb3d, bounds1: &Aabb3d) -> Aabb3d {
-		Aabb3d {
-			bounds: [
-				Interval::from_intervals(&bounds0.bounds[0], &bounds1.bounds[0]),
-				Interval::from_intervals(&bounds0.bounds[1], &bounds1.bounds[1]),
-				Interval::from_intervals(&bounds0.bounds[2], &bounds1.bounds[2]),
-			],
-		}
+		Aabb3d::new([
+			bounds0.bounds[0].union(&bounds1.bounds[0]),
+			bounds0.bounds[1].union(&bounds1.bounds[1]),
+			bounds0.bounds[2].union(&bounds1.bounds[2]),
+		])
 	}
 
+	#[cfg(not(feature = "simd"))]
 	pub fn hit(&self, ray: &Ray, interval: &Interval) -> bool {
-		let mut imin: f64 = interval.min;
-		let mut imax: f64 = interval.max;
+		let mut imin: Float = interval.min;
+		let mut imax: Float = interval.max;
 
 		for a in 0..3 {
 			let inv_d = 1.0 / ray.direction[a];
@@ -54,6 +82,7 @@ impl Aabb3d {
 			if inv_d < 0.0 {
 				swap(&mut t0, &mut t1);
 			}
+			t1 *= SLAB_ROUNDING;
 
 			if t0 > imin {
 				imin = t0;
@@ -68,11 +97,30 @@ impl Aabb3d {
 			}
 		}
 
-		return true;
+		true
+	}
+
+	#[cfg(feature = "simd")]
+	pub fn hit(&self, ray: &Ray, interval: &Interval) -> bool {
+		// The padding lane of the direction is 1 so the unused lane never divides by zero
+		let direction = FloatX4::new([ray.direction.x, ray.direction.y, ray.direction.z, 1.0]);
+		let inv_d = FloatX4::ONE / direction;
+		let orig = ray.origin.lanes();
+
+		let t0 = (self.min_lanes - orig) * inv_d;
+		let t1 = (self.max_lanes - orig) * inv_d;
+
+		let [nx, ny, nz, _] = t0.min(t1).to_array();
+		let [fx, fy, fz, _] = t0.max(t1).to_array();
+
+		let imin = interval.min.max(nx).max(ny).max(nz);
+		let imax = interval.max.min(fx).min(fy).min(fz);
+
+		imax * SLAB_ROUNDING > imin
 	}
 
 	pub fn pad(bounds: &Aabb3d) -> Aabb3d {
-		const DELTA: f64 = 0.00001;
+		const DELTA: Float = 0.00001;
 		let ix = if bounds.bounds[0].size() >= DELTA {
 			bounds.bounds[0]
 		} else {
@@ -89,24 +137,20 @@ impl Aabb3d {
 			Interval::expand(&bounds.bounds[2], DELTA)
 		};
 
-		Aabb3d {
-			bounds: [ix, iy, iz],
-		}
+		Aabb3d::new([ix, iy, iz])
 	}
 
-	pub fn lt(lhs: &Aabb3d, rhs: &Aabb3d, axis: usize) -> bool {
-		lhs.bounds[axis].min < rhs.bounds[axis].min
+	pub fn axis(&self, axis: usize) -> &Interval {
+		&self.bounds[axis]
 	}
 }
 
 impl Default for Aabb3d {
 	fn default() -> Self {
-		Aabb3d {
-			bounds: [
-				Interval::new(0.0, 0.0),
-				Interval::new(0.0, 0.0),
-				Interval::new(0.0, 0.0),
-			],
-		}
+		Aabb3d::new([
+			Interval::new(0.0, 0.0),
+			Interval::new(0.0, 0.0),
+			Interval::new(0.0, 0.0),
+		])
 	}
 }
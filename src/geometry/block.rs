@@ -0,0 +1,105 @@
+use std::sync::Arc;
+
+use crate::geometry::{Aabb3d, Float, HitResult, Hittable, Interval, Ray, Solid, Span, Vec3f};
+use crate::material::Material;
+
+// Axis aligned box between two opposite corners as a single solid, unlike `cuboid` which is just
+// its six faces. Each face is UV mapped over its full extent.
+pub struct Block {
+	min: Vec3f,
+	max: Vec3f,
+	material: Arc<dyn Material>,
+	bounds: Aabb3d,
+}
+
+fn unit(axis: usize) -> Vec3f {
+	let mut v = [0.0; 3];
+	v[axis] = 1.0;
+	Vec3f::new(v[0], v[1], v[2])
+}
+
+impl Block {
+	pub fn new(a: Vec3f, b: Vec3f, material: Arc<dyn Material>) -> Block {
+		let (min, max) = (a.min(b), a.max(b));
+
+		Block {
+			min,
+			max,
+			material,
+			bounds: Aabb3d::pad(&Aabb3d::from_corners(min, max)),
+		}
+	}
+
+	// The face perpendicular to `axis` that faces along `sign`
+	fn hit_at(&self, ray: &Ray, t: Float, axis: usize, sign: Float) -> HitResult<'_> {
+		let point = ray.at(t);
+		let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+		let along =
+			|axis: usize| (point[axis] - self.min[axis]) / (self.max[axis] - self.min[axis]);
+
+		HitResult {
+			point,
+			normal: unit(axis) * sign,
+			t,
+			material: self.material.as_ref(),
+			u: along(u_axis),
+			v: along(v_axis),
+			color: None,
+			edge: None,
+			id: 0,
+			tangent: Some(unit(u_axis)),
+			derivatives: None,
+			normal_derivatives: None,
+			footprint: None,
+		}
+	}
+}
+
+impl Solid for Block {
+	fn spans(&self, ray: &Ray) -> Vec<Span<'_>> {
+		// The latest slab the ray enters and the earliest it leaves, with the axis of each
+		let mut enter = (Float::NEG_INFINITY, 0, 0.0);
+		let mut exit = (Float::INFINITY, 0, 0.0);
+
+		for axis in 0..3 {
+			let (origin, direction) = (ray.origin[axis], ray.direction[axis]);
+			if direction == 0.0 {
+				if origin < self.min[axis] || origin > self.max[axis] {
+					return Vec::new();
+				}
+				continue;
+			}
+
+			let sign = direction.signum();
+			let t0 = (self.min[axis] - origin) / direction;
+			let t1 = (self.max[axis] - origin) / direction;
+			let (near, far) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+
+			if near > enter.0 {
+				enter = (near, axis, -sign);
+			}
+			if far < exit.0 {
+				exit = (far, axis, sign);
+			}
+		}
+
+		if enter.0 > exit.0 || !enter.0.is_finite() {
+			return Vec::new();
+		}
+
+		vec![Span {
+			enter: self.hit_at(ray, enter.0, enter.1, enter.2),
+			exit: self.hit_at(ray, exit.0, exit.1, exit.2),
+		}]
+	}
+}
+
+impl Hittable for Block {
+	fn hit(&self, interval: &Interval, ray: &Ray) -> Option<HitResult<'_>> {
+		self.hit_spans(interval, ray)
+	}
+
+	fn bounds(&self) -> &Aabb3d {
+		&self.bounds
+	}
+}
@@ -0,0 +1,369 @@
+use std::sync::Arc;
+
+#[cfg(feature = "simd")]
+use crate::geometry::FloatX4;
+use crate::geometry::{Aabb3d, Float, HitResult, Hittable, Interval, Ray, SLAB_ROUNDING};
+use crate::random;
+use crate::stats;
+#[cfg(feature = "simd")]
+use wide::CmpLt;
+
+const WIDTH: usize = 4;
+const STACK_SIZE: usize = 64;
+
+#[derive(Clone, Copy)]
+enum BvhChild {
+	Empty,
+	Node(u32),
+	Leaf(u32),
+}
+
+// Child bounds are stored per axis so all four boxes can be tested at once
+struct BvhNode {
+	min: [[Float; WIDTH]; 3],
+	max: [[Float; WIDTH]; 3],
+	children: [BvhChild; WIDTH],
+}
+
+impl BvhNode {
+	fn empty() -> BvhNode {
+		BvhNode {
+			min: [[0.0; WIDTH]; 3],
+			max: [[0.0; WIDTH]; 3],
+			children: [BvhChild::Empty; WIDTH],
+		}
+	}
+
+	fn set_child(&mut self, slot: usize, child: BvhChild, bounds: &Aabb3d) {
+		for axis in 0..3 {
+			self.min[axis][slot] = bounds.axis(axis).min;
+			self.max[axis][slot] = bounds.axis(axis).max;
+		}
+
+		self.children[slot] = child;
+	}
+
+	// Returns a bitmask of the children whose boxes overlap the ray in [tmin, tmax]
+	#[cfg(not(feature = "simd"))]
+	fn hit(&self, ray: &PackedRay, tmin: Float, tmax: Float) -> u32 {
+		let mut mask = 0;
+
+		for slot in 0..WIDTH {
+			let mut imin = tmin;
+			let mut imax = tmax;
+
+			for axis in 0..3 {
+				let t0 = (self.min[axis][slot] - ray.origin[axis]) * ray.inv_d[axis];
+				let t1 = (self.max[axis][slot] - ray.origin[axis]) * ray.inv_d[axis];
+
+				imin = imin.max(t0.min(t1));
+				imax = imax.min(t0.max(t1) * SLAB_ROUNDING);
+			}
+
+			if imin < imax {
+				mask |= 1 << slot;
+			}
+		}
+
+		mask
+	}
+
+	#[cfg(feature = "simd")]
+	fn hit(&self, ray: &PackedRay, tmin: Float, tmax: Float) -> u32 {
+		let mut imin = FloatX4::splat(tmin);
+		let mut imax = FloatX4::splat(tmax);
+
+		for axis in 0..3 {
+			let origin = FloatX4::splat(ray.origin[axis]);
+			let inv_d = FloatX4::splat(ray.inv_d[axis]);
+
+			let t0 = (FloatX4::new(self.min[axis]) - origin) * inv_d;
+			let t1 = (FloatX4::new(self.max[axis]) - origin) * inv_d;
+
+			imin = imin.max(t0.min(t1));
+			imax = imax.min(t0.max(t1) * FloatX4::splat(SLAB_ROUNDING));
+		}
+
+		imin.cmp_lt(imax).move_mask() as u32
+	}
+}
+
+// Per ray values that are reused for every node visited
+struct PackedRay {
+	origin: [Float; 3],
+	inv_d: [Float; 3],
+}
+
+impl PackedRay {
+	fn new(ray: &Ray) -> PackedRay {
+		PackedRay {
+			origin: [ray.origin.x, ray.origin.y, ray.origin.z],
+			inv_d: [
+				1.0 / ray.direction.x,
+				1.0 / ray.direction.y,
+				1.0 / ray.direction.z,
+			],
+		}
+	}
+}
+
+// Bounds of a primitive while the tree is built, sorting these is cheaper than calling
+// bounds() through the primitives
+#[derive(Clone, Copy)]
+struct BuildItem {
+	bounds: Aabb3d,
+	index: u32,
+}
+
+// Flattened 4-wide BVH, node 0 is the root. Nodes and primitives each live in one allocation and
+// refer to each other by index, the primitives are stored in the order the leaves visit them.
+// Meshes keep their triangles by value this way, other objects are Arc'd. Unbounded objects are
+// tested one by one before the tree is traversed.
+pub struct Bvh<T: Hittable = Arc<dyn Hittable>> {
+	bounds: Aabb3d,
+	tree_bounds: Aabb3d,
+	nodes: Vec<BvhNode>,
+	primitives: Vec<T>,
+	unbounded: Vec<T>,
+}
+
+impl Bvh {
+	pub fn new(objects: &[Arc<dyn Hittable>]) -> Bvh {
+		Bvh::from_primitives(objects.to_vec())
+	}
+}
+
+impl<T: Hittable> Bvh<T> {
+	pub fn from_primitives(primitives: Vec<T>) -> Bvh<T> {
+		let (unbounded, mut bounded): (Vec<_>, Vec<_>) = primitives
+			.into_iter()
+			.partition(|primitive| !primitive.is_bounded());
+
+		let mut items: Vec<BuildItem> = bounded
+			.iter()
+			.enumerate()
+			.map(|(index, primitive)| BuildItem {
+				bounds: *primitive.bounds(),
+				index: index as u32,
+			})
+			.collect();
+
+		// A 4-wide tree has about a third as many nodes as leaves
+		let mut nodes = Vec::with_capacity(items.len() / 3 + 1);
+		let mut order = Vec::with_capacity(items.len());
+		let tree_bounds = if items.is_empty() {
+			Aabb3d::empty()
+		} else {
+			Bvh::<T>::build(&mut nodes, &mut order, &mut items)
+		};
+
+		// Moves every primitive to its leaf in place, order[leaf] is the original index
+		let mut rank = vec![0u32; order.len()];
+		for (leaf, &index) in order.iter().enumerate() {
+			rank[index as usize] = leaf as u32;
+		}
+		for i in 0..rank.len() {
+			while rank[i] as usize != i {
+				let j = rank[i] as usize;
+				bounded.swap(i, j);
+				rank.swap(i, j);
+			}
+		}
+
+		let bounds = unbounded.iter().fold(tree_bounds, |bounds, object| {
+			Aabb3d::from_bounds(&bounds, object.bounds())
+		});
+
+		let bvh = Bvh {
+			bounds,
+			tree_bounds,
+			nodes,
+			primitives: bounded,
+			unbounded,
+		};
+		stats::add_bvh(bvh.nodes.len(), bvh.memory());
+
+		bvh
+	}
+
+	pub fn node_count(&self) -> usize {
+		self.nodes.len()
+	}
+
+	// Bytes of the nodes and of the primitives stored in the tree, Arc'd objects only count
+	// their pointers
+	pub fn memory(&self) -> usize {
+		self.nodes.capacity() * size_of::<BvhNode>()
+			+ (self.primitives.capacity() + self.unbounded.capacity()) * size_of::<T>()
+	}
+
+	fn build(nodes: &mut Vec<BvhNode>, order: &mut Vec<u32>, items: &mut [BuildItem]) -> Aabb3d {
+		let index = nodes.len();
+		nodes.push(BvhNode::empty());
+
+		let mut bounds: Option<Aabb3d> = None;
+
+		for (slot, group) in Bvh::<T>::split(items).into_iter().enumerate() {
+			let (child, child_bounds) = if group.len() == 1 {
+				let leaf = order.len() as u32;
+				order.push(group[0].index);
+				(BvhChild::Leaf(leaf), group[0].bounds)
+			} else {
+				let node = nodes.len() as u32;
+				(BvhChild::Node(node), Bvh::<T>::build(nodes, order, group))
+			};
+
+			nodes[index].set_child(slot, child, &child_bounds);
+
+			bounds = Some(match bounds {
+				Some(bounds) => Aabb3d::from_bounds(&bounds, &child_bounds),
+				None => child_bounds,
+			});
+		}
+
+		bounds.unwrap_or_default()
+	}
+
+	// Splits the items into up to four non-empty groups with two levels of median splits
+	fn split(items: &mut [BuildItem]) -> Vec<&mut [BuildItem]> {
+		if items.len() <= WIDTH {
+			return items.chunks_mut(1).collect();
+		}
+
+		let (left, right) = Bvh::<T>::split_median(items);
+		let (left0, left1) = Bvh::<T>::split_median(left);
+		let (right0, right1) = Bvh::<T>::split_median(right);
+
+		vec![left0, left1, right0, right1]
+	}
+
+	fn split_median(items: &mut [BuildItem]) -> (&mut [BuildItem], &mut [BuildItem]) {
+		let axis = random::index(3);
+
+		// Sort the items on the randomly chosen axis
+		items.sort_by(|a, b| {
+			let a = a.bounds.axis(axis).min;
+			let b = b.bounds.axis(axis).min;
+			a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+		});
+
+		let mid = items.len() / 2;
+		items.split_at_mut(mid)
+	}
+}
+
+impl<T: Hittable> Hittable for Bvh<T> {
+	fn hit(&self, interval: &Interval, ray: &Ray) -> Option<HitResult<'_>> {
+		let mut nearest = interval.max;
+		let mut nearest_result = None;
+
+		for object in &self.unbounded {
+			if let Some(hit_result) = object.hit(&Interval::new(interval.min, nearest), ray) {
+				nearest = hit_result.t;
+				nearest_result = Some(hit_result);
+			}
+		}
+
+		if self.nodes.is_empty()
+			|| !self
+				.tree_bounds
+				.hit(ray, &Interval::new(interval.min, nearest))
+		{
+			return nearest_result;
+		}
+
+		let packed = PackedRay::new(ray);
+
+		let mut stack = [0u32; STACK_SIZE];
+		let mut top = 1;
+
+		while top > 0 {
+			top -= 1;
+			let node = &self.nodes[stack[top] as usize];
+			stats::count_node();
+			let mask = node.hit(&packed, interval.min, nearest);
+
+			for slot in 0..WIDTH {
+				if mask & (1 << slot) == 0 {
+					continue;
+				}
+
+				match node.children[slot] {
+					BvhChild::Empty => {}
+					BvhChild::Node(child) => {
+						stack[top] = child;
+						top += 1;
+					}
+					BvhChild::Leaf(primitive) => {
+						stats::count_primitive();
+						let bounded = Interval::new(interval.min, nearest);
+						if let Some(hit_result) =
+							self.primitives[primitive as usize].hit(&bounded, ray)
+						{
+							nearest = hit_result.t;
+							nearest_result = Some(hit_result);
+						}
+					}
+				}
+			}
+		}
+
+		nearest_result
+	}
+
+	fn hit_any(&self, interval: &Interval, ray: &Ray) -> bool {
+		if self
+			.unbounded
+			.iter()
+			.any(|object| object.hit_any(interval, ray))
+		{
+			return true;
+		}
+
+		if self.nodes.is_empty() || !self.tree_bounds.hit(ray, interval) {
+			return false;
+		}
+
+		let packed = PackedRay::new(ray);
+
+		let mut stack = [0u32; STACK_SIZE];
+		let mut top = 1;
+
+		while top > 0 {
+			top -= 1;
+			let node = &self.nodes[stack[top] as usize];
+			stats::count_node();
+			let mask = node.hit(&packed, interval.min, interval.max);
+
+			for slot in 0..WIDTH {
+				if mask & (1 << slot) == 0 {
+					continue;
+				}
+
+				match node.children[slot] {
+					BvhChild::Empty => {}
+					BvhChild::Node(child) => {
+						stack[top] = child;
+						top += 1;
+					}
+					BvhChild::Leaf(primitive) => {
+						stats::count_primitive();
+						if self.primitives[primitive as usize].hit_any(interval, ray) {
+							return true;
+						}
+					}
+				}
+			}
+		}
+
+		false
+	}
+
+	fn bounds(&self) -> &Aabb3d {
+		&self.bounds
+	}
+
+	fn is_bounded(&self) -> bool {
+		self.unbounded.is_empty()
+	}
+}
@@ -0,0 +1,126 @@
+use crate::geometry::{Aabb3d, HitResult, Hittable, Interval, Ray};
+
+// Stretch of a ray inside a solid, from the surface where it enters to where it leaves
+pub struct Span<'a> {
+	pub enter: HitResult<'a>,
+	pub exit: HitResult<'a>,
+}
+
+// Closed shape that can tell where along a ray it is inside, which is what CSG is built from.
+// Normals point out of the solid at both ends of a span.
+pub trait Solid: Hittable {
+	// Every span along the whole line of the ray, including behind its origin, sorted and
+	// without overlaps
+	fn spans(&self, ray: &Ray) -> Vec<Span<'_>>;
+
+	// The first surface of any span within `interval`, for implementing Hittable
+	fn hit_spans(&self, interval: &Interval, ray: &Ray) -> Option<HitResult<'_>> {
+		self.spans(ray)
+			.into_iter()
+			.flat_map(|span| [span.enter, span.exit])
+			.find(|hit| interval.surrounds(hit.t))
+	}
+}
+
+#[derive(Copy, Clone)]
+pub enum Operation {
+	Union,
+	Intersection,
+	// The first solid with the second one cut out of it
+	Difference,
+}
+
+impl Operation {
+	fn inside(self, a: bool, b: bool) -> bool {
+		match self {
+			Operation::Union => a || b,
+			Operation::Intersection => a && b,
+			Operation::Difference => a && !b,
+		}
+	}
+}
+
+// Boolean combination of two solids, itself a solid so trees of them can be built
+pub struct Csg {
+	a: Box<dyn Solid>,
+	b: Box<dyn Solid>,
+	operation: Operation,
+	bounds: Aabb3d,
+}
+
+impl Csg {
+	pub fn new(operation: Operation, a: Box<dyn Solid>, b: Box<dyn Solid>) -> Csg {
+		let (bounds_a, bounds_b) = (a.bounds(), b.bounds());
+		let bounds = match operation {
+			Operation::Union => Aabb3d::from_bounds(bounds_a, bounds_b),
+			Operation::Intersection => Aabb3d::from_intervals(
+				bounds_a.axis(0).intersect(bounds_b.axis(0)),
+				bounds_a.axis(1).intersect(bounds_b.axis(1)),
+				bounds_a.axis(2).intersect(bounds_b.axis(2)),
+			),
+			Operation::Difference => *bounds_a,
+		};
+
+		Csg {
+			a,
+			b,
+			operation,
+			bounds,
+		}
+	}
+}
+
+impl Solid for Csg {
+	fn spans(&self, ray: &Ray) -> Vec<Span<'_>> {
+		if !self.bounds.hit(ray, &Interval::UNIVERSE) {
+			return Vec::new();
+		}
+
+		// Walk the surfaces of both solids in order, tracking which ones the ray is inside
+		let mut events = Vec::new();
+		for (from_b, spans) in [(false, self.a.spans(ray)), (true, self.b.spans(ray))] {
+			for span in spans {
+				events.push((from_b, true, span.enter));
+				events.push((from_b, false, span.exit));
+			}
+		}
+		events.sort_by(|(_, _, x), (_, _, y)| x.t.total_cmp(&y.t));
+
+		let (mut inside_a, mut inside_b) = (false, false);
+		let mut enter = None;
+		let mut spans = Vec::new();
+		for (from_b, entering, mut hit) in events {
+			if from_b {
+				inside_b = entering;
+				// Where the second solid is cut out, its inside faces out of the result
+				if let Operation::Difference = self.operation {
+					hit.normal = -hit.normal;
+				}
+			} else {
+				inside_a = entering;
+			}
+
+			let inside = self.operation.inside(inside_a, inside_b);
+			match enter.take() {
+				None if inside => enter = Some(hit),
+				Some(start) if !inside => spans.push(Span {
+					enter: start,
+					exit: hit,
+				}),
+				start => enter = start,
+			}
+		}
+
+		spans
+	}
+}
+
+impl Hittable for Csg {
+	fn hit(&self, interval: &Interval, ray: &Ray) -> Option<HitResult<'_>> {
+		self.hit_spans(interval, ray)
+	}
+
+	fn bounds(&self) -> &Aabb3d {
+		&self.bounds
+	}
+}
@@ -0,0 +1,258 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::error::{RendererError, Result};
+use crate::geometry::{Aabb3d, Bvh, Float, HitResult, Hittable, Interval, Ray, Vec3f};
+use crate::material::Material;
+use crate::stats;
+
+#[derive(Copy, Clone)]
+pub enum CurveBasis {
+	// Passes through every point, the ends are extended by mirroring their neighbors
+	CatmullRom,
+	// Piecewise cubic with 3n + 1 control points, passing through every third one
+	Bezier,
+}
+
+impl CurveBasis {
+	// Points along the curve, `subdivisions` straight segments for every cubic span
+	fn polyline(self, points: &[Vec3f], subdivisions: u32) -> Option<Vec<Vec3f>> {
+		let spans: Vec<[Vec3f; 4]> = match self {
+			CurveBasis::CatmullRom => {
+				if points.len() < 2 {
+					return None;
+				}
+				let last = points.len() - 1;
+				let mut padded = vec![points[0] * 2.0 - points[1]];
+				padded.extend_from_slice(points);
+				padded.push(points[last] * 2.0 - points[last - 1]);
+				padded
+					.windows(4)
+					.map(|p| [p[0], p[1], p[2], p[3]])
+					.collect()
+			}
+			CurveBasis::Bezier => {
+				if points.len() < 4 || !(points.len() - 1).is_multiple_of(3) {
+					return None;
+				}
+				points
+					.windows(4)
+					.step_by(3)
+					.map(|p| [p[0], p[1], p[2], p[3]])
+					.collect()
+			}
+		};
+
+		let mut polyline = vec![self.at(&spans[0], 0.0)];
+		for span in &spans {
+			for step in 1..=subdivisions {
+				polyline.push(self.at(span, step as Float / subdivisions as Float));
+			}
+		}
+
+		Some(polyline)
+	}
+
+	fn at(self, [p0, p1, p2, p3]: &[Vec3f; 4], t: Float) -> Vec3f {
+		let (t2, t3) = (t * t, t * t * t);
+		match self {
+			CurveBasis::CatmullRom => {
+				(*p1 * 2.0
+					+ (*p2 - *p0) * t
+					+ (*p0 * 2.0 - *p1 * 5.0 + *p2 * 4.0 - *p3) * t2
+					+ (*p3 - *p0 + (*p1 - *p2) * 3.0) * t3)
+					* 0.5
+			}
+			CurveBasis::Bezier => {
+				let s = 1.0 - t;
+				*p0 * (s * s * s) + *p1 * (3.0 * s * s * t) + *p2 * (3.0 * s * t2) + *p3 * t3
+			}
+		}
+	}
+}
+
+// Straight piece of a curve, a capsule so that consecutive segments join without gaps
+struct CurveSegment {
+	a: Vec3f,
+	b: Vec3f,
+	radius: Float,
+	// Where along the whole curve the segment starts and ends, for the u coordinate
+	u: (Float, Float),
+	material: Arc<dyn Material>,
+	bounds: Aabb3d,
+}
+
+// Roots of a t^2 + 2 half_b t + c
+fn roots(a: Float, half_b: Float, c: Float) -> Option<[Float; 2]> {
+	let d = half_b * half_b - a * c;
+	if a == 0.0 || d < 0.0 {
+		return None;
+	}
+	let sqrtd = d.sqrt();
+	Some([(-half_b - sqrtd) / a, (-half_b + sqrtd) / a])
+}
+
+impl CurveSegment {
+	fn intersect(&self, interval: &Interval, ray: &Ray) -> Option<Float> {
+		let ba = self.b - self.a;
+		let oa = ray.origin - self.a;
+		let d = ray.direction;
+		let r2 = self.radius * self.radius;
+
+		let baba = ba.lengthsq();
+		let bad = Vec3f::dot(&ba, &d);
+		let baoa = Vec3f::dot(&ba, &oa);
+
+		let mut nearest: Option<Float> = None;
+		let mut consider = |t: Float| {
+			if interval.surrounds(t) && nearest.is_none_or(|nearest| t < nearest) {
+				nearest = Some(t);
+			}
+		};
+
+		// The tube around the segment, only between its ends
+		if let Some(ts) = roots(
+			baba * d.lengthsq() - bad * bad,
+			baba * Vec3f::dot(&d, &oa) - baoa * bad,
+			baba * oa.lengthsq() - baoa * baoa - r2 * baba,
+		) {
+			for t in ts {
+				let along = baoa + t * bad;
+				if along > 0.0 && along < baba {
+					consider(t);
+				}
+			}
+		}
+
+		// The round caps
+		for center in [self.a, self.b] {
+			let oc = ray.origin - center;
+			if let Some(ts) = roots(d.lengthsq(), Vec3f::dot(&oc, &d), oc.lengthsq() - r2) {
+				ts.into_iter().for_each(&mut consider);
+			}
+		}
+
+		nearest
+	}
+}
+
+impl Hittable for CurveSegment {
+	fn hit(&self, interval: &Interval, ray: &Ray) -> Option<HitResult<'_>> {
+		let t = self.intersect(interval, ray)?;
+		let point = ray.at(t);
+
+		let ba = self.b - self.a;
+		let along = (Vec3f::dot(&(point - self.a), &ba) / ba.lengthsq()).clamp(0.0, 1.0);
+
+		Some(HitResult {
+			point,
+			normal: (point - (self.a + ba * along)).unit(),
+			t,
+			material: self.material.as_ref(),
+			u: self.u.0 + (self.u.1 - self.u.0) * along,
+			v: 0.5,
+			color: None,
+			edge: None,
+			id: 0,
+			// Along the curve, so anisotropic materials highlight like hair
+			tangent: Some(ba),
+			derivatives: None,
+			normal_derivatives: None,
+			footprint: None,
+		})
+	}
+
+	fn hit_any(&self, interval: &Interval, ray: &Ray) -> bool {
+		self.intersect(interval, ray).is_some()
+	}
+
+	fn bounds(&self) -> &Aabb3d {
+		&self.bounds
+	}
+}
+
+// Many thin curves like hair, grass, ropes or cables in one BVH over their segments. Every curve
+// tapers from `radius` at its first point to `tip_radius` at its last, and u runs from 0 to 1
+// along it.
+pub struct Curves {
+	bvh: Bvh<CurveSegment>,
+}
+
+impl Curves {
+	pub fn new(
+		curves: &[Vec<Vec3f>],
+		basis: CurveBasis,
+		subdivisions: u32,
+		radius: Float,
+		tip_radius: Float,
+		material: Arc<dyn Material>,
+	) -> Result<Curves> {
+		let start = Instant::now();
+		let mut segments: Vec<CurveSegment> = Vec::new();
+
+		for (index, points) in curves.iter().enumerate() {
+			let polyline = basis.polyline(points, subdivisions.max(1)).ok_or_else(|| {
+				RendererError::Scene {
+					path: None,
+					message: format!(
+						"curve {} has {} points, Catmull-Rom curves need at least 2 and Bezier \
+						 curves 3n + 1",
+						index,
+						points.len()
+					),
+				}
+			})?;
+
+			let count = (polyline.len() - 1) as Float;
+			for (i, pair) in polyline.windows(2).enumerate() {
+				let (a, b) = (pair[0], pair[1]);
+				if (b - a).lengthsq() == 0.0 {
+					continue;
+				}
+				let middle = (i as Float + 0.5) / count;
+				let radius = radius + (tip_radius - radius) * middle;
+				let extent = Vec3f::new(radius, radius, radius);
+
+				segments.push(CurveSegment {
+					a,
+					b,
+					radius,
+					u: (i as Float / count, (i + 1) as Float / count),
+					material: Arc::clone(&material),
+					bounds: Aabb3d::from_corners(a.min(b) - extent, a.max(b) + extent),
+				});
+			}
+		}
+
+		let built = Instant::now();
+		let count = segments.len();
+		let bvh = Bvh::from_primitives(segments);
+		let bvh_build = built.elapsed();
+
+		log::info!(
+			"Built {} segments of {} curves in {:.0?}, BVH built in {:.0?}",
+			count,
+			curves.len(),
+			built - start,
+			bvh_build
+		);
+		// Segments are not triangles, only the build time counts
+		stats::add_mesh(0, bvh_build);
+
+		Ok(Curves { bvh })
+	}
+}
+
+impl Hittable for Curves {
+	fn hit(&self, interval: &Interval, ray: &Ray) -> Option<HitResult<'_>> {
+		self.bvh.hit(interval, ray)
+	}
+
+	fn hit_any(&self, interval: &Interval, ray: &Ray) -> bool {
+		self.bvh.hit_any(interval, ray)
+	}
+
+	fn bounds(&self) -> &Aabb3d {
+		self.bvh.bounds()
+	}
+}
@@ -0,0 +1,157 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::geometry::{Aabb3d, Float, HitResult, Hittable, Interval, Ray, Solid, Span, Vec3f};
+use crate::material::Material;
+
+// Capped cylinder standing on the center of its bottom cap, pointing up the y axis. The side is
+// UV mapped around and up, the caps like a disk seen from above.
+pub struct Cylinder {
+	base: Vec3f,
+	radius: Float,
+	height: Float,
+	material: Arc<dyn Material>,
+	bounds: Aabb3d,
+}
+
+#[derive(Copy, Clone)]
+enum Surface {
+	Side,
+	Bottom,
+	Top,
+}
+
+impl Cylinder {
+	pub fn new(base: Vec3f, radius: Float, height: Float, material: Arc<dyn Material>) -> Cylinder {
+		let extent = Vec3f::new(radius, 0.0, radius);
+		let bounds = Aabb3d::pad(&Aabb3d::from_corners(
+			base - extent,
+			base + extent + Vec3f::new(0.0, height, 0.0),
+		));
+
+		Cylinder {
+			base,
+			radius,
+			height,
+			material,
+			bounds,
+		}
+	}
+
+	fn hit_at(&self, ray: &Ray, t: Float, surface: Surface) -> HitResult<'_> {
+		let point = ray.at(t);
+		let offset = point - self.base;
+
+		let (normal, u, v) = match surface {
+			Surface::Side => (
+				Vec3f::new(offset.x, 0.0, offset.z) / self.radius,
+				0.5 + Float::atan2(offset.z, offset.x) / (2.0 * PI as Float),
+				offset.y / self.height,
+			),
+			Surface::Bottom | Surface::Top => (
+				Vec3f::new(
+					0.0,
+					if let Surface::Top = surface {
+						1.0
+					} else {
+						-1.0
+					},
+					0.0,
+				),
+				0.5 + offset.x / (2.0 * self.radius),
+				0.5 + offset.z / (2.0 * self.radius),
+			),
+		};
+
+		HitResult {
+			point,
+			normal,
+			t,
+			material: self.material.as_ref(),
+			u,
+			v,
+			color: None,
+			edge: None,
+			id: 0,
+			tangent: match surface {
+				Surface::Side => Some(Vec3f::new(normal.z, 0.0, -normal.x)),
+				Surface::Bottom | Surface::Top => Some(Vec3f::new(1.0, 0.0, 0.0)),
+			},
+			derivatives: None,
+			normal_derivatives: None,
+			footprint: None,
+		}
+	}
+}
+
+impl Solid for Cylinder {
+	fn spans(&self, ray: &Ray) -> Vec<Span<'_>> {
+		let origin = ray.origin - self.base;
+		let direction = ray.direction;
+
+		// Inside the infinite tube around the axis
+		let a = direction.x * direction.x + direction.z * direction.z;
+		let half_b = origin.x * direction.x + origin.z * direction.z;
+		let c = origin.x * origin.x + origin.z * origin.z - self.radius * self.radius;
+		let (mut enter, mut exit) = if a == 0.0 {
+			if c > 0.0 {
+				return Vec::new();
+			}
+			(
+				(Float::NEG_INFINITY, Surface::Side),
+				(Float::INFINITY, Surface::Side),
+			)
+		} else {
+			let d = half_b * half_b - a * c;
+			if d < 0.0 {
+				return Vec::new();
+			}
+			let sqrtd = d.sqrt();
+			(
+				((-half_b - sqrtd) / a, Surface::Side),
+				((-half_b + sqrtd) / a, Surface::Side),
+			)
+		};
+
+		// Between the planes of the caps
+		if direction.y == 0.0 {
+			if origin.y < 0.0 || origin.y > self.height {
+				return Vec::new();
+			}
+		} else {
+			let bottom = (-origin.y / direction.y, Surface::Bottom);
+			let top = ((self.height - origin.y) / direction.y, Surface::Top);
+			let (near, far) = if bottom.0 < top.0 {
+				(bottom, top)
+			} else {
+				(top, bottom)
+			};
+
+			if near.0 > enter.0 {
+				enter = near;
+			}
+			if far.0 < exit.0 {
+				exit = far;
+			}
+		}
+
+		if enter.0 > exit.0 {
+			return Vec::new();
+		}
+
+		vec![Span {
+			enter: self.hit_at(ray, enter.0, enter.1),
+			exit: self.hit_at(ray, exit.0, exit.1),
+		}]
+	}
+}
+
+impl Hittable for Cylinder {
+	fn hit(&self, interval: &Interval, ray: &Ray) -> Option<HitResult<'_>> {
+		self.hit_spans(interval, ray)
+	}
+
+	fn bounds(&self) -> &Aabb3d {
+		&self.bounds
+	}
+}
@@ -0,0 +1,129 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::error::{RendererError, Result};
+use crate::geometry::{
+	Aabb3d, Bvh, Corner, Float, HitResult, Hittable, IndexedMesh, Interval, Ray, Triangle, Uv,
+	Vec3f,
+};
+use crate::material::Material;
+use crate::stats;
+
+// Terrain from a grayscale image, its columns spread over `size.x` along x and its rows over
+// `size.z` along z starting at `corner`. Black is at the height of the corner, white `size.y`
+// above it. Every pixel is a vertex of a grid of smooth shaded triangles facing up, with UVs
+// that map the same image onto it like an image texture.
+pub struct Heightfield {
+	bvh: Bvh<Triangle>,
+}
+
+impl Heightfield {
+	pub fn new(
+		path: &str,
+		corner: Vec3f,
+		size: Vec3f,
+		material: Arc<dyn Material>,
+	) -> Result<Heightfield> {
+		let start = Instant::now();
+		let image = image::open(path)
+			.map_err(|source| RendererError::Image {
+				path: path.to_string(),
+				source,
+			})?
+			.into_luma16();
+		let (width, height) = (image.width() as usize, image.height() as usize);
+
+		if width < 2 || height < 2 {
+			return Err(RendererError::Scene {
+				path: Some(path.to_string()),
+				message: "a heightfield needs an image of at least 2x2 pixels".to_string(),
+			});
+		}
+
+		let uv = |x: usize, z: usize| {
+			Uv::new(
+				x as Float / (width - 1) as Float,
+				z as Float / (height - 1) as Float,
+			)
+		};
+
+		let mut positions = Vec::with_capacity(width * height);
+		for z in 0..height {
+			for x in 0..width {
+				let uv = uv(x, z);
+				let elevation = image.get_pixel(x as u32, z as u32)[0] as Float / u16::MAX as Float;
+				positions
+					.push(corner + Vec3f::new(uv.u * size.x, elevation * size.y, uv.v * size.z));
+			}
+		}
+
+		let corner = |x: usize, z: usize| Corner {
+			position: z * width + x,
+			uv: uv(x, z),
+			color: Vec3f::new(1.0, 1.0, 1.0),
+		};
+
+		let mut faces = Vec::with_capacity((width - 1) * (height - 1) * 2);
+		for z in 0..height - 1 {
+			for x in 0..width - 1 {
+				faces.push([corner(x, z), corner(x, z + 1), corner(x + 1, z + 1)]);
+				faces.push([corner(x, z), corner(x + 1, z + 1), corner(x + 1, z)]);
+			}
+		}
+
+		let mesh = IndexedMesh { positions, faces };
+		let normals = mesh.vertex_normals();
+		let tris: Vec<Triangle> = mesh
+			.faces
+			.iter()
+			.map(|[a, b, c]| {
+				Triangle::new(
+					mesh.positions[a.position],
+					mesh.positions[b.position],
+					mesh.positions[c.position],
+					a.uv,
+					b.uv,
+					c.uv,
+					Arc::clone(&material),
+				)
+				.with_normals(
+					normals[a.position].unit(),
+					normals[b.position].unit(),
+					normals[c.position].unit(),
+				)
+			})
+			.collect();
+
+		let loaded = Instant::now();
+		let count = tris.len();
+		let bvh = Bvh::from_primitives(tris);
+		let bvh_build = loaded.elapsed();
+
+		log::info!(
+			"Built {} triangles from the {}x{} heightfield {} in {:.0?}, BVH built in {:.0?}",
+			count,
+			width,
+			height,
+			path,
+			loaded - start,
+			bvh_build
+		);
+		stats::add_mesh(count, bvh_build);
+
+		Ok(Heightfield { bvh })
+	}
+}
+
+impl Hittable for Heightfield {
+	fn hit(&self, interval: &Interval, ray: &Ray) -> Option<HitResult<'_>> {
+		self.bvh.hit(interval, ray)
+	}
+
+	fn hit_any(&self, interval: &Interval, ray: &Ray) -> bool {
+		self.bvh.hit_any(interval, ray)
+	}
+
+	fn bounds(&self) -> &Aabb3d {
+		self.bvh.bounds()
+	}
+}
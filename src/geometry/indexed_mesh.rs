@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+
+use crate::geometry::{Float, Uv, Vec3f};
+
+// A face's use of a vertex. Texture coordinates and colors belong to the corner rather than the
+// position, so UV seams survive processing the positions.
+#[derive(Copy, Clone)]
+pub struct Corner {
+	pub position: usize,
+	pub uv: Uv,
+	pub color: Vec3f,
+}
+
+impl Corner {
+	fn between(a: &Corner, b: &Corner, position: usize) -> Corner {
+		Corner {
+			position,
+			uv: (a.uv + b.uv) * 0.5,
+			color: (a.color + b.color) * 0.5,
+		}
+	}
+}
+
+// Triangle mesh with shared positions, what mesh processing works on before the triangles are
+// built
+pub struct IndexedMesh {
+	pub positions: Vec<Vec3f>,
+	pub faces: Vec<[Corner; 3]>,
+}
+
+fn edge(a: usize, b: usize) -> (usize, usize) {
+	(a.min(b), a.max(b))
+}
+
+impl IndexedMesh {
+	fn face_normal(&self, face: &[Corner; 3]) -> Vec3f {
+		let [a, b, c] = face.map(|corner| self.positions[corner.position]);
+		Vec3f::cross(&(b - a), &(c - a))
+	}
+
+	// Area weighted normals of the faces around each position
+	pub fn vertex_normals(&self) -> Vec<Vec3f> {
+		let mut normals = vec![Vec3f::new(0.0, 0.0, 0.0); self.positions.len()];
+
+		for face in &self.faces {
+			let normal = self.face_normal(face);
+			for corner in face {
+				normals[corner.position] += normal;
+			}
+		}
+
+		normals
+	}
+
+	// Normals for every corner of every face, averaged over the faces around the corner's
+	// position that are less than `max_angle` (in degrees) away from the face itself. Edges
+	// sharper than that stay creased.
+	pub fn corner_normals(&self, max_angle: Float) -> Vec<[Vec3f; 3]> {
+		let face_normals: Vec<Vec3f> = self
+			.faces
+			.iter()
+			.map(|face| self.face_normal(face))
+			.collect();
+
+		let mut around: Vec<Vec<usize>> = vec![Vec::new(); self.positions.len()];
+		for (index, face) in self.faces.iter().enumerate() {
+			for corner in face {
+				around[corner.position].push(index);
+			}
+		}
+
+		let min_cos = max_angle.to_radians().cos();
+		self.faces
+			.iter()
+			.zip(&face_normals)
+			.map(|(face, &normal)| {
+				let unit = normal.unit();
+				face.map(|corner| {
+					let sum = around[corner.position]
+						.iter()
+						.map(|&other| face_normals[other])
+						.filter(|other| Vec3f::dot(&unit, &other.unit()) >= min_cos)
+						.fold(Vec3f::new(0.0, 0.0, 0.0), |sum, other| sum + other);
+
+					// Degenerate faces have no direction to average
+					if sum.lengthsq() > 0.0 {
+						sum.unit()
+					} else {
+						unit
+					}
+				})
+			})
+			.collect()
+	}
+
+	// Merges positions closer than `epsilon`, for meshes exported with split vertices
+	// everywhere. Faces which collapse in the process are dropped.
+	pub fn weld(&self, epsilon: Float) -> IndexedMesh {
+		let cell = |p: Vec3f| {
+			(
+				(p.x / epsilon).floor() as i64,
+				(p.y / epsilon).floor() as i64,
+				(p.z / epsilon).floor() as i64,
+			)
+		};
+
+		let mut grid: HashMap<(i64, i64, i64), Vec<usize>> = HashMap::new();
+		let mut positions: Vec<Vec3f> = Vec::new();
+		let remap: Vec<usize> = self
+			.positions
+			.iter()
+			.map(|&position| {
+				let (x, y, z) = cell(position);
+				for dx in -1..=1 {
+					for dy in -1..=1 {
+						for dz in -1..=1 {
+							let close = grid.get(&(x + dx, y + dy, z + dz)).and_then(|cell| {
+								cell.iter().copied().find(|&index| {
+									(positions[index] - position).lengthsq() <= epsilon * epsilon
+								})
+							});
+							if let Some(index) = close {
+								return index;
+							}
+						}
+					}
+				}
+
+				grid.entry((x, y, z)).or_default().push(positions.len());
+				positions.push(position);
+				positions.len() - 1
+			})
+			.collect();
+
+		let faces = self
+			.faces
+			.iter()
+			.map(|face| {
+				face.map(|corner| Corner {
+					position: remap[corner.position],
+					..corner
+				})
+			})
+			.filter(|[a, b, c]| {
+				a.position != b.position && b.position != c.position && c.position != a.position
+			})
+			.collect();
+
+		IndexedMesh { positions, faces }
+	}
+
+	// One step of Loop subdivision, every triangle becomes four and the surface approaches a smooth
+	// limit. Edges with a single face are kept as boundary curves, as are edges shared by more
+	// than two faces. UVs and colors are interpolated linearly.
+	pub fn subdivide(&self) -> IndexedMesh {
+		self.split(true)
+	}
+
+	// Splits every triangle into four at the middle of its edges without moving anything
+	pub fn tessellate(&self) -> IndexedMesh {
+		self.split(false)
+	}
+
+	fn split(&self, smooth: bool) -> IndexedMesh {
+		// The corners opposite to every edge
+		let mut opposite: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+		for [a, b, c] in &self.faces {
+			let [a, b, c] = [a.position, b.position, c.position];
+			for (from, to, across) in [(a, b, c), (b, c, a), (c, a, b)] {
+				opposite.entry(edge(from, to)).or_default().push(across);
+			}
+		}
+
+		let mut edges: Vec<(usize, usize)> = opposite.keys().copied().collect();
+		edges.sort_unstable();
+
+		let mut positions = if smooth {
+			self.smoothed_positions(&edges, &opposite)
+		} else {
+			self.positions.clone()
+		};
+
+		let mut midpoints: HashMap<(usize, usize), usize> = HashMap::new();
+		for &(a, b) in &edges {
+			let midpoint = match opposite[&(a, b)].as_slice() {
+				&[c, d] if smooth => {
+					(self.positions[a] + self.positions[b]) * 0.375
+						+ (self.positions[c] + self.positions[d]) * 0.125
+				}
+				_ => (self.positions[a] + self.positions[b]) * 0.5,
+			};
+			midpoints.insert((a, b), positions.len());
+			positions.push(midpoint);
+		}
+
+		let faces = self
+			.faces
+			.iter()
+			.flat_map(|[a, b, c]| {
+				let at = |x: &Corner, y: &Corner| {
+					Corner::between(x, y, midpoints[&edge(x.position, y.position)])
+				};
+				let (ab, bc, ca) = (at(a, b), at(b, c), at(c, a));
+				[[*a, ab, ca], [ab, *b, bc], [ca, bc, *c], [ab, bc, ca]]
+			})
+			.collect();
+
+		IndexedMesh { positions, faces }
+	}
+
+	// Loop's weights for the existing positions
+	fn smoothed_positions(
+		&self,
+		edges: &[(usize, usize)],
+		opposite: &HashMap<(usize, usize), Vec<usize>>,
+	) -> Vec<Vec3f> {
+		let mut neighbors: Vec<Vec<usize>> = vec![Vec::new(); self.positions.len()];
+		let mut boundary: Vec<Vec<usize>> = vec![Vec::new(); self.positions.len()];
+		for &(a, b) in edges {
+			neighbors[a].push(b);
+			neighbors[b].push(a);
+			if opposite[&(a, b)].len() != 2 {
+				boundary[a].push(b);
+				boundary[b].push(a);
+			}
+		}
+
+		self.positions
+			.iter()
+			.enumerate()
+			.map(|(index, &position)| {
+				let sum = |indices: &[usize]| {
+					indices
+						.iter()
+						.fold(Vec3f::new(0.0, 0.0, 0.0), |sum, &i| sum + self.positions[i])
+				};
+
+				match (boundary[index].len(), neighbors[index].len()) {
+					(0, 0) => position,
+					(0, k) => {
+						let beta = if k == 3 {
+							3.0 / 16.0
+						} else {
+							3.0 / (8.0 * k as Float)
+						};
+						position * (1.0 - k as Float * beta) + sum(&neighbors[index]) * beta
+					}
+					(2, _) => position * 0.75 + sum(&boundary[index]) * 0.125,
+					// Corners where boundaries meet stay where they are
+					_ => position,
+				}
+			})
+			.collect()
+	}
+}
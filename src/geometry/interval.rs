@@ -1,78 +1,196 @@
-use crate::geometry::{Aabb3d, Vec3f};
-use crate::material::Material;
-
 use std::sync::Arc;
 
+use crate::geometry::{Aabb3d, Float, Uv, Vec3f};
+use crate::material::Material;
+
 #[derive(Debug, Clone, Copy)]
 pub struct Interval {
-	pub min: f64,
-	pub max: f64,
+	pub min: Float,
+	pub max: Float,
 }
 
 impl Interval {
+	// Contains nothing, the union with any interval is that interval
+	pub const EMPTY: Interval = Interval {
+		min: Float::INFINITY,
+		max: Float::NEG_INFINITY,
+	};
+
+	pub const UNIVERSE: Interval = Interval {
+		min: Float::NEG_INFINITY,
+		max: Float::INFINITY,
+	};
+
 	pub fn new_ray() -> Self {
 		Interval {
 			// Add a bit of padding to prevent "shadow acne"
 			min: 0.000001,
-			max: std::f64::MAX,
+			max: Float::MAX,
 		}
 	}
 
-	pub fn new(min: f64, max: f64) -> Self {
+	pub fn new(min: Float, max: Float) -> Self {
 		Interval { min, max }
 	}
 
 	pub fn from_intervals(i0: &Interval, i1: &Interval) -> Interval {
+		i0.union(i1)
+	}
+
+	// Smallest interval containing both
+	pub fn union(&self, other: &Interval) -> Interval {
+		Interval {
+			min: Float::min(self.min, other.min),
+			max: Float::max(self.max, other.max),
+		}
+	}
+
+	// Overlap of both, empty if they are disjoint
+	pub fn intersect(&self, other: &Interval) -> Interval {
 		Interval {
-			min: f64::min(i0.min, i1.min),
-			max: f64::max(i0.max, i1.max),
+			min: Float::max(self.min, other.min),
+			max: Float::min(self.max, other.max),
 		}
 	}
 
-	pub fn expand(interval: &Interval, delta: f64) -> Interval {
+	pub fn expand(interval: &Interval, delta: Float) -> Interval {
 		Interval {
 			min: interval.min - delta / 2.0,
 			max: interval.max + delta / 2.0,
 		}
 	}
 
-	pub fn size(&self) -> f64 {
+	pub fn size(&self) -> Float {
 		self.max - self.min
 	}
 
-	pub fn surrounds(&self, x: f64) -> bool {
+	pub fn is_empty(&self) -> bool {
+		self.min > self.max
+	}
+
+	pub fn clamp(&self, x: Float) -> Float {
+		if x < self.min {
+			self.min
+		} else if x > self.max {
+			self.max
+		} else {
+			x
+		}
+	}
+
+	pub fn surrounds(&self, x: Float) -> bool {
 		self.min < x && x < self.max
 	}
 
-	pub fn contains(&self, x: f64) -> bool {
-		self.min <= x && self.max <= self.max
+	pub fn contains(&self, x: Float) -> bool {
+		self.min <= x && x <= self.max
 	}
 }
 
 pub trait Hittable: Send + Sync {
-	fn hit(&self, interval: &Interval, ray: &Ray) -> Option<HitResult>;
+	fn hit(&self, interval: &Interval, ray: &Ray) -> Option<HitResult<'_>>;
 	fn bounds(&self) -> &Aabb3d;
+
+	// Objects without finite bounds, like infinite planes, are kept out of BVH nodes since
+	// their boxes would inflate every ancestor
+	fn is_bounded(&self) -> bool {
+		true
+	}
+
+	// Occlusion query for shadow rays, returns as soon as any hit in the interval is found
+	fn hit_any(&self, interval: &Interval, ray: &Ray) -> bool {
+		self.hit(interval, ray).is_some()
+	}
+}
+
+// Objects shared between several places in a scene
+impl<T: Hittable + ?Sized> Hittable for Arc<T> {
+	fn hit(&self, interval: &Interval, ray: &Ray) -> Option<HitResult<'_>> {
+		self.as_ref().hit(interval, ray)
+	}
+
+	fn bounds(&self) -> &Aabb3d {
+		self.as_ref().bounds()
+	}
+
+	fn is_bounded(&self) -> bool {
+		self.as_ref().is_bounded()
+	}
+
+	fn hit_any(&self, interval: &Interval, ray: &Ray) -> bool {
+		self.as_ref().hit_any(interval, ray)
+	}
 }
 
 #[derive(Debug, Default)]
 pub struct Ray {
 	pub origin: Vec3f,
 	pub direction: Vec3f,
+	// When the ray is traced while the shutter is open, from 0 as it opens to 1 as it closes.
+	// Rays scattered from it keep its time, so the whole path sees the scene at one moment.
+	pub time: Float,
 }
 
 impl Ray {
-	pub fn at(&self, t: f64) -> Vec3f {
+	pub fn at(&self, t: Float) -> Vec3f {
 		self.direction * t + self.origin
 	}
 }
 
-pub struct HitResult {
+// The rays through the next pixel along x and along y, as offsets from the origin and the
+// direction of a ray. The directions are offset at the scale of the ray's direction.
+#[derive(Debug, Clone, Copy)]
+pub struct RayDifferentials {
+	pub origin: [Vec3f; 2],
+	pub direction: [Vec3f; 2],
+}
+
+impl RayDifferentials {
+	// Offsets of the points where the neighboring rays cross the plane through `point` with
+	// `normal`, from the point. None where a neighbor runs along the plane.
+	pub fn at(&self, ray: &Ray, point: Vec3f, normal: Vec3f) -> Option<[Vec3f; 2]> {
+		let offset = |axis: usize| {
+			let origin = ray.origin + self.origin[axis];
+			let direction = ray.direction + self.direction[axis];
+			let cosine = Vec3f::dot(&direction, &normal);
+			if cosine.abs() < 1e-12 {
+				return None;
+			}
+
+			let t = Vec3f::dot(&(point - origin), &normal) / cosine;
+			Some(origin + direction * t - point)
+		};
+
+		Some([offset(0)?, offset(1)?])
+	}
+}
+
+// Borrows the material from the primitive that was hit, so discarded hits cost no refcount traffic
+pub struct HitResult<'a> {
 	pub point: Vec3f,
 	pub normal: Vec3f,
-	pub t: f64,
-	pub material: Arc<dyn Material>,
-	pub u: f64,
-	pub v: f64,
+	pub t: Float,
+	pub material: &'a dyn Material,
+	pub u: Float,
+	pub v: Float,
+	// Interpolated vertex color, for meshes that have them
+	pub color: Option<Vec3f>,
+	// Smallest barycentric (or quad) coordinate of the hit, 0 on an edge of the primitive
+	pub edge: Option<Float>,
+	// Set by Tagged, 0 for objects without an ID
+	pub id: u32,
+	// Direction in which u grows along the surface, not normalized and not necessarily
+	// perpendicular to the normal
+	pub tangent: Option<Vec3f>,
+	// How the point moves along the surface per unit of u and v, for surfaces whose UVs can be
+	// differentiated
+	pub derivatives: Option<[Vec3f; 2]>,
+	// How the normal turns per unit of u and v, for curved surfaces. None where it does not
+	// or it is not known.
+	pub normal_derivatives: Option<[Vec3f; 2]>,
+	// Axes of the area of the UVs that the ray's pixel covers, set by integrators that trace ray
+	// cones for filtering textures
+	pub footprint: Option<[Uv; 2]>,
 }
 
 pub struct HittableGroup {
@@ -84,7 +202,7 @@ impl HittableGroup {
 	pub fn new() -> Self {
 		HittableGroup {
 			group: Vec::new(),
-			bounds: Aabb3d::default(),
+			bounds: Aabb3d::empty(),
 		}
 	}
 
@@ -94,8 +212,14 @@ impl HittableGroup {
 	}
 }
 
+impl Default for HittableGroup {
+	fn default() -> Self {
+		HittableGroup::new()
+	}
+}
+
 impl Hittable for HittableGroup {
-	fn hit(&self, interval: &Interval, ray: &Ray) -> Option<HitResult> {
+	fn hit(&self, interval: &Interval, ray: &Ray) -> Option<HitResult<'_>> {
 		let mut nearest = interval.max;
 		let mut nearest_result = None;
 
@@ -109,6 +233,16 @@ impl Hittable for HittableGroup {
 		nearest_result
 	}
 
+	fn hit_any(&self, interval: &Interval, ray: &Ray) -> bool {
+		self.group
+			.iter()
+			.any(|hittable| hittable.hit_any(interval, ray))
+	}
+
+	fn is_bounded(&self) -> bool {
+		self.group.iter().all(|hittable| hittable.is_bounded())
+	}
+
 	fn bounds(&self) -> &Aabb3d {
 		&self.bounds
 	}
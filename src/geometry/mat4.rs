@@ -0,0 +1,200 @@
+use std::ops::Mul;
+
+use crate::geometry::{Float, Vec3f};
+
+// Row-major 4x4 matrix acting on column vectors, so `a * b` applies `b` first
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat4 {
+	pub m: [[Float; 4]; 4],
+}
+
+impl Mat4 {
+	pub const IDENTITY: Mat4 = Mat4 {
+		m: [
+			[1.0, 0.0, 0.0, 0.0],
+			[0.0, 1.0, 0.0, 0.0],
+			[0.0, 0.0, 1.0, 0.0],
+			[0.0, 0.0, 0.0, 1.0],
+		],
+	};
+
+	pub fn translate(offset: Vec3f) -> Mat4 {
+		Mat4 {
+			m: [
+				[1.0, 0.0, 0.0, offset.x],
+				[0.0, 1.0, 0.0, offset.y],
+				[0.0, 0.0, 1.0, offset.z],
+				[0.0, 0.0, 0.0, 1.0],
+			],
+		}
+	}
+
+	pub fn scale(factors: Vec3f) -> Mat4 {
+		Mat4 {
+			m: [
+				[factors.x, 0.0, 0.0, 0.0],
+				[0.0, factors.y, 0.0, 0.0],
+				[0.0, 0.0, factors.z, 0.0],
+				[0.0, 0.0, 0.0, 1.0],
+			],
+		}
+	}
+
+	// Counterclockwise rotation around `axis` when looking down the axis towards the origin
+	pub fn rotate(axis: Vec3f, degrees: Float) -> Mat4 {
+		let Vec3f { x, y, z } = axis.unit();
+		let (sin, cos) = degrees.to_radians().sin_cos();
+		let t = 1.0 - cos;
+
+		Mat4 {
+			m: [
+				[
+					t * x * x + cos,
+					t * x * y - sin * z,
+					t * x * z + sin * y,
+					0.0,
+				],
+				[
+					t * x * y + sin * z,
+					t * y * y + cos,
+					t * y * z - sin * x,
+					0.0,
+				],
+				[
+					t * x * z - sin * y,
+					t * y * z + sin * x,
+					t * z * z + cos,
+					0.0,
+				],
+				[0.0, 0.0, 0.0, 1.0],
+			],
+		}
+	}
+
+	// Camera to world transform for a camera at `position` looking at `target`. Like Camera,
+	// the camera looks down its -z axis with y up.
+	pub fn look_at(position: Vec3f, target: Vec3f, up: Vec3f) -> Mat4 {
+		let z = (position - target).unit();
+		let x = Vec3f::cross(&up, &z).unit();
+		let y = Vec3f::cross(&z, &x);
+
+		Mat4 {
+			m: [
+				[x.x, y.x, z.x, position.x],
+				[x.y, y.y, z.y, position.y],
+				[x.z, y.z, z.z, position.z],
+				[0.0, 0.0, 0.0, 1.0],
+			],
+		}
+	}
+
+	pub fn transpose(&self) -> Mat4 {
+		let mut m = [[0.0; 4]; 4];
+		for (row, values) in m.iter_mut().enumerate() {
+			for (column, value) in values.iter_mut().enumerate() {
+				*value = self.m[column][row];
+			}
+		}
+
+		Mat4 { m }
+	}
+
+	// Gauss-Jordan elimination with partial pivoting, None if the matrix is singular
+	pub fn inverse(&self) -> Option<Mat4> {
+		let mut a = self.m;
+		let mut inverse = Mat4::IDENTITY.m;
+
+		for column in 0..4 {
+			let pivot = (column..4)
+				.max_by(|&i, &j| a[i][column].abs().total_cmp(&a[j][column].abs()))
+				.unwrap_or(column);
+
+			if a[pivot][column].abs() < Float::EPSILON {
+				return None;
+			}
+
+			a.swap(column, pivot);
+			inverse.swap(column, pivot);
+
+			let scale = 1.0 / a[column][column];
+			for k in 0..4 {
+				a[column][k] *= scale;
+				inverse[column][k] *= scale;
+			}
+
+			for row in 0..4 {
+				if row == column {
+					continue;
+				}
+
+				let factor = a[row][column];
+				for k in 0..4 {
+					a[row][k] -= factor * a[column][k];
+					inverse[row][k] -= factor * inverse[column][k];
+				}
+			}
+		}
+
+		Some(Mat4 { m: inverse })
+	}
+
+	// Points are affected by translation, a projective bottom row is divided out
+	pub fn transform_point(&self, point: Vec3f) -> Vec3f {
+		let m = &self.m;
+		let transformed = Vec3f::new(
+			m[0][0] * point.x + m[0][1] * point.y + m[0][2] * point.z + m[0][3],
+			m[1][0] * point.x + m[1][1] * point.y + m[1][2] * point.z + m[1][3],
+			m[2][0] * point.x + m[2][1] * point.y + m[2][2] * point.z + m[2][3],
+		);
+		let w = m[3][0] * point.x + m[3][1] * point.y + m[3][2] * point.z + m[3][3];
+
+		if w == 1.0 {
+			transformed
+		} else {
+			transformed / w
+		}
+	}
+
+	// Directions ignore the translation
+	pub fn transform_vector(&self, vector: Vec3f) -> Vec3f {
+		let m = &self.m;
+		Vec3f::new(
+			m[0][0] * vector.x + m[0][1] * vector.y + m[0][2] * vector.z,
+			m[1][0] * vector.x + m[1][1] * vector.y + m[1][2] * vector.z,
+			m[2][0] * vector.x + m[2][1] * vector.y + m[2][2] * vector.z,
+		)
+	}
+
+	// Normals have to be transformed by the inverse transpose to stay perpendicular under
+	// non-uniform scaling, so this is called on the inverse of the matrix used for the points.
+	// The result is not normalized.
+	pub fn transform_normal(&self, normal: Vec3f) -> Vec3f {
+		let m = &self.m;
+		Vec3f::new(
+			m[0][0] * normal.x + m[1][0] * normal.y + m[2][0] * normal.z,
+			m[0][1] * normal.x + m[1][1] * normal.y + m[2][1] * normal.z,
+			m[0][2] * normal.x + m[1][2] * normal.y + m[2][2] * normal.z,
+		)
+	}
+}
+
+impl Default for Mat4 {
+	fn default() -> Self {
+		Mat4::IDENTITY
+	}
+}
+
+impl Mul for Mat4 {
+	type Output = Mat4;
+
+	fn mul(self, rhs: Mat4) -> Mat4 {
+		let mut m = [[0.0; 4]; 4];
+		for (row, values) in m.iter_mut().enumerate() {
+			for (column, value) in values.iter_mut().enumerate() {
+				*value = (0..4).map(|k| self.m[row][k] * rhs.m[k][column]).sum();
+			}
+		}
+
+		Mat4 { m }
+	}
+}
@@ -1,144 +1,400 @@
-use rand::Rng;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Instant;
 
-use crate::geometry::{Aabb3d, HitResult, Hittable, Interval, Ray, Triangle, Uv, Vec3f};
-use crate::material::Material;
+use crate::assets::Assets;
+use crate::error::{RendererError, Result};
+use crate::geometry::{
+	Bvh, Corner, Float, Hittable, IndexedMesh, MovingTriangle, Triangle, Uv, Vec3f,
+};
+use crate::material::{Diffuse, DiffuseLight, Material, SolidColor, Texture};
+use crate::stats;
 
-pub struct Bvh {
-	bounds: Aabb3d,
-	left: Arc<dyn Hittable>,
-	right: Arc<dyn Hittable>,
+// Subdivides every triangle `subdivisions` times into four and moves the vertices along the
+// smoothed vertex normals by the red channel of `texture` times `scale`, so the heightmap changes
+// the silhouette and not just the shading
+pub struct Displacement {
+	pub texture: Arc<dyn Texture>,
+	pub scale: Float,
+	pub subdivisions: u32,
 }
 
-impl Bvh {
-	pub fn new(objects: &mut Vec<Arc<dyn Hittable>>) -> Bvh {
-		let axis = rand::thread_rng().gen_range(0..=2);
-		let span = objects.len();
-
-		let left: Arc<dyn Hittable>;
-		let right: Arc<dyn Hittable>;
-
-		if objects.len() == 1 {
-			// If leaf node
-			left = Arc::clone(&objects[0]);
-			right = Arc::clone(&objects[0]);
-		} else if objects.len() == 2 {
-			if Aabb3d::lt(objects[0].bounds(), objects[1].bounds(), axis) {
-				left = Arc::clone(&objects[0]);
-				right = Arc::clone(&objects[1]);
-			} else {
-				left = Arc::clone(&objects[1]);
-				right = Arc::clone(&objects[0]);
-			}
-		} else {
-			// Sort the objects on the randomly chosen axis
-			objects.sort_by(|a, b| {
-				if Aabb3d::lt(&a.bounds(), &b.bounds(), axis) {
-					std::cmp::Ordering::Greater
-				} else {
-					std::cmp::Ordering::Less
-				}
-			});
-
-			let mid = span / 2;
-			let (left_objects, right_objects) = objects.split_at(mid);
+impl Displacement {
+	fn apply(&self, mesh: IndexedMesh) -> IndexedMesh {
+		let mut mesh = mesh;
+		for _ in 0..self.subdivisions {
+			mesh = mesh.tessellate();
+		}
 
-			left = Arc::new(Bvh::new(&mut left_objects.to_vec()));
-			right = Arc::new(Bvh::new(&mut right_objects.to_vec()));
+		// Every position moves once so faces stay connected, on UV seams the height comes from
+		// the first face using it
+		let mut uvs: Vec<Option<Uv>> = vec![None; mesh.positions.len()];
+		for corner in mesh.faces.iter().flatten() {
+			uvs[corner.position].get_or_insert(corner.uv);
 		}
 
-		Bvh {
-			bounds: Aabb3d::from_bounds(&left.bounds(), &right.bounds()),
-			left: left,
-			right: right,
+		let normals = mesh.vertex_normals();
+		for ((position, normal), uv) in mesh.positions.iter_mut().zip(normals).zip(uvs) {
+			// Stray positions outside of any face have no normal to move along
+			let Some(uv) = uv.filter(|_| normal.lengthsq() > 0.0) else {
+				continue;
+			};
+
+			let height = self.texture.value(uv.u, uv.v, position).x;
+			*position += normal.unit() * (height * self.scale);
 		}
+
+		mesh
 	}
 }
 
-impl Hittable for Bvh {
-	fn hit(&self, interval: &Interval, ray: &Ray) -> Option<HitResult> {
-		if !self.bounds.hit(ray, interval) {
-			return None;
+// Processing applied to every model of an OBJ file before its triangles are built, in this
+// order: welding positions closer than `weld`, `subdivisions` steps of Loop subdivision to
+// smooth low-poly cages and the displacement. With `smooth_normals` the triangles interpolate
+// normals averaged across edges flatter than that angle in degrees, otherwise they are shaded
+// flat.
+#[derive(Default)]
+pub struct MeshOptions {
+	pub weld: Option<Float>,
+	pub subdivisions: u32,
+	pub displacement: Option<Displacement>,
+	pub smooth_normals: Option<Float>,
+}
+
+// Uses `material` for every triangle in the OBJ file
+pub fn load_mesh(
+	path: &str,
+	material: Arc<dyn Material>,
+	options: &MeshOptions,
+) -> Result<Bvh<Triangle>> {
+	let start = Instant::now();
+	let tris = load(path, options, |_, _| Ok(Arc::clone(&material)))?;
+	Ok(build(path, start, tris))
+}
+
+// Uses the diffuse color or `map_Kd` texture from the OBJ's MTL file, or makes a light of
+// materials with an emission `Ke` or `map_Ke`, `fallback` for objects without a material.
+// Textures are looked up next to the OBJ file first, then in every directory of
+// `texture_paths`, and loaded through `assets`.
+pub fn load_mesh_with_materials(
+	path: &str,
+	fallback: Arc<dyn Material>,
+	texture_paths: &[PathBuf],
+	options: &MeshOptions,
+	assets: &Assets,
+) -> Result<Bvh<Triangle>> {
+	let start = Instant::now();
+	let tris = load(
+		path,
+		options,
+		mtl_materials(path, fallback, texture_paths, assets),
+	)?;
+	Ok(build(path, start, tris))
+}
+
+// Like load_mesh, with the vertices moving through the same triangles in the OBJ files of
+// `samples` over the shutter interval. The samples are spaced evenly from the time it opens,
+// where the mesh is in `path`, to the time it closes.
+pub fn load_moving_mesh(
+	path: &str,
+	samples: &[String],
+	material: Arc<dyn Material>,
+	options: &MeshOptions,
+) -> Result<Bvh<MovingTriangle>> {
+	let start = Instant::now();
+	let tris = load(path, options, |_, _| Ok(Arc::clone(&material)))?;
+	let moving = deform(tris, samples, &material, options)?;
+	Ok(build(path, start, moving))
+}
+
+// Like load_mesh_with_materials, the materials come from the MTL file of `path` and the samples
+// only move the vertices
+pub fn load_moving_mesh_with_materials(
+	path: &str,
+	samples: &[String],
+	fallback: Arc<dyn Material>,
+	texture_paths: &[PathBuf],
+	options: &MeshOptions,
+	assets: &Assets,
+) -> Result<Bvh<MovingTriangle>> {
+	let start = Instant::now();
+	let tris = load(
+		path,
+		options,
+		mtl_materials(path, Arc::clone(&fallback), texture_paths, assets),
+	)?;
+	let moving = deform(tris, samples, &fallback, options)?;
+	Ok(build(path, start, moving))
+}
+
+fn deform(
+	tris: Vec<Triangle>,
+	samples: &[String],
+	material: &Arc<dyn Material>,
+	options: &MeshOptions,
+) -> Result<Vec<MovingTriangle>> {
+	let samples = samples
+		.iter()
+		.map(|sample| {
+			let sample_tris = load(sample, options, |_, _| Ok(Arc::clone(material)))?;
+			if sample_tris.len() != tris.len() {
+				return Err(RendererError::Scene {
+					path: Some(sample.clone()),
+					message: format!(
+						"a motion sample needs the same triangles as the mesh, it has {} instead of {}",
+						sample_tris.len(),
+						tris.len()
+					),
+				});
+			}
+			Ok(sample_tris)
+		})
+		.collect::<Result<Vec<_>>>()?;
+
+	Ok(tris
+		.into_iter()
+		.enumerate()
+		.map(|(index, tri)| {
+			let later: Vec<&Triangle> = samples.iter().map(|sample| &sample[index]).collect();
+			MovingTriangle::new(tri, &later)
+		})
+		.collect())
+}
+
+// Builds the materials of an MTL file as load_mesh_with_materials describes, once per material
+fn mtl_materials<'a>(
+	path: &'a str,
+	fallback: Arc<dyn Material>,
+	texture_paths: &'a [PathBuf],
+	assets: &'a Assets,
+) -> impl FnMut(&[tobj::Material], Option<usize>) -> Result<Arc<dyn Material>> + 'a {
+	let mut materials: Vec<Option<Arc<dyn Material>>> = Vec::new();
+
+	move |materials_file, id| {
+		let Some(id) = id.filter(|&id| id < materials_file.len()) else {
+			return Ok(Arc::clone(&fallback));
+		};
+
+		let material = &materials_file[id];
+		if materials.len() <= id {
+			materials.resize(id + 1, None);
+		}
+
+		if let Some(built) = &materials[id] {
+			return Ok(Arc::clone(built));
 		}
 
-		let hit_left = self.left.hit(interval, ray);
-		let hit_right = self.right.hit(
-			&Interval::new(
-				interval.min,
-				if let Some(hit) = hit_left.as_ref() {
-					hit.t
+		let image = |name: &str| -> Result<Arc<dyn Texture>> {
+			let texture_path = resolve_texture(Path::new(path), name, texture_paths)?;
+			Ok(assets.image(&texture_path)?)
+		};
+
+		// Screens, signs and other glowing parts are lights. `map_Ke` is scaled by the brightest
+		// channel of `Ke`, which exporters often leave at zero next to a map.
+		let emissive = material.emissive.unwrap_or([0.0; 3]).map(|c| c as Float);
+		let strength = emissive.into_iter().fold(0.0, Float::max);
+		let emission: Option<Arc<dyn Texture>> = match material.unknown_param.get("map_Ke") {
+			Some(name) => Some(image(name)?),
+			None if strength > 0.0 => {
+				let [r, g, b] = emissive.map(|c| c / strength);
+				Some(Arc::new(SolidColor::new(Vec3f::new(r, g, b))))
+			}
+			None => None,
+		};
+
+		let built: Arc<dyn Material> = match emission {
+			Some(emission) => Arc::new(
+				DiffuseLight::new(emission).with_intensity(if strength > 0.0 {
+					strength
 				} else {
-					interval.max
-				},
+					1.0
+				}),
 			),
-			ray,
-		);
+			None => {
+				let texture: Arc<dyn Texture> = match &material.diffuse_texture {
+					Some(name) => image(name)?,
+					None => {
+						let [r, g, b] = material.diffuse.unwrap_or([0.8; 3]);
+						Arc::new(SolidColor::new(Vec3f::new(
+							r as Float, g as Float, b as Float,
+						)))
+					}
+				};
+				Arc::new(Diffuse::new(texture))
+			}
+		};
+		materials[id] = Some(Arc::clone(&built));
+		Ok(built)
+	}
+}
+
+// Exporters write texture paths relative to the OBJ file, often with Windows separators or
+// absolute paths from the machine the asset was made on, so the bare file name is tried last
+pub fn resolve_texture(obj_path: &Path, name: &str, texture_paths: &[PathBuf]) -> Result<PathBuf> {
+	let name = name.replace('\\', "/");
+	let relative = Path::new(&name);
+	let file_name = relative.file_name().map(Path::new).unwrap_or(relative);
+
+	let obj_dir = obj_path.parent().unwrap_or(Path::new(""));
+	let mut candidates = vec![obj_dir.join(relative)];
+	candidates.extend(texture_paths.iter().map(|dir| dir.join(relative)));
+	candidates.push(obj_dir.join(file_name));
+	candidates.extend(texture_paths.iter().map(|dir| dir.join(file_name)));
+
+	candidates
+		.into_iter()
+		.find(|candidate| candidate.is_file())
+		.ok_or_else(|| RendererError::Io {
+			path: name.clone(),
+			source: io::Error::new(
+				io::ErrorKind::NotFound,
+				format!(
+					"texture not found next to {} or in the texture paths",
+					obj_path.display()
+				),
+			),
+		})
+}
+
+// Positions with the same index are shared, UVs and colors are per corner
+fn indexed(mesh: &tobj::Mesh) -> IndexedMesh {
+	let positions = mesh
+		.positions
+		.chunks_exact(3)
+		.map(|p| Vec3f::new(p[0] as Float + 3.5, p[1] as Float, p[2] as Float - 1.5))
+		.collect();
+
+	let corner = |index: usize| {
+		let position = mesh.indices[index] as usize;
 
-		if hit_left.is_some() {
-			hit_left
+		// Meshes exported without UVs get all of their texture coordinates at the origin
+		let uv = if mesh.texcoord_indices.is_empty() {
+			Uv::new(0.0, 0.0)
 		} else {
-			hit_right
+			let t = mesh.texcoord_indices[index] as usize * 2;
+			Uv::new(mesh.texcoords[t] as Float, mesh.texcoords[t + 1] as Float)
+		};
+
+		// OBJ files with `v x y z r g b` lines, colors share the position indices
+		let color = if mesh.vertex_color.is_empty() {
+			Vec3f::new(1.0, 1.0, 1.0)
+		} else {
+			let c = &mesh.vertex_color[position * 3..position * 3 + 3];
+			Vec3f::new(c[0] as Float, c[1] as Float, c[2] as Float)
+		};
+
+		Corner {
+			position,
+			uv,
+			color,
 		}
-	}
+	};
 
-	fn bounds(&self) -> &Aabb3d {
-		&self.bounds
-	}
+	let faces = (0..mesh.indices.len() / 3)
+		.map(|face| [corner(face * 3), corner(face * 3 + 1), corner(face * 3 + 2)])
+		.collect();
+
+	IndexedMesh { positions, faces }
 }
 
-pub fn load_mesh(path: &str, material: Arc<dyn Material>) -> Bvh {
-	println!("Loading {}", path);
+fn load(
+	path: &str,
+	options: &MeshOptions,
+	mut material_for: impl FnMut(&[tobj::Material], Option<usize>) -> Result<Arc<dyn Material>>,
+) -> Result<Vec<Triangle>> {
+	log::info!("Loading {}", path);
 
-	let mut tris: Vec<Arc<dyn Hittable>> = Vec::new();
+	let mut tris: Vec<Triangle> = Vec::new();
 
-	let mut options = tobj::LoadOptions::default();
+	let load_options = tobj::LoadOptions {
+		triangulate: true,
+		single_index: false,
+		..Default::default()
+	};
 
-	options.triangulate = true;
-	options.single_index = false;
+	let (models, materials) =
+		tobj::load_obj(path, &load_options).map_err(|source| RendererError::Mesh {
+			path: path.to_string(),
+			source,
+		})?;
 
-	let (models, _) = tobj::load_obj(&path, &options).unwrap();
+	// A missing or broken MTL file only matters to callers that use its materials
+	let materials = materials.unwrap_or_else(|err| {
+		log::debug!("{}: no materials: {}", path, err);
+		Vec::new()
+	});
 
-	for (_, m) in models.iter().enumerate() {
+	for m in models.iter() {
 		let cmesh = &m.mesh;
+		let material = material_for(&materials, cmesh.material_id)?;
 		let face_count = cmesh.indices.len() / 3;
+		let has_colors = !cmesh.vertex_color.is_empty();
+
+		let mut mesh = indexed(cmesh);
+		if let Some(epsilon) = options.weld {
+			mesh = mesh.weld(epsilon);
+		}
+		for _ in 0..options.subdivisions {
+			mesh = mesh.subdivide();
+		}
+		if let Some(displacement) = &options.displacement {
+			mesh = displacement.apply(mesh);
+		}
+		tris.reserve(mesh.faces.len());
+		let normals = options
+			.smooth_normals
+			.map(|max_angle| mesh.corner_normals(max_angle));
 
-		for face in 0..face_count {
-			let p0 = cmesh.indices[face * 3] as usize * 3;
-			let p1 = cmesh.indices[face * 3 + 1] as usize * 3;
-			let p2 = cmesh.indices[face * 3 + 2] as usize * 3;
-
-			let v0 = &cmesh.positions[p0..p0 + 3];
-			let v1 = &cmesh.positions[p1..p1 + 3];
-			let v2 = &cmesh.positions[p2..p2 + 3];
-
-			let ti0 = cmesh.texcoord_indices[face * 3] as usize * 2;
-			let ti1 = cmesh.texcoord_indices[face * 3 + 1] as usize * 2;
-			let ti2 = cmesh.texcoord_indices[face * 3 + 2] as usize * 2;
-
-			let t0 = &cmesh.texcoords[ti0..ti0 + 2];
-			let t1 = &cmesh.texcoords[ti1..ti1 + 2];
-			let t2 = &cmesh.texcoords[ti2..ti2 + 2];
-
-			let tri = Triangle::new(
-				Vec3f::new(v0[0] as f64 + 3.5, v0[1] as f64, v0[2] as f64 - 1.5),
-				Vec3f::new(v1[0] as f64 + 3.5, v1[1] as f64, v1[2] as f64 - 1.5),
-				Vec3f::new(v2[0] as f64 + 3.5, v2[1] as f64, v2[2] as f64 - 1.5),
-				Uv::new(t0[0] as f64, t0[1] as f64),
-				Uv::new(t1[0] as f64, t1[1] as f64),
-				Uv::new(t2[0] as f64, t2[1] as f64),
+		for (index, [a, b, c]) in mesh.faces.iter().enumerate() {
+			let mut tri = Triangle::new(
+				mesh.positions[a.position],
+				mesh.positions[b.position],
+				mesh.positions[c.position],
+				a.uv,
+				b.uv,
+				c.uv,
 				Arc::clone(&material),
 			);
+			if has_colors {
+				tri = tri.with_colors(a.color, b.color, c.color);
+			}
+			if let Some(normals) = &normals {
+				let [normal_a, normal_b, normal_c] = normals[index];
+				tri = tri.with_normals(normal_a, normal_b, normal_c);
+			}
 
-			tris.push(Arc::new(tri));
+			tris.push(tri);
 		}
 
-		println!(
-			"\t{}: {} verts, {} faces",
+		log::debug!(
+			"{}: {} verts, {} faces, {} after processing",
 			m.name,
 			m.mesh.positions.len() / 3,
 			face_count,
+			mesh.faces.len(),
 		);
 	}
 
-	Bvh::new(&mut tris)
+	Ok(tris)
+}
+
+fn build<T: Hittable>(path: &str, start: Instant, tris: Vec<T>) -> Bvh<T> {
+	let loaded = Instant::now();
+	let count = tris.len();
+	let bvh = Bvh::from_primitives(tris);
+	let bvh_build = loaded.elapsed();
+
+	log::info!(
+		"Loaded {} triangles from {} in {:.0?}, BVH of {} nodes built in {:.0?}, {:.1} MB",
+		count,
+		path,
+		loaded - start,
+		bvh.node_count(),
+		bvh_build,
+		bvh.memory() as f64 / 1e6
+	);
+	stats::add_mesh(count, bvh_build);
+
+	bvh
 }
@@ -1,15 +1,57 @@
 mod aabb3d;
+mod block;
+mod bvh;
+mod csg;
+mod curve;
+mod cylinder;
+mod heightfield;
+mod indexed_mesh;
 mod interval;
+mod mat4;
 mod mesh;
 mod plane;
+mod point_cloud;
+mod quad;
+mod quat;
+mod raycast;
 mod sphere;
+mod tagged;
+mod transform;
 mod triangle;
 mod vec3f;
+mod volume;
 
 pub use self::aabb3d::*;
+pub use self::block::*;
+pub use self::bvh::*;
+pub use self::csg::*;
+pub use self::curve::*;
+pub use self::cylinder::*;
+pub use self::heightfield::*;
+pub use self::indexed_mesh::*;
 pub use self::interval::*;
+pub use self::mat4::*;
 pub use self::mesh::*;
 pub use self::plane::*;
+pub use self::point_cloud::*;
+pub use self::quad::*;
+pub use self::quat::*;
+pub use self::raycast::*;
 pub use self::sphere::*;
+pub use self::tagged::*;
+pub use self::transform::*;
 pub use self::triangle::*;
 pub use self::vec3f::*;
+pub use self::volume::*;
+
+#[cfg(feature = "f32")]
+pub type Float = f32;
+
+#[cfg(not(feature = "f32"))]
+pub type Float = f64;
+
+#[cfg(all(feature = "simd", feature = "f32"))]
+pub type FloatX4 = wide::f32x4;
+
+#[cfg(all(feature = "simd", not(feature = "f32")))]
+pub type FloatX4 = wide::f64x4;
@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use crate::geometry::{Aabb3d, HitResult, Hittable, Interval, Ray, Vec3f};
+use crate::geometry::{Aabb3d, Float, HitResult, Hittable, Interval, Ray, Vec3f};
 use crate::material::Material;
 
 pub struct Plane {
@@ -8,6 +8,7 @@ pub struct Plane {
 	pub xbasis: Vec3f,
 	pub ybasis: Vec3f,
 	pub point: Vec3f,
+	pub uv_scale: Float,
 	pub material: Arc<dyn Material>,
 	pub bounds: Aabb3d,
 }
@@ -15,25 +16,36 @@ pub struct Plane {
 impl Plane {
 	pub fn new(xbasis: Vec3f, ybasis: Vec3f, point: Vec3f, material: Arc<dyn Material>) -> Plane {
 		let bounds = Aabb3d::from_corners(
-			Vec3f::new(f64::MIN, f64::MIN, f64::MIN),
-			Vec3f::new(f64::MAX, f64::MAX, f64::MAX),
+			Vec3f::new(Float::MIN, Float::MIN, Float::MIN),
+			Vec3f::new(Float::MAX, Float::MAX, Float::MAX),
 		);
 
 		let normal = Vec3f::cross(&xbasis, &ybasis).unit();
 
+		// UVs are distances along an orthonormal basis, so textures neither shear nor stretch
+		// when the given basis vectors are skewed or not unit length
+		let ybasis = ybasis - xbasis * (Vec3f::dot(&ybasis, &xbasis) / xbasis.lengthsq());
+
 		Plane {
 			normal,
-			xbasis,
-			ybasis,
+			xbasis: xbasis.unit(),
+			ybasis: ybasis.unit(),
 			point,
+			uv_scale: 1.0,
 			material,
 			bounds,
 		}
 	}
+
+	// UVs change by `scale` per unit of distance on the plane
+	pub fn with_uv_scale(mut self, scale: Float) -> Plane {
+		self.uv_scale = scale;
+		self
+	}
 }
 
 impl Hittable for Plane {
-	fn hit(&self, interval: &Interval, ray: &Ray) -> Option<HitResult> {
+	fn hit(&self, interval: &Interval, ray: &Ray) -> Option<HitResult<'_>> {
 		let po = self.point - ray.origin;
 		let t = Vec3f::dot(&po, &self.normal) / Vec3f::dot(&ray.direction, &self.normal);
 
@@ -42,19 +54,37 @@ impl Hittable for Plane {
 
 		if interval.surrounds(t) {
 			Some(HitResult {
-				t: t,
+				t,
 				point: hit_point,
 				normal: self.normal,
-				material: Arc::clone(&self.material),
-				u: Vec3f::dot(&self.xbasis, &offset),
-				v: Vec3f::dot(&self.ybasis, &offset),
+				material: self.material.as_ref(),
+				u: Vec3f::dot(&self.xbasis, &offset) * self.uv_scale,
+				v: Vec3f::dot(&self.ybasis, &offset) * self.uv_scale,
+				color: None,
+				edge: None,
+				id: 0,
+				tangent: Some(self.xbasis),
+				derivatives: Some([self.xbasis / self.uv_scale, self.ybasis / self.uv_scale]),
+				normal_derivatives: None,
+				footprint: None,
 			})
 		} else {
 			None
 		}
 	}
 
+	fn hit_any(&self, interval: &Interval, ray: &Ray) -> bool {
+		let po = self.point - ray.origin;
+		let t = Vec3f::dot(&po, &self.normal) / Vec3f::dot(&ray.direction, &self.normal);
+
+		interval.surrounds(t)
+	}
+
 	fn bounds(&self) -> &Aabb3d {
 		&self.bounds
 	}
+
+	fn is_bounded(&self) -> bool {
+		false
+	}
 }
@@ -0,0 +1,459 @@
+use std::fs;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::error::{RendererError, Result};
+use crate::geometry::{Aabb3d, Float, HitResult, Hittable, Interval, Ray, Vec3f};
+use crate::material::Material;
+use crate::stats;
+
+// Points per leaf of the BVH
+const LEAF_SIZE: usize = 4;
+
+#[derive(Copy, Clone)]
+pub enum SplatShape {
+	// Flat disks facing along the point normals, or towards the ray for points without one
+	Disk,
+	Sphere,
+}
+
+// Point of a scan, the normal is zero when the file has none
+#[derive(Copy, Clone)]
+pub struct Point {
+	pub position: Vec3f,
+	pub normal: Vec3f,
+	pub color: Vec3f,
+}
+
+// Binary tree over ranges of the sorted points, the left child directly follows its parent
+struct Node {
+	bounds: Aabb3d,
+	start: u32,
+	// Leaves have points, inner nodes the index of their right child
+	count: u32,
+	right: u32,
+}
+
+// LiDAR or photogrammetry points rendered as splats of `radius`, colored by the material's
+// texture times the point colors. Points are kept in one flat array under a dedicated BVH, as
+// millions of separate hittables would not fit into memory.
+pub struct PointCloud {
+	points: Vec<Point>,
+	nodes: Vec<Node>,
+	radius: Float,
+	shape: SplatShape,
+	material: Arc<dyn Material>,
+	bounds: Aabb3d,
+}
+
+// Deep enough for a tree over 2^64 leaves
+const STACK_SIZE: usize = 64;
+
+fn point_bounds(points: &[Point], radius: Float) -> Aabb3d {
+	let extent = Vec3f::new(radius, radius, radius);
+	points.iter().fold(Aabb3d::empty(), |bounds, point| {
+		Aabb3d::from_bounds(
+			&bounds,
+			&Aabb3d::from_corners(point.position - extent, point.position + extent),
+		)
+	})
+}
+
+impl PointCloud {
+	pub fn new(
+		mut points: Vec<Point>,
+		radius: Float,
+		shape: SplatShape,
+		material: Arc<dyn Material>,
+	) -> PointCloud {
+		let start = Instant::now();
+		let mut nodes = Vec::new();
+		if !points.is_empty() {
+			build(&mut points, 0, radius, &mut nodes);
+		}
+
+		let bvh_build = start.elapsed();
+		log::info!(
+			"Built a BVH over {} points in {:.0?}",
+			points.len(),
+			bvh_build
+		);
+		// Points are not triangles, only the build time counts
+		stats::add_mesh(0, bvh_build);
+		stats::add_bvh(
+			nodes.len(),
+			nodes.capacity() * size_of::<Node>() + points.capacity() * size_of::<Point>(),
+		);
+
+		PointCloud {
+			bounds: nodes.first().map_or(Aabb3d::empty(), |root| root.bounds),
+			points,
+			nodes,
+			radius,
+			shape,
+			material,
+		}
+	}
+
+	pub fn load(
+		path: &str,
+		radius: Float,
+		shape: SplatShape,
+		material: Arc<dyn Material>,
+	) -> Result<PointCloud> {
+		log::info!("Loading {}", path);
+		Ok(PointCloud::new(read_ply(path)?, radius, shape, material))
+	}
+
+	fn intersect(&self, point: &Point, interval: &Interval, ray: &Ray) -> Option<(Float, Vec3f)> {
+		match self.shape {
+			SplatShape::Sphere => {
+				let oc = ray.origin - point.position;
+				let a = ray.direction.lengthsq();
+				let half_b = Vec3f::dot(&oc, &ray.direction);
+				let c = oc.lengthsq() - self.radius * self.radius;
+				let d = half_b * half_b - a * c;
+				if d < 0.0 {
+					return None;
+				}
+
+				let sqrtd = d.sqrt();
+				let t = [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a]
+					.into_iter()
+					.find(|&t| interval.surrounds(t))?;
+				Some((t, (ray.at(t) - point.position) / self.radius))
+			}
+			SplatShape::Disk => {
+				let normal = if point.normal.lengthsq() > 0.0 {
+					point.normal
+				} else {
+					-ray.direction.unit()
+				};
+
+				let denominator = Vec3f::dot(&ray.direction, &normal);
+				if denominator == 0.0 {
+					return None;
+				}
+
+				let t = Vec3f::dot(&(point.position - ray.origin), &normal) / denominator;
+				if !interval.surrounds(t)
+					|| (ray.at(t) - point.position).lengthsq() > self.radius * self.radius
+				{
+					return None;
+				}
+
+				// Disks have no back, they face whichever way the ray comes from
+				Some((t, if denominator > 0.0 { -normal } else { normal }))
+			}
+		}
+	}
+
+	// Closest point along the ray with the t and normal of the hit
+	fn traverse(&self, interval: &Interval, ray: &Ray, any: bool) -> Option<(usize, Float, Vec3f)> {
+		if self.nodes.is_empty() {
+			return None;
+		}
+
+		let mut closest: Option<(usize, Float, Vec3f)> = None;
+		let mut interval = *interval;
+		let mut stack = [0; STACK_SIZE];
+		let mut depth = 1;
+
+		while depth > 0 {
+			depth -= 1;
+			let index = stack[depth];
+			let node = &self.nodes[index];
+			if !node.bounds.hit(ray, &interval) {
+				continue;
+			}
+
+			if node.count == 0 {
+				stack[depth] = node.right as usize;
+				stack[depth + 1] = index + 1;
+				depth += 2;
+				continue;
+			}
+
+			let start = node.start as usize;
+			for i in start..start + node.count as usize {
+				if let Some((t, normal)) = self.intersect(&self.points[i], &interval, ray) {
+					closest = Some((i, t, normal));
+					if any {
+						return closest;
+					}
+					interval.max = t;
+				}
+			}
+		}
+
+		closest
+	}
+}
+
+// Sorts `points` into the tree below a new node at the end of `nodes`
+fn build(points: &mut [Point], start: usize, radius: Float, nodes: &mut Vec<Node>) {
+	let bounds = point_bounds(points, radius);
+	let index = nodes.len();
+	nodes.push(Node {
+		bounds,
+		start: start as u32,
+		count: points.len() as u32,
+		right: 0,
+	});
+
+	if points.len() <= LEAF_SIZE {
+		return;
+	}
+
+	let axis = (0..3)
+		.max_by(|&a, &b| bounds.axis(a).size().total_cmp(&bounds.axis(b).size()))
+		.unwrap_or(0);
+	let middle = points.len() / 2;
+	points.select_nth_unstable_by(middle, |a, b| a.position[axis].total_cmp(&b.position[axis]));
+
+	let (left, right) = points.split_at_mut(middle);
+	build(left, start, radius, nodes);
+	nodes[index].count = 0;
+	nodes[index].right = nodes.len() as u32;
+	build(right, start + middle, radius, nodes);
+}
+
+impl Hittable for PointCloud {
+	fn hit(&self, interval: &Interval, ray: &Ray) -> Option<HitResult<'_>> {
+		let (index, t, normal) = self.traverse(interval, ray, false)?;
+
+		Some(HitResult {
+			point: ray.at(t),
+			normal,
+			t,
+			material: self.material.as_ref(),
+			u: 0.0,
+			v: 0.0,
+			color: Some(self.points[index].color),
+			edge: None,
+			id: 0,
+			tangent: None,
+			derivatives: None,
+			normal_derivatives: None,
+			footprint: None,
+		})
+	}
+
+	fn hit_any(&self, interval: &Interval, ray: &Ray) -> bool {
+		self.traverse(interval, ray, true).is_some()
+	}
+
+	fn bounds(&self) -> &Aabb3d {
+		&self.bounds
+	}
+}
+
+fn ply_error(path: &str, message: impl Into<String>) -> RendererError {
+	RendererError::Scene {
+		path: Some(path.to_string()),
+		message: message.into(),
+	}
+}
+
+#[derive(Copy, Clone)]
+enum PlyType {
+	I8,
+	U8,
+	I16,
+	U16,
+	I32,
+	U32,
+	F32,
+	F64,
+}
+
+impl PlyType {
+	fn parse(name: &str) -> Option<PlyType> {
+		Some(match name {
+			"char" | "int8" => PlyType::I8,
+			"uchar" | "uint8" => PlyType::U8,
+			"short" | "int16" => PlyType::I16,
+			"ushort" | "uint16" => PlyType::U16,
+			"int" | "int32" => PlyType::I32,
+			"uint" | "uint32" => PlyType::U32,
+			"float" | "float32" => PlyType::F32,
+			"double" | "float64" => PlyType::F64,
+			_ => return None,
+		})
+	}
+
+	fn size(self) -> usize {
+		match self {
+			PlyType::I8 | PlyType::U8 => 1,
+			PlyType::I16 | PlyType::U16 => 2,
+			PlyType::I32 | PlyType::U32 | PlyType::F32 => 4,
+			PlyType::F64 => 8,
+		}
+	}
+
+	// `bytes` holds at least `size` bytes
+	fn read(self, bytes: &[u8]) -> f64 {
+		let b2 = || [bytes[0], bytes[1]];
+		let b4 = || [bytes[0], bytes[1], bytes[2], bytes[3]];
+		match self {
+			PlyType::I8 => bytes[0] as i8 as f64,
+			PlyType::U8 => bytes[0] as f64,
+			PlyType::I16 => i16::from_le_bytes(b2()) as f64,
+			PlyType::U16 => u16::from_le_bytes(b2()) as f64,
+			PlyType::I32 => i32::from_le_bytes(b4()) as f64,
+			PlyType::U32 => u32::from_le_bytes(b4()) as f64,
+			PlyType::F32 => f32::from_le_bytes(b4()) as f64,
+			PlyType::F64 => f64::from_le_bytes([
+				bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
+			]),
+		}
+	}
+
+	// Integer colors are 0 to their maximum, floats 0 to 1
+	fn color_scale(self) -> f64 {
+		match self {
+			PlyType::U8 => 255.0,
+			PlyType::U16 => 65535.0,
+			_ => 1.0,
+		}
+	}
+}
+
+// Vertices of an ASCII or binary little endian PLY file with x, y, z and optionally nx, ny, nz
+// and red, green, blue properties. The vertices have to be the first element, everything after
+// them is ignored.
+fn read_ply(path: &str) -> Result<Vec<Point>> {
+	let data = fs::read(path).map_err(|source| RendererError::Io {
+		path: path.to_string(),
+		source,
+	})?;
+
+	const END: &[u8] = b"end_header";
+	let header_end = data
+		.windows(END.len())
+		.position(|window| window == END)
+		.ok_or_else(|| ply_error(path, "not a PLY file, end_header is missing"))?;
+	let body_start = data[header_end..]
+		.iter()
+		.position(|&byte| byte == b'\n')
+		.map_or(data.len(), |newline| header_end + newline + 1);
+	let header = String::from_utf8_lossy(&data[..header_end]);
+
+	let mut lines = header.lines();
+	if lines.next().map(str::trim) != Some("ply") {
+		return Err(ply_error(path, "not a PLY file"));
+	}
+
+	let mut binary = false;
+	let mut count = None;
+	let mut properties: Vec<(String, PlyType)> = Vec::new();
+	let mut in_vertices = false;
+	for line in lines {
+		let words: Vec<&str> = line.split_whitespace().collect();
+		match words.as_slice() {
+			["format", "ascii", ..] => binary = false,
+			["format", "binary_little_endian", ..] => binary = true,
+			["format", format, ..] => {
+				return Err(ply_error(
+					path,
+					format!("unsupported PLY format {}", format),
+				))
+			}
+			["element", "vertex", n] if count.is_none() => {
+				if !properties.is_empty() {
+					return Err(ply_error(path, "the vertices have to be the first element"));
+				}
+				count = n.parse::<usize>().ok();
+				in_vertices = true;
+			}
+			["element", ..] => {
+				if count.is_none() {
+					return Err(ply_error(path, "the vertices have to be the first element"));
+				}
+				in_vertices = false;
+			}
+			["property", "list", ..] if in_vertices => {
+				return Err(ply_error(
+					path,
+					"list properties of vertices are not supported",
+				))
+			}
+			["property", kind, name] if in_vertices => {
+				let kind = PlyType::parse(kind)
+					.ok_or_else(|| ply_error(path, format!("unknown property type {}", kind)))?;
+				properties.push((name.to_string(), kind));
+			}
+			_ => {}
+		}
+	}
+
+	let count = count.ok_or_else(|| ply_error(path, "no vertex element"))?;
+	let find = |name: &str| properties.iter().position(|(property, _)| property == name);
+	let (Some(x), Some(y), Some(z)) = (find("x"), find("y"), find("z")) else {
+		return Err(ply_error(path, "vertices need x, y and z"));
+	};
+	let normal = find("nx").zip(find("ny")).zip(find("nz"));
+	let color = find("red").zip(find("green")).zip(find("blue"));
+
+	let body = &data[body_start..];
+	let stride: usize = properties.iter().map(|(_, kind)| kind.size()).sum();
+	let text = if binary {
+		Default::default()
+	} else {
+		String::from_utf8_lossy(body)
+	};
+	let mut rows = text.lines().filter(|line| !line.trim().is_empty());
+
+	let mut values = vec![0.0; properties.len()];
+	let mut points = Vec::with_capacity(count);
+	for index in 0..count {
+		if binary {
+			let row = body
+				.get(index * stride..(index + 1) * stride)
+				.ok_or_else(|| ply_error(path, "the file ends before all vertices"))?;
+			let mut offset = 0;
+			for (value, (_, kind)) in values.iter_mut().zip(&properties) {
+				*value = kind.read(&row[offset..]);
+				offset += kind.size();
+			}
+		} else {
+			let row = rows
+				.next()
+				.ok_or_else(|| ply_error(path, "the file ends before all vertices"))?;
+			for (value, word) in values.iter_mut().zip(row.split_whitespace()) {
+				*value = word
+					.parse()
+					.map_err(|_| ply_error(path, format!("invalid number {}", word)))?;
+			}
+		}
+
+		let vector = |x: usize, y: usize, z: usize, scale: f64| {
+			Vec3f::new(
+				(values[x] / scale) as Float,
+				(values[y] / scale) as Float,
+				(values[z] / scale) as Float,
+			)
+		};
+
+		points.push(Point {
+			position: vector(x, y, z, 1.0),
+			normal: match normal {
+				Some(((nx, ny), nz)) => {
+					let normal = vector(nx, ny, nz, 1.0);
+					if normal.lengthsq() > 0.0 {
+						normal.unit()
+					} else {
+						normal
+					}
+				}
+				None => Vec3f::new(0.0, 0.0, 0.0),
+			},
+			color: match color {
+				Some(((r, g), b)) => vector(r, g, b, properties[r].1.color_scale()),
+				None => Vec3f::new(1.0, 1.0, 1.0),
+			},
+		});
+	}
+
+	Ok(points)
+}
@@ -0,0 +1,121 @@
+use std::sync::Arc;
+
+use crate::geometry::{Aabb3d, Float, HitResult, Hittable, HittableGroup, Interval, Ray, Vec3f};
+use crate::material::Material;
+
+// Parallelogram spanned by `u` and `v` from the corner `q`. Like triangles it is one-sided, it
+// faces along u x v.
+pub struct Quad {
+	q: Vec3f,
+	u: Vec3f,
+	v: Vec3f,
+	normal: Vec3f,
+	d: Float,
+	w: Vec3f,
+	material: Arc<dyn Material>,
+	bounds: Aabb3d,
+}
+
+impl Quad {
+	pub fn new(q: Vec3f, u: Vec3f, v: Vec3f, material: Arc<dyn Material>) -> Quad {
+		let n = Vec3f::cross(&u, &v);
+		let normal = n.unit();
+
+		let bounds = Aabb3d::pad(&Aabb3d::from_bounds(
+			&Aabb3d::from_corners(q, q + u + v),
+			&Aabb3d::from_corners(q + u, q + v),
+		));
+
+		Quad {
+			q,
+			u,
+			v,
+			normal,
+			d: Vec3f::dot(&normal, &q),
+			w: n / Vec3f::dot(&n, &n),
+			material,
+			bounds,
+		}
+	}
+
+	// Returns the ray parameter and the coordinates of the hit along u and v
+	fn intersect(&self, interval: &Interval, ray: &Ray) -> Option<(Float, Float, Float)> {
+		let denom = Vec3f::dot(&self.normal, &ray.direction);
+
+		// We either hit the back of the quad, or the ray is parallel to it
+		if denom >= 0.0 {
+			return None;
+		}
+
+		let t = (self.d - Vec3f::dot(&self.normal, &ray.origin)) / denom;
+		if !interval.surrounds(t) {
+			return None;
+		}
+
+		let offset = ray.at(t) - self.q;
+		let alpha = Vec3f::dot(&self.w, &Vec3f::cross(&offset, &self.v));
+		let beta = Vec3f::dot(&self.w, &Vec3f::cross(&self.u, &offset));
+
+		if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+			return None;
+		}
+
+		Some((t, alpha, beta))
+	}
+}
+
+impl Hittable for Quad {
+	fn hit(&self, interval: &Interval, ray: &Ray) -> Option<HitResult<'_>> {
+		let (t, u, v) = self.intersect(interval, ray)?;
+
+		Some(HitResult {
+			point: ray.at(t),
+			normal: self.normal,
+			t,
+			material: self.material.as_ref(),
+			u,
+			v,
+			color: None,
+			edge: Some(u.min(1.0 - u).min(v).min(1.0 - v)),
+			id: 0,
+			tangent: Some(self.u),
+			derivatives: Some([self.u, self.v]),
+			normal_derivatives: None,
+			footprint: None,
+		})
+	}
+
+	fn hit_any(&self, interval: &Interval, ray: &Ray) -> bool {
+		self.intersect(interval, ray).is_some()
+	}
+
+	fn bounds(&self) -> &Aabb3d {
+		&self.bounds
+	}
+}
+
+// Axis aligned box between two opposite corners, its six faces point outwards
+pub fn cuboid(a: Vec3f, b: Vec3f, material: Arc<dyn Material>) -> HittableGroup {
+	let min = a.min(b);
+	let max = a.max(b);
+
+	let dx = Vec3f::new(max.x - min.x, 0.0, 0.0);
+	let dy = Vec3f::new(0.0, max.y - min.y, 0.0);
+	let dz = Vec3f::new(0.0, 0.0, max.z - min.z);
+
+	let faces = [
+		(Vec3f::new(min.x, min.y, max.z), dx, dy),
+		(Vec3f::new(max.x, min.y, max.z), -dz, dy),
+		(Vec3f::new(max.x, min.y, min.z), -dx, dy),
+		(Vec3f::new(min.x, min.y, min.z), dz, dy),
+		(Vec3f::new(min.x, max.y, max.z), dx, -dz),
+		(Vec3f::new(min.x, min.y, min.z), dx, dz),
+	];
+
+	let mut group = HittableGroup::new();
+	for (q, u, v) in faces {
+		group.add(Box::new(Quad::new(q, u, v, Arc::clone(&material))));
+	}
+
+	group
+}
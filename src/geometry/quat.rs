@@ -0,0 +1,259 @@
+use std::ops::Mul;
+
+use crate::geometry::{Float, Mat4, Vec3f};
+
+// Rotation as a unit quaternion, `w` is the scalar part
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quat {
+	pub w: Float,
+	pub x: Float,
+	pub y: Float,
+	pub z: Float,
+}
+
+impl Quat {
+	pub const IDENTITY: Quat = Quat {
+		w: 1.0,
+		x: 0.0,
+		y: 0.0,
+		z: 0.0,
+	};
+
+	// Same convention as Mat4::rotate
+	pub fn from_axis_angle(axis: Vec3f, degrees: Float) -> Quat {
+		let axis = axis.unit();
+		let (sin, cos) = (degrees.to_radians() / 2.0).sin_cos();
+
+		Quat {
+			w: cos,
+			x: axis.x * sin,
+			y: axis.y * sin,
+			z: axis.z * sin,
+		}
+	}
+
+	// Rotation part of a matrix without scaling or shearing
+	pub fn from_mat4(matrix: &Mat4) -> Quat {
+		let m = &matrix.m;
+		let trace = m[0][0] + m[1][1] + m[2][2];
+
+		// Divide by the largest of the four components to keep the result accurate
+		let quat = if trace > 0.0 {
+			let s = (trace + 1.0).sqrt() * 2.0;
+			Quat {
+				w: s / 4.0,
+				x: (m[2][1] - m[1][2]) / s,
+				y: (m[0][2] - m[2][0]) / s,
+				z: (m[1][0] - m[0][1]) / s,
+			}
+		} else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+			let s = (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt() * 2.0;
+			Quat {
+				w: (m[2][1] - m[1][2]) / s,
+				x: s / 4.0,
+				y: (m[0][1] + m[1][0]) / s,
+				z: (m[0][2] + m[2][0]) / s,
+			}
+		} else if m[1][1] > m[2][2] {
+			let s = (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt() * 2.0;
+			Quat {
+				w: (m[0][2] - m[2][0]) / s,
+				x: (m[0][1] + m[1][0]) / s,
+				y: s / 4.0,
+				z: (m[1][2] + m[2][1]) / s,
+			}
+		} else {
+			let s = (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt() * 2.0;
+			Quat {
+				w: (m[1][0] - m[0][1]) / s,
+				x: (m[0][2] + m[2][0]) / s,
+				y: (m[1][2] + m[2][1]) / s,
+				z: s / 4.0,
+			}
+		};
+
+		quat.normalize()
+	}
+
+	pub fn to_mat4(&self) -> Mat4 {
+		let Quat { w, x, y, z } = *self;
+
+		Mat4 {
+			m: [
+				[
+					1.0 - 2.0 * (y * y + z * z),
+					2.0 * (x * y - w * z),
+					2.0 * (x * z + w * y),
+					0.0,
+				],
+				[
+					2.0 * (x * y + w * z),
+					1.0 - 2.0 * (x * x + z * z),
+					2.0 * (y * z - w * x),
+					0.0,
+				],
+				[
+					2.0 * (x * z - w * y),
+					2.0 * (y * z + w * x),
+					1.0 - 2.0 * (x * x + y * y),
+					0.0,
+				],
+				[0.0, 0.0, 0.0, 1.0],
+			],
+		}
+	}
+
+	pub fn dot(a: &Quat, b: &Quat) -> Float {
+		a.w * b.w + a.x * b.x + a.y * b.y + a.z * b.z
+	}
+
+	pub fn normalize(&self) -> Quat {
+		let length = Quat::dot(self, self).sqrt();
+
+		Quat {
+			w: self.w / length,
+			x: self.x / length,
+			y: self.y / length,
+			z: self.z / length,
+		}
+	}
+
+	// The inverse rotation
+	pub fn conjugate(&self) -> Quat {
+		Quat {
+			w: self.w,
+			x: -self.x,
+			y: -self.y,
+			z: -self.z,
+		}
+	}
+
+	pub fn rotate(&self, v: Vec3f) -> Vec3f {
+		let axis = Vec3f::new(self.x, self.y, self.z);
+		let t = Vec3f::cross(&axis, &v) * 2.0;
+
+		v + t * self.w + Vec3f::cross(&axis, &t)
+	}
+
+	// Constant speed interpolation along the shorter arc, `t` from 0 at `a` to 1 at `b`
+	pub fn slerp(a: &Quat, b: &Quat, t: Float) -> Quat {
+		let mut cos = Quat::dot(a, b);
+
+		// q and -q are the same rotation, flip one to avoid going the long way around
+		let b = if cos < 0.0 {
+			cos = -cos;
+			Quat {
+				w: -b.w,
+				x: -b.x,
+				y: -b.y,
+				z: -b.z,
+			}
+		} else {
+			*b
+		};
+
+		// Nearly identical rotations, fall back to a linear blend to avoid dividing by ~0
+		let (wa, wb) = if cos > 0.9995 {
+			(1.0 - t, t)
+		} else {
+			let theta = cos.acos();
+			let sin = theta.sin();
+			(((1.0 - t) * theta).sin() / sin, (t * theta).sin() / sin)
+		};
+
+		Quat {
+			w: a.w * wa + b.w * wb,
+			x: a.x * wa + b.x * wb,
+			y: a.y * wa + b.y * wb,
+			z: a.z * wa + b.z * wb,
+		}
+		.normalize()
+	}
+}
+
+impl Default for Quat {
+	fn default() -> Self {
+		Quat::IDENTITY
+	}
+}
+
+// Hamilton product, `a * b` applies `b` first
+impl Mul for Quat {
+	type Output = Quat;
+
+	fn mul(self, rhs: Quat) -> Quat {
+		Quat {
+			w: self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+			x: self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+			y: self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+			z: self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn assert_mat_eq(a: &Mat4, b: &Mat4) {
+		for (row_a, row_b) in a.m.iter().zip(&b.m) {
+			for (x, y) in row_a.iter().zip(row_b) {
+				assert!((x - y).abs() < 1e-4, "{:?} != {:?}", a, b);
+			}
+		}
+	}
+
+	// q and -q are the same rotation
+	fn assert_quat_eq(a: &Quat, b: &Quat) {
+		assert!(Quat::dot(a, b).abs() > 1.0 - 1e-4, "{:?} != {:?}", a, b);
+	}
+
+	#[test]
+	fn from_mat4_inverts_to_mat4() {
+		// Rotations that take each branch of from_mat4
+		let rotations = [
+			Mat4::rotate(Vec3f::new(0.0, 1.0, 0.0), 30.0),
+			Mat4::rotate(Vec3f::new(1.0, 0.0, 0.0), 170.0),
+			Mat4::rotate(Vec3f::new(0.0, 1.0, 0.0), 180.0),
+			Mat4::rotate(Vec3f::new(0.0, 0.0, 1.0), -160.0),
+			Mat4::rotate(Vec3f::new(1.0, 2.0, 3.0), 75.0)
+				* Mat4::rotate(Vec3f::new(0.0, 1.0, 0.0), 40.0),
+		];
+		for m in &rotations {
+			assert_mat_eq(&Quat::from_mat4(m).to_mat4(), m);
+		}
+	}
+
+	#[test]
+	fn from_axis_angle_matches_mat4_rotate() {
+		let axis = Vec3f::new(1.0, -1.0, 2.0);
+		assert_mat_eq(
+			&Quat::from_axis_angle(axis, 60.0).to_mat4(),
+			&Mat4::rotate(axis, 60.0),
+		);
+	}
+
+	#[test]
+	fn slerp_reaches_both_ends() {
+		let a = Quat::from_axis_angle(Vec3f::new(0.0, 1.0, 0.0), 20.0);
+		let b = Quat::from_axis_angle(Vec3f::new(1.0, 0.0, 1.0), 150.0);
+		assert_quat_eq(&Quat::slerp(&a, &b, 0.0), &a);
+		assert_quat_eq(&Quat::slerp(&a, &b, 1.0), &b);
+
+		// Also when b has to be flipped to take the shorter arc
+		let c = Quat::from_axis_angle(Vec3f::new(0.0, 1.0, 0.0), 350.0);
+		assert_quat_eq(&Quat::slerp(&a, &c, 0.0), &a);
+		assert_quat_eq(&Quat::slerp(&a, &c, 1.0), &c);
+	}
+
+	#[test]
+	fn slerp_takes_the_shorter_arc() {
+		let y = Vec3f::new(0.0, 1.0, 0.0);
+		let halfway = Quat::slerp(
+			&Quat::from_axis_angle(y, 10.0),
+			&Quat::from_axis_angle(y, 350.0),
+			0.5,
+		);
+		assert_quat_eq(&halfway, &Quat::IDENTITY);
+	}
+}
@@ -0,0 +1,51 @@
+use crate::geometry::{Float, Hittable, HittableGroup, Interval, Ray, Vec3f};
+
+// The nearest surface along a ray query, for picking objects and measuring distances outside
+// of rendering
+#[derive(Debug, Clone, Copy)]
+pub struct Intersection {
+	// Set by Tagged, see object_id, 0 for objects without an ID
+	pub id: u32,
+	// From the origin, in scene units
+	pub distance: Float,
+	pub point: Vec3f,
+	// As the primitive reports it, not flipped towards the ray
+	pub normal: Vec3f,
+	pub u: Float,
+	pub v: Float,
+}
+
+impl HittableGroup {
+	// Casts a ray from `origin` along `direction`, which does not need to be normalized, against
+	// the same objects and BVHs that are rendered. Media are seen through, their collisions are
+	// random.
+	pub fn raycast(&self, origin: Vec3f, direction: Vec3f) -> Option<Intersection> {
+		if direction.lengthsq() == 0.0 {
+			return None;
+		}
+
+		let ray = Ray {
+			origin,
+			direction: direction.unit(),
+			time: 0.0,
+		};
+		let mut interval = Interval::new_ray();
+
+		loop {
+			let hit_result = self.hit(&interval, &ray)?;
+			if hit_result.material.is_phase_function() {
+				interval.min = hit_result.t;
+				continue;
+			}
+
+			return Some(Intersection {
+				id: hit_result.id,
+				distance: hit_result.t,
+				point: hit_result.point,
+				normal: hit_result.normal,
+				u: hit_result.u,
+				v: hit_result.v,
+			});
+		}
+	}
+}
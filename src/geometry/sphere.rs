@@ -1,17 +1,18 @@
+use std::f64::consts::PI;
 use std::sync::Arc;
 
-use crate::geometry::{Aabb3d, HitResult, Hittable, Interval, Ray, Vec3f};
+use crate::geometry::{Aabb3d, Float, HitResult, Hittable, Interval, Ray, Solid, Span, Vec3f};
 use crate::material::Material;
 
 pub struct Sphere {
 	center: Vec3f,
-	radius: f64,
+	radius: Float,
 	material: Arc<dyn Material>,
 	bounds: Aabb3d,
 }
 
 impl Sphere {
-	pub fn new(center: Vec3f, radius: f64, material: Arc<dyn Material>) -> Sphere {
+	pub fn new(center: Vec3f, radius: Float, material: Arc<dyn Material>) -> Sphere {
 		let rv = Vec3f::new(radius, radius, radius);
 		let bounds = Aabb3d::from_corners(center - rv, center + rv);
 
@@ -22,10 +23,8 @@ impl Sphere {
 			bounds,
 		}
 	}
-}
 
-impl Hittable for Sphere {
-	fn hit(&self, interval: &Interval, ray: &Ray) -> Option<HitResult> {
+	fn intersect(&self, interval: &Interval, ray: &Ray) -> Option<Float> {
 		let oc = ray.origin - self.center;
 		let a = ray.direction.lengthsq();
 		let half_b = Vec3f::dot(&oc, &ray.direction);
@@ -47,19 +46,83 @@ impl Hittable for Sphere {
 			}
 		}
 
+		Some(root)
+	}
+
+	fn hit_at(&self, ray: &Ray, root: Float) -> HitResult<'_> {
 		let point = ray.at(root);
 
-		Some(HitResult {
+		let normal = (point - self.center) / self.radius;
+
+		// Longitude around the y axis from -x and latitude from the bottom, like an
+		// equirectangular image wrapped around the sphere
+		let u = (Float::atan2(-normal.z, normal.x) + PI as Float) / (2.0 * PI as Float);
+		let v = (-normal.y).clamp(-1.0, 1.0).acos() / PI as Float;
+
+		// Along the circles of latitude and the meridians, undefined at the poles
+		let ring = (normal.x * normal.x + normal.z * normal.z).sqrt();
+		let derivatives = (ring > 1e-6).then(|| {
+			[
+				Vec3f::new(normal.z, 0.0, -normal.x) * (2.0 * PI as Float * self.radius),
+				Vec3f::new(
+					-normal.y * normal.x / ring,
+					ring,
+					-normal.y * normal.z / ring,
+				) * (PI as Float * self.radius),
+			]
+		});
+
+		HitResult {
 			t: root,
-			point: point,
-			normal: (point - self.center) / self.radius,
-			material: Arc::clone(&self.material),
-			u: 0.0,
-			v: 0.0,
-		})
+			point,
+			normal,
+			material: self.material.as_ref(),
+			u,
+			v,
+			color: None,
+			edge: None,
+			id: 0,
+			// Around the y axis, vanishes at the poles
+			tangent: Some(Vec3f::new(normal.z, 0.0, -normal.x)),
+			derivatives,
+			// The normal is the offset from the center over the radius
+			normal_derivatives: derivatives.map(|axes| axes.map(|axis| axis / self.radius)),
+			footprint: None,
+		}
+	}
+}
+
+impl Hittable for Sphere {
+	fn hit(&self, interval: &Interval, ray: &Ray) -> Option<HitResult<'_>> {
+		let root = self.intersect(interval, ray)?;
+		Some(self.hit_at(ray, root))
+	}
+
+	fn hit_any(&self, interval: &Interval, ray: &Ray) -> bool {
+		self.intersect(interval, ray).is_some()
 	}
 
 	fn bounds(&self) -> &Aabb3d {
 		&self.bounds
 	}
 }
+
+impl Solid for Sphere {
+	fn spans(&self, ray: &Ray) -> Vec<Span<'_>> {
+		let oc = ray.origin - self.center;
+		let a = ray.direction.lengthsq();
+		let half_b = Vec3f::dot(&oc, &ray.direction);
+		let c = oc.lengthsq() - self.radius * self.radius;
+		let d = half_b * half_b - a * c;
+
+		if d < 0.0 {
+			return Vec::new();
+		}
+
+		let sqrtd = d.sqrt();
+		vec![Span {
+			enter: self.hit_at(ray, (-half_b - sqrtd) / a),
+			exit: self.hit_at(ray, (-half_b + sqrtd) / a),
+		}]
+	}
+}
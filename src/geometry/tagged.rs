@@ -0,0 +1,84 @@
+use crate::geometry::{Aabb3d, HitResult, Hittable, Interval, Ray};
+
+// Gives every hit on an object the same ID, so the object can be picked out in the ID pass
+pub struct Tagged {
+	object: Box<dyn Hittable>,
+	id: u32,
+}
+
+impl Tagged {
+	pub fn new(object: Box<dyn Hittable>, id: u32) -> Tagged {
+		Tagged { object, id }
+	}
+}
+
+impl Hittable for Tagged {
+	fn hit(&self, interval: &Interval, ray: &Ray) -> Option<HitResult<'_>> {
+		let mut hit_result = self.object.hit(interval, ray)?;
+		hit_result.id = self.id;
+
+		Some(hit_result)
+	}
+
+	fn hit_any(&self, interval: &Interval, ray: &Ray) -> bool {
+		self.object.hit_any(interval, ray)
+	}
+
+	fn bounds(&self) -> &Aabb3d {
+		self.object.bounds()
+	}
+
+	fn is_bounded(&self) -> bool {
+		self.object.is_bounded()
+	}
+}
+
+// Stable ID of an object name, as Cryptomatte computes it: the 32 bit MurmurHash3 of the name,
+// nudged so that its bits read as a finite, normal f32. Never 0, which stands for no object.
+pub fn object_id(name: &str) -> u32 {
+	let mut hash = murmur3(name.as_bytes(), 0);
+
+	let exponent = (hash >> 23) & 0xff;
+	if exponent == 0 || exponent == 0xff {
+		hash ^= 1 << 23;
+	}
+
+	hash
+}
+
+// 32 bit x86 variant
+pub fn murmur3(data: &[u8], seed: u32) -> u32 {
+	const C1: u32 = 0xcc9e2d51;
+	const C2: u32 = 0x1b873593;
+
+	let mix = |k: u32| k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+
+	let mut hash = seed;
+	let mut blocks = data.chunks_exact(4);
+	for block in &mut blocks {
+		let k = u32::from_le_bytes([block[0], block[1], block[2], block[3]]);
+		hash ^= mix(k);
+		hash = hash
+			.rotate_left(13)
+			.wrapping_mul(5)
+			.wrapping_add(0xe6546b64);
+	}
+
+	let tail = blocks.remainder();
+	if !tail.is_empty() {
+		let k = tail
+			.iter()
+			.rev()
+			.fold(0u32, |k, &byte| (k << 8) | byte as u32);
+		hash ^= mix(k);
+	}
+
+	hash ^= data.len() as u32;
+	hash ^= hash >> 16;
+	hash = hash.wrapping_mul(0x85ebca6b);
+	hash ^= hash >> 13;
+	hash = hash.wrapping_mul(0xc2b2ae35);
+	hash ^= hash >> 16;
+
+	hash
+}
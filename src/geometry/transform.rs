@@ -0,0 +1,231 @@
+use crate::geometry::{Aabb3d, Float, HitResult, Hittable, Interval, Mat4, Ray, Vec3f};
+
+// Moves an object by `offset`, rays are moved the other way instead of touching the object
+pub struct Translate {
+	object: Box<dyn Hittable>,
+	offset: Vec3f,
+	bounds: Aabb3d,
+}
+
+impl Translate {
+	pub fn new(object: Box<dyn Hittable>, offset: Vec3f) -> Translate {
+		let bounds = object.bounds();
+		let bounds = Aabb3d::from_intervals(
+			Interval::new(bounds.axis(0).min + offset.x, bounds.axis(0).max + offset.x),
+			Interval::new(bounds.axis(1).min + offset.y, bounds.axis(1).max + offset.y),
+			Interval::new(bounds.axis(2).min + offset.z, bounds.axis(2).max + offset.z),
+		);
+
+		Translate {
+			object,
+			offset,
+			bounds,
+		}
+	}
+
+	fn to_object(&self, ray: &Ray) -> Ray {
+		Ray {
+			origin: ray.origin - self.offset,
+			direction: ray.direction,
+			time: ray.time,
+		}
+	}
+}
+
+impl Hittable for Translate {
+	fn hit(&self, interval: &Interval, ray: &Ray) -> Option<HitResult<'_>> {
+		let mut hit_result = self.object.hit(interval, &self.to_object(ray))?;
+		hit_result.point += self.offset;
+
+		Some(hit_result)
+	}
+
+	fn hit_any(&self, interval: &Interval, ray: &Ray) -> bool {
+		self.object.hit_any(interval, &self.to_object(ray))
+	}
+
+	fn bounds(&self) -> &Aabb3d {
+		&self.bounds
+	}
+
+	fn is_bounded(&self) -> bool {
+		self.object.is_bounded()
+	}
+}
+
+// Rotates an object around the y axis by `angle` degrees
+pub struct RotateY {
+	object: Box<dyn Hittable>,
+	sin: Float,
+	cos: Float,
+	bounds: Aabb3d,
+}
+
+impl RotateY {
+	pub fn new(object: Box<dyn Hittable>, angle: Float) -> RotateY {
+		let (sin, cos) = angle.to_radians().sin_cos();
+
+		// Bounds of the eight rotated corners
+		let bounds = object.bounds();
+		let mut min = Vec3f::new(Float::MAX, Float::MAX, Float::MAX);
+		let mut max = Vec3f::new(Float::MIN, Float::MIN, Float::MIN);
+
+		for i in 0..8 {
+			let rotated = rotate(corner(bounds, i), sin, cos);
+
+			min = min.min(rotated);
+			max = max.max(rotated);
+		}
+
+		RotateY {
+			object,
+			sin,
+			cos,
+			bounds: Aabb3d::from_corners(min, max),
+		}
+	}
+
+	fn to_object(&self, ray: &Ray) -> Ray {
+		Ray {
+			origin: rotate(ray.origin, -self.sin, self.cos),
+			direction: rotate(ray.direction, -self.sin, self.cos),
+			time: ray.time,
+		}
+	}
+}
+
+// One of the eight corners of the box, the bits of `index` pick the side along x, y and z
+fn corner(bounds: &Aabb3d, index: usize) -> Vec3f {
+	let side = |axis: usize| {
+		if index & (1 << axis) == 0 {
+			bounds.axis(axis).min
+		} else {
+			bounds.axis(axis).max
+		}
+	};
+	Vec3f::new(side(0), side(1), side(2))
+}
+
+fn rotate(v: Vec3f, sin: Float, cos: Float) -> Vec3f {
+	Vec3f::new(cos * v.x + sin * v.z, v.y, -sin * v.x + cos * v.z)
+}
+
+impl Hittable for RotateY {
+	fn hit(&self, interval: &Interval, ray: &Ray) -> Option<HitResult<'_>> {
+		let mut hit_result = self.object.hit(interval, &self.to_object(ray))?;
+		hit_result.point = rotate(hit_result.point, self.sin, self.cos);
+		hit_result.normal = rotate(hit_result.normal, self.sin, self.cos);
+		hit_result.tangent = hit_result
+			.tangent
+			.map(|tangent| rotate(tangent, self.sin, self.cos));
+		hit_result.derivatives = hit_result
+			.derivatives
+			.map(|derivatives| derivatives.map(|d| rotate(d, self.sin, self.cos)));
+		hit_result.normal_derivatives = hit_result
+			.normal_derivatives
+			.map(|derivatives| derivatives.map(|d| rotate(d, self.sin, self.cos)));
+
+		Some(hit_result)
+	}
+
+	fn hit_any(&self, interval: &Interval, ray: &Ray) -> bool {
+		self.object.hit_any(interval, &self.to_object(ray))
+	}
+
+	fn bounds(&self) -> &Aabb3d {
+		&self.bounds
+	}
+
+	fn is_bounded(&self) -> bool {
+		self.object.is_bounded()
+	}
+}
+
+// Places an object with an affine matrix, which may rotate, scale and shear it. Rays are brought
+// into the space of the object and the hit back out of it.
+pub struct Transform {
+	object: Box<dyn Hittable>,
+	to_world: Mat4,
+	to_object: Mat4,
+	bounds: Aabb3d,
+}
+
+impl Transform {
+	// None for matrices that cannot be inverted, like a scale of 0
+	pub fn new(object: Box<dyn Hittable>, to_world: Mat4) -> Option<Transform> {
+		let to_object = to_world.inverse()?;
+
+		// Unbounded objects keep their infinite bounds, which the corners would turn into NaN
+		let bounds = if object.is_bounded() {
+			let mut min = Vec3f::new(Float::MAX, Float::MAX, Float::MAX);
+			let mut max = Vec3f::new(Float::MIN, Float::MIN, Float::MIN);
+			for i in 0..8 {
+				let transformed = to_world.transform_point(corner(object.bounds(), i));
+				min = min.min(transformed);
+				max = max.max(transformed);
+			}
+			Aabb3d::from_corners(min, max)
+		} else {
+			*object.bounds()
+		};
+
+		Some(Transform {
+			object,
+			to_world,
+			to_object,
+			bounds,
+		})
+	}
+
+	// The ray in object space with a unit direction, and how much longer a step along it is than
+	// one along the world ray
+	fn to_object(&self, ray: &Ray) -> (Ray, Float) {
+		let direction = self.to_object.transform_vector(ray.direction);
+		let stretch = direction.length();
+
+		let ray = Ray {
+			origin: self.to_object.transform_point(ray.origin),
+			direction: direction / stretch,
+			time: ray.time,
+		};
+		(ray, stretch)
+	}
+}
+
+impl Hittable for Transform {
+	fn hit(&self, interval: &Interval, ray: &Ray) -> Option<HitResult<'_>> {
+		let (object_ray, stretch) = self.to_object(ray);
+		let object_interval = Interval::new(interval.min * stretch, interval.max * stretch);
+		let mut hit_result = self.object.hit(&object_interval, &object_ray)?;
+
+		let normal = |normal: Vec3f| self.to_object.transform_normal(normal);
+		hit_result.t /= stretch;
+		hit_result.point = self.to_world.transform_point(hit_result.point);
+		hit_result.normal = normal(hit_result.normal).unit();
+		hit_result.tangent = hit_result
+			.tangent
+			.map(|tangent| self.to_world.transform_vector(tangent));
+		hit_result.derivatives = hit_result
+			.derivatives
+			.map(|derivatives| derivatives.map(|d| self.to_world.transform_vector(d)));
+		hit_result.normal_derivatives = hit_result
+			.normal_derivatives
+			.map(|derivatives| derivatives.map(normal));
+
+		Some(hit_result)
+	}
+
+	fn hit_any(&self, interval: &Interval, ray: &Ray) -> bool {
+		let (object_ray, stretch) = self.to_object(ray);
+		let object_interval = Interval::new(interval.min * stretch, interval.max * stretch);
+		self.object.hit_any(&object_interval, &object_ray)
+	}
+
+	fn bounds(&self) -> &Aabb3d {
+		&self.bounds
+	}
+
+	fn is_bounded(&self) -> bool {
+		self.object.is_bounded()
+	}
+}
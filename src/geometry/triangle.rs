@@ -1,14 +1,14 @@
 use std::sync::Arc;
 
-use crate::geometry::{Aabb3d, HitResult, Hittable, Interval, Ray, Vec3f};
+use crate::geometry::{Aabb3d, Float, HitResult, Hittable, Interval, Ray, Vec3f};
 use crate::material::Material;
 
 use std::ops::{Add, Mul};
 
 #[derive(Copy, Clone)]
 pub struct Uv {
-	pub u: f64,
-	pub v: f64,
+	pub u: Float,
+	pub v: Float,
 }
 
 impl Add for Uv {
@@ -22,10 +22,10 @@ impl Add for Uv {
 	}
 }
 
-impl Mul<f64> for Uv {
+impl Mul<Float> for Uv {
 	type Output = Uv;
 
-	fn mul(self, rhs: f64) -> Self::Output {
+	fn mul(self, rhs: Float) -> Self::Output {
 		Uv {
 			u: self.u * rhs,
 			v: self.v * rhs,
@@ -34,7 +34,7 @@ impl Mul<f64> for Uv {
 }
 
 impl Uv {
-	pub fn new(u: f64, v: f64) -> Uv {
+	pub fn new(u: Float, v: Float) -> Uv {
 		Uv { u, v }
 	}
 }
@@ -51,6 +51,11 @@ pub struct Triangle {
 	pub normal: Vec3f,
 	pub material: Arc<dyn Material>,
 	pub bounds: Aabb3d,
+
+	// Boxed so triangles without vertex colors stay small
+	pub colors: Option<Box<[Vec3f; 3]>>,
+	// Per vertex normals interpolated for shading, boxed the same way
+	pub normals: Option<Box<[Vec3f; 3]>>,
 }
 
 impl Triangle {
@@ -66,64 +71,73 @@ impl Triangle {
 		let ab = b - a;
 		let ac = c - a;
 
-		let minx = f64::min(f64::min(a.x, b.x), c.x);
-		let miny = f64::min(f64::min(a.y, b.y), c.y);
-		let minz = f64::min(f64::min(a.z, b.z), c.z);
-
-		let maxx = f64::max(f64::max(a.x, b.x), c.x);
-		let maxy = f64::max(f64::max(a.y, b.y), c.y);
-		let maxz = f64::max(f64::max(a.z, b.z), c.z);
-
 		Triangle {
-			a: a,
+			a,
 			ab: b - a,
 			ac: c - a,
-			uv_a: uv_a,
-			uv_b: uv_b,
-			uv_c: uv_c,
+			uv_a,
+			uv_b,
+			uv_c,
 			normal: Vec3f::cross(&ab, &ac),
-			material: material,
-			bounds: Aabb3d::pad(&Aabb3d::from_corners(
-				Vec3f::new(minx, miny, minz),
-				Vec3f::new(maxx, maxy, maxz),
-			)),
+			material,
+			bounds: Aabb3d::pad(&Aabb3d::from_corners(a.min(b).min(c), a.max(b).max(c))),
+			colors: None,
+			normals: None,
 		}
 	}
-}
 
-impl Hittable for Triangle {
-	fn hit(&self, interval: &Interval, ray: &Ray) -> Option<HitResult> {
-		let d = -Vec3f::dot(&self.normal, &ray.direction);
+	pub fn with_colors(mut self, color_a: Vec3f, color_b: Vec3f, color_c: Vec3f) -> Triangle {
+		self.colors = Some(Box::new([color_a, color_b, color_c]));
+		self
+	}
 
-		// We either hit the back of the triangle, or the ray is parallel to the normal
-		if d <= 0.0 {
-			return None;
-		}
+	pub fn with_normals(mut self, normal_a: Vec3f, normal_b: Vec3f, normal_c: Vec3f) -> Triangle {
+		self.normals = Some(Box::new([normal_a, normal_b, normal_c]));
+		self
+	}
 
-		// Calculate the intersection of the ray onto the plane
-		let ap = ray.origin - self.a;
-		let t = Vec3f::dot(&ap, &self.normal) / d;
+	// Solves the edges for the directions of increasing u and v, None for degenerate UVs
+	fn derivatives(&self, ab: Vec3f, ac: Vec3f) -> Option<[Vec3f; 2]> {
+		let (du_b, dv_b) = (self.uv_b.u - self.uv_a.u, self.uv_b.v - self.uv_a.v);
+		let (du_c, dv_c) = (self.uv_c.u - self.uv_a.u, self.uv_c.v - self.uv_a.v);
+		let determinant = du_b * dv_c - du_c * dv_b;
 
-		// Check if the intersection is in the interval
-		if !interval.contains(t) {
+		if determinant.abs() < Float::EPSILON {
 			return None;
 		}
 
-		// Find barycentric coordinates for triangle
-		let e = Vec3f::cross(&(ray.direction * -1.0), &ap);
-		let v = Vec3f::dot(&self.ac, &e) / d;
-
-		if v < 0.0 || v > 1.0 {
-			return None;
-		}
+		Some([
+			(ab * dv_c - ac * dv_b) / determinant,
+			(ac * du_b - ab * du_c) / determinant,
+		])
+	}
 
-		let w = -Vec3f::dot(&self.ab, &e) / d;
+	// Hits the triangle as if its corners were `corners` and its shading normals `normals`, for
+	// meshes that deform while keeping their UVs, colors and material
+	fn hit_moved(
+		&self,
+		interval: &Interval,
+		ray: &Ray,
+		corners: [Vec3f; 3],
+		normals: Option<&[Vec3f; 3]>,
+	) -> Option<HitResult<'_>> {
+		let [a, b, c] = corners;
+		let (ab, ac) = (b - a, c - a);
+		let normal = Vec3f::cross(&ab, &ac);
+		let hit = intersect(a, ab, ac, normal, interval, ray)?;
 
-		// Check barycentric constraints
-		if w < 0.0 || v + w > 1.0 {
-			return None;
-		}
+		Some(self.shade(ray, hit, normal, normals, self.derivatives(ab, ac)))
+	}
 
+	#[inline]
+	fn shade(
+		&self,
+		ray: &Ray,
+		(t, v, w): (Float, Float, Float),
+		normal: Vec3f,
+		normals: Option<&[Vec3f; 3]>,
+		derivatives: Option<[Vec3f; 2]>,
+	) -> HitResult<'_> {
 		// Perform the delayed division
 		let u = 1.0 - v - w;
 
@@ -133,14 +147,173 @@ impl Hittable for Triangle {
 
 		let uv = self.uv_a * u + self.uv_b * v + self.uv_c * w;
 
-		Some(HitResult {
+		HitResult {
 			point: ray.at(t),
-			normal: self.normal.unit(),
+			normal: match normals {
+				Some([a, b, c]) => (*a * u + *b * v + *c * w).unit(),
+				None => normal.unit(),
+			},
 			t,
-			material: Arc::clone(&self.material),
+			material: self.material.as_ref(),
 			u: uv.u,
 			v: uv.v,
-		})
+			color: self
+				.colors
+				.as_deref()
+				.map(|[a, b, c]| *a * u + *b * v + *c * w),
+			edge: Some(u.min(v).min(w)),
+			id: 0,
+			tangent: derivatives.map(|[du, _]| du),
+			derivatives,
+			normal_derivatives: None,
+			footprint: None,
+		}
+	}
+}
+
+// Returns the ray parameter and the barycentric coordinates of b and c on the triangle with the
+// corner `a` and the edges `ab` and `ac`, `normal` being their cross product
+#[inline]
+fn intersect(
+	a: Vec3f,
+	ab: Vec3f,
+	ac: Vec3f,
+	normal: Vec3f,
+	interval: &Interval,
+	ray: &Ray,
+) -> Option<(Float, Float, Float)> {
+	let d = -Vec3f::dot(&normal, &ray.direction);
+
+	// We either hit the back of the triangle, or the ray is parallel to the normal
+	if d <= 0.0 {
+		return None;
+	}
+
+	// Calculate the intersection of the ray onto the plane
+	let ap = ray.origin - a;
+	let t = Vec3f::dot(&ap, &normal) / d;
+
+	// Check if the intersection is in the interval
+	if !interval.contains(t) {
+		return None;
+	}
+
+	// Find barycentric coordinates for triangle
+	let e = Vec3f::cross(&-ray.direction, &ap);
+	let v = Vec3f::dot(&ac, &e) / d;
+
+	if !(0.0..=1.0).contains(&v) {
+		return None;
+	}
+
+	let w = -Vec3f::dot(&ab, &e) / d;
+
+	// Check barycentric constraints
+	if w < 0.0 || v + w > 1.0 {
+		return None;
+	}
+
+	Some((t, v, w))
+}
+
+impl Hittable for Triangle {
+	fn hit(&self, interval: &Interval, ray: &Ray) -> Option<HitResult<'_>> {
+		let hit = intersect(self.a, self.ab, self.ac, self.normal, interval, ray)?;
+
+		Some(self.shade(
+			ray,
+			hit,
+			self.normal,
+			self.normals.as_deref(),
+			self.derivatives(self.ab, self.ac),
+		))
+	}
+
+	fn hit_any(&self, interval: &Interval, ray: &Ray) -> bool {
+		intersect(self.a, self.ab, self.ac, self.normal, interval, ray).is_some()
+	}
+
+	fn bounds(&self) -> &Aabb3d {
+		&self.bounds
+	}
+}
+
+// A triangle of a deforming mesh, its corners and shading normals move through samples spaced
+// evenly over the shutter interval and are interpolated linearly at the time of each ray
+pub struct MovingTriangle {
+	triangle: Triangle,
+	positions: Box<[[Vec3f; 3]]>,
+	normals: Option<Box<[[Vec3f; 3]]>>,
+	bounds: Aabb3d,
+}
+
+impl MovingTriangle {
+	// `triangle` is the first sample and gives the UVs, colors and material, the later ones only
+	// their corners and normals. Shading normals are only kept if every sample has them.
+	pub fn new(triangle: Triangle, later: &[&Triangle]) -> MovingTriangle {
+		let corners = |t: &Triangle| [t.a, t.a + t.ab, t.a + t.ac];
+		let positions: Box<[[Vec3f; 3]]> = std::iter::once(&triangle)
+			.chain(later.iter().copied())
+			.map(corners)
+			.collect();
+		let normals = std::iter::once(&triangle)
+			.chain(later.iter().copied())
+			.map(|t| t.normals.as_deref().copied())
+			.collect();
+
+		let bounds = positions
+			.iter()
+			.flatten()
+			.fold(Aabb3d::empty(), |bounds, &corner| {
+				Aabb3d::from_bounds(&bounds, &Aabb3d::from_corners(corner, corner))
+			});
+
+		MovingTriangle {
+			triangle,
+			positions,
+			normals,
+			bounds: Aabb3d::pad(&bounds),
+		}
+	}
+
+	// The samples around the time of `ray` and how far it is from the first to the second
+	fn at(&self, ray: &Ray) -> (usize, Float) {
+		let last = self.positions.len() - 1;
+		if last == 0 {
+			return (0, 0.0);
+		}
+		let position = ray.time.clamp(0.0, 1.0) * last as Float;
+		let index = (position as usize).min(last - 1);
+		(index, position - index as Float)
+	}
+
+	fn corners(&self, ray: &Ray) -> ([Vec3f; 3], Option<[Vec3f; 3]>) {
+		let (index, s) = self.at(ray);
+		let next = (index + 1).min(self.positions.len() - 1);
+		let lerp = |from: &[Vec3f; 3], to: &[Vec3f; 3]| {
+			[0, 1, 2].map(|corner| from[corner] * (1.0 - s) + to[corner] * s)
+		};
+
+		(
+			lerp(&self.positions[index], &self.positions[next]),
+			self.normals
+				.as_deref()
+				.map(|normals| lerp(&normals[index], &normals[next])),
+		)
+	}
+}
+
+impl Hittable for MovingTriangle {
+	fn hit(&self, interval: &Interval, ray: &Ray) -> Option<HitResult<'_>> {
+		let (corners, normals) = self.corners(ray);
+		self.triangle
+			.hit_moved(interval, ray, corners, normals.as_ref())
+	}
+
+	fn hit_any(&self, interval: &Interval, ray: &Ray) -> bool {
+		let ([a, b, c], _) = self.corners(ray);
+		let (ab, ac) = (b - a, c - a);
+		intersect(a, ab, ac, Vec3f::cross(&ab, &ac), interval, ray).is_some()
 	}
 
 	fn bounds(&self) -> &Aabb3d {
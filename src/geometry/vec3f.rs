@@ -1,32 +1,60 @@
-use rand::Rng;
-use std::ops::{Add, Div, Index, Mul, Sub};
+use std::f64::consts::PI;
+use std::ops::{Add, AddAssign, Div, DivAssign, Index, Mul, MulAssign, Neg, Sub, SubAssign};
+
+use crate::geometry::Float;
+#[cfg(feature = "simd")]
+use crate::geometry::FloatX4;
+use crate::random;
 
 #[derive(Debug, Clone, Copy, Default)]
 pub struct Vec3f {
-	pub x: f64,
-	pub y: f64,
-	pub z: f64,
+	pub x: Float,
+	pub y: Float,
+	pub z: Float,
 }
 
 impl Vec3f {
-	pub const fn new(x: f64, y: f64, z: f64) -> Vec3f {
+	pub const fn new(x: Float, y: Float, z: Float) -> Vec3f {
 		Vec3f { x, y, z }
 	}
 
+	// Uniformly distributed on the unit sphere. Added to a normal it gives the cosine weighted
+	// directions of a Lambertian surface.
 	pub fn rand() -> Vec3f {
-		let mut rand = rand::thread_rng();
-		Vec3f {
-			x: rand.gen_range(-1.0..=1.0),
-			y: rand.gen_range(-1.0..=1.0),
-			z: rand.gen_range(-1.0..=1.0),
+		let z = random::range(-1.0, 1.0);
+		let phi = random::float() * 2.0 * PI as Float;
+		let r = (1.0 - z * z).max(0.0).sqrt();
+		Vec3f::new(r * phi.cos(), r * phi.sin(), z)
+	}
+
+	// Uniformly distributed in the unit disk in the xy plane
+	pub fn random_in_unit_disk() -> Vec3f {
+		loop {
+			let p = Vec3f::new(random::range(-1.0, 1.0), random::range(-1.0, 1.0), 0.0);
+			if p.lengthsq() < 1.0 {
+				return p;
+			}
 		}
-		.unit()
 	}
 
-	pub fn dot(lhs: &Vec3f, rhs: &Vec3f) -> f64 {
+	// True if every component is close to zero, e.g. a scatter direction that would turn into
+	// NaNs when normalized
+	pub fn near_zero(&self) -> bool {
+		const EPSILON: Float = 1e-8;
+		self.x.abs() < EPSILON && self.y.abs() < EPSILON && self.z.abs() < EPSILON
+	}
+
+	#[cfg(not(feature = "simd"))]
+	pub fn dot(lhs: &Vec3f, rhs: &Vec3f) -> Float {
 		lhs.x * rhs.x + lhs.y * rhs.y + lhs.z * rhs.z
 	}
 
+	#[cfg(feature = "simd")]
+	pub fn dot(lhs: &Vec3f, rhs: &Vec3f) -> Float {
+		(lhs.lanes() * rhs.lanes()).reduce_add()
+	}
+
+	#[cfg(not(feature = "simd"))]
 	pub fn cross(a: &Vec3f, b: &Vec3f) -> Vec3f {
 		Vec3f {
 			x: a.y * b.z - a.z * b.y,
@@ -35,17 +63,74 @@ impl Vec3f {
 		}
 	}
 
+	#[cfg(feature = "simd")]
+	pub fn cross(a: &Vec3f, b: &Vec3f) -> Vec3f {
+		let a_yzx = FloatX4::new([a.y, a.z, a.x, 0.0]);
+		let a_zxy = FloatX4::new([a.z, a.x, a.y, 0.0]);
+		let b_yzx = FloatX4::new([b.y, b.z, b.x, 0.0]);
+		let b_zxy = FloatX4::new([b.z, b.x, b.y, 0.0]);
+
+		Vec3f::from_lanes(a_yzx * b_zxy - a_zxy * b_yzx)
+	}
+
+	// The fourth lane is always zero so horizontal sums only see x, y and z
+	#[cfg(feature = "simd")]
+	pub fn lanes(&self) -> FloatX4 {
+		FloatX4::new([self.x, self.y, self.z, 0.0])
+	}
+
+	#[cfg(feature = "simd")]
+	pub fn from_lanes(lanes: FloatX4) -> Vec3f {
+		let [x, y, z, _] = lanes.to_array();
+		Vec3f { x, y, z }
+	}
+
 	pub fn reflect(v: Vec3f, normal: Vec3f) -> Vec3f {
 		let b = Vec3f::dot(&v, &normal);
 		v - normal * 2.0 * b
 	}
 
-	pub fn length(&self) -> f64 {
-		(self.x * self.x + self.y * self.y + self.z * self.z).sqrt()
+	// `v` and `normal` are unit vectors on opposite sides of the surface, `ratio` is the ratio
+	// of the refraction indices
+	pub fn refract(v: Vec3f, normal: Vec3f, ratio: Float) -> Vec3f {
+		let cos_theta = Float::min(-Vec3f::dot(&v, &normal), 1.0);
+		let perpendicular = (v + normal * cos_theta) * ratio;
+		let parallel = normal * -(1.0 - perpendicular.lengthsq()).abs().sqrt();
+		perpendicular + parallel
+	}
+
+	// Component-wise minimum and maximum
+	pub fn min(self, rhs: Vec3f) -> Vec3f {
+		Vec3f::new(self.x.min(rhs.x), self.y.min(rhs.y), self.z.min(rhs.z))
+	}
+
+	pub fn max(self, rhs: Vec3f) -> Vec3f {
+		Vec3f::new(self.x.max(rhs.x), self.y.max(rhs.y), self.z.max(rhs.z))
+	}
+
+	pub fn abs(self) -> Vec3f {
+		Vec3f::new(self.x.abs(), self.y.abs(), self.z.abs())
+	}
+
+	pub fn clamp(self, min: Float, max: Float) -> Vec3f {
+		Vec3f::new(
+			self.x.clamp(min, max),
+			self.y.clamp(min, max),
+			self.z.clamp(min, max),
+		)
 	}
 
-	pub fn lengthsq(&self) -> f64 {
-		self.x * self.x + self.y * self.y + self.z * self.z
+	pub fn length(&self) -> Float {
+		self.lengthsq().sqrt()
+	}
+
+	// False if any component is NaN or infinite
+	pub fn is_finite(&self) -> bool {
+		self.x.is_finite() && self.y.is_finite() && self.z.is_finite()
+	}
+
+	pub fn lengthsq(&self) -> Float {
+		Vec3f::dot(self, self)
 	}
 
 	pub fn unit(&self) -> Vec3f {
@@ -66,14 +151,20 @@ impl Add for Vec3f {
 	}
 }
 
-impl Add<f64> for Vec3f {
+impl Add<Float> for Vec3f {
 	type Output = Vec3f;
 
-	fn add(self, rhs: f64) -> Vec3f {
+	fn add(self, rhs: Float) -> Vec3f {
 		Vec3f::new(self.x + rhs, self.y + rhs, self.z + rhs)
 	}
 }
 
+impl AddAssign for Vec3f {
+	fn add_assign(&mut self, rhs: Vec3f) {
+		*self = *self + rhs;
+	}
+}
+
 impl Sub for Vec3f {
 	type Output = Vec3f;
 
@@ -82,6 +173,20 @@ impl Sub for Vec3f {
 	}
 }
 
+impl SubAssign for Vec3f {
+	fn sub_assign(&mut self, rhs: Vec3f) {
+		*self = *self - rhs;
+	}
+}
+
+impl Neg for Vec3f {
+	type Output = Vec3f;
+
+	fn neg(self) -> Vec3f {
+		Vec3f::new(-self.x, -self.y, -self.z)
+	}
+}
+
 impl Mul for Vec3f {
 	type Output = Vec3f;
 
@@ -90,26 +195,44 @@ impl Mul for Vec3f {
 	}
 }
 
-impl Mul<f64> for Vec3f {
+impl Mul<Float> for Vec3f {
 	type Output = Vec3f;
 
-	fn mul(self, rhs: f64) -> Vec3f {
+	fn mul(self, rhs: Float) -> Vec3f {
 		Vec3f::new(self.x * rhs, self.y * rhs, self.z * rhs)
 	}
 }
 
-impl Div<f64> for Vec3f {
+impl MulAssign for Vec3f {
+	fn mul_assign(&mut self, rhs: Vec3f) {
+		*self = *self * rhs;
+	}
+}
+
+impl MulAssign<Float> for Vec3f {
+	fn mul_assign(&mut self, rhs: Float) {
+		*self = *self * rhs;
+	}
+}
+
+impl Div<Float> for Vec3f {
 	type Output = Vec3f;
 
-	fn div(self, rhs: f64) -> Vec3f {
+	fn div(self, rhs: Float) -> Vec3f {
 		Vec3f::new(self.x / rhs, self.y / rhs, self.z / rhs)
 	}
 }
 
+impl DivAssign<Float> for Vec3f {
+	fn div_assign(&mut self, rhs: Float) {
+		*self = *self / rhs;
+	}
+}
+
 impl Index<usize> for Vec3f {
-	type Output = f64;
+	type Output = Float;
 
-	fn index(&self, index: usize) -> &f64 {
+	fn index(&self, index: usize) -> &Float {
 		match index {
 			0 => &self.x,
 			1 => &self.y,
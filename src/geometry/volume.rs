@@ -0,0 +1,485 @@
+use std::fs;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::error::{RendererError, Result};
+use crate::geometry::{Aabb3d, Float, HitResult, Hittable, Interval, Ray, Vec3f};
+use crate::material::Material;
+use crate::random;
+use crate::stats;
+
+// Voxels along each side of a brick, the size of NanoVDB's leaf nodes
+const BRICK: i32 = 8;
+const BRICK_VOXELS: usize = 512;
+
+// Sparse density grid of bricks of 8^3 voxels, found through a dense table over the bricks
+// around the active voxels. Voxel values sit at integer coordinates.
+pub struct DensityGrid {
+	// First voxel of the table and its size in bricks
+	origin: [i32; 3],
+	dims: [usize; 3],
+	// Brick of every table cell, brick 0 is empty and fills everything without data
+	cells: Vec<u32>,
+	bricks: Vec<[f32; BRICK_VOXELS]>,
+	// Bounds of the active voxels, inclusive
+	min: [i32; 3],
+	max: [i32; 3],
+	maximum: f32,
+}
+
+fn voxel(i: i32, j: i32, k: i32) -> usize {
+	(((i & 7) << 6) | ((j & 7) << 3) | (k & 7)) as usize
+}
+
+impl DensityGrid {
+	// Bricks with the voxel their values start at, and the bounds of the active voxels
+	fn new(
+		bricks: Vec<([i32; 3], [f32; BRICK_VOXELS])>,
+		min: [i32; 3],
+		max: [i32; 3],
+	) -> DensityGrid {
+		let mut origin = [i32::MAX; 3];
+		let mut end = [i32::MIN; 3];
+		for (corner, _) in &bricks {
+			for axis in 0..3 {
+				origin[axis] = origin[axis].min(corner[axis]);
+				end[axis] = end[axis].max(corner[axis] + BRICK);
+			}
+		}
+		let dims = [0, 1, 2].map(|axis| ((end[axis] - origin[axis]) / BRICK).max(0) as usize);
+
+		let mut grid = DensityGrid {
+			origin,
+			dims,
+			cells: vec![0; dims[0] * dims[1] * dims[2]],
+			bricks: vec![[0.0; BRICK_VOXELS]],
+			min,
+			max,
+			maximum: 0.0,
+		};
+
+		for (corner, values) in bricks {
+			let cell = grid
+				.cell(corner[0], corner[1], corner[2])
+				.expect("bricks lie within the table");
+			grid.cells[cell] = grid.bricks.len() as u32;
+			grid.maximum = values
+				.iter()
+				.fold(grid.maximum, |maximum, &v| maximum.max(v));
+			grid.bricks.push(values);
+		}
+
+		stats::add_geometry(
+			grid.cells.capacity() * size_of::<u32>()
+				+ grid.bricks.capacity() * size_of::<[f32; BRICK_VOXELS]>(),
+		);
+		grid
+	}
+
+	pub fn load(path: &str, name: Option<&str>) -> Result<DensityGrid> {
+		log::info!("Loading {}", path);
+		let start = Instant::now();
+		let grid = read_nanovdb(path, name)?;
+
+		log::info!(
+			"Loaded {} bricks of {}x{}x{} voxels in {:.0?}",
+			grid.bricks.len() - 1,
+			grid.max[0] - grid.min[0] + 1,
+			grid.max[1] - grid.min[1] + 1,
+			grid.max[2] - grid.min[2] + 1,
+			start.elapsed()
+		);
+		Ok(grid)
+	}
+
+	fn cell(&self, i: i32, j: i32, k: i32) -> Option<usize> {
+		let [x, y, z] = [(i, 0), (j, 1), (k, 2)].map(|(index, axis)| {
+			let brick = (index - self.origin[axis]).div_euclid(BRICK);
+			(brick >= 0 && (brick as usize) < self.dims[axis]).then_some(brick as usize)
+		});
+		Some((x? * self.dims[1] + y?) * self.dims[2] + z?)
+	}
+
+	fn value(&self, i: i32, j: i32, k: i32) -> f32 {
+		match self.cell(i, j, k) {
+			Some(cell) => self.bricks[self.cells[cell] as usize][voxel(i, j, k)],
+			None => 0.0,
+		}
+	}
+
+	// Trilinear interpolation between the voxels around `p`, in voxel coordinates
+	fn sample(&self, p: Vec3f) -> Float {
+		let (x, y, z) = (p.x.floor(), p.y.floor(), p.z.floor());
+		let (fx, fy, fz) = (p.x - x, p.y - y, p.z - z);
+		let (i, j, k) = (x as i32, y as i32, z as i32);
+
+		let mut sum = 0.0;
+		for (di, wx) in [(0, 1.0 - fx), (1, fx)] {
+			for (dj, wy) in [(0, 1.0 - fy), (1, fy)] {
+				for (dk, wz) in [(0, 1.0 - fz), (1, fz)] {
+					sum += wx * wy * wz * self.value(i + di, j + dj, k + dk) as Float;
+				}
+			}
+		}
+
+		sum
+	}
+}
+
+// Participating medium filling a box with the density of a grid, for smoke and clouds. The
+// grid's active voxels are stretched over the box from `corner` spanning `size`, and the voxel
+// values are scaled by `density`, the extinction per unit length. Rays are scattered by the
+// material, usually Isotropic, where they collide with the medium.
+pub struct Volume {
+	grid: DensityGrid,
+	corner: Vec3f,
+	size: Vec3f,
+	density: Float,
+	// Upper bound of the extinction anywhere in the box for delta and ratio tracking
+	majorant: Float,
+	material: Arc<dyn Material>,
+	bounds: Aabb3d,
+}
+
+impl Volume {
+	pub fn new(
+		grid: DensityGrid,
+		corner: Vec3f,
+		size: Vec3f,
+		density: Float,
+		material: Arc<dyn Material>,
+	) -> Volume {
+		let far = corner + size;
+
+		Volume {
+			majorant: grid.maximum as Float * density,
+			grid,
+			corner: corner.min(far),
+			size: size.abs(),
+			density,
+			material,
+			bounds: Aabb3d::pad(&Aabb3d::from_corners(corner, far)),
+		}
+	}
+
+	// Extinction at a point inside the box
+	fn extinction(&self, point: Vec3f) -> Float {
+		let local = point - self.corner;
+		let index = Vec3f::new(
+			self.to_index(local.x / self.size.x, 0),
+			self.to_index(local.y / self.size.y, 1),
+			self.to_index(local.z / self.size.z, 2),
+		);
+		self.grid.sample(index) * self.density
+	}
+
+	// Voxel coordinate of a fraction along an axis of the box, whose faces are the outer faces
+	// of the outermost voxels
+	fn to_index(&self, fraction: Float, axis: usize) -> Float {
+		let (min, max) = (self.grid.min[axis] as Float, self.grid.max[axis] as Float);
+		min - 0.5 + fraction * (max - min + 1.0)
+	}
+
+	// Where the ray is inside the box within `interval`
+	fn clip(&self, interval: &Interval, ray: &Ray) -> Option<(Float, Float)> {
+		let (mut near, mut far) = (interval.min, interval.max);
+		let far_corner = self.corner + self.size;
+
+		for axis in 0..3 {
+			let (origin, direction) = (ray.origin[axis], ray.direction[axis]);
+			if direction == 0.0 {
+				if origin < self.corner[axis] || origin > far_corner[axis] {
+					return None;
+				}
+				continue;
+			}
+
+			let t0 = (self.corner[axis] - origin) / direction;
+			let t1 = (far_corner[axis] - origin) / direction;
+			near = near.max(t0.min(t1));
+			far = far.min(t0.max(t1));
+		}
+
+		(near < far).then_some((near, far))
+	}
+
+	// Distance to the next tentative collision against the majorant
+	fn step(&self, length: Float) -> Float {
+		-(1.0 - random::float()).ln() / (self.majorant * length)
+	}
+
+	// Fraction of light that passes through the medium along the ray within `interval`,
+	// estimated by ratio tracking
+	pub fn transmittance(&self, interval: &Interval, ray: &Ray) -> Float {
+		let Some((mut t, far)) = self.clip(interval, ray) else {
+			return 1.0;
+		};
+		if self.majorant <= 0.0 {
+			return 1.0;
+		}
+
+		let length = ray.direction.length();
+		let mut transmittance = 1.0;
+		loop {
+			t += self.step(length);
+			if t >= far {
+				return transmittance;
+			}
+			transmittance *= 1.0 - self.extinction(ray.at(t)) / self.majorant;
+
+			// Russian roulette once little light is left, so dense media end early
+			if transmittance < 0.1 {
+				if random::float() < 0.5 {
+					return 0.0;
+				}
+				transmittance *= 2.0;
+			}
+		}
+	}
+}
+
+impl Hittable for Volume {
+	// Delta tracking, a collision with the medium is accepted with the ratio of the extinction
+	// there to the majorant
+	fn hit(&self, interval: &Interval, ray: &Ray) -> Option<HitResult<'_>> {
+		let (mut t, far) = self.clip(interval, ray)?;
+		if self.majorant <= 0.0 {
+			return None;
+		}
+
+		let length = ray.direction.length();
+		loop {
+			t += self.step(length);
+			if t >= far {
+				return None;
+			}
+
+			let point = ray.at(t);
+			if random::float() * self.majorant < self.extinction(point) {
+				return Some(HitResult {
+					point,
+					// Media have no surface, the normal only faces the ray
+					normal: -ray.direction / length,
+					t,
+					material: self.material.as_ref(),
+					u: 0.0,
+					v: 0.0,
+					color: None,
+					edge: None,
+					id: 0,
+					tangent: None,
+					derivatives: None,
+					normal_derivatives: None,
+					footprint: None,
+				});
+			}
+		}
+	}
+
+	// Blocked with the probability that the light is absorbed or scattered on the way
+	fn hit_any(&self, interval: &Interval, ray: &Ray) -> bool {
+		random::float() >= self.transmittance(interval, ray)
+	}
+
+	fn bounds(&self) -> &Aabb3d {
+		&self.bounds
+	}
+}
+
+fn vdb_error(path: &str, message: impl Into<String>) -> RendererError {
+	RendererError::Scene {
+		path: Some(path.to_string()),
+		message: message.into(),
+	}
+}
+
+fn bytes<const N: usize>(data: &[u8], offset: usize) -> Option<[u8; N]> {
+	data.get(offset..offset.checked_add(N)?)?.try_into().ok()
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+	bytes(data, offset).map(u16::from_le_bytes)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+	bytes(data, offset).map(u32::from_le_bytes)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+	bytes(data, offset).map(u64::from_le_bytes)
+}
+
+fn read_i32(data: &[u8], offset: usize) -> Option<i32> {
+	bytes(data, offset).map(i32::from_le_bytes)
+}
+
+fn read_i64(data: &[u8], offset: usize) -> Option<i64> {
+	bytes(data, offset).map(i64::from_le_bytes)
+}
+
+fn read_f32(data: &[u8], offset: usize) -> Option<f32> {
+	bytes(data, offset).map(f32::from_le_bytes)
+}
+
+// The size of the NanoVDB structures that the reader skips over
+const FILE_HEADER: usize = 16;
+const FILE_METADATA: usize = 176;
+const GRID_DATA: usize = 672;
+const ROOT_DATA: usize = 64;
+const ROOT_TILE: usize = 32;
+const LEAF_VALUES: usize = 96;
+const GRID_TYPE_FLOAT: u32 = 1;
+
+// First float grid of an uncompressed NanoVDB file, or the one called `name`. OpenVDB files
+// can be converted with `nanovdb_convert`.
+fn read_nanovdb(path: &str, name: Option<&str>) -> Result<DensityGrid> {
+	let data = fs::read(path).map_err(|source| RendererError::Io {
+		path: path.to_string(),
+		source,
+	})?;
+	let corrupt = || vdb_error(path, "the NanoVDB file is truncated or corrupt");
+
+	if !data.starts_with(b"NanoVDB") {
+		return Err(vdb_error(path, "not a NanoVDB file"));
+	}
+
+	let count = read_u16(&data, 12).ok_or_else(corrupt)?;
+	let mut offset = FILE_HEADER;
+	for _ in 0..count {
+		let grid_size = read_u64(&data, offset).ok_or_else(corrupt)? as usize;
+		let file_size = read_u64(&data, offset + 8).ok_or_else(corrupt)? as usize;
+		let grid_type = read_u32(&data, offset + 32).ok_or_else(corrupt)?;
+		let name_size = read_u32(&data, offset + 136).ok_or_else(corrupt)? as usize;
+		let codec = read_u16(&data, offset + 168).ok_or_else(corrupt)?;
+
+		let name_start = offset + FILE_METADATA;
+		let grid_name = data
+			.get(name_start..name_start + name_size)
+			.ok_or_else(corrupt)?;
+		let grid_name = String::from_utf8_lossy(grid_name);
+		let grid_name = grid_name.trim_end_matches('\0');
+		let start = name_start + name_size;
+		offset = start + file_size;
+
+		if name.is_some_and(|name| name != grid_name) {
+			continue;
+		}
+		if grid_type != GRID_TYPE_FLOAT {
+			if name.is_some() {
+				return Err(vdb_error(
+					path,
+					format!("grid {} is not a float grid", grid_name),
+				));
+			}
+			continue;
+		}
+		if codec != 0 {
+			return Err(vdb_error(
+				path,
+				format!(
+					"grid {} is compressed, only uncompressed grids are supported",
+					grid_name
+				),
+			));
+		}
+
+		let grid = data.get(start..start + grid_size).ok_or_else(corrupt)?;
+		let grid = read_grid(grid).ok_or_else(corrupt)?;
+		if grid.bricks.len() == 1 {
+			return Err(vdb_error(path, format!("grid {} is empty", grid_name)));
+		}
+		return Ok(grid);
+	}
+
+	Err(vdb_error(
+		path,
+		match name {
+			Some(name) => format!("no grid called {}", name),
+			None => "no float grid".to_string(),
+		},
+	))
+}
+
+fn read_grid(grid: &[u8]) -> Option<DensityGrid> {
+	let tree = GRID_DATA;
+	let root = tree + read_u64(grid, tree + 24)? as usize;
+	let min = [0, 4, 8].map(|offset| read_i32(grid, root + offset));
+	let max = [12, 16, 20].map(|offset| read_i32(grid, root + offset));
+	let table_size = read_u32(grid, root + 24)? as usize;
+
+	let mut bricks = Vec::new();
+	for index in 0..table_size {
+		let tile = root + ROOT_DATA + index * ROOT_TILE;
+		let child = read_i64(grid, tile + 8)?;
+		if child != 0 {
+			let upper = root.checked_add_signed(child as isize)?;
+			// Nodes only store the bounds of their active voxels, which lie within the node
+			let origin = [0, 4, 8].map(|offset| read_i32(grid, upper + offset).map(|c| c & !4095));
+			let origin = [origin[0]?, origin[1]?, origin[2]?];
+			read_internal(grid, upper, 5, origin, &mut bricks)?;
+		}
+	}
+
+	Some(DensityGrid::new(
+		bricks,
+		[min[0]?, min[1]?, min[2]?],
+		[max[0]?, max[1]?, max[2]?],
+	))
+}
+
+// Bricks below an internal node with 2^log2dim children along each side, the upper nodes have
+// 32 lower nodes of 16 leaves each
+fn read_internal(
+	grid: &[u8],
+	node: usize,
+	log2dim: u32,
+	origin: [i32; 3],
+	bricks: &mut Vec<([i32; 3], [f32; BRICK_VOXELS])>,
+) -> Option<()> {
+	let children = 1usize << (3 * log2dim);
+	let mask_size = children / 8;
+	let child_mask = node + 32 + mask_size;
+	let table = (node + 32 + 2 * mask_size + 16).next_multiple_of(32);
+	// Voxels along each side of a child
+	let child_dim = if log2dim == 5 { 128 } else { BRICK };
+
+	let side = (1 << log2dim) - 1;
+
+	for n in 0..children {
+		let offset = [n >> (2 * log2dim), (n >> log2dim) & side, n & side];
+		let corner = [0, 1, 2].map(|axis| origin[axis] + offset[axis] as i32 * child_dim);
+		let entry = table + n * 8;
+
+		let word = read_u64(grid, child_mask + n / 64 * 8)?;
+		if word & (1 << (n % 64)) != 0 {
+			let child = node.checked_add_signed(read_i64(grid, entry)? as isize)?;
+			if log2dim == 5 {
+				read_internal(grid, child, 4, corner, bricks)?;
+			} else {
+				let mut values = [0.0; BRICK_VOXELS];
+				for (i, value) in values.iter_mut().enumerate() {
+					*value = read_f32(grid, child + LEAF_VALUES + i * 4)?;
+				}
+				bricks.push((corner, values));
+			}
+			continue;
+		}
+
+		// Tiles fill the whole child with one value
+		let value = read_f32(grid, entry)?;
+		if value != 0.0 {
+			for x in (0..child_dim).step_by(BRICK as usize) {
+				for y in (0..child_dim).step_by(BRICK as usize) {
+					for z in (0..child_dim).step_by(BRICK as usize) {
+						bricks.push((
+							[corner[0] + x, corner[1] + y, corner[2] + z],
+							[value; BRICK_VOXELS],
+						));
+					}
+				}
+			}
+		}
+	}
+
+	Some(())
+}
@@ -0,0 +1,72 @@
+use crate::geometry::{Float, Hittable, HittableGroup, Interval, Ray, RayDifferentials, Vec3f};
+use crate::integrator::{Integrator, Sample};
+use crate::stats;
+
+// Shades the first hit by whether a cosine distributed ray escapes within `max_distance`,
+// ignoring materials and lights. Rays that miss the scene are white.
+pub struct AmbientOcclusion {
+	pub max_distance: Float,
+}
+
+impl AmbientOcclusion {
+	pub fn new(max_distance: Float) -> AmbientOcclusion {
+		AmbientOcclusion { max_distance }
+	}
+}
+
+impl Default for AmbientOcclusion {
+	fn default() -> Self {
+		AmbientOcclusion::new(Float::MAX)
+	}
+}
+
+impl Integrator for AmbientOcclusion {
+	fn li(
+		&self,
+		ray: &Ray,
+		world: &HittableGroup,
+		_: Vec3f,
+		_: Float,
+		_: Option<RayDifferentials>,
+	) -> Sample {
+		let white = Vec3f::new(1.0, 1.0, 1.0);
+
+		stats::count_ray();
+		let Some(hit_result) = world.hit(&Interval::new_ray(), ray) else {
+			return Sample::opaque(white);
+		};
+
+		if hit_result.material.is_holdout() {
+			return Sample::HOLDOUT.with_id(hit_result.id);
+		}
+
+		// Occlusion is measured on the side the camera sees
+		let normal = if Vec3f::dot(&hit_result.normal, &ray.direction) > 0.0 {
+			-hit_result.normal
+		} else {
+			hit_result.normal
+		};
+
+		let mut direction = normal + Vec3f::rand();
+		if direction.near_zero() {
+			direction = normal;
+		}
+
+		let occlusion_ray = Ray {
+			origin: hit_result.point,
+			direction: direction.unit(),
+			time: ray.time,
+		};
+		let interval = Interval::new(Interval::new_ray().min, self.max_distance);
+
+		stats::count_ray();
+		stats::count_bounce();
+		let color = if world.hit_any(&interval, &occlusion_ray) {
+			Vec3f::new(0.0, 0.0, 0.0)
+		} else {
+			white
+		};
+
+		Sample::opaque(color).with_id(hit_result.id)
+	}
+}
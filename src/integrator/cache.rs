@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use crate::geometry::{Float, Vec3f};
+use crate::integrator::MAX_LIGHT_GROUPS;
+
+// Light arriving at a point of a diffuse surface from everything but the lights that are sampled
+// directly, as the mean radiance of cosine distributed rays. How far a record is used scales
+// with `radius`, the harmonic mean distance its rays traveled, which is small in corners and
+// close to other surfaces where the light changes quickly.
+#[derive(Debug, Clone, Copy)]
+pub struct Record {
+	pub point: Vec3f,
+	pub normal: Vec3f,
+	pub radius: Float,
+	pub radiance: Vec3f,
+	pub groups: [Vec3f; MAX_LIGHT_GROUPS],
+}
+
+#[derive(Default)]
+struct Records {
+	entries: Vec<Record>,
+	// Indices of the records reaching into each cell of a grid, by the log2 of the cell size and
+	// the cell. A record is in the cells of the one size that is at least twice the distance it
+	// is valid within, which it overlaps at most 8 of.
+	cells: HashMap<(i32, [i64; 3]), Vec<usize>>,
+	levels: Vec<i32>,
+}
+
+// Diffuse light cached by Ward's irradiance caching, shared by all threads of a render and built
+// up as they go. A point takes the weighted mean of the records close enough to it and facing
+// the same way, and a new record is computed where there are none. `accuracy` is the largest
+// error allowed, lower values place the records more densely.
+pub struct IrradianceCache {
+	pub accuracy: Float,
+	pub rays: u32,
+	records: RwLock<Records>,
+}
+
+impl IrradianceCache {
+	pub fn new(accuracy: Float, rays: u32) -> IrradianceCache {
+		IrradianceCache {
+			accuracy: accuracy.max(1e-3),
+			rays: rays.max(1),
+			records: RwLock::new(Records::default()),
+		}
+	}
+
+	fn weight(&self, record: &Record, point: Vec3f, normal: Vec3f) -> Option<Float> {
+		let offset = point - record.point;
+		// Records on surfaces in front of the point see light it does not
+		if Vec3f::dot(&offset, &(normal + record.normal)) < -0.01 * record.radius {
+			return None;
+		}
+
+		let error = offset.length() / record.radius
+			+ (1.0 - Vec3f::dot(&normal, &record.normal)).max(0.0).sqrt();
+		// Falls off to 0 where the record stops being used, instead of Ward's 1 / error, so that
+		// the records' edges do not show
+		(error < self.accuracy).then(|| 1.0 / error.max(1e-6) - 1.0 / self.accuracy)
+	}
+
+	// The records around `point` on a surface facing `normal` interpolated, None if there are
+	// none close enough
+	pub fn lookup(
+		&self,
+		point: Vec3f,
+		normal: Vec3f,
+	) -> Option<(Vec3f, [Vec3f; MAX_LIGHT_GROUPS])> {
+		let records = self.records.read().unwrap();
+		let mut total = 0.0;
+		let mut radiance = Vec3f::new(0.0, 0.0, 0.0);
+		let mut groups = [Vec3f::new(0.0, 0.0, 0.0); MAX_LIGHT_GROUPS];
+
+		for &level in &records.levels {
+			let Some(indices) = records.cells.get(&(level, cell(point, level))) else {
+				continue;
+			};
+			for &index in indices {
+				let record = &records.entries[index];
+				let Some(weight) = self.weight(record, point, normal) else {
+					continue;
+				};
+				total += weight;
+				radiance += record.radiance * weight;
+				for (group, light) in groups.iter_mut().zip(record.groups) {
+					*group += light * weight;
+				}
+			}
+		}
+
+		if total <= 0.0 {
+			return None;
+		}
+		Some((radiance / total, groups.map(|group| group / total)))
+	}
+
+	pub fn insert(&self, record: Record) {
+		let reach = record.radius * self.accuracy;
+		let level = (2.0 * reach).log2().ceil() as i32;
+		let [low, high] = [-reach, reach]
+			.map(|offset| cell(record.point + Vec3f::new(offset, offset, offset), level));
+
+		let mut records = self.records.write().unwrap();
+		let index = records.entries.len();
+		records.entries.push(record);
+		if !records.levels.contains(&level) {
+			records.levels.push(level);
+		}
+		for x in low[0]..=high[0] {
+			for y in low[1]..=high[1] {
+				for z in low[2]..=high[2] {
+					records
+						.cells
+						.entry((level, [x, y, z]))
+						.or_default()
+						.push(index);
+				}
+			}
+		}
+	}
+
+	// Forgets all records, for when the scene has changed
+	pub fn clear(&self) {
+		*self.records.write().unwrap() = Records::default();
+	}
+}
+
+// The cell of the grid with cells 2^`level` across that `point` is in
+fn cell(point: Vec3f, level: i32) -> [i64; 3] {
+	let size = (2.0 as Float).powi(level);
+	[point.x, point.y, point.z].map(|coordinate| (coordinate / size).floor() as i64)
+}
@@ -0,0 +1,118 @@
+use std::str::FromStr;
+
+use crate::geometry::{Float, Hittable, HittableGroup, Interval, Ray, RayDifferentials, Vec3f};
+use crate::integrator::{Integrator, Sample};
+use crate::stats;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DebugMode {
+	// Geometric normal as is, so inverted normals show up as the opposite color
+	Normals,
+	// Brightness falls off with the hit distance, half at `DEPTH_SCALE`
+	Depth,
+	// Texture coordinates wrapped to [0, 1) in red and green
+	Uv,
+	// Edges of triangles and quads in white over gray, other primitives stay gray
+	Wireframe,
+	// BVH nodes plus primitives tested for the camera ray, from blue (none) to red at
+	// `HEATMAP_SCALE` or more
+	Heatmap,
+}
+
+pub const MODES: &[&str] = &["normals", "depth", "uv", "wireframe", "heatmap"];
+
+const DEPTH_SCALE: Float = 10.0;
+const EDGE_WIDTH: Float = 0.03;
+const HEATMAP_SCALE: Float = 200.0;
+
+impl FromStr for DebugMode {
+	type Err = String;
+
+	fn from_str(value: &str) -> Result<Self, Self::Err> {
+		match value {
+			"normals" => Ok(DebugMode::Normals),
+			"depth" => Ok(DebugMode::Depth),
+			"uv" => Ok(DebugMode::Uv),
+			"wireframe" => Ok(DebugMode::Wireframe),
+			"heatmap" => Ok(DebugMode::Heatmap),
+			_ => Err(format!(
+				"unknown mode '{}', expected one of {}",
+				value,
+				MODES.join(", ")
+			)),
+		}
+	}
+}
+
+// Blue, cyan, green, yellow, red for t from 0 to 1
+pub fn heat(t: Float) -> Vec3f {
+	let t = t.clamp(0.0, 1.0) * 4.0;
+
+	match t {
+		t if t < 1.0 => Vec3f::new(0.0, t, 1.0),
+		t if t < 2.0 => Vec3f::new(0.0, 1.0, 2.0 - t),
+		t if t < 3.0 => Vec3f::new(t - 2.0, 1.0, 0.0),
+		t => Vec3f::new(1.0, 4.0 - t, 0.0),
+	}
+}
+
+// False color view of the first hit of every camera ray, misses are black apart from the
+// heatmap, which also shows the cost of rays that miss
+pub struct DebugView {
+	pub mode: DebugMode,
+}
+
+impl DebugView {
+	pub fn new(mode: DebugMode) -> DebugView {
+		DebugView { mode }
+	}
+}
+
+impl Integrator for DebugView {
+	fn li(
+		&self,
+		ray: &Ray,
+		world: &HittableGroup,
+		_: Vec3f,
+		_: Float,
+		_: Option<RayDifferentials>,
+	) -> Sample {
+		Sample::opaque(self.color(ray, world))
+	}
+}
+
+impl DebugView {
+	fn color(&self, ray: &Ray, world: &HittableGroup) -> Vec3f {
+		stats::count_ray();
+		let (nodes, primitives) = stats::traversal();
+		let hit = world.hit(&Interval::new_ray(), ray);
+
+		if self.mode == DebugMode::Heatmap {
+			let (nodes_after, primitives_after) = stats::traversal();
+			let tests = (nodes_after - nodes) + (primitives_after - primitives);
+			return heat(tests as Float / HEATMAP_SCALE);
+		}
+
+		let Some(hit_result) = hit else {
+			return Vec3f::new(0.0, 0.0, 0.0);
+		};
+
+		match self.mode {
+			DebugMode::Normals => (hit_result.normal + 1.0) * 0.5,
+			DebugMode::Depth => {
+				let depth = DEPTH_SCALE / (DEPTH_SCALE + hit_result.t);
+				Vec3f::new(depth, depth, depth)
+			}
+			DebugMode::Uv => Vec3f::new(
+				hit_result.u.rem_euclid(1.0),
+				hit_result.v.rem_euclid(1.0),
+				0.0,
+			),
+			DebugMode::Wireframe => match hit_result.edge {
+				Some(edge) if edge < EDGE_WIDTH => Vec3f::new(1.0, 1.0, 1.0),
+				_ => Vec3f::new(0.2, 0.2, 0.2),
+			},
+			DebugMode::Heatmap => unreachable!(),
+		}
+	}
+}
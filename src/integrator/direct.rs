@@ -0,0 +1,139 @@
+use crate::geometry::{Float, Hittable, HittableGroup, Interval, Ray, RayDifferentials, Vec3f};
+use crate::integrator::{
+	footprint, footprint_of, Environment, Fog, Integrator, LightTree, PointLight, Sample, Sky,
+};
+use crate::stats;
+
+// Emission at the first hit plus the light that one scattered ray picks up from an emitter or
+// the background, without following it any further. Indirect light is missing entirely.
+#[derive(Default)]
+pub struct DirectLighting {
+	pub fog: Option<Fog>,
+	pub sky: Option<Sky>,
+	pub environment: Option<Environment>,
+	pub lights: LightTree,
+}
+
+impl DirectLighting {
+	pub fn with_fog(mut self, fog: Fog) -> DirectLighting {
+		self.fog = Some(fog);
+		self
+	}
+
+	// The sky replaces the background and its sun is sampled directly
+	pub fn with_sky(mut self, sky: Sky) -> DirectLighting {
+		self.sky = Some(sky);
+		self
+	}
+
+	// The environment replaces the background and is sampled directly like the sun
+	pub fn with_environment(mut self, environment: Environment) -> DirectLighting {
+		self.environment = Some(environment);
+		self
+	}
+
+	pub fn with_lights(mut self, lights: Vec<PointLight>) -> DirectLighting {
+		self.lights = LightTree::new(lights);
+		self
+	}
+
+	// What the point lights scatter towards the camera from the fog within `distance`
+	fn in_scattered(&self, world: &HittableGroup, ray: &Ray, distance: Float) -> Vec3f {
+		match &self.fog {
+			Some(fog) => self.lights.in_scattered(world, ray, distance, fog),
+			None => Vec3f::new(0.0, 0.0, 0.0),
+		}
+	}
+
+	fn escaped(&self, ray: &Ray, background: Vec3f, sun: Float) -> Vec3f {
+		let light = match (&self.sky, &self.environment) {
+			(Some(sky), _) => sky.radiance(ray.direction, sun),
+			(None, Some(environment)) => environment.radiance(ray.direction, sun),
+			(None, None) => background,
+		};
+		self.fogged(light, Float::INFINITY)
+	}
+
+	fn fogged(&self, color: Vec3f, distance: Float) -> Vec3f {
+		match &self.fog {
+			Some(fog) => fog.apply(color, distance),
+			None => color,
+		}
+	}
+
+	fn emitted(&self, ray: &Ray, world: &HittableGroup, background: Vec3f, sun: Float) -> Vec3f {
+		stats::count_ray();
+		match world.hit(&Interval::new_ray(), ray) {
+			Some(hit_result) => self.fogged(
+				hit_result.material.emit(hit_result.u, hit_result.v),
+				hit_result.t * ray.direction.length(),
+			),
+			None => self.escaped(ray, background, sun),
+		}
+	}
+}
+
+impl Integrator for DirectLighting {
+	fn li(
+		&self,
+		ray: &Ray,
+		world: &HittableGroup,
+		background: Vec3f,
+		spread: Float,
+		differentials: Option<RayDifferentials>,
+	) -> Sample {
+		stats::count_ray();
+		let Some(mut hit_result) = world.hit(&Interval::new_ray(), ray) else {
+			return Sample::opaque(
+				self.escaped(ray, background, 1.0) + self.in_scattered(world, ray, Float::INFINITY),
+			);
+		};
+
+		if hit_result.material.is_holdout() {
+			return Sample::HOLDOUT.with_id(hit_result.id);
+		}
+
+		let distance = hit_result.t * ray.direction.length();
+		match differentials
+			.and_then(|differentials| differentials.at(ray, hit_result.point, hit_result.normal))
+		{
+			Some(offsets) => footprint_of(&mut hit_result, offsets),
+			None => footprint(&mut hit_result, ray, spread * distance),
+		}
+
+		let mut emitted = hit_result.material.emit(hit_result.u, hit_result.v);
+		let sunlight = match (&self.sky, &self.environment) {
+			(Some(sky), _) => sky.direct(world, ray, &hit_result),
+			(None, Some(environment)) => environment.direct(world, ray, &hit_result),
+			(None, None) => None,
+		};
+		if let Some(sunlight) = sunlight {
+			emitted += sunlight
+				* self
+					.fog
+					.map_or(1.0, |fog| fog.transmittance(Float::INFINITY));
+		}
+		emitted += self.lights.direct(world, ray, &hit_result);
+
+		let color = match hit_result.material.scatter(ray, &hit_result) {
+			Some((attenuation, scattered)) => {
+				stats::count_bounce();
+				let pdf = hit_result
+					.material
+					.pdf(ray, &hit_result, scattered.direction);
+				let sun = match (&self.sky, &self.environment, sunlight) {
+					(Some(sky), _, Some(_)) => sky.sun_weight(pdf),
+					(None, Some(environment), Some(_)) => {
+						environment.weight(scattered.direction, pdf)
+					}
+					_ => 1.0,
+				};
+				emitted + attenuation * self.emitted(&scattered, world, background, sun)
+			}
+			None => emitted,
+		};
+
+		let color = self.fogged(color, distance) + self.in_scattered(world, ray, distance);
+		Sample::opaque(color).with_id(hit_result.id)
+	}
+}
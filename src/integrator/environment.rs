@@ -0,0 +1,225 @@
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::BufReader;
+
+use image::codecs::hdr::HdrDecoder;
+
+use crate::error::{RendererError, Result};
+use crate::geometry::{Float, HitResult, Hittable, HittableGroup, Interval, Ray, Vec3f};
+use crate::integrator::{luminance, power};
+use crate::random;
+use crate::stats;
+
+const PI_F: Float = PI as Float;
+
+// An equirectangular image of the light around the scene, usually an HDR photograph, with +y up
+// and the middle of the image towards -z. Pixels are picked for shadow rays in proportion to
+// their luminance times the solid angle they cover, so that a small bright sun in the image is
+// found directly instead of by the few paths that happen to escape towards it.
+pub struct Environment {
+	width: usize,
+	height: usize,
+	pixels: Vec<Vec3f>,
+	intensity: Float,
+	// Radians the image is turned about +y, clockwise seen from above like the azimuth of the sun
+	rotation: Float,
+	// Running sum of the weights of the rows, the last entry is the total
+	rows: Vec<Float>,
+	// Running sum of the weights of the pixels within each row, in row order
+	columns: Vec<Float>,
+}
+
+impl Environment {
+	// Radiance .hdr files, or any other image the image crate can read, which keeps the full
+	// range of .exr
+	pub fn load(path: &str, intensity: Float, rotation: Float) -> Result<Environment> {
+		let error = |source| RendererError::Image {
+			path: path.to_string(),
+			source,
+		};
+
+		// Opened like other images .hdr would be clipped to 8 bit
+		let (width, height, pixels) = if path.to_lowercase().ends_with(".hdr") {
+			let file = File::open(path).map_err(|source| RendererError::Io {
+				path: path.to_string(),
+				source,
+			})?;
+			let decoder = HdrDecoder::new(BufReader::new(file)).map_err(error)?;
+			let metadata = decoder.metadata();
+			let pixels = decoder.read_image_hdr().map_err(error)?;
+			(metadata.width, metadata.height, pixels)
+		} else {
+			let image = image::open(path).map_err(error)?.into_rgb32f();
+			(
+				image.width(),
+				image.height(),
+				image.pixels().copied().collect(),
+			)
+		};
+
+		if width == 0 || height == 0 {
+			return Err(RendererError::Scene {
+				path: Some(path.to_string()),
+				message: "the environment image is empty".to_string(),
+			});
+		}
+
+		let pixels = pixels
+			.into_iter()
+			.map(|pixel| {
+				let [r, g, b] = pixel.0.map(|c| c.max(0.0) as Float);
+				Vec3f::new(r, g, b)
+			})
+			.collect();
+		Ok(Environment::new(
+			width as usize,
+			height as usize,
+			pixels,
+			intensity,
+			rotation,
+		))
+	}
+
+	// `width` by `height` linear colors in row order, from the top of the image, and the rotation
+	// in degrees
+	pub fn new(
+		width: usize,
+		height: usize,
+		pixels: Vec<Vec3f>,
+		intensity: Float,
+		rotation: Float,
+	) -> Environment {
+		let mut rows = Vec::with_capacity(height);
+		let mut columns = Vec::with_capacity(width * height);
+		let mut total = 0.0;
+
+		for (y, row) in pixels.chunks_exact(width).enumerate() {
+			// Rows near the poles cover less of the sphere
+			let sin_theta = (PI_F * (y as Float + 0.5) / height as Float).sin();
+			let mut sum = 0.0;
+			for &pixel in row {
+				sum += luminance(pixel) * sin_theta;
+				columns.push(sum);
+			}
+			total += sum;
+			rows.push(total);
+		}
+
+		Environment {
+			width,
+			height,
+			pixels,
+			intensity,
+			rotation: rotation.to_radians(),
+			rows,
+			columns,
+		}
+	}
+
+	// Pixel coordinates of `direction`, from 0 to the width and height
+	fn uv(&self, direction: Vec3f) -> (Float, Float) {
+		let direction = direction.unit();
+		let phi = direction.x.atan2(-direction.z) - self.rotation;
+		let u = (phi / (2.0 * PI_F) + 0.5).rem_euclid(1.0);
+		let v = direction.y.clamp(-1.0, 1.0).acos() / PI_F;
+		(u * self.width as Float, v * self.height as Float)
+	}
+
+	fn pixel(&self, (u, v): (Float, Float)) -> (usize, usize) {
+		(
+			(u as usize).min(self.width - 1),
+			(v as usize).min(self.height - 1),
+		)
+	}
+
+	// Light arriving from `direction`, scaled by `weight` (see weight)
+	pub fn radiance(&self, direction: Vec3f, weight: Float) -> Vec3f {
+		let (x, y) = self.pixel(self.uv(direction));
+		self.pixels[y * self.width + x] * (self.intensity * weight)
+	}
+
+	// A direction towards a pixel picked by its weight, uniformly within the pixel, and its
+	// density per unit solid angle. None for an image that is black all over.
+	fn sample(&self) -> Option<(Vec3f, Float)> {
+		let total = *self.rows.last()?;
+		if total <= 0.0 {
+			return None;
+		}
+
+		let target = random::float() * total;
+		let y = self
+			.rows
+			.partition_point(|&sum| sum <= target)
+			.min(self.height - 1);
+		let row = &self.columns[y * self.width..(y + 1) * self.width];
+		let target = random::float() * row[self.width - 1];
+		let x = row
+			.partition_point(|&sum| sum <= target)
+			.min(self.width - 1);
+
+		let u = (x as Float + random::float()) / self.width as Float;
+		let v = (y as Float + random::float()) / self.height as Float;
+		let phi = (u - 0.5) * 2.0 * PI_F + self.rotation;
+		let (sin_theta, cos_theta) = (v * PI_F).sin_cos();
+		let direction = Vec3f::new(sin_theta * phi.sin(), cos_theta, -sin_theta * phi.cos());
+
+		Some((direction, self.density(x, y, sin_theta)))
+	}
+
+	fn density(&self, x: usize, y: usize, sin_theta: Float) -> Float {
+		let total = self.rows[self.height - 1];
+		if total <= 0.0 || sin_theta <= 0.0 {
+			return 0.0;
+		}
+
+		let index = y * self.width + x;
+		let weight = self.columns[index] - if x > 0 { self.columns[index - 1] } else { 0.0 };
+		let pixels = (self.width * self.height) as Float;
+		weight / total * pixels / (2.0 * PI_F * PI_F * sin_theta)
+	}
+
+	// Density of sample per unit solid angle towards `direction`
+	fn pdf(&self, direction: Vec3f) -> Float {
+		let (u, v) = self.uv(direction);
+		let (x, y) = self.pixel((u, v));
+		self.density(x, y, (v / self.height as Float * PI_F).sin())
+	}
+
+	// How much of the environment a ray scattered towards `direction` with the material's `pdf`
+	// still sees, after it was sampled directly at the hit the ray leaves, see Sky::sun_weight
+	pub fn weight(&self, direction: Vec3f, pdf: Option<Float>) -> Float {
+		pdf.map_or(0.0, |pdf| power(pdf, self.pdf(direction)))
+	}
+
+	// Light from the environment reflected at a hit towards the ray through a shadow ray in a
+	// sampled direction. None for materials that cannot be evaluated for a given direction, and
+	// for black images.
+	pub fn direct(
+		&self,
+		world: &HittableGroup,
+		ray: &Ray,
+		hit_result: &HitResult,
+	) -> Option<Vec3f> {
+		let (direction, pdf) = self.sample()?;
+		let reflected = hit_result.material.eval(ray, hit_result, direction)?;
+		if reflected.lengthsq() == 0.0 || pdf <= 0.0 {
+			return Some(Vec3f::new(0.0, 0.0, 0.0));
+		}
+
+		stats::count_ray();
+		let shadow = Ray {
+			origin: hit_result.point,
+			direction,
+			time: ray.time,
+		};
+		if world.hit_any(&Interval::new_ray(), &shadow) {
+			return Some(Vec3f::new(0.0, 0.0, 0.0));
+		}
+
+		let weight = match hit_result.material.pdf(ray, hit_result, direction) {
+			Some(material_pdf) => power(pdf, material_pdf),
+			None => 1.0,
+		};
+		Some(reflected * self.radiance(direction, weight / pdf))
+	}
+}
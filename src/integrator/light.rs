@@ -0,0 +1,159 @@
+use std::f64::consts::PI;
+
+use crate::geometry::{Float, HitResult, Hittable, HittableGroup, Interval, Ray, Vec3f};
+use crate::integrator::Fog;
+use crate::random;
+use crate::stats;
+
+const PI_F: Float = PI as Float;
+
+// Closer to the light than this a point on a ray is taken to be this far away, which keeps the
+// falloff and the equi-angular density finite
+const MIN_DISTANCE: Float = 1e-4;
+
+// A light with no size at `position` sending `intensity` per unit solid angle in every
+// direction, or only into the cone of `spot`. Rays cannot hit it, it is only seen through
+// shadow rays from surfaces and from the fog along every segment of a path.
+#[derive(Debug, Clone, Copy)]
+pub struct PointLight {
+	pub position: Vec3f,
+	pub intensity: Vec3f,
+	pub spot: Option<Spot>,
+}
+
+// A cone around `direction` whose light fades out from the cosine `cos_inner` of its angle to
+// the axis to `cos_outer`
+#[derive(Debug, Clone, Copy)]
+pub struct Spot {
+	pub direction: Vec3f,
+	pub cos_outer: Float,
+	pub cos_inner: Float,
+}
+
+impl Spot {
+	// `angle` is the full opening of the cone in degrees, the outer `blend` of it fades out
+	pub fn new(direction: Vec3f, angle: Float, blend: Float) -> Spot {
+		let outer = angle.to_radians() / 2.0;
+		Spot {
+			direction: direction.unit(),
+			cos_outer: outer.cos(),
+			cos_inner: (outer * (1.0 - blend.clamp(0.0, 1.0))).cos(),
+		}
+	}
+
+	fn falloff(&self, direction: Vec3f) -> Float {
+		let cosine = Vec3f::dot(&direction, &self.direction);
+		if cosine <= self.cos_outer {
+			return 0.0;
+		}
+		if cosine >= self.cos_inner {
+			return 1.0;
+		}
+		let t = (cosine - self.cos_outer) / (self.cos_inner - self.cos_outer);
+		t * t * (3.0 - 2.0 * t)
+	}
+}
+
+impl PointLight {
+	pub fn new(position: Vec3f, intensity: Vec3f) -> PointLight {
+		PointLight {
+			position,
+			intensity,
+			spot: None,
+		}
+	}
+
+	pub fn with_spot(mut self, spot: Spot) -> PointLight {
+		self.spot = Some(spot);
+		self
+	}
+
+	// Irradiance at `point` facing the light, unless something is in the way
+	fn arriving(&self, world: &HittableGroup, ray: &Ray, point: Vec3f) -> Option<(Vec3f, Vec3f)> {
+		let to_light = self.position - point;
+		let distance = to_light.length().max(MIN_DISTANCE);
+		let direction = to_light / distance;
+
+		let falloff = self.spot.map_or(1.0, |spot| spot.falloff(-direction));
+		if falloff <= 0.0 {
+			return None;
+		}
+
+		stats::count_ray();
+		let shadow = Ray {
+			origin: point,
+			direction,
+			time: ray.time,
+		};
+		let interval = Interval::new(Interval::new_ray().min, distance);
+		if world.hit_any(&interval, &shadow) {
+			return None;
+		}
+
+		Some((
+			direction,
+			self.intensity * (falloff / (distance * distance)),
+		))
+	}
+
+	// Light reflected at a hit towards the ray, nothing for materials that cannot be evaluated
+	// for a given direction since their scattered rays never find it either
+	pub fn direct(&self, world: &HittableGroup, ray: &Ray, hit_result: &HitResult) -> Vec3f {
+		let zero = Vec3f::new(0.0, 0.0, 0.0);
+		let Some((direction, irradiance)) = self.arriving(world, ray, hit_result.point) else {
+			return zero;
+		};
+
+		match hit_result.material.eval(ray, hit_result, direction) {
+			Some(reflected) => reflected * irradiance,
+			None => zero,
+		}
+	}
+
+	// Light scattered towards the ray origin by the fog within `distance` along the ray, dimmed
+	// by the fog on the way there and back. The fog scatters all the light it takes away, its
+	// color stands for the light around the scene only. The point of scattering is picked by
+	// equi-angular sampling, evenly by the angle it is seen at from the light, which puts most
+	// samples where the ray passes closest and makes the light's shafts converge.
+	pub fn in_scattered(
+		&self,
+		world: &HittableGroup,
+		ray: &Ray,
+		distance: Float,
+		fog: &Fog,
+	) -> Vec3f {
+		let zero = Vec3f::new(0.0, 0.0, 0.0);
+		let length = ray.direction.length();
+		let end = distance.min(fog.max_distance);
+		if fog.density <= 0.0 || length == 0.0 || end <= 0.0 {
+			return zero;
+		}
+
+		let direction = ray.direction / length;
+		let along = Vec3f::dot(&(self.position - ray.origin), &direction);
+		let closest = (ray.origin + direction * along - self.position)
+			.length()
+			.max(MIN_DISTANCE);
+		let (near, far) = (
+			((0.0 - along) / closest).atan(),
+			((end - along) / closest).atan(),
+		);
+		if far - near <= 0.0 {
+			return zero;
+		}
+
+		let angle = near + random::float() * (far - near);
+		let t = along + closest * angle.tan();
+		let pdf = closest / ((far - near) * (closest * closest + (t - along) * (t - along)));
+
+		let point = ray.origin + direction * t;
+		let Some((_, irradiance)) = self.arriving(world, ray, point) else {
+			return zero;
+		};
+
+		let light_distance = (self.position - point).length();
+		let transmittance = fog.transmittance(t) * fog.transmittance(light_distance);
+		let phase = 1.0 / (4.0 * PI_F);
+		irradiance * (fog.density * phase * transmittance / pdf)
+	}
+}
@@ -0,0 +1,284 @@
+use std::f64::consts::PI;
+
+use crate::geometry::{Float, HitResult, HittableGroup, Ray, Vec3f};
+use crate::integrator::{luminance, Fog, PointLight};
+use crate::random;
+
+const PI_F: Float = PI as Float;
+
+// Closer to a group of lights than this a point is taken to be this far away, so that the
+// lights around it keep a finite importance
+const MIN_DISTANCE: Float = 1e-4;
+
+// Bounds on the directions a group of lights shines into, after Conty and Kulla (2018): the
+// axes of all its lights are within `spread` radians of `axis`, and each light shines no further
+// than `emission` radians from its own axis
+#[derive(Debug, Clone, Copy)]
+struct Cone {
+	axis: Vec3f,
+	spread: Float,
+	emission: Float,
+}
+
+impl Cone {
+	fn of(light: &PointLight) -> Cone {
+		match light.spot {
+			Some(spot) => Cone {
+				axis: spot.direction,
+				spread: 0.0,
+				emission: spot.cos_outer.clamp(-1.0, 1.0).acos(),
+			},
+			None => Cone {
+				axis: Vec3f::new(0.0, 1.0, 0.0),
+				spread: PI_F,
+				emission: PI_F / 2.0,
+			},
+		}
+	}
+
+	// The smallest cone around both
+	fn union(a: Cone, b: Cone) -> Cone {
+		let (a, b) = if a.spread >= b.spread { (a, b) } else { (b, a) };
+		let emission = a.emission.max(b.emission);
+		let between = Vec3f::dot(&a.axis, &b.axis).clamp(-1.0, 1.0).acos();
+		if (between + b.spread).min(PI_F) <= a.spread {
+			return Cone { emission, ..a };
+		}
+
+		let spread = (a.spread + between + b.spread) / 2.0;
+		if spread >= PI_F || between.sin() < 1e-6 {
+			return Cone {
+				axis: a.axis,
+				spread: PI_F,
+				emission,
+			};
+		}
+
+		// a's axis turned towards b's until the cone just covers both
+		let turn = spread - a.spread;
+		let axis = (a.axis * (between - turn).sin() + b.axis * turn.sin()) / between.sin();
+		Cone {
+			axis: axis.unit(),
+			spread,
+			emission,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Child {
+	Light(usize),
+	// The index of the second child, the first one follows its parent
+	Split(usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Node {
+	min: Vec3f,
+	max: Vec3f,
+	power: Float,
+	cone: Cone,
+	child: Child,
+}
+
+// A binary tree over point and spot lights, which picks a single light to sample for a point
+// instead of sampling every light there. Every step down is taken at random in proportion to an
+// estimate of how much light each side sends to the point, from its power, its distance and the
+// directions it shines into, so that the lights that matter are found about as often among
+// thousands as among a few.
+#[derive(Default)]
+pub struct LightTree {
+	lights: Vec<PointLight>,
+	nodes: Vec<Node>,
+}
+
+impl LightTree {
+	pub fn new(lights: Vec<PointLight>) -> LightTree {
+		let mut tree = LightTree {
+			lights,
+			nodes: Vec::new(),
+		};
+		let mut order: Vec<usize> = (0..tree.lights.len()).collect();
+		if !order.is_empty() {
+			tree.build(&mut order);
+		}
+		tree
+	}
+
+	fn build(&mut self, order: &mut [usize]) -> usize {
+		let index = self.nodes.len();
+		if let [light] = *order {
+			let position = self.lights[light].position;
+			let spot = self.lights[light].spot;
+			// Power over the whole sphere or over the cone of a spot
+			let solid_angle = spot.map_or(4.0 * PI_F, |spot| 2.0 * PI_F * (1.0 - spot.cos_outer));
+			self.nodes.push(Node {
+				min: position,
+				max: position,
+				power: luminance(self.lights[light].intensity).max(0.0) * solid_angle,
+				cone: Cone::of(&self.lights[light]),
+				child: Child::Light(light),
+			});
+			return index;
+		}
+
+		// Halves along the longest side of the box around the lights
+		let (min, max) = order.iter().fold(
+			(
+				self.lights[order[0]].position,
+				self.lights[order[0]].position,
+			),
+			|(min, max), &light| {
+				let position = self.lights[light].position;
+				(min.min(position), max.max(position))
+			},
+		);
+		let extent = max - min;
+		let axis = if extent.x >= extent.y && extent.x >= extent.z {
+			0
+		} else if extent.y >= extent.z {
+			1
+		} else {
+			2
+		};
+		order.sort_by(|&a, &b| {
+			let (a, b) = (self.lights[a].position[axis], self.lights[b].position[axis]);
+			a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+		});
+		let (left, right) = order.split_at_mut(order.len() / 2);
+
+		self.nodes.push(Node {
+			min,
+			max,
+			power: 0.0,
+			cone: Cone::of(&self.lights[left[0]]),
+			child: Child::Split(0),
+		});
+		let first = self.build(left);
+		let second = self.build(right);
+		let (a, b) = (self.nodes[first], self.nodes[second]);
+		let node = &mut self.nodes[index];
+		node.power = a.power + b.power;
+		node.cone = Cone::union(a.cone, b.cone);
+		node.child = Child::Split(second);
+		index
+	}
+
+	// How much light the node's lights could send to `point`, on a surface facing `normal` if
+	// there is one. Never 0 for a node with a light that reaches the point.
+	fn importance(&self, node: &Node, point: Vec3f, normal: Option<Vec3f>) -> Float {
+		let center = (node.min + node.max) * 0.5;
+		let radius = (node.max - node.min).length() * 0.5;
+		let offset = point - center;
+		let distance = offset.length().max(MIN_DISTANCE);
+		let direction = offset / distance;
+
+		// Angle the box takes up seen from the point
+		let bounds = if distance <= radius {
+			PI_F
+		} else {
+			(radius / distance).asin()
+		};
+
+		let angle = Vec3f::dot(&node.cone.axis, &direction)
+			.clamp(-1.0, 1.0)
+			.acos();
+		let outside = (angle - node.cone.spread - bounds).max(0.0);
+		if outside >= node.cone.emission {
+			return 0.0;
+		}
+
+		let incidence = match normal {
+			Some(normal) => {
+				let angle = Vec3f::dot(&normal, &-direction).clamp(-1.0, 1.0).acos();
+				let angle = (angle - bounds).max(0.0);
+				if angle >= PI_F / 2.0 {
+					return 0.0;
+				}
+				angle.cos()
+			}
+			None => 1.0,
+		};
+
+		node.power * outside.cos().max(0.0) * incidence / distance.max(radius).powi(2)
+	}
+
+	// A light picked by the importance of each side, and the probability it was picked with.
+	// None without lights, or where none of them can reach.
+	fn pick(&self, importance: impl Fn(&Node) -> Float) -> Option<(&PointLight, Float)> {
+		let mut index = 0;
+		let mut probability = 1.0;
+		loop {
+			match self.nodes.get(index)?.child {
+				Child::Light(light) => return Some((&self.lights[light], probability)),
+				Child::Split(second) => {
+					let first = importance(&self.nodes[index + 1]);
+					let total = first + importance(&self.nodes[second]);
+					if total <= 0.0 {
+						return None;
+					}
+					let share = first / total;
+					if random::float() < share {
+						index += 1;
+						probability *= share;
+					} else {
+						index = second;
+						probability *= 1.0 - share;
+					}
+				}
+			}
+		}
+	}
+
+	// Light from one of the lights reflected at a hit towards the ray, see PointLight::direct
+	pub fn direct(&self, world: &HittableGroup, ray: &Ray, hit_result: &HitResult) -> Vec3f {
+		// Volumes scatter light from every side
+		let normal = (!hit_result.material.is_phase_function()).then(|| {
+			if Vec3f::dot(&ray.direction, &hit_result.normal) > 0.0 {
+				-hit_result.normal.unit()
+			} else {
+				hit_result.normal.unit()
+			}
+		});
+
+		match self.pick(|node| self.importance(node, hit_result.point, normal)) {
+			Some((light, probability)) => light.direct(world, ray, hit_result) / probability,
+			None => Vec3f::new(0.0, 0.0, 0.0),
+		}
+	}
+
+	// Light scattered by the fog along the ray from one of the lights, see
+	// PointLight::in_scattered
+	pub fn in_scattered(
+		&self,
+		world: &HittableGroup,
+		ray: &Ray,
+		distance: Float,
+		fog: &Fog,
+	) -> Vec3f {
+		let zero = Vec3f::new(0.0, 0.0, 0.0);
+		let length = ray.direction.length();
+		if self.lights.is_empty() || length == 0.0 {
+			return zero;
+		}
+
+		// Only by power and distance, a spot may light the ray elsewhere than where it passes
+		// closest to the spot
+		let direction = ray.direction / length;
+		let end = distance.min(fog.max_distance);
+		let importance = |node: &Node| {
+			let center = (node.min + node.max) * 0.5;
+			let along = Vec3f::dot(&(center - ray.origin), &direction).clamp(0.0, end);
+			let closest = (ray.origin + direction * along - center).length();
+			let radius = (node.max - node.min).length() * 0.5;
+			node.power / closest.max(radius).max(MIN_DISTANCE).powi(2)
+		};
+
+		match self.pick(importance) {
+			Some((light, probability)) => {
+				light.in_scattered(world, ray, distance, fog) / probability
+			}
+			None => zero,
+		}
+	}
+}
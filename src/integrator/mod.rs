@@ -0,0 +1,251 @@
+use std::sync::Arc;
+
+use crate::geometry::{Float, HitResult, HittableGroup, Ray, RayDifferentials, Uv, Vec3f};
+
+mod ao;
+mod cache;
+mod debug;
+mod direct;
+mod environment;
+mod light;
+mod light_tree;
+mod path;
+mod sky;
+
+pub use ao::AmbientOcclusion;
+pub use cache::IrradianceCache;
+pub use debug::{heat, DebugMode, DebugView, MODES};
+pub use direct::DirectLighting;
+pub use environment::Environment;
+pub use light::{PointLight, Spot};
+pub use light_tree::LightTree;
+pub use path::PathTracer;
+pub use sky::{solar_position, sun_direction, Sky};
+
+pub const MAX_LIGHT_GROUPS: usize = 8;
+
+// Render wide fog between the camera and everything it sees, and between bounces. Light fades
+// exponentially with the distance it travels through the fog and is replaced by `color`, so far
+// away objects take on the color of the haze. No more than `max_distance` of fog lies along any
+// ray, which keeps the background visible through it.
+#[derive(Debug, Clone, Copy)]
+pub struct Fog {
+	pub density: Float,
+	pub color: Vec3f,
+	pub max_distance: Float,
+}
+
+impl Fog {
+	// Fraction of the light that makes it `distance` through the fog
+	pub fn transmittance(&self, distance: Float) -> Float {
+		(-self.density * distance.min(self.max_distance)).exp()
+	}
+
+	// `color` seen from `distance` away, or from beyond the scene for infinite distances
+	pub fn apply(&self, color: Vec3f, distance: Float) -> Vec3f {
+		let transmittance = self.transmittance(distance);
+		color * transmittance + self.color * (1.0 - transmittance)
+	}
+}
+
+fn luminance(color: Vec3f) -> Float {
+	0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+// Power heuristic weight of a sample from one of two strategies
+fn power(pdf: Float, other: Float) -> Float {
+	let (pdf2, other2) = (pdf * pdf, other * other);
+	if pdf2 + other2 > 0.0 {
+		pdf2 / (pdf2 + other2)
+	} else {
+		0.0
+	}
+}
+
+// Texture footprints follow the rays through the neighboring pixels, the camera's differentials,
+// for as long as the path is reflected or refracted specularly. Where the camera has none and
+// after the first rough bounce, ray cones stand in for them: a cone is as wide as the footprint
+// it starts from (or a pixel's `spread` at the camera) and every surface it hits at a grazing
+// angle stretches its footprint along the ray. Rough materials widen the cone by the angle of
+// their lobe, 1 / sqrt(pdf).
+fn widen(spread: Float, pdf: Option<Float>) -> Float {
+	match pdf {
+		Some(pdf) if pdf > 0.0 => spread + 1.0 / pdf.sqrt(),
+		_ => spread,
+	}
+}
+
+// Sets the footprint of a cone `width` across at the hit, in UVs, for surfaces that have
+// derivatives
+fn footprint(hit_result: &mut HitResult, ray: &Ray, width: Float) {
+	// Along the ray as it runs over the surface, and across it
+	let normal = hit_result.normal.unit();
+	let direction = ray.direction.unit();
+	let cosine = Vec3f::dot(&direction, &normal);
+	let mut along = direction - normal * cosine;
+	if along.lengthsq() < 1e-12 {
+		let Some([dpdu, _]) = hit_result.derivatives else {
+			return;
+		};
+		along = dpdu;
+	}
+	let along = along.unit();
+	let across = Vec3f::cross(&normal, &along);
+
+	footprint_of(
+		hit_result,
+		[along * (width / cosine.abs().max(0.05)), across * width],
+	);
+}
+
+// Sets the footprint spanned by the offsets `axes` from the hit point, in UVs
+fn footprint_of(hit_result: &mut HitResult, axes: [Vec3f; 2]) {
+	if let Some(footprint) = uv_offsets(hit_result, axes) {
+		hit_result.footprint = Some(footprint);
+	}
+}
+
+// Least squares UVs of offsets from the hit point in the plane of the derivatives
+fn uv_offsets(hit_result: &HitResult, axes: [Vec3f; 2]) -> Option<[Uv; 2]> {
+	let [dpdu, dpdv] = hit_result.derivatives?;
+
+	let (e, f, g) = (
+		Vec3f::dot(&dpdu, &dpdu),
+		Vec3f::dot(&dpdu, &dpdv),
+		Vec3f::dot(&dpdv, &dpdv),
+	);
+	let determinant = e * g - f * f;
+	if determinant.abs() < 1e-20 {
+		return None;
+	}
+	Some(axes.map(|axis| {
+		let (a, b) = (Vec3f::dot(&axis, &dpdu), Vec3f::dot(&axis, &dpdv));
+		Uv::new((g * a - f * b) / determinant, (e * b - f * a) / determinant)
+	}))
+}
+
+// The differentials of `scattered` when it is the mirror image of `ray` at the hit, or its
+// refraction with the ratio of refraction indices `ratio`. `offsets` are where the neighboring
+// rays met the surface, the neighbors bounce off the normal as it turned there on curved
+// surfaces, which spreads them apart on convex mirrors and lenses. None for any other bounce.
+fn scatter_differentials(
+	differentials: &RayDifferentials,
+	ray: &Ray,
+	hit_result: &HitResult,
+	offsets: [Vec3f; 2],
+	scattered: &Ray,
+	ratio: Option<Float>,
+) -> Option<RayDifferentials> {
+	let direction = ray.direction.unit();
+	let out = scattered.direction.unit();
+	let normal = hit_result.normal.unit();
+	let sign = if Vec3f::dot(&direction, &normal) < 0.0 {
+		1.0
+	} else {
+		-1.0
+	};
+	let normal = normal * sign;
+	let matches = |expected: Vec3f| (expected - out).lengthsq() < 1e-6;
+
+	// The ratio for refractions, None for reflections
+	let refraction = if matches(Vec3f::reflect(direction, normal)) {
+		None
+	} else {
+		Some(ratio.filter(|&ratio| matches(Vec3f::refract(direction, normal, ratio)))?)
+	};
+	let bounce = |neighbor: Vec3f, normal: Vec3f| match refraction {
+		None => Some(Vec3f::reflect(neighbor, normal)),
+		// Neighbors that are reflected internally have no refraction to follow
+		Some(ratio) => {
+			let cosine = -Vec3f::dot(&neighbor, &normal);
+			(ratio * ratio * (1.0 - cosine * cosine) <= 1.0)
+				.then(|| Vec3f::refract(neighbor, normal, ratio))
+		}
+	};
+
+	let turns = hit_result
+		.normal_derivatives
+		.zip(uv_offsets(hit_result, offsets))
+		.map(|([dndu, dndv], uvs)| uvs.map(|uv| (dndu * uv.u + dndv * uv.v) * sign))
+		.unwrap_or([Vec3f::new(0.0, 0.0, 0.0); 2]);
+
+	let length = scattered.direction.length();
+	let offset = |axis: usize| {
+		let neighbor = (ray.direction + differentials.direction[axis]).unit();
+		let bounced = bounce(neighbor, (normal + turns[axis]).unit())?;
+		Some(bounced.unit() * length - scattered.direction)
+	};
+
+	Some(RayDifferentials {
+		origin: offsets,
+		direction: [offset(0)?, offset(1)?],
+	})
+}
+
+// What one camera ray contributes to its pixel, `alpha` is 0 where it hit a holdout. `groups`
+// holds the part of `color` that came from the lights of each light group, `id` is the ID of the
+// first object the ray hit or 0.
+#[derive(Debug, Clone, Copy)]
+pub struct Sample {
+	pub color: Vec3f,
+	pub alpha: Float,
+	pub groups: [Vec3f; MAX_LIGHT_GROUPS],
+	pub id: u32,
+}
+
+impl Sample {
+	pub const HOLDOUT: Sample = Sample {
+		color: Vec3f::new(0.0, 0.0, 0.0),
+		alpha: 0.0,
+		groups: [Vec3f::new(0.0, 0.0, 0.0); MAX_LIGHT_GROUPS],
+		id: 0,
+	};
+
+	pub fn opaque(color: Vec3f) -> Sample {
+		Sample {
+			color,
+			alpha: 1.0,
+			groups: [Vec3f::new(0.0, 0.0, 0.0); MAX_LIGHT_GROUPS],
+			id: 0,
+		}
+	}
+
+	pub fn with_id(self, id: u32) -> Sample {
+		Sample { id, ..self }
+	}
+}
+
+// Estimates the radiance arriving along a camera ray, whose pixel covers `spread` radians and
+// whose neighbors are given by `differentials` where the camera has them, see Camera. Random
+// numbers come from the calling thread's generator in `random`, which the renderer seeds per row.
+pub trait Integrator: Send + Sync {
+	fn li(
+		&self,
+		ray: &Ray,
+		world: &HittableGroup,
+		background: Vec3f,
+		spread: Float,
+		differentials: Option<RayDifferentials>,
+	) -> Sample;
+
+	// Names of the light groups the integrator separates, the renderer outputs an image for each
+	fn light_groups(&self) -> &[String] {
+		&[]
+	}
+
+	// Called before rendering a frame in which objects have moved, for integrators that keep what
+	// they learned about the scene from one render to the next
+	fn scene_changed(&self) {}
+}
+
+pub const NAMES: &[&str] = &["path", "ao", "direct"];
+
+// Integrators selectable by name on the command line, with their default settings
+pub fn find(name: &str) -> Option<Arc<dyn Integrator>> {
+	match name {
+		"path" => Some(Arc::new(PathTracer::default())),
+		"ao" => Some(Arc::new(AmbientOcclusion::default())),
+		"direct" => Some(Arc::new(DirectLighting::default())),
+		_ => None,
+	}
+}
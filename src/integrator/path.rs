@@ -0,0 +1,489 @@
+use std::f64::consts::PI;
+
+use crate::geometry::{
+	Float, HitResult, Hittable, HittableGroup, Interval, Ray, RayDifferentials, Vec3f,
+};
+use crate::integrator::cache::Record;
+use crate::integrator::{
+	footprint, footprint_of, scatter_differentials, widen, Environment, Fog, Integrator,
+	IrradianceCache, LightTree, PointLight, Sample, Sky, MAX_LIGHT_GROUPS,
+};
+use crate::material::{basis, Medium};
+use crate::random;
+use crate::stats;
+
+// Dielectrics a path can be nested in at once, deeper ones are not tracked
+const MAX_MEDIA: usize = 8;
+
+// Bounds on how far a cache record reaches, in widths of the pixel's footprint at its point, so
+// that records neither crowd into corners nor smear light across the image
+const MIN_REACH: Float = 2.0;
+const MAX_REACH: Float = 40.0;
+
+// The dielectric media a path is inside of, innermost last. Media are told apart by their
+// properties, so that e.g. the inner and outer surface of a CSG glass enclose the same one.
+struct Media {
+	entries: [Medium; MAX_MEDIA],
+	len: usize,
+}
+
+impl Media {
+	fn new() -> Media {
+		Media {
+			entries: [Medium {
+				refraction_index: 1.0,
+				priority: 0,
+			}; MAX_MEDIA],
+			len: 0,
+		}
+	}
+
+	fn enter(&mut self, medium: Medium) {
+		if self.len < MAX_MEDIA {
+			self.entries[self.len] = medium;
+			self.len += 1;
+		}
+	}
+
+	fn leave(&mut self, medium: Medium) {
+		if let Some(index) = self.entries[..self.len]
+			.iter()
+			.rposition(|&entry| entry == medium)
+		{
+			self.entries.copy_within(index + 1..self.len, index);
+			self.len -= 1;
+		}
+	}
+
+	// The medium with the highest priority other than `medium`, the latest entered on ties
+	fn dominant(&self, medium: Medium) -> Option<Medium> {
+		self.entries[..self.len]
+			.iter()
+			.filter(|&&entry| entry != medium)
+			.max_by_key(|medium| medium.priority)
+			.copied()
+	}
+}
+
+// Where a path picks up from, the camera or a cached surface whose light it estimates
+#[derive(Clone, Copy)]
+struct Start {
+	depth: u32,
+	sun: Float,
+	width: Float,
+	spread: Float,
+	// Whether diffuse surfaces take their light from the cache, only for paths from the camera
+	cached: bool,
+}
+
+// Path tracer that follows one scattered ray per bounce. Light from emitters in a light group is
+// also added to that group's share of the sample. Paths keep track of the dielectrics they are
+// in, so that nested ones like ice in water in a glass refract by the ratio of the media on both
+// sides, and surfaces inside a medium of higher priority are passed through.
+pub struct PathTracer {
+	pub max_depth: u32,
+	pub light_groups: Vec<String>,
+	pub fog: Option<Fog>,
+	pub sky: Option<Sky>,
+	pub environment: Option<Environment>,
+	pub lights: LightTree,
+	pub cache: Option<IrradianceCache>,
+}
+
+impl PathTracer {
+	pub fn new(max_depth: u32) -> PathTracer {
+		PathTracer {
+			max_depth,
+			light_groups: Vec::new(),
+			fog: None,
+			sky: None,
+			environment: None,
+			lights: LightTree::default(),
+			cache: None,
+		}
+	}
+
+	// Names of the groups that DiffuseLight::with_group indices refer to, at most
+	// MAX_LIGHT_GROUPS
+	pub fn with_light_groups(mut self, names: Vec<String>) -> PathTracer {
+		self.light_groups = names;
+		self
+	}
+
+	pub fn with_fog(mut self, fog: Fog) -> PathTracer {
+		self.fog = Some(fog);
+		self
+	}
+
+	// The sky replaces the background and lights the scene with its sun
+	pub fn with_sky(mut self, sky: Sky) -> PathTracer {
+		self.sky = Some(sky);
+		self
+	}
+
+	// An HDR image replaces the background and lights the scene, sampled directly like the sun
+	pub fn with_environment(mut self, environment: Environment) -> PathTracer {
+		self.environment = Some(environment);
+		self
+	}
+
+	// Point and spot lights, one of which is sampled at every bounce and in the fog along every
+	// segment
+	pub fn with_lights(mut self, lights: Vec<PointLight>) -> PathTracer {
+		self.lights = LightTree::new(lights);
+		self
+	}
+
+	// Paths end at the first diffuse surface they hit, which takes the light arriving there
+	// interpolated from the cache, at a bias but far fewer paths in mostly diffuse scenes
+	pub fn with_cache(mut self, cache: IrradianceCache) -> PathTracer {
+		self.cache = Some(cache);
+		self
+	}
+
+	// Light from beyond the scene, with the sun or the environment weighted by how much of it
+	// was left over by sampling it directly at the previous bounce
+	fn escaped(&self, ray: &Ray, background: Vec3f, sun: Float) -> Vec3f {
+		match (&self.sky, &self.environment) {
+			(Some(sky), _) => sky.radiance(ray.direction, sun),
+			(None, Some(environment)) => environment.radiance(ray.direction, sun),
+			(None, None) => background,
+		}
+	}
+
+	// Light from the sun or the environment reflected at the hit through a shadow ray
+	fn direct(&self, world: &HittableGroup, ray: &Ray, hit_result: &HitResult) -> Option<Vec3f> {
+		match (&self.sky, &self.environment) {
+			(Some(sky), _) => sky.direct(world, ray, hit_result),
+			(None, Some(environment)) => environment.direct(world, ray, hit_result),
+			(None, None) => None,
+		}
+	}
+
+	// How much of the sun or the environment a ray scattered towards `direction` with the
+	// material's `pdf` still sees, after `sampled` it directly at the hit it leaves
+	fn sun_weight(&self, direction: Vec3f, pdf: Option<Float>, sampled: bool) -> Float {
+		match (&self.sky, &self.environment, sampled) {
+			(Some(sky), _, true) => sky.sun_weight(pdf),
+			(None, Some(environment), true) => environment.weight(direction, pdf),
+			_ => 1.0,
+		}
+	}
+
+	// The light arriving at a diffuse hit from the cache, or from a new record traced from it
+	// with `next` where there is none close enough
+	fn cached(
+		&self,
+		cache: &IrradianceCache,
+		world: &HittableGroup,
+		ray: &Ray,
+		hit_result: &HitResult,
+		background: Vec3f,
+		next: Start,
+	) -> (Vec3f, [Vec3f; MAX_LIGHT_GROUPS]) {
+		let normal = if Vec3f::dot(&ray.direction, &hit_result.normal) > 0.0 {
+			-hit_result.normal
+		} else {
+			hit_result.normal
+		};
+		if let Some(cached) = cache.lookup(hit_result.point, normal) {
+			return cached;
+		}
+
+		let mut radiance = Vec3f::new(0.0, 0.0, 0.0);
+		let mut groups = [Vec3f::new(0.0, 0.0, 0.0); MAX_LIGHT_GROUPS];
+		let mut inverse_distances = 0.0;
+		// Cosine distributed over a grid of strata of the hemisphere, any rays left over are not
+		// stratified
+		let (tangent, bitangent) = basis(normal);
+		let strata = (cache.rays as Float).sqrt() as u32;
+		for index in 0..cache.rays {
+			let (mut u, mut v) = (random::float(), random::float());
+			if index < strata * strata {
+				u = ((index / strata) as Float + u) / strata as Float;
+				v = ((index % strata) as Float + v) / strata as Float;
+			}
+			let (radius, angle) = (u.sqrt(), 2.0 * PI as Float * v);
+			let direction = tangent * (radius * angle.cos())
+				+ bitangent * (radius * angle.sin())
+				+ normal * (1.0 - u).max(0.0).sqrt();
+			let scattered = Ray {
+				origin: hit_result.point,
+				direction,
+				time: ray.time,
+			};
+
+			stats::count_bounce();
+			let (sample, distance) = self.trace(&scattered, world, background, None, next);
+			radiance += sample.color;
+			for (group, light) in groups.iter_mut().zip(sample.groups) {
+				*group += light;
+			}
+			inverse_distances += 1.0 / distance;
+		}
+
+		let rays = cache.rays as Float;
+		let (radiance, groups) = (radiance / rays, groups.map(|group| group / rays));
+		let radius = (rays / inverse_distances).clamp(
+			MIN_REACH * next.width / cache.accuracy,
+			MAX_REACH * next.width / cache.accuracy,
+		);
+		// Without a footprint there is nothing to bound the record by, it is used only here
+		if radius > 0.0 && radius.is_finite() {
+			cache.insert(Record {
+				point: hit_result.point,
+				normal,
+				radius,
+				radiance,
+				groups,
+			});
+		}
+		(radiance, groups)
+	}
+
+	// Follows a path from `start`, also returning how far its first segment went
+	fn trace(
+		&self,
+		ray: &Ray,
+		world: &HittableGroup,
+		background: Vec3f,
+		differentials: Option<RayDifferentials>,
+		start: Start,
+	) -> (Sample, Float) {
+		let mut sample = Sample::opaque(Vec3f::new(0.0, 0.0, 0.0));
+		let mut throughput = Vec3f::new(1.0, 1.0, 1.0);
+		let mut ray = Ray {
+			origin: ray.origin,
+			direction: ray.direction,
+			time: ray.time,
+		};
+
+		let mut media = Media::new();
+		let mut depth = start.depth;
+		let mut sun = start.sun;
+		let (mut width, mut spread) = (start.width, start.spread);
+		let mut differentials = differentials;
+		let mut first = None;
+
+		while depth < self.max_depth {
+			stats::count_ray();
+			let Some(mut hit_result) = world.hit(&Interval::new_ray(), &ray) else {
+				self.through_fog(world, &ray, &mut sample, &mut throughput, Float::INFINITY);
+				sample.color += throughput * self.escaped(&ray, background, sun);
+				return (sample, first.unwrap_or(Float::INFINITY));
+			};
+
+			if depth == 0 {
+				// Holdouts only cut out the pixel when they are seen directly, further down the
+				// path they are black
+				if hit_result.material.is_holdout() {
+					return (Sample::HOLDOUT.with_id(hit_result.id), 0.0);
+				}
+
+				sample.id = hit_result.id;
+			}
+
+			let distance = hit_result.t * ray.direction.length();
+			first.get_or_insert(distance);
+			self.through_fog(world, &ray, &mut sample, &mut throughput, distance);
+			width += spread * distance;
+			let offsets = differentials.and_then(|differentials| {
+				differentials.at(&ray, hit_result.point, hit_result.normal)
+			});
+
+			let medium = hit_result.material.medium();
+			let entering = Vec3f::dot(&ray.direction, &hit_result.normal) < 0.0;
+			let outside = medium.and_then(|medium| media.dominant(medium));
+
+			if let Some(medium) = medium {
+				// Overlapping surfaces of lower priority media do not exist, the path carries on
+				// without using up a bounce
+				if outside.is_some_and(|outside| outside.priority > medium.priority) {
+					if entering {
+						media.enter(medium);
+					} else {
+						media.leave(medium);
+					}
+					ray = Ray {
+						origin: hit_result.point,
+						direction: ray.direction,
+						time: ray.time,
+					};
+					differentials = differentials.zip(offsets).map(|(differentials, offsets)| {
+						RayDifferentials {
+							origin: offsets,
+							..differentials
+						}
+					});
+					continue;
+				}
+			}
+
+			match offsets {
+				Some(offsets) => footprint_of(&mut hit_result, offsets),
+				None => footprint(&mut hit_result, &ray, width),
+			}
+			let emitted = throughput * hit_result.material.emit(hit_result.u, hit_result.v);
+			sample.color += emitted;
+			if let Some(group) = hit_result.material.light_group() {
+				if group < MAX_LIGHT_GROUPS {
+					sample.groups[group] += emitted;
+				}
+			}
+
+			let sunlight = self.direct(world, &ray, &hit_result);
+			if let Some(sunlight) = sunlight {
+				let haze = self
+					.fog
+					.map_or(1.0, |fog| fog.transmittance(Float::INFINITY));
+				sample.color += throughput * sunlight * haze;
+			}
+			sample.color += throughput * self.lights.direct(world, &ray, &hit_result);
+
+			if let Some(cache) = self.cache.as_ref().filter(|_| start.cached) {
+				let normal = if entering {
+					hit_result.normal
+				} else {
+					-hit_result.normal
+				};
+				let albedo = hit_result
+					.material
+					.eval(&ray, &hit_result, normal)
+					.filter(|_| hit_result.material.is_diffuse());
+				if let Some(albedo) = albedo {
+					let pdf = hit_result.material.pdf(&ray, &hit_result, normal);
+					let next = Start {
+						depth: depth + 1,
+						sun: self.sun_weight(normal, pdf, sunlight.is_some()),
+						width,
+						spread: widen(spread, pdf),
+						cached: false,
+					};
+					let (radiance, groups) =
+						self.cached(cache, world, &ray, &hit_result, background, next);
+					// The albedo over pi times the irradiance, pi times the mean radiance of the
+					// records
+					let reflected = throughput * albedo * PI as Float;
+					sample.color += reflected * radiance;
+					for (group, light) in sample.groups.iter_mut().zip(groups) {
+						*group += reflected * light;
+					}
+					return (sample, first.unwrap_or(Float::INFINITY));
+				}
+			}
+
+			let outside = outside.map_or(1.0, |outside| outside.refraction_index);
+			let ratio = medium.map(|medium| {
+				if entering {
+					outside / medium.refraction_index
+				} else {
+					medium.refraction_index / outside
+				}
+			});
+			let Some((attenuation, scattered)) =
+				hit_result
+					.material
+					.scatter_between(&ray, &hit_result, outside)
+			else {
+				return (sample, first.unwrap_or(Float::INFINITY));
+			};
+
+			if let Some(medium) = medium {
+				let transmitted =
+					(Vec3f::dot(&scattered.direction, &hit_result.normal) < 0.0) == entering;
+				match (transmitted, entering) {
+					(true, true) => media.enter(medium),
+					(true, false) => media.leave(medium),
+					_ => {}
+				}
+			}
+
+			let pdf = hit_result
+				.material
+				.pdf(&ray, &hit_result, scattered.direction);
+			sun = self.sun_weight(scattered.direction, pdf, sunlight.is_some());
+			spread = widen(spread, pdf);
+
+			// After a rough bounce the cone goes on from the footprint of the differentials
+			differentials = differentials
+				.zip(offsets)
+				.and_then(|(differentials, offsets)| {
+					let next = scatter_differentials(
+						&differentials,
+						&ray,
+						&hit_result,
+						offsets,
+						&scattered,
+						ratio,
+					);
+					if next.is_none() {
+						width = offsets[0].length().max(offsets[1].length());
+					}
+					next
+				});
+
+			stats::count_bounce();
+			throughput *= attenuation;
+			ray = scattered;
+			depth += 1;
+		}
+
+		// Paths that run out of bounces see the background
+		sample.color += throughput * self.escaped(&ray, background, sun);
+		(sample, first.unwrap_or(Float::INFINITY))
+	}
+
+	// Dims the throughput by the fog along `distance` of a path segment and adds the fog's own
+	// color in its place, along with the light it scatters from the point lights
+	fn through_fog(
+		&self,
+		world: &HittableGroup,
+		ray: &Ray,
+		sample: &mut Sample,
+		throughput: &mut Vec3f,
+		distance: Float,
+	) {
+		if let Some(fog) = &self.fog {
+			sample.color += *throughput * self.lights.in_scattered(world, ray, distance, fog);
+			let transmittance = fog.transmittance(distance);
+			sample.color += *throughput * fog.color * (1.0 - transmittance);
+			*throughput *= transmittance;
+		}
+	}
+}
+
+impl Default for PathTracer {
+	fn default() -> Self {
+		PathTracer::new(5)
+	}
+}
+
+impl Integrator for PathTracer {
+	fn li(
+		&self,
+		ray: &Ray,
+		world: &HittableGroup,
+		background: Vec3f,
+		spread: Float,
+		differentials: Option<RayDifferentials>,
+	) -> Sample {
+		let start = Start {
+			depth: 0,
+			sun: 1.0,
+			width: 0.0,
+			spread,
+			cached: self.cache.is_some(),
+		};
+		self.trace(ray, world, background, differentials, start).0
+	}
+
+	fn light_groups(&self) -> &[String] {
+		&self.light_groups
+	}
+
+	fn scene_changed(&self) {
+		if let Some(cache) = &self.cache {
+			cache.clear();
+		}
+	}
+}
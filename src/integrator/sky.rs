@@ -0,0 +1,260 @@
+use std::f64::consts::PI;
+
+use crate::geometry::{Float, HitResult, Hittable, HittableGroup, Interval, Ray, Vec3f};
+use crate::integrator::power;
+use crate::random;
+use crate::stats;
+
+const PI_F: Float = PI as Float;
+
+// Perez et al.'s distribution of the sky light, relative to the zenith, with the coefficients
+// `a` to `e` of one of luminance Y and chromaticities x and y
+fn perez([a, b, c, d, e]: [Float; 5], cos_theta: Float, gamma: Float) -> Float {
+	(1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+}
+
+// Linear sRGB of CIE xyY
+fn xyy_to_rgb(x: Float, y: Float, luminance: Float) -> Vec3f {
+	let (cx, cz) = (x / y * luminance, (1.0 - x - y) / y * luminance);
+	Vec3f::new(
+		3.2406 * cx - 1.5372 * luminance - 0.4986 * cz,
+		-0.9689 * cx + 1.8758 * luminance + 0.0415 * cz,
+		0.0557 * cx - 0.2040 * luminance + 1.0570 * cz,
+	)
+	.max(Vec3f::new(0.0, 0.0, 0.0))
+}
+
+// Fraction of the sunlight at 680, 550 and 440 nm that makes it through the atmosphere, from
+// Rayleigh scattering and the haze of the turbidity along the air mass towards the sun
+fn sun_transmittance(cos_theta: Float, turbidity: Float) -> Vec3f {
+	let cos_theta = cos_theta.clamp(0.0, 1.0);
+	let air_mass = 1.0 / (cos_theta + 0.15 * (93.885 - cos_theta.acos().to_degrees()).powf(-1.253));
+	let haze = 0.04608 * turbidity - 0.04586;
+	let [r, g, b] = [0.68 as Float, 0.55, 0.44].map(|wavelength| {
+		let depth = 0.008735 * wavelength.powf(-4.08) + haze * wavelength.powf(-1.3);
+		(-air_mass * depth).exp()
+	});
+	Vec3f::new(r, g, b)
+}
+
+// Unit vector towards the sun, the azimuth in degrees clockwise from north (-z) towards east (+x)
+pub fn sun_direction(azimuth: Float, elevation: Float) -> Vec3f {
+	let (azimuth, elevation) = (azimuth.to_radians(), elevation.to_radians());
+	Vec3f::new(
+		elevation.cos() * azimuth.sin(),
+		elevation.sin(),
+		-elevation.cos() * azimuth.cos(),
+	)
+}
+
+// Azimuth and elevation of the sun in degrees for a place (north and east positive) on a day of
+// the year (1 is January 1st) at a time in hours UTC, following NOAA's solar equations
+pub fn solar_position(
+	latitude: Float,
+	longitude: Float,
+	day_of_year: u32,
+	hours: Float,
+) -> (Float, Float) {
+	let year = 2.0 * PI_F / 365.0 * (day_of_year as Float - 1.0 + (hours - 12.0) / 24.0);
+	let (sin1, cos1) = year.sin_cos();
+	let (sin2, cos2) = (2.0 * year).sin_cos();
+	let (sin3, cos3) = (3.0 * year).sin_cos();
+
+	// Equation of time in minutes and the declination of the sun
+	let equation =
+		229.18 * (0.000075 + 0.001868 * cos1 - 0.032077 * sin1 - 0.014615 * cos2 - 0.040849 * sin2);
+	let declination = 0.006918 - 0.399912 * cos1 + 0.070257 * sin1 - 0.006758 * cos2
+		+ 0.000907 * sin2
+		- 0.002697 * cos3
+		+ 0.00148 * sin3;
+
+	let solar_minutes = hours * 60.0 + equation + 4.0 * longitude;
+	let hour_angle = (solar_minutes / 4.0 - 180.0).to_radians();
+	let latitude = latitude.to_radians();
+
+	let sin_elevation =
+		latitude.sin() * declination.sin() + latitude.cos() * declination.cos() * hour_angle.cos();
+	let elevation = sin_elevation.clamp(-1.0, 1.0).asin();
+	// Measured from south towards west, turned to start at north
+	let azimuth = hour_angle
+		.sin()
+		.atan2(hour_angle.cos() * latitude.sin() - declination.tan() * latitude.cos())
+		+ PI_F;
+
+	(azimuth.to_degrees(), elevation.to_degrees())
+}
+
+// Procedural daylight sky after Preetham et al. with a sun disk. `turbidity` is the haziness of the
+// air, 2 for a clear and 10 for a hazy day. The sky is scaled to a luminance of `sky_intensity` at
+// the zenith, and `sun_intensity` is the irradiance of the sun on a surface facing it outside the
+// atmosphere, which dims and reddens it towards the horizon. Integrators sample the sun directly,
+// which keeps the penumbras of its angular diameter soft without the noise of finding it by chance.
+pub struct Sky {
+	sun: Vec3f,
+	// Cosine of the angular radius of the sun
+	cos_sun: Float,
+	sun_irradiance: Vec3f,
+	sun_radiance: Vec3f,
+	sky_intensity: Float,
+	// Perez coefficients and zenith values for Y, x and y, and the distribution at the zenith
+	coefficients: [[Float; 5]; 3],
+	zenith: [Float; 3],
+	at_zenith: [Float; 3],
+}
+
+impl Sky {
+	pub fn new(
+		sun: Vec3f,
+		angular_diameter: Float,
+		turbidity: Float,
+		sun_intensity: Float,
+		sky_intensity: Float,
+	) -> Sky {
+		let sun = sun.unit();
+		let t = turbidity;
+		let coefficients = [
+			[
+				0.1787 * t - 1.4630,
+				-0.3554 * t + 0.4275,
+				-0.0227 * t + 5.3251,
+				0.1206 * t - 2.5771,
+				-0.0670 * t + 0.3703,
+			],
+			[
+				-0.0193 * t - 0.2592,
+				-0.0665 * t + 0.0008,
+				-0.0004 * t + 0.2125,
+				-0.0641 * t - 0.8989,
+				-0.0033 * t + 0.0452,
+			],
+			[
+				-0.0167 * t - 0.2608,
+				-0.0950 * t + 0.0092,
+				-0.0079 * t + 0.2102,
+				-0.0441 * t - 1.6537,
+				-0.0109 * t + 0.0529,
+			],
+		];
+
+		// The model only covers the sun above the horizon
+		let theta = sun.y.clamp(0.0, 1.0).acos().min(PI_F / 2.0 - 0.01);
+		let chi = (4.0 / 9.0 - t / 120.0) * (PI_F - 2.0 * theta);
+		let polynomial = |m: [[Float; 4]; 3]| {
+			let angles = [theta.powi(3), theta.powi(2), theta, 1.0];
+			let row = |r: [Float; 4]| r.iter().zip(&angles).map(|(a, b)| a * b).sum::<Float>();
+			t * t * row(m[0]) + t * row(m[1]) + row(m[2])
+		};
+		let zenith = [
+			(4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192,
+			polynomial([
+				[0.00166, -0.00375, 0.00209, 0.0],
+				[-0.02903, 0.06377, -0.03202, 0.00394],
+				[0.11693, -0.21196, 0.06052, 0.25886],
+			]),
+			polynomial([
+				[0.00275, -0.00610, 0.00317, 0.0],
+				[-0.04214, 0.08970, -0.04153, 0.00516],
+				[0.15346, -0.26756, 0.06670, 0.26688],
+			]),
+		];
+		let at_zenith = coefficients.map(|c| perez(c, 1.0, theta));
+
+		let mut sky = Sky {
+			sun,
+			cos_sun: (angular_diameter.to_radians() / 2.0).cos(),
+			sun_irradiance: Vec3f::new(0.0, 0.0, 0.0),
+			sun_radiance: Vec3f::new(0.0, 0.0, 0.0),
+			sky_intensity,
+			coefficients,
+			zenith,
+			at_zenith,
+		};
+
+		sky.sun_irradiance = sun_transmittance(sun.y, turbidity) * sun_intensity;
+		sky.sun_radiance = sky.sun_irradiance / (2.0 * PI_F * (1.0 - sky.cos_sun));
+		sky
+	}
+
+	fn xyy(&self, direction: Vec3f) -> [Float; 3] {
+		let cos_theta = direction.y.max(0.001);
+		let gamma = Vec3f::dot(&direction, &self.sun).clamp(-1.0, 1.0).acos();
+		[0, 1, 2].map(|i| {
+			self.zenith[i] * perez(self.coefficients[i], cos_theta, gamma) / self.at_zenith[i]
+		})
+	}
+
+	// Light arriving from `direction`, with the sun disk weighted by `sun` (see sun_weight).
+	// Below the horizon the sky continues with its color at the horizon.
+	pub fn radiance(&self, direction: Vec3f, sun: Float) -> Vec3f {
+		let direction = direction.unit();
+		let [luminance, x, y] = self.xyy(direction);
+		let sky = xyy_to_rgb(x, y, luminance / self.zenith[0] * self.sky_intensity);
+
+		if sun > 0.0 && Vec3f::dot(&direction, &self.sun) >= self.cos_sun {
+			sky + self.sun_radiance * sun
+		} else {
+			sky
+		}
+	}
+
+	// Uniformly distributed over the sun disk
+	fn sample_sun(&self) -> Vec3f {
+		let cos_theta = 1.0 - random::float() * (1.0 - self.cos_sun);
+		let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+		let phi = random::float() * 2.0 * PI_F;
+
+		let helper = if self.sun.x.abs() > 0.9 {
+			Vec3f::new(0.0, 1.0, 0.0)
+		} else {
+			Vec3f::new(1.0, 0.0, 0.0)
+		};
+		let tangent = Vec3f::cross(&helper, &self.sun).unit();
+		let bitangent = Vec3f::cross(&self.sun, &tangent);
+		(tangent * phi.cos() + bitangent * phi.sin()) * sin_theta + self.sun * cos_theta
+	}
+
+	// Density of sample_sun per unit solid angle
+	fn sun_pdf(&self) -> Float {
+		1.0 / (2.0 * PI_F * (1.0 - self.cos_sun))
+	}
+
+	// How much of the sun disk a ray scattered with the material's `pdf` still sees, after the
+	// sun was sampled directly at the hit it leaves. Materials with a pdf share the sun between
+	// both samples by multiple importance sampling, the others leave it to the direct sample.
+	pub fn sun_weight(&self, pdf: Option<Float>) -> Float {
+		pdf.map_or(0.0, |pdf| power(pdf, self.sun_pdf()))
+	}
+
+	// Sunlight reflected at a hit towards the ray through a shadow ray to a random point on the
+	// sun. None for materials that cannot be evaluated for a given direction, which only see the
+	// sun when they happen to scatter into it.
+	pub fn direct(
+		&self,
+		world: &HittableGroup,
+		ray: &Ray,
+		hit_result: &HitResult,
+	) -> Option<Vec3f> {
+		let direction = self.sample_sun();
+		let reflected = hit_result.material.eval(ray, hit_result, direction)?;
+		if reflected.lengthsq() == 0.0 {
+			return Some(reflected);
+		}
+
+		stats::count_ray();
+		let shadow = Ray {
+			origin: hit_result.point,
+			direction,
+			time: ray.time,
+		};
+		if world.hit_any(&Interval::new_ray(), &shadow) {
+			return Some(Vec3f::new(0.0, 0.0, 0.0));
+		}
+
+		// The sun's radiance over the pdf of the sample, its solid angle, is its irradiance
+		let weight = match hit_result.material.pdf(ray, hit_result, direction) {
+			Some(pdf) => power(self.sun_pdf(), pdf),
+			None => 1.0,
+		};
+		Some(reflected * self.sun_irradiance * weight)
+	}
+}
@@ -0,0 +1,209 @@
+use exr::image::write::WritableImage;
+use exr::image::{AnyChannel, AnyChannels, Encoding, FlatSamples, Image, Layer};
+use exr::math::Vec2;
+use exr::meta::attribute::{AttributeValue, IntegerBounds, Text};
+use exr::meta::header::{ImageAttributes, LayerAttributes};
+use exr::prelude::SmallVec;
+
+use raytracer::error::{RendererError, Result};
+use raytracer::geometry::{murmur3, object_id, Float, Vec3f};
+use raytracer::render::{RenderOutput, ID_RANKS};
+
+// Name of the Cryptomatte layer, its parts are CryptoObject00, CryptoObject01, ...
+const CRYPTOMATTE: &str = "CryptoObject";
+
+// AOVs of a render besides its light groups, which are always written
+pub struct Aovs<'a> {
+	pub depth: bool,
+	// Names of the scene objects for the Cryptomatte manifest, None leaves out the ID layers
+	pub objects: Option<&'a [String]>,
+}
+
+impl Aovs<'_> {
+	pub fn count(&self, output: &RenderOutput) -> usize {
+		output.light_groups.len() + self.depth as usize + self.objects.is_some() as usize
+	}
+}
+
+type Channels = AnyChannels<FlatSamples>;
+
+fn text(value: &str, path: &str) -> Result<Text> {
+	Text::new_or_none(value).ok_or_else(|| RendererError::Io {
+		path: path.to_string(),
+		source: std::io::Error::other(format!("'{}' cannot be stored in an EXR header", value)),
+	})
+}
+
+fn channel(name: &str, samples: FlatSamples, path: &str) -> Result<AnyChannel<FlatSamples>> {
+	Ok(AnyChannel::new(text(name, path)?, samples))
+}
+
+// Everything is stored at full float precision, the ID layers would not survive half floats
+#[allow(clippy::unnecessary_cast)]
+fn floats(values: impl Iterator<Item = Float>) -> FlatSamples {
+	FlatSamples::F32(values.map(|value| value as f32).collect())
+}
+
+fn rgb(layer: &str, colors: &[Vec3f], path: &str) -> Result<Vec<AnyChannel<FlatSamples>>> {
+	let prefix = |channel: &str| match layer {
+		"" => channel.to_string(),
+		_ => format!("{}.{}", layer, channel),
+	};
+
+	Ok(vec![
+		channel(
+			&prefix("R"),
+			floats(colors.iter().map(|color| color.x)),
+			path,
+		)?,
+		channel(
+			&prefix("G"),
+			floats(colors.iter().map(|color| color.y)),
+			path,
+		)?,
+		channel(
+			&prefix("B"),
+			floats(colors.iter().map(|color| color.z)),
+			path,
+		)?,
+	])
+}
+
+fn layer(
+	name: &str,
+	channels: Vec<AnyChannel<FlatSamples>>,
+	size: Vec2<usize>,
+	path: &str,
+) -> Result<Layer<Channels>> {
+	Ok(Layer::new(
+		size,
+		LayerAttributes::named(text(name, path)?),
+		Encoding::FAST_LOSSLESS,
+		AnyChannels::sort(SmallVec::from_vec(channels)),
+	))
+}
+
+// Object names mapped to their IDs as hex, non-ASCII characters are escaped so that the JSON
+// fits into an EXR text attribute
+fn manifest(objects: &[String]) -> String {
+	let entries: Vec<String> = objects
+		.iter()
+		.map(|name| {
+			let mut escaped = String::new();
+			for character in name.chars() {
+				match character {
+					'"' => escaped.push_str("\\\""),
+					'\\' => escaped.push_str("\\\\"),
+					' '..='~' => escaped.push(character),
+					_ => {
+						let mut units = [0; 2];
+						for unit in character.encode_utf16(&mut units) {
+							escaped.push_str(&format!("\\u{:04x}", unit));
+						}
+					}
+				}
+			}
+
+			format!("\"{}\":\"{:08x}\"", escaped, object_id(name))
+		})
+		.collect();
+
+	format!("{{{}}}", entries.join(","))
+}
+
+// Cryptomatte layers with two ranks each, the ID of an object stored as the bits of a float
+// followed by its coverage
+fn cryptomatte(
+	output: &RenderOutput,
+	objects: &[String],
+	size: Vec2<usize>,
+	path: &str,
+) -> Result<Vec<Layer<Channels>>> {
+	let key = &format!("{:08x}", murmur3(CRYPTOMATTE.as_bytes(), 0))[..7];
+	let prefix = format!("cryptomatte/{}/", key);
+	let manifest = manifest(objects);
+
+	let mut layers = Vec::new();
+	for part in 0..ID_RANKS / 2 {
+		let name = format!("{}{:02}", CRYPTOMATTE, part);
+		let ids = |rank: usize| {
+			FlatSamples::F32(
+				output
+					.ids
+					.iter()
+					.map(|ids| f32::from_bits(ids[part * 2 + rank].0))
+					.collect(),
+			)
+		};
+		let coverage = |rank: usize| floats(output.ids.iter().map(|ids| ids[part * 2 + rank].1));
+
+		let channels = vec![
+			channel(&format!("{}.R", name), ids(0), path)?,
+			channel(&format!("{}.G", name), coverage(0), path)?,
+			channel(&format!("{}.B", name), ids(1), path)?,
+			channel(&format!("{}.A", name), coverage(1), path)?,
+		];
+
+		let mut layer = layer(&name, channels, size, path)?;
+		for (attribute, value) in [
+			("name", CRYPTOMATTE),
+			("hash", "MurmurHash3_32"),
+			("conversion", "uint32_to_float32"),
+			("manifest", &manifest),
+		] {
+			layer.attributes.other.insert(
+				text(&format!("{}{}", prefix, attribute), path)?,
+				AttributeValue::Text(text(value, path)?),
+			);
+		}
+
+		layers.push(layer);
+	}
+
+	Ok(layers)
+}
+
+// Writes the render and its AOVs as the parts of one EXR, with channels named layer.channel the
+// way Nuke and Blender expect. The beauty pass is the unprefixed RGBA part.
+pub fn write_exr(output: &RenderOutput, aovs: &Aovs, path: &str) -> Result<()> {
+	let size = Vec2(
+		output.image.width() as usize,
+		output.image.height() as usize,
+	);
+
+	let mut beauty = rgb("", &output.colors, path)?;
+	beauty.push(channel("A", floats(output.alpha.iter().cloned()), path)?);
+	let mut layers = vec![layer("rgba", beauty, size, path)?];
+
+	for group in &output.light_groups {
+		layers.push(layer(
+			&group.name,
+			rgb(&group.name, &group.colors, path)?,
+			size,
+			path,
+		)?);
+	}
+
+	if aovs.depth {
+		let bounces = floats(output.depth.iter().cloned());
+		layers.push(layer(
+			"bounces",
+			vec![channel("bounces.Y", bounces, path)?],
+			size,
+			path,
+		)?);
+	}
+
+	if let Some(objects) = aovs.objects {
+		layers.extend(cryptomatte(output, objects, size, path)?);
+	}
+
+	let attributes = ImageAttributes::new(IntegerBounds::from_dimensions(size));
+	Image::from_layers(attributes, layers)
+		.write()
+		.to_file(path)
+		.map_err(|error| RendererError::Io {
+			path: path.to_string(),
+			source: std::io::Error::other(error),
+		})
+}
@@ -0,0 +1,24 @@
+pub mod animation;
+pub mod aperture;
+pub mod assets;
+pub mod camera;
+pub mod dither;
+pub mod effects;
+pub mod error;
+pub mod exposure;
+pub mod film;
+pub mod filter;
+pub mod geometry;
+pub mod integrator;
+pub mod lut;
+pub mod material;
+pub mod random;
+pub mod render;
+pub mod scene_builder;
+pub mod scene_file;
+pub mod scenes;
+pub mod script;
+pub mod stats;
+
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
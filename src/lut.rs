@@ -0,0 +1,130 @@
+use crate::error::{RendererError, Result};
+use crate::geometry::{Float, Vec3f};
+
+// A 3D color lookup table from a .cube file as written by Resolve, Nuke and most grading tools,
+// applied to the display colors of the image. Colors between the entries are interpolated
+// trilinearly.
+#[derive(Debug, Clone)]
+pub struct Lut {
+	size: usize,
+	domain_min: Vec3f,
+	domain_max: Vec3f,
+	// Output colors with red changing fastest, then green, then blue
+	table: Vec<Vec3f>,
+}
+
+// Grading tools write 17, 33 or 65 entries per side, beyond this a file is most likely broken
+const MAX_SIZE: usize = 256;
+
+impl Lut {
+	pub fn load(path: &str) -> Result<Lut> {
+		let text = std::fs::read_to_string(path).map_err(|source| RendererError::Io {
+			path: path.to_string(),
+			source,
+		})?;
+
+		Lut::parse(&text).map_err(|message| RendererError::Lut {
+			path: path.to_string(),
+			message,
+		})
+	}
+
+	pub fn parse(text: &str) -> std::result::Result<Lut, String> {
+		let mut size = None;
+		let mut domain_min = Vec3f::new(0.0, 0.0, 0.0);
+		let mut domain_max = Vec3f::new(1.0, 1.0, 1.0);
+		let mut table = Vec::new();
+
+		for (number, line) in (1..).zip(text.lines()) {
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+			let error = |message: String| format!("line {}: {}", number, message);
+			let (keyword, rest) = match line.split_once(char::is_whitespace) {
+				Some((keyword, rest)) => (keyword, rest),
+				None => (line, ""),
+			};
+			let color = |words: &str| match numbers(words).map_err(error)?[..] {
+				[r, g, b] => Ok(Vec3f::new(r, g, b)),
+				_ => Err(error("expected three numbers".to_string())),
+			};
+
+			match keyword {
+				"TITLE" => {}
+				"LUT_3D_SIZE" => match rest.trim().parse() {
+					Ok(value @ 2..=MAX_SIZE) => size = Some(value),
+					_ => return Err(error(format!("the size must be from 2 to {}", MAX_SIZE))),
+				},
+				"LUT_1D_SIZE" => return Err(error("only 3D LUTs are supported".to_string())),
+				"DOMAIN_MIN" => domain_min = color(rest)?,
+				"DOMAIN_MAX" => domain_max = color(rest)?,
+				// Resolve's older way of writing a domain that is the same for all channels
+				"LUT_3D_INPUT_RANGE" => match numbers(rest).map_err(error)?[..] {
+					[min, max] => {
+						domain_min = Vec3f::new(min, min, min);
+						domain_max = Vec3f::new(max, max, max);
+					}
+					_ => return Err(error("expected two numbers".to_string())),
+				},
+				_ if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+					return Err(error(format!("unknown keyword '{}'", keyword)))
+				}
+				_ => table.push(color(line)?),
+			}
+		}
+
+		let size = size.ok_or("there is no LUT_3D_SIZE")?;
+		if table.len() != size * size * size {
+			return Err(format!(
+				"expected {} entries for a size of {}, found {}",
+				size * size * size,
+				size,
+				table.len()
+			));
+		}
+		if (0..3).any(|axis| domain_max[axis] <= domain_min[axis]) {
+			return Err("DOMAIN_MAX must be above DOMAIN_MIN".to_string());
+		}
+
+		Ok(Lut {
+			size,
+			domain_min,
+			domain_max,
+			table,
+		})
+	}
+
+	pub fn apply(&self, color: Vec3f) -> Vec3f {
+		let last = (self.size - 1) as Float;
+		let coordinate = |axis: usize| {
+			let t = (color[axis] - self.domain_min[axis])
+				/ (self.domain_max[axis] - self.domain_min[axis]);
+			let position = t.clamp(0.0, 1.0) * last;
+			let index = (position as usize).min(self.size - 2);
+			(index, position - index as Float)
+		};
+		let ((r, fr), (g, fg), (b, fb)) = (coordinate(0), coordinate(1), coordinate(2));
+		let at = |r: usize, g: usize, b: usize| self.table[(b * self.size + g) * self.size + r];
+		let lerp = |a: Vec3f, b: Vec3f, t: Float| a * (1.0 - t) + b * t;
+
+		let plane = |b: usize| {
+			lerp(
+				lerp(at(r, g, b), at(r + 1, g, b), fr),
+				lerp(at(r, g + 1, b), at(r + 1, g + 1, b), fr),
+				fg,
+			)
+		};
+		lerp(plane(b), plane(b + 1), fb)
+	}
+}
+
+fn numbers(words: &str) -> std::result::Result<Vec<Float>, String> {
+	words
+		.split_whitespace()
+		.map(|word| {
+			word.parse()
+				.map_err(|_| format!("'{}' is not a number", word))
+		})
+		.collect()
+}
@@ -1,350 +1,558 @@
-mod camera;
-mod geometry;
-mod material;
-
-use image::{ImageBuffer, Rgb, RgbImage};
+mod cli;
+mod layers;
+mod server;
+mod tev;
+mod video;
+mod watch;
+
+use image::RgbImage;
 use std::io::Write;
-use std::sync::{Arc, Mutex};
-use std::thread::JoinHandle;
-
-use camera::Camera;
-use geometry::{load_mesh, HittableGroup, Plane, Sphere, Vec3f};
-use material::{CheckerTexture, Diffuse, DiffuseLight, ImageTexture, Metal, SolidColor, Texture};
-
-const IMAGE_WIDTH: u32 = 768;
-const IMAGE_HEIGHT: u32 = 768;
-const SAMPLES: usize = 444;
-const THREADS: usize = 64;
-const BACKGROUND: Vec3f = Vec3f {
-	x: 0.0,
-	y: 0.0,
-	z: 0.0,
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use cli::{Command, Options};
+use layers::{write_exr, Aovs};
+use raytracer::animation::{CameraAnimation, Turntable};
+use raytracer::assets::Assets;
+use raytracer::camera::Camera;
+use raytracer::error::{RendererError, Result};
+use raytracer::geometry::{Float, HittableGroup};
+use raytracer::integrator::{Integrator, PathTracer};
+use raytracer::lut::Lut;
+use raytracer::material::Overridden;
+use raytracer::render::{
+	render, render_for, OutputFormat, PostProcess, PreviewCallback, Progress, RenderOutput,
+	RenderSettings,
 };
-
-#[derive(Copy, Clone)]
-struct Pixel {
-	r: u8,
-	g: u8,
-	b: u8,
+use raytracer::scene_file::SceneFile;
+use raytracer::scenes::{SCENES, SCENE_FILES};
+use raytracer::stats::RenderStats;
+use tev::TevClient;
+use video::VideoWriter;
+
+const FRAMES_DIR: &str = "frames";
+const FPS: u32 = 30;
+
+fn update_progress(progress: Progress) {
+	let percent = f32::round(progress.completed as f32 / progress.total as f32 * 100.0);
+	print!(
+		"\r{}% complete, {:.2} Mrays/s...",
+		percent,
+		progress.rays_per_second / 1e6
+	);
+	std::io::stdout().flush().unwrap();
 }
 
-struct ImageFragment {
-	pixels: Vec<Pixel>,
-}
+// The settings of the scene file, or the default ones for built-in scenes, with the preset and
+// the flags of the options on top. The resolution is not scaled yet.
+fn render_settings(options: &Options, scene_file: Option<&SceneFile>) -> Result<RenderSettings> {
+	let mut settings = match scene_file {
+		Some(scene_file) => scene_file.settings(RenderSettings::default())?,
+		None => RenderSettings::default(),
+	};
+	if let Some(preset) = options.preset {
+		settings = preset.apply(settings);
+	}
 
-impl ImageFragment {
-	fn new(pixel_count: u32) -> Self {
-		ImageFragment {
-			pixels: Vec::with_capacity(pixel_count as usize),
-		}
+	settings.scale = options.scale.unwrap_or(settings.scale);
+	if options.scale_samples {
+		settings.samples = ((settings.samples as Float * settings.scale).round() as usize).max(1);
+	}
+	settings.region = options.region;
+	settings.threads = options.threads.unwrap_or(settings.threads);
+	settings.seed = options.seed.or(settings.seed);
+	settings.clamp = options.clamp.or(settings.clamp);
+	settings.filter = options.filter.unwrap_or(settings.filter);
+	if let Some(offset) = options.sample_offset {
+		settings.sample_offset = offset.for_frame(0);
 	}
+	settings.format = options.format.unwrap_or(settings.format);
+	settings.aovs.depth |= options.depth_aov;
+	settings.aovs.ids |= options.id_aov;
+	settings.aovs.exposure |= options.exposure_check;
+	Ok(settings)
 }
 
-fn update_progress(completed: u32) {
-	let percent = f32::round(completed as f32 / IMAGE_HEIGHT as f32 * 100.0);
-	print!("\r{}% complete...", percent);
-	std::io::stdout().flush().unwrap();
+// The post-processing of the scene file, or the default one for built-in scenes, with the flags
+// of the options on top
+fn post_process(options: &Options, scene_file: Option<&SceneFile>) -> Result<PostProcess> {
+	let mut post = match scene_file {
+		Some(scene_file) => scene_file.post_process()?,
+		None => PostProcess::default(),
+	};
+	post.dither = options.dither.or(post.dither);
+	if let Some(path) = &options.lut {
+		post.lut = Some(Arc::new(Lut::load(path)?));
+	}
+	Ok(post)
 }
 
-fn render(
-	world: Arc<HittableGroup>,
-	camera: Arc<Camera>,
-	row_start: u32,
-	row_end: u32,
-	completed: Arc<Mutex<u32>>,
-) -> ImageFragment {
-	let rows = row_end - row_start;
-	let mut fragment = ImageFragment::new(rows * IMAGE_WIDTH);
-
-	for row in row_start..row_end {
-		let y = row;
-		for x in 0..IMAGE_WIDTH {
-			let mut color = Vec3f::new(0.0, 0.0, 0.0);
-			for _ in 0..SAMPLES {
-				let ray = camera.intial_ray(x, y);
-				let value = camera.raycast(&ray, &world, 5);
-				color = color + value;
-			}
-
-			color = color / SAMPLES as f64;
-
-			fragment.pixels.push(Pixel {
-				r: (color.x * 255.0) as u8,
-				g: (color.y * 255.0) as u8,
-				b: (color.z * 255.0) as u8,
-			});
-		}
-
-		{
-			let mut counter = completed.lock().unwrap();
-			*counter += 1;
-			update_progress(*counter);
-		}
+// Bad samples are left out of the image but usually point at a bug in a material or a mesh
+fn warn_rejected(output: &RenderOutput) {
+	if output.rejected > 0 {
+		log::warn!(
+			"{} samples were NaN or infinite and left out",
+			output.rejected
+		);
 	}
-
-	fragment
 }
 
-#[allow(dead_code, unused_variables)]
-fn scene_cube(scene: &mut HittableGroup) -> Arc<Camera> {
-	let camera = Arc::new(Camera::new(
-		BACKGROUND,
-		Vec3f::new(0.0, 1.0, 0.0),
-		Vec3f::new(0.0, 0.0, -5.0),
-		70.0,
-		IMAGE_WIDTH as u32,
-		IMAGE_HEIGHT as u32,
-	));
-
-	let plane_texture = Arc::new(CheckerTexture::new(
-		Vec3f::new(1.0, 1.0, 1.0),
-		Vec3f::new(0.7, 0.0, 0.0),
-		1.0,
-	));
-
-	let plane_diffuse = Arc::new(Diffuse::new(plane_texture));
-
-	let gray_texture = Arc::new(SolidColor::new(Vec3f::new(0.8, 0.8, 0.8)));
-	let gray_metal = Arc::new(Metal::new(gray_texture));
-
-	let purple_texture = Arc::new(SolidColor::new(Vec3f::new(0.98, 0.70, 0.65)));
-	let purple_diffuse = Arc::new(Diffuse::new(purple_texture));
-
-	let ant_texture: Arc<dyn Texture> = Arc::new(ImageTexture::new("models/IS.png"));
-	let ant_diffuse = Arc::new(Diffuse::new(Arc::clone(&ant_texture)));
-	let ant_metal = Arc::new(Metal::new(ant_texture));
-
-	let white_texture = Arc::new(SolidColor::new(Vec3f::new(10.0, 10.0, 10.0)));
-	let white_light = Arc::new(DiffuseLight::new(white_texture));
-
-	let ant = load_mesh("models/cube.obj", ant_metal);
-
-	scene.add(Box::new(ant));
-
-	scene.add(Box::new(Plane::new(
-		Vec3f::new(1.0, 0.0, 0.0),
-		Vec3f::new(0.0, 0.0, -1.0),
-		Vec3f::new(0.0, -0.1, 0.0),
-		plane_diffuse,
-	)));
-
-	/*
-	scene.add(Box::new(Sphere::new(
-		Vec3f::new(0.0, 1.5, -5.0),
-		1.0,
-		gray_metal,
-	)));
-
-	 */
-	camera
+fn render_frame(
+	options: &Options,
+	scene: &Arc<HittableGroup>,
+	camera: Arc<Camera>,
+	integrator: &Arc<dyn Integrator>,
+	settings: &RenderSettings,
+	post: &PostProcess,
+	preview: &Option<PreviewCallback>,
+) -> Result<RenderOutput> {
+	let Some(budget) = options.time else {
+		let output = render(
+			Arc::clone(scene),
+			camera,
+			Arc::clone(integrator),
+			settings,
+			post,
+			Arc::new(update_progress),
+			preview.clone(),
+			None,
+		)?;
+
+		// End the progress line before anything else gets logged
+		println!();
+		warn_rejected(&output);
+		return Ok(output);
+	};
+
+	let output = render_for(
+		Arc::clone(scene),
+		camera,
+		Arc::clone(integrator),
+		settings,
+		budget,
+		post,
+		Arc::new(update_progress),
+		preview.clone(),
+		None,
+	)?;
+
+	println!();
+	warn_rejected(&output);
+	log::info!("{} samples per pixel", output.samples);
+	Ok(output)
 }
 
-#[allow(dead_code, unused_variables)]
-fn scene_tank(scene: &mut HittableGroup) -> Arc<Camera> {
-	let camera = Arc::new(Camera::new(
-		BACKGROUND,
-		Vec3f::new(-1.0, 6.0, 20.0),
-		Vec3f::new(0.0, 0.0, -5.0),
-		70.0,
-		IMAGE_WIDTH as u32,
-		IMAGE_HEIGHT as u32,
-	));
-
-	let plane_texture = Arc::new(CheckerTexture::new(
-		Vec3f::new(0.75, 0.75, 0.75),
-		Vec3f::new(103.0 / 255.0, 197.0 / 255.0, 211.0 / 255.0),
-		0.5,
-	));
-
-	let plane_diffuse = Arc::new(Diffuse::new(plane_texture));
-
-	let gray_texture = Arc::new(SolidColor::new(Vec3f::new(0.8, 0.8, 0.8)));
-	let gray_metal = Arc::new(Metal::new(gray_texture));
-
-	let purple_texture = Arc::new(SolidColor::new(Vec3f::new(0.98, 0.70, 0.65)));
-	let purple_diffuse = Arc::new(Diffuse::new(purple_texture));
-
-	let ant_texture: Arc<dyn Texture> = Arc::new(ImageTexture::new("models/IS.png"));
-	let ant_diffuse = Arc::new(Diffuse::new(Arc::clone(&ant_texture)));
-	let ant_metal = Arc::new(Metal::new(ant_texture));
-
-	let white_texture = Arc::new(SolidColor::new(Vec3f::new(10.0, 10.0, 10.0)));
-	let white_light = Arc::new(DiffuseLight::new(white_texture));
-
-	let ant = load_mesh("models/IS.obj", ant_diffuse);
-
-	scene.add(Box::new(ant));
-
-	scene.add(Box::new(Plane::new(
-		Vec3f::new(1.0, 0.0, 0.0),
-		Vec3f::new(0.0, 0.0, -1.0),
-		Vec3f::new(0.0, -0.1, 0.0),
-		plane_diffuse,
-	)));
-
-	scene.add(Box::new(Sphere::new(
-		Vec3f::new(1.0, 25.0, 10.0),
-		7.5,
-		white_light,
-	)));
-
-	camera
+fn save(buffer: &RgbImage, path: &str) -> Result<()> {
+	buffer.save(path).map_err(|source| RendererError::Image {
+		path: path.to_string(),
+		source,
+	})
 }
 
-#[allow(dead_code, unused_variables)]
-fn scene_ant(scene: &mut HittableGroup) -> Arc<Camera> {
-	let camera = Arc::new(Camera::new(
-		BACKGROUND,
-		Vec3f::new(0.0, 2.0, 0.0),
-		Vec3f::new(0.0, 0.0, -5.0),
-		90.0,
-		IMAGE_WIDTH as u32,
-		IMAGE_HEIGHT as u32,
-	));
+// RGBA if holdouts cut into the image, RGB otherwise
+fn save_render(output: &RenderOutput, path: &str) -> Result<()> {
+	let result = if output.is_opaque() {
+		output.image.save(path)
+	} else {
+		output.rgba_image().save(path)
+	};
+
+	result.map_err(|source| RendererError::Image {
+		path: path.to_string(),
+		source,
+	})
+}
 
-	let plane_texture = Arc::new(CheckerTexture::new(
-		Vec3f::new(1.0, 1.0, 1.0),
-		Vec3f::new(0.7, 0.0, 0.0),
-		1.0,
-	));
+fn save_depth(output: &RenderOutput, path: &str) -> Result<()> {
+	log::info!(
+		"Depth AOV in {}, red is {:.2} bounces per sample",
+		path,
+		output.max_depth()
+	);
+	save(&output.depth_image(), path)
+}
 
-	let plane_diffuse = Arc::new(Diffuse::new(plane_texture));
+fn save_exposure(output: &RenderOutput, false_color: &str, histogram: &str) -> Result<()> {
+	let (clipped, crushed) = output.clipping();
+	log::info!(
+		"Exposure in {} and {}, {:.1}% of the pixels clip and {:.1}% are black",
+		false_color,
+		histogram,
+		clipped * 100.0,
+		crushed * 100.0
+	);
+	save(&output.false_color_image(), false_color)?;
+	save(&output.histogram_image(), histogram)
+}
 
-	let gray_texture = Arc::new(SolidColor::new(Vec3f::new(0.8, 0.8, 0.8)));
-	let gray_metal = Arc::new(Metal::new(gray_texture));
+// Writes the render and the AOVs that are enabled, `path` makes a file name from a layer name
+// ("" for the render itself) and an extension. With more than one AOV, or the EXR format, they
+// go into a single multi-layer EXR together with the render instead of separate images.
+fn save_all(
+	settings: &RenderSettings,
+	output: &RenderOutput,
+	objects: &[String],
+	path: impl Fn(&str, &str) -> String,
+) -> Result<()> {
+	let aovs = Aovs {
+		depth: settings.aovs.depth,
+		objects: settings.aovs.ids.then_some(objects),
+	};
+	if settings.aovs.exposure {
+		save_exposure(
+			output,
+			&path("false_color", "png"),
+			&path("histogram", "png"),
+		)?;
+	}
+	if settings.format == OutputFormat::Exr {
+		return write_exr(output, &aovs, &path("", "exr"));
+	}
 
-	let purple_texture = Arc::new(SolidColor::new(Vec3f::new(0.98, 0.70, 0.65)));
-	let purple_diffuse = Arc::new(Diffuse::new(purple_texture));
+	save_render(output, &path("", "png"))?;
+	if aovs.count(output) > 1 {
+		let exr = path("", "exr");
+		log::info!("AOVs in {}", exr);
+		return write_exr(output, &aovs, &exr);
+	}
 
-	let ant_texture: Arc<dyn Texture> = Arc::new(ImageTexture::new("models/ant.png"));
-	let ant_diffuse = Arc::new(Diffuse::new(Arc::clone(&ant_texture)));
-	let ant_metal = Arc::new(Metal::new(ant_texture));
+	for group in &output.light_groups {
+		save(&group.image, &path(&group.name, "png"))?;
+	}
+	if settings.aovs.depth {
+		save_depth(output, &path("depth", "png"))?;
+	}
+	if settings.aovs.ids {
+		save(&output.id_image(), &path("id", "png"))?;
+	}
 
-	let white_texture = Arc::new(SolidColor::new(Vec3f::new(10.0, 10.0, 10.0)));
-	let white_light = Arc::new(DiffuseLight::new(white_texture));
+	Ok(())
+}
 
-	let ant = load_mesh("models/ant.obj", purple_diffuse);
+// Logs what the built scene keeps in memory, as a hint at what to cut down in scenes that do
+// not fit
+fn report_scene() {
+	let stats = RenderStats::collect(Duration::ZERO);
+	let megabytes = |bytes: u64| bytes as f64 / 1e6;
+
+	log::info!(
+		"{} triangles, {} BVH nodes, {:.1} MB of geometry and {:.1} MB of textures, about {:.1} MB \
+		 in total",
+		stats.triangles,
+		stats.bvh_nodes,
+		megabytes(stats.geometry_bytes),
+		megabytes(stats.texture_bytes),
+		megabytes(stats.memory_bytes())
+	);
+}
 
-	scene.add(Box::new(ant));
+// Logs the statistics of the whole run and optionally writes them to `path` as JSON
+fn report(render_time: Duration, path: Option<&str>) -> Result<()> {
+	let stats = RenderStats::collect(render_time);
+
+	log::info!(
+		"{} triangles, BVH built in {:.0?}",
+		stats.triangles,
+		stats.bvh_build
+	);
+	log::info!(
+		"{} rays in {:.1?}, {:.2} Mrays/s, average depth {:.2}",
+		stats.rays,
+		stats.render_time,
+		stats.rays_per_second() / 1e6,
+		stats.average_depth()
+	);
+
+	let Some(path) = path else {
+		return Ok(());
+	};
+
+	std::fs::write(path, stats.to_json()).map_err(|source| RendererError::Io {
+		path: path.to_string(),
+		source,
+	})
+}
 
-	scene.add(Box::new(Plane::new(
-		Vec3f::new(1.0, 0.0, 0.0),
-		Vec3f::new(0.0, 0.0, -1.0),
-		Vec3f::new(0.0, -0.1, 0.0),
-		plane_diffuse,
-	)));
+fn connect_tev(address: &str, width: u32, height: u32) -> Result<PreviewCallback> {
+	let client = TevClient::connect(address, "render", width, height).map_err(|source| {
+		RendererError::Io {
+			path: address.to_string(),
+			source,
+		}
+	})?;
+	let client = Arc::new(client);
+	Ok(Arc::new(move |region, colors| {
+		client.update(region, colors)
+	}))
+}
 
-	scene.add(Box::new(Sphere::new(
-		Vec3f::new(1.0, 4.5, -3.5),
-		2.0,
-		white_light,
-	)));
+// The scene with every material replaced by the override of the options, if there is one
+fn override_materials(options: &Options, scene: HittableGroup) -> HittableGroup {
+	let Some(material) = options.material_override else {
+		return scene;
+	};
 
-	camera
+	let mut overridden = HittableGroup::new();
+	overridden.add(Box::new(Overridden::new(Box::new(scene), material)));
+	overridden
 }
 
-#[allow(dead_code, unused_variables)]
-fn scene_spheres(scene: &mut HittableGroup) -> Arc<Camera> {
-	let camera = Arc::new(Camera::new(
-		BACKGROUND,
-		Vec3f::new(0.0, 0.0, 0.0),
-		Vec3f::new(0.0, 0.0, -1.0),
-		70.0,
-		IMAGE_WIDTH as u32,
-		IMAGE_HEIGHT as u32,
-	));
-
-	let plane_texture = Arc::new(CheckerTexture::new(
-		Vec3f::new(1.0, 1.0, 1.0),
-		Vec3f::new(0.7, 0.0, 0.0),
-		10.0,
-	));
-
-	let plane_diffuse = Arc::new(Diffuse::new(plane_texture));
-
-	let gray_texture = Arc::new(SolidColor::new(Vec3f::new(0.8, 0.8, 0.8)));
-	let gray_metal = Arc::new(Metal::new(gray_texture));
-
-	let purple_texture = Arc::new(SolidColor::new(Vec3f::new(0.98, 0.70, 0.65)));
-	let purple_diffuse = Arc::new(Diffuse::new(purple_texture));
-
-	let white_texture = Arc::new(SolidColor::new(Vec3f::new(1.0, 1.0, 1.0)));
-	let white_light = Arc::new(DiffuseLight::new(white_texture));
-
-	scene.add(Box::new(Plane::new(
-		Vec3f::new(1.0, 0.0, 0.0),
-		Vec3f::new(0.0, 0.0, -1.0),
-		Vec3f::new(0.0, -0.1, 0.0),
-		plane_diffuse,
-	)));
-
-	scene.add(Box::new(Sphere::new(
-		Vec3f::new(-0.4, 0.35, -1.0),
-		0.2,
-		gray_metal,
-	)));
-
-	scene.add(Box::new(Sphere::new(
-		Vec3f::new(0.25, 0.15, -1.0),
-		0.2,
-		white_light,
-	)));
-
-	camera
+// render.png and render_LAYER.png etc.
+fn render_path(layer: &str, extension: &str) -> String {
+	match layer {
+		"" => format!("render.{}", extension),
+		_ => format!("render_{}.{}", layer, extension),
+	}
 }
 
-fn main() {
-	let mut scene = HittableGroup::new();
-
-	let camera = scene_tank(&mut scene);
-
-	let scene: Arc<HittableGroup> = Arc::new(scene);
+// Writes the scene file or built-in scene given on the command line to `path`
+fn export(options: &Options, path: &str) -> Result<()> {
+	let scene_file = match &options.scene_file {
+		Some(scene_path) => SceneFile::load(scene_path)?,
+		None => {
+			let name = options.scene_name.as_deref().unwrap_or(SCENES[0].0);
+			let Some((_, describe)) = SCENE_FILES.iter().find(|(scene, _)| *scene == name) else {
+				let names: Vec<&str> = SCENE_FILES.iter().map(|(name, _)| *name).collect();
+				return Err(RendererError::Scene {
+					path: None,
+					message: format!(
+						"the built-in scene {} cannot be exported, only {}",
+						name,
+						names.join(", ")
+					),
+				});
+			};
+			let settings = render_settings(options, None)?;
+			let mut scene_file = describe(settings.width, settings.height);
+			scene_file.render.samples = settings.samples;
+			scene_file
+		}
+	};
+
+	std::fs::write(path, scene_file.to_toml()?).map_err(|source| RendererError::Io {
+		path: path.to_string(),
+		source,
+	})?;
+	log::info!("{} objects written to {}", scene_file.objects.len(), path);
+	Ok(())
+}
 
-	let mut threads: Vec<JoinHandle<ImageFragment>> = Vec::new();
+fn run(options: Options) -> Result<()> {
+	if let Some(path) = &options.export {
+		return export(&options, path);
+	}
 
-	let rows_common = IMAGE_HEIGHT / (THREADS as u32);
-	let rows_last = rows_common + IMAGE_HEIGHT % (THREADS as u32);
-	let mut rows = [rows_common; THREADS];
-	rows[THREADS - 1] = rows_last;
+	if options.watch {
+		return watch::watch(&options);
+	}
 
-	println!("Rendering...");
+	// Keeps the meshes and textures of scenes whose objects move between the builds of the frames
+	let assets = Assets::default();
+	let (scene, camera, settings, post, integrator, objects, scene_file) = match &options.scene_file
+	{
+		Some(path) => {
+			let scene_file = SceneFile::load(path)?;
+			let (scene, camera) = scene_file.build_with(&assets)?;
+			let settings = render_settings(&options, Some(&scene_file))?;
+			(
+				scene,
+				camera,
+				settings,
+				post_process(&options, Some(&scene_file))?,
+				scene_file.integrator(&settings)?,
+				scene_file.object_names(),
+				Some(scene_file),
+			)
+		}
+		None => {
+			let settings = render_settings(&options, None)?;
+			let mut scene = HittableGroup::new();
+			let build = options.scene.unwrap_or(SCENES[0].1);
+			let camera = build(&mut scene, settings.width, settings.height)?;
+			(
+				scene,
+				camera,
+				settings,
+				post_process(&options, None)?,
+				Arc::new(PathTracer::new(settings.max_depth)) as Arc<dyn Integrator>,
+				Vec::new(),
+				None,
+			)
+		}
+	};
+	report_scene();
+	let settings = settings.scaled();
+	let camera = Arc::new(camera.resized(settings.width, settings.height));
+	let (width, height) = (settings.width, settings.height);
+
+	let scene = Arc::new(override_materials(&options, scene));
+	let integrator = options.integrator.clone().unwrap_or(integrator);
+
+	let preview = options
+		.tev
+		.as_ref()
+		.map(|address| connect_tev(address, width, height))
+		.transpose()?;
+
+	let camera_path = match &scene_file {
+		Some(scene_file) => scene_file.camera_path()?,
+		None => None,
+	};
+	let animation: Option<Box<dyn CameraAnimation>> = match (options.turntable, camera_path) {
+		(Some(frames), _) => {
+			if scene_file
+				.as_ref()
+				.is_some_and(|scene_file| !scene_file.camera.keys.is_empty())
+			{
+				log::warn!("The turntable replaces the camera keys of the scene file");
+			}
+			let mut turntable = Turntable::from_camera(&camera, frames);
+			if let Some(radius) = options.radius {
+				turntable.radius = radius;
+			}
+			if let Some(elevation) = options.elevation {
+				turntable.elevation = elevation;
+			}
+			Some(Box::new(turntable))
+		}
+		(None, camera_path) => camera_path.map(|path| Box::new(path) as Box<dyn CameraAnimation>),
+	};
+
+	let Some(animation) = animation else {
+		println!("Rendering...");
+
+		let start = Instant::now();
+		let output = render_frame(
+			&options,
+			&scene,
+			camera,
+			&integrator,
+			&settings,
+			&post,
+			&preview,
+		)?;
+		let render_time = start.elapsed();
+
+		save_all(&settings, &output, &objects, render_path)?;
+		return report(render_time, options.stats.then_some("render.json"));
+	};
+
+	let frames = animation.frames();
+	let mut video = match &options.video {
+		Some(path) => {
+			let fps = options.fps.unwrap_or(FPS);
+			let writer =
+				VideoWriter::new(path, width, height, fps).map_err(|source| RendererError::Io {
+					path: path.clone(),
+					source,
+				})?;
+			Some(writer)
+		}
+		None => {
+			std::fs::create_dir_all(FRAMES_DIR).map_err(|source| RendererError::Io {
+				path: FRAMES_DIR.to_string(),
+				source,
+			})?;
+			None
+		}
+	};
 
-	let completed = Arc::new(Mutex::new(0u32));
+	if video.is_some() && !integrator.light_groups().is_empty() {
+		log::warn!("Light groups are not written for videos, render to frames/ instead");
+	}
 
-	let mut start = 0;
-	for i in 0..THREADS {
-		let new_scene = Arc::clone(&scene);
-		let new_camera = Arc::clone(&camera);
-		let new_counter = Arc::clone(&completed);
-		let end = start + rows[i];
+	let mut render_time = Duration::ZERO;
 
-		threads.push(std::thread::spawn(move || {
-			render(new_scene, new_camera, start, end, new_counter)
-		}));
+	for frame in 0..frames {
+		println!("Rendering frame {}/{}...", frame + 1, frames);
 
-		start = end;
+		let frame_camera = Arc::new(animation.camera(&camera, frame));
+		let frame_scene = match scene_file
+			.as_ref()
+			.filter(|file| file.has_animated_objects())
+		{
+			Some(scene_file) => {
+				let (scene, _) = scene_file.build_frame(&assets, frame)?;
+				integrator.scene_changed();
+				Arc::new(override_materials(&options, scene))
+			}
+			None => scene.clone(),
+		};
+		let frame_settings = RenderSettings {
+			sample_offset: options
+				.sample_offset
+				.map_or(settings.sample_offset, |offset| offset.for_frame(frame)),
+			..settings
+		};
+		let start = Instant::now();
+		let output = render_frame(
+			&options,
+			&frame_scene,
+			frame_camera,
+			&integrator,
+			&frame_settings,
+			&post,
+			&preview,
+		)?;
+		render_time += start.elapsed();
+
+		match video.as_mut() {
+			Some(video) => {
+				video
+					.write_frame(&output.image)
+					.map_err(|source| RendererError::Io {
+						path: options.video.clone().unwrap_or_default(),
+						source,
+					})?
+			}
+			None => save_all(&settings, &output, &objects, |layer, extension| {
+				let layer = if layer.is_empty() { "frame" } else { layer };
+				format!("{}/{}_{:04}.{}", FRAMES_DIR, layer, frame, extension)
+			})?,
+		}
 	}
 
-	let fragments: Vec<ImageFragment> = threads
-		.into_iter()
-		.map(|handle| handle.join().unwrap())
-		.collect();
+	if let Some(video) = video {
+		video.finish().map_err(|source| RendererError::Io {
+			path: options.video.clone().unwrap_or_default(),
+			source,
+		})?;
+	}
 
-	let mut buffer: RgbImage = ImageBuffer::new(IMAGE_WIDTH, IMAGE_HEIGHT);
+	// Statistics cover every frame and end up next to the video or in the frames directory
+	let stats_path = match &options.video {
+		Some(path) => Path::new(path).with_extension("json"),
+		None => Path::new(FRAMES_DIR).join("stats.json"),
+	};
+
+	report(
+		render_time,
+		options
+			.stats
+			.then(|| stats_path.to_string_lossy())
+			.as_deref(),
+	)
+}
 
-	let mut x = 0;
-	let mut y = 0;
-	for fragment in fragments {
-		for pixel in fragment.pixels {
-			buffer.put_pixel(x, y, Rgb([pixel.r, pixel.g, pixel.b]));
+fn main() {
+	env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
 
-			x += 1;
-			if x == IMAGE_WIDTH {
-				x = 0;
-				y += 1;
-			}
+	let command = match cli::parse(std::env::args().skip(1)) {
+		Ok(command) => command,
+		Err(err) => {
+			eprintln!("{}\n\n{}", err, cli::USAGE);
+			std::process::exit(1);
 		}
-	}
+	};
 
-	buffer.save("render.png").unwrap();
+	let result = match command {
+		Command::Render(options) => run(*options),
+		Command::Serve(address) => server::serve(&address, RenderSettings::default()),
+	};
+
+	if let Err(err) = result {
+		eprintln!("\nerror: {}", err);
+		std::process::exit(1);
+	}
 }
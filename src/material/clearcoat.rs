@@ -0,0 +1,80 @@
+use std::sync::Arc;
+
+use crate::geometry::{Float, HitResult, Ray, Vec3f};
+use crate::material::{facing, reflectance, Ggx, Material};
+use crate::random;
+
+// Thin clear lacquer over another material, e.g. car paint over a rough metal. Rays reflect off
+// the coat with its Fresnel reflectance and otherwise scatter off the base, losing what the coat
+// reflects back in on the way out. Refraction inside the thin layer is ignored.
+pub struct Clearcoat {
+	base: Arc<dyn Material>,
+	refraction_index: Float,
+	distribution: Ggx,
+}
+
+impl Clearcoat {
+	pub fn new(base: Arc<dyn Material>, refraction_index: Float) -> Clearcoat {
+		Clearcoat {
+			base,
+			refraction_index,
+			distribution: Ggx::new(0.0),
+		}
+	}
+
+	pub fn with_roughness(mut self, roughness: Float) -> Clearcoat {
+		self.distribution = Ggx::new(roughness);
+		self
+	}
+
+	fn reflectance(&self, direction: Vec3f, normal: Vec3f) -> Float {
+		let cosine = Vec3f::dot(&direction.unit(), &normal).abs().min(1.0);
+		reflectance(cosine, 1.0 / self.refraction_index)
+	}
+}
+
+impl Material for Clearcoat {
+	fn scatter(&self, ray: &Ray, hit_result: &HitResult) -> Option<(Vec3f, Ray)> {
+		let normal = facing(ray, hit_result);
+
+		if random::float() < self.reflectance(ray.direction, normal) {
+			let sample = self.distribution.sample(ray.direction, normal, None)?;
+			return Some((
+				Vec3f::new(1.0, 1.0, 1.0) * sample.weight,
+				Ray {
+					origin: hit_result.point,
+					direction: sample.direction,
+					time: ray.time,
+				},
+			));
+		}
+
+		let (attenuation, scattered) = self.base.scatter(ray, hit_result)?;
+		let transmitted = 1.0 - self.reflectance(scattered.direction, normal);
+
+		Some((attenuation * transmitted, scattered))
+	}
+
+	fn emit(&self, u: Float, v: Float) -> Vec3f {
+		self.base.emit(u, v)
+	}
+
+	fn light_group(&self) -> Option<usize> {
+		self.base.light_group()
+	}
+
+	fn is_light(&self) -> bool {
+		self.base.is_light()
+	}
+
+	fn pdf(&self, ray: &Ray, hit_result: &HitResult, direction: Vec3f) -> Option<Float> {
+		let normal = facing(ray, hit_result);
+		let coat = self
+			.distribution
+			.pdf(ray.direction, normal, None, direction)?;
+		let base = self.base.pdf(ray, hit_result, direction)?;
+		let reflected = self.reflectance(ray.direction, normal);
+
+		Some(reflected * coat + (1.0 - reflected) * base)
+	}
+}
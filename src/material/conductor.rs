@@ -0,0 +1,155 @@
+use std::sync::Arc;
+
+use crate::geometry::{Float, HitResult, Ray, Vec3f};
+use crate::material::{facing, tangent, Ggx, Material, Texture, ThinFilm};
+
+// Complex indices of refraction (eta + ik) for red, green and blue
+pub const CONDUCTORS: &[(&str, [Float; 3], [Float; 3])] = &[
+	(
+		"gold",
+		[0.143119, 0.374957, 1.44248],
+		[3.98316, 2.38572, 1.60322],
+	),
+	(
+		"copper",
+		[0.200438, 0.924033, 1.10221],
+		[3.91295, 2.45285, 2.14219],
+	),
+	(
+		"aluminum",
+		[1.65746, 0.880369, 0.521229],
+		[9.22387, 6.26952, 4.837],
+	),
+	(
+		"silver",
+		[0.155265, 0.116723, 0.138342],
+		[4.82835, 3.12225, 2.14696],
+	),
+];
+
+// Metal whose color comes from its complex index of refraction through the exact Fresnel
+// equations, so the tint changes towards grazing angles the way measured metals do. Uses the
+// same GGX microfacets as Metal.
+pub struct Conductor {
+	eta: Vec3f,
+	k: Vec3f,
+	distribution: Ggx,
+	rotation: Option<Arc<dyn Texture>>,
+	film: Option<ThinFilm>,
+}
+
+impl Conductor {
+	pub fn new(eta: Vec3f, k: Vec3f) -> Conductor {
+		Conductor {
+			eta,
+			k,
+			distribution: Ggx::new(0.0),
+			rotation: None,
+			film: None,
+		}
+	}
+
+	pub fn preset(name: &str) -> Option<Conductor> {
+		CONDUCTORS
+			.iter()
+			.find(|(preset, _, _)| *preset == name)
+			.map(|(_, eta, k)| {
+				Conductor::new(
+					Vec3f::new(eta[0], eta[1], eta[2]),
+					Vec3f::new(k[0], k[1], k[2]),
+				)
+			})
+	}
+
+	pub fn with_roughness(mut self, roughness: Float) -> Conductor {
+		self.distribution = Ggx::new(roughness);
+		self
+	}
+
+	// Roughness along the surface tangent (the direction of increasing u) and across it
+	pub fn with_anisotropic_roughness(
+		mut self,
+		roughness_u: Float,
+		roughness_v: Float,
+	) -> Conductor {
+		self.distribution = Ggx::anisotropic(roughness_u, roughness_v);
+		self
+	}
+
+	// Turns the tangent around the normal by the red channel of `texture`, a full turn at 1
+	pub fn with_tangent_rotation(mut self, texture: Arc<dyn Texture>) -> Conductor {
+		self.rotation = Some(texture);
+		self
+	}
+
+	pub fn with_thin_film(mut self, film: ThinFilm) -> Conductor {
+		self.film = Some(film);
+		self
+	}
+
+	fn fresnel(&self, cos_theta: Float) -> Vec3f {
+		if let Some(film) = &self.film {
+			return film.reflectance(cos_theta, self.eta, self.k);
+		}
+
+		Vec3f::new(
+			fresnel(cos_theta, self.eta.x, self.k.x),
+			fresnel(cos_theta, self.eta.y, self.k.y),
+			fresnel(cos_theta, self.eta.z, self.k.z),
+		)
+	}
+}
+
+// Unpolarized reflectance of a conductor in air, as in pbrt's FrConductor
+fn fresnel(cos_theta: Float, eta: Float, k: Float) -> Float {
+	let cos2 = cos_theta.clamp(0.0, 1.0).powi(2);
+	let sin2 = 1.0 - cos2;
+	let eta2 = eta * eta;
+	let k2 = k * k;
+
+	let t0 = eta2 - k2 - sin2;
+	let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+	let t1 = a2_plus_b2 + cos2;
+	let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+	let t2 = 2.0 * cos_theta * a;
+	let rs = (t1 - t2) / (t1 + t2);
+
+	let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+	let t4 = t2 * sin2;
+	let rp = rs * (t3 - t4) / (t3 + t4);
+
+	0.5 * (rp + rs)
+}
+
+impl Material for Conductor {
+	fn scatter(&self, ray: &Ray, hit_result: &HitResult) -> Option<(Vec3f, Ray)> {
+		let normal = facing(ray, hit_result);
+		let tangent = tangent(&self.distribution, &self.rotation, hit_result, normal);
+		let sample = self.distribution.sample(ray.direction, normal, tangent)?;
+
+		Some((
+			self.fresnel(sample.cos_theta) * sample.weight,
+			Ray {
+				origin: hit_result.point,
+				direction: sample.direction,
+				time: ray.time,
+			},
+		))
+	}
+
+	fn eval(&self, ray: &Ray, hit_result: &HitResult, direction: Vec3f) -> Option<Vec3f> {
+		let normal = facing(ray, hit_result);
+		let tangent = tangent(&self.distribution, &self.rotation, hit_result, normal);
+		let (value, cos_theta) =
+			self.distribution
+				.eval(ray.direction, normal, tangent, direction)?;
+		Some(self.fresnel(cos_theta) * value)
+	}
+
+	fn pdf(&self, ray: &Ray, hit_result: &HitResult, direction: Vec3f) -> Option<Float> {
+		let normal = facing(ray, hit_result);
+		let tangent = tangent(&self.distribution, &self.rotation, hit_result, normal);
+		self.distribution
+			.pdf(ray.direction, normal, tangent, direction)
+	}
+}
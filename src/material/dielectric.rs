@@ -0,0 +1,101 @@
+use crate::geometry::{Float, HitResult, Ray, Vec3f};
+use crate::material::{reflectance, Material, Medium, ThinFilm};
+use crate::random;
+
+// Clear glass-like material that refracts or reflects every ray, e.g. 1.5 for glass. With an
+// index of 1 and a thin film it makes a soap bubble.
+pub struct Dielectric {
+	refraction_index: Float,
+	film: Option<ThinFilm>,
+	priority: u32,
+}
+
+impl Dielectric {
+	pub const fn new(refraction_index: Float) -> Dielectric {
+		Dielectric {
+			refraction_index,
+			film: None,
+			priority: 0,
+		}
+	}
+
+	// The film only changes the reflections of rays coming from outside
+	pub fn with_thin_film(mut self, film: ThinFilm) -> Dielectric {
+		self.film = Some(film);
+		self
+	}
+
+	// Where dielectrics overlap, the one with the highest priority fills the overlap, e.g. ice
+	// above water above the glass they are in
+	pub fn with_priority(mut self, priority: u32) -> Dielectric {
+		self.priority = priority;
+		self
+	}
+}
+
+impl Material for Dielectric {
+	fn scatter(&self, ray: &Ray, hit_result: &HitResult) -> Option<(Vec3f, Ray)> {
+		self.scatter_between(ray, hit_result, 1.0)
+	}
+
+	fn medium(&self) -> Option<Medium> {
+		Some(Medium {
+			refraction_index: self.refraction_index,
+			priority: self.priority,
+		})
+	}
+
+	fn scatter_between(
+		&self,
+		ray: &Ray,
+		hit_result: &HitResult,
+		outside: Float,
+	) -> Option<(Vec3f, Ray)> {
+		let direction = ray.direction.unit();
+
+		// Normals point out of the object, so a ray going along the normal is leaving it
+		let entering = Vec3f::dot(&direction, &hit_result.normal) < 0.0;
+		let (normal, ratio) = if entering {
+			(hit_result.normal, outside / self.refraction_index)
+		} else {
+			(-hit_result.normal, self.refraction_index / outside)
+		};
+
+		let cos_theta = Float::min(-Vec3f::dot(&direction, &normal), 1.0);
+		let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+		let white = Vec3f::new(1.0, 1.0, 1.0);
+
+		let (attenuation, scattered) = match self.film.filter(|_| entering) {
+			_ if ratio * sin_theta > 1.0 => (white, Vec3f::reflect(direction, normal)),
+			// Reflect with the average reflectance of the channels and reweight for each
+			Some(film) => {
+				let index = Vec3f::new(1.0, 1.0, 1.0) * self.refraction_index;
+				let reflected = film.reflectance(cos_theta, index, Vec3f::new(0.0, 0.0, 0.0));
+				let probability = (reflected.x + reflected.y + reflected.z) / 3.0;
+
+				if probability > random::float() {
+					(reflected / probability, Vec3f::reflect(direction, normal))
+				} else {
+					(
+						(white - reflected) / (1.0 - probability),
+						Vec3f::refract(direction, normal, ratio),
+					)
+				}
+			}
+			None if reflectance(cos_theta, ratio) > random::float() => {
+				(white, Vec3f::reflect(direction, normal))
+			}
+			None => (white, Vec3f::refract(direction, normal, ratio)),
+		};
+
+		Some((
+			attenuation,
+			Ray {
+				origin: hit_result.point,
+				direction: scattered,
+				time: ray.time,
+			},
+		))
+	}
+}
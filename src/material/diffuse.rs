@@ -1,7 +1,8 @@
+use std::f64::consts::PI;
 use std::sync::Arc;
 
-use crate::geometry::{HitResult, Ray, Vec3f};
-use crate::material::{Material, Texture};
+use crate::geometry::{Float, HitResult, Ray, Vec3f};
+use crate::material::{albedo, facing, Material, Texture};
 
 pub struct Diffuse {
 	albedo: Arc<dyn Texture>,
@@ -14,27 +15,122 @@ impl Diffuse {
 }
 
 impl Material for Diffuse {
-	fn scatter(&self, _: &Ray, hit_result: &HitResult) -> Option<(Vec3f, Ray)> {
-		let direction = hit_result.normal + Vec3f::rand();
+	fn scatter(&self, ray: &Ray, hit_result: &HitResult) -> Option<(Vec3f, Ray)> {
+		let mut direction = hit_result.normal + Vec3f::rand();
+
+		// The random vector can cancel out the normal
+		if direction.near_zero() {
+			direction = hit_result.normal;
+		}
 
 		Some((
-			self.albedo
-				.value(hit_result.u, hit_result.v, &hit_result.point),
+			albedo(self.albedo.as_ref(), hit_result),
 			Ray {
 				origin: hit_result.point,
-				direction: direction,
+				direction,
+				time: ray.time,
 			},
 		))
 	}
+
+	fn eval(&self, ray: &Ray, hit_result: &HitResult, direction: Vec3f) -> Option<Vec3f> {
+		let cosine = Vec3f::dot(&direction.unit(), &facing(ray, hit_result)).max(0.0);
+		Some(albedo(self.albedo.as_ref(), hit_result) * (cosine / PI as Float))
+	}
+
+	fn is_diffuse(&self) -> bool {
+		true
+	}
+}
+
+// Piecewise Gaussian fit of a CIE 1931 color matching function by Wyman et al., every lobe
+// is a weight, a peak wavelength in nm and the widths below and above it
+fn matching(lobes: &[[Float; 4]], wavelength: Float) -> Float {
+	lobes
+		.iter()
+		.map(|&[weight, peak, below, above]| {
+			let width = if wavelength < peak { below } else { above };
+			weight * (-0.5 * ((wavelength - peak) / width).powi(2)).exp()
+		})
+		.sum()
 }
 
+// Linear sRGB color of a black body at `temperature` in Kelvin, from Planck's law over the
+// visible spectrum, scaled to a luminance of 1. Colors outside the sRGB gamut are clipped.
+pub fn blackbody(temperature: Float) -> Vec3f {
+	let temperature = temperature.clamp(1000.0, 40000.0);
+	let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+
+	for step in 0..=80 {
+		let wavelength = 380.0 + step as Float * 5.0;
+		// The constant factors of Planck's law cancel out with the scaling
+		let radiance =
+			1.0 / (wavelength.powi(5) * ((1.4388e7 / (wavelength * temperature)).exp() - 1.0));
+
+		x += radiance
+			* matching(
+				&[
+					[1.056, 599.8, 37.9, 31.0],
+					[0.362, 442.0, 16.0, 26.7],
+					[-0.065, 501.1, 20.4, 26.2],
+				],
+				wavelength,
+			);
+		y += radiance
+			* matching(
+				&[[0.821, 568.8, 46.9, 40.5], [0.286, 530.9, 16.3, 31.1]],
+				wavelength,
+			);
+		z += radiance
+			* matching(
+				&[[1.217, 437.0, 11.8, 36.0], [0.681, 459.0, 26.0, 13.8]],
+				wavelength,
+			);
+	}
+
+	let (x, z) = (x / y, z / y);
+	Vec3f::new(
+		3.2406 * x - 1.5372 - 0.4986 * z,
+		-0.9689 * x + 1.8758 + 0.0415 * z,
+		0.0557 * x - 0.2040 + 1.0570 * z,
+	)
+	.max(Vec3f::new(0.0, 0.0, 0.0))
+}
+
+// Emits its texture at the UVs of the hit, so image textures light up screens and signs on
+// meshes. `intensity` scales the texture beyond the range of an image, and the color of a
+// temperature tints it like a practical light.
 pub struct DiffuseLight {
 	emit: Arc<dyn Texture>,
+	intensity: Float,
+	tint: Vec3f,
+	group: Option<usize>,
 }
 
 impl DiffuseLight {
 	pub fn new(emit: Arc<dyn Texture>) -> DiffuseLight {
-		DiffuseLight { emit }
+		DiffuseLight {
+			emit,
+			intensity: 1.0,
+			tint: Vec3f::new(1.0, 1.0, 1.0),
+			group: None,
+		}
+	}
+
+	pub fn with_intensity(mut self, intensity: Float) -> DiffuseLight {
+		self.intensity = intensity;
+		self
+	}
+
+	// Color temperature in Kelvin, see blackbody
+	pub fn with_temperature(mut self, temperature: Float) -> DiffuseLight {
+		self.tint = blackbody(temperature);
+		self
+	}
+
+	pub fn with_group(mut self, group: usize) -> DiffuseLight {
+		self.group = Some(group);
+		self
 	}
 }
 
@@ -43,7 +139,15 @@ impl Material for DiffuseLight {
 		None
 	}
 
-	fn emit(&self, u: f64, v: f64) -> Vec3f {
-		self.emit.value(u, v, &Vec3f::new(0.0, 0.0, 0.0))
+	fn emit(&self, u: Float, v: Float) -> Vec3f {
+		self.emit.value(u, v, &Vec3f::new(0.0, 0.0, 0.0)) * self.tint * self.intensity
+	}
+
+	fn light_group(&self) -> Option<usize> {
+		self.group
+	}
+
+	fn is_light(&self) -> bool {
+		true
 	}
 }
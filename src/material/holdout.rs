@@ -0,0 +1,16 @@
+use crate::geometry::{HitResult, Ray, Vec3f};
+use crate::material::Material;
+
+// Cuts a transparent hole into the image wherever it is seen directly and is black to every
+// other ray, so it still blocks light and hides what is behind it
+pub struct Holdout;
+
+impl Material for Holdout {
+	fn scatter(&self, _: &Ray, _: &HitResult) -> Option<(Vec3f, Ray)> {
+		None
+	}
+
+	fn is_holdout(&self) -> bool {
+		true
+	}
+}
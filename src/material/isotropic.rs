@@ -0,0 +1,42 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::geometry::{Float, HitResult, Ray, Vec3f};
+use crate::material::{albedo, Material, Texture};
+
+// Phase function of participating media, scattering into every direction alike. The texture is
+// the single scattering albedo, the fraction of light that is scattered rather than absorbed.
+pub struct Isotropic {
+	albedo: Arc<dyn Texture>,
+}
+
+impl Isotropic {
+	pub const fn new(texture: Arc<dyn Texture>) -> Isotropic {
+		Isotropic { albedo: texture }
+	}
+}
+
+impl Material for Isotropic {
+	fn scatter(&self, ray: &Ray, hit_result: &HitResult) -> Option<(Vec3f, Ray)> {
+		Some((
+			albedo(self.albedo.as_ref(), hit_result),
+			Ray {
+				origin: hit_result.point,
+				direction: Vec3f::rand(),
+				time: ray.time,
+			},
+		))
+	}
+
+	fn eval(&self, _: &Ray, hit_result: &HitResult, _: Vec3f) -> Option<Vec3f> {
+		Some(albedo(self.albedo.as_ref(), hit_result) / (4.0 * PI as Float))
+	}
+
+	fn pdf(&self, _: &Ray, _: &HitResult, _: Vec3f) -> Option<Float> {
+		Some(1.0 / (4.0 * PI as Float))
+	}
+
+	fn is_phase_function(&self) -> bool {
+		true
+	}
+}
@@ -0,0 +1,49 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::error::{RendererError, Result};
+use crate::material::Material;
+
+// Materials defined once by name and shared by every object that refers to them
+#[derive(Default, Clone)]
+pub struct MaterialLibrary {
+	materials: BTreeMap<String, Arc<dyn Material>>,
+}
+
+impl MaterialLibrary {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	// Replaces any material of the same name
+	pub fn add(&mut self, name: &str, material: Arc<dyn Material>) {
+		self.materials.insert(name.to_string(), material);
+	}
+
+	pub fn with_material(mut self, name: &str, material: Arc<dyn Material>) -> Self {
+		self.add(name, material);
+		self
+	}
+
+	pub fn contains(&self, name: &str) -> bool {
+		self.materials.contains_key(name)
+	}
+
+	pub fn get(&self, name: &str) -> Result<Arc<dyn Material>> {
+		self.materials.get(name).map(Arc::clone).ok_or_else(|| {
+			let names: Vec<&str> = self.materials.keys().map(String::as_str).collect();
+			RendererError::Scene {
+				path: None,
+				message: if names.is_empty() {
+					format!("unknown material '{}', no materials are defined", name)
+				} else {
+					format!(
+						"unknown material '{}', expected one of {}",
+						name,
+						names.join(", ")
+					)
+				},
+			}
+		})
+	}
+}
@@ -1,28 +1,78 @@
 use std::sync::Arc;
 
-use crate::geometry::{HitResult, Ray, Vec3f};
-use crate::material::{Material, Texture};
+use crate::geometry::{Float, HitResult, Ray, Vec3f};
+use crate::material::{albedo, facing, tangent, Ggx, Material, Texture};
 
+// Conductor with GGX microfacets, the albedo is the reflectance at normal incidence and goes to
+// white at grazing angles (Schlick). A roughness of 0 is a perfect mirror.
 pub struct Metal {
 	albedo: Arc<dyn Texture>,
+	distribution: Ggx,
+	rotation: Option<Arc<dyn Texture>>,
 }
 
 impl Metal {
-	pub const fn new(texture: Arc<dyn Texture>) -> Metal {
-		Metal { albedo: texture }
+	pub fn new(texture: Arc<dyn Texture>) -> Metal {
+		Metal {
+			albedo: texture,
+			distribution: Ggx::new(0.0),
+			rotation: None,
+		}
+	}
+
+	pub fn with_roughness(mut self, roughness: Float) -> Metal {
+		self.distribution = Ggx::new(roughness);
+		self
+	}
+
+	// Roughness along the surface tangent (the direction of increasing u) and across it
+	pub fn with_anisotropic_roughness(mut self, roughness_u: Float, roughness_v: Float) -> Metal {
+		self.distribution = Ggx::anisotropic(roughness_u, roughness_v);
+		self
+	}
+
+	// Turns the tangent around the normal by the red channel of `texture`, a full turn at 1
+	pub fn with_tangent_rotation(mut self, texture: Arc<dyn Texture>) -> Metal {
+		self.rotation = Some(texture);
+		self
 	}
 }
 
 impl Material for Metal {
 	fn scatter(&self, ray: &Ray, hit_result: &HitResult) -> Option<(Vec3f, Ray)> {
-		let reflected = Vec3f::reflect(ray.direction, hit_result.normal);
+		let normal = facing(ray, hit_result);
+		let tangent = tangent(&self.distribution, &self.rotation, hit_result, normal);
+		let sample = self.distribution.sample(ray.direction, normal, tangent)?;
+
+		let f0 = albedo(self.albedo.as_ref(), hit_result);
+		let fresnel = f0 + (Vec3f::new(1.0, 1.0, 1.0) - f0) * (1.0 - sample.cos_theta).powi(5);
+
 		Some((
-			self.albedo
-				.value(hit_result.u, hit_result.v, &hit_result.point),
+			fresnel * sample.weight,
 			Ray {
 				origin: hit_result.point,
-				direction: reflected,
+				direction: sample.direction,
+				time: ray.time,
 			},
 		))
 	}
+
+	fn eval(&self, ray: &Ray, hit_result: &HitResult, direction: Vec3f) -> Option<Vec3f> {
+		let normal = facing(ray, hit_result);
+		let tangent = tangent(&self.distribution, &self.rotation, hit_result, normal);
+		let (value, cos_theta) =
+			self.distribution
+				.eval(ray.direction, normal, tangent, direction)?;
+
+		let f0 = albedo(self.albedo.as_ref(), hit_result);
+		let fresnel = f0 + (Vec3f::new(1.0, 1.0, 1.0) - f0) * (1.0 - cos_theta).powi(5);
+		Some(fresnel * value)
+	}
+
+	fn pdf(&self, ray: &Ray, hit_result: &HitResult, direction: Vec3f) -> Option<Float> {
+		let normal = facing(ray, hit_result);
+		let tangent = tangent(&self.distribution, &self.rotation, hit_result, normal);
+		self.distribution
+			.pdf(ray.direction, normal, tangent, direction)
+	}
 }
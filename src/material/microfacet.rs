@@ -0,0 +1,232 @@
+use std::f64::consts::PI;
+
+use crate::geometry::{Float, Vec3f};
+use crate::random;
+
+// A direction sampled from the distribution of visible microfacet normals
+pub struct MicrofacetSample {
+	pub direction: Vec3f,
+	// Cosine between the outgoing direction and the microfacet normal, for the Fresnel term
+	pub cos_theta: Float,
+	// Masking-shadowing weight G2 / G1, the rest of the BRDF over the pdf cancels out
+	pub weight: Float,
+}
+
+// GGX (Trowbridge-Reitz) distribution of microfacet normals, sampled by visible normals after
+// Heitz, "Sampling the GGX Distribution of Visible Normals" (2018). The alphas are the squares of
+// the perceptual roughness along the tangent and the bitangent, 0 is a perfect mirror.
+pub struct Ggx {
+	alpha_x: Float,
+	alpha_y: Float,
+}
+
+// Keeps one smooth direction of an otherwise rough distribution from dividing by zero
+const MIN_ALPHA: Float = 1e-4;
+
+// Tangents completing `normal` to an orthonormal basis, after Duff et al. (2017)
+pub fn basis(normal: Vec3f) -> (Vec3f, Vec3f) {
+	let sign = Float::copysign(1.0, normal.z);
+	let a = -1.0 / (sign + normal.z);
+	let b = normal.x * normal.y * a;
+
+	(
+		Vec3f::new(
+			1.0 + sign * normal.x * normal.x * a,
+			sign * b,
+			-sign * normal.x,
+		),
+		Vec3f::new(b, sign + normal.y * normal.y * a, -normal.y),
+	)
+}
+
+// Converts between world space and the shading frame, where the normal is +z
+struct Frame {
+	tangent: Vec3f,
+	bitangent: Vec3f,
+	normal: Vec3f,
+}
+
+impl Frame {
+	// `tangent` has to be a unit vector perpendicular to the normal, without one the frame is
+	// oriented arbitrarily around the normal
+	fn new(normal: Vec3f, tangent: Option<Vec3f>) -> Frame {
+		let (tangent, bitangent) = match tangent {
+			Some(tangent) => (tangent, Vec3f::cross(&normal, &tangent)),
+			None => basis(normal),
+		};
+		Frame {
+			tangent,
+			bitangent,
+			normal,
+		}
+	}
+
+	fn to_local(&self, v: Vec3f) -> Vec3f {
+		Vec3f::new(
+			Vec3f::dot(&v, &self.tangent),
+			Vec3f::dot(&v, &self.bitangent),
+			Vec3f::dot(&v, &self.normal),
+		)
+	}
+
+	fn to_world(&self, v: Vec3f) -> Vec3f {
+		self.tangent * v.x + self.bitangent * v.y + self.normal * v.z
+	}
+}
+
+impl Ggx {
+	pub fn new(roughness: Float) -> Ggx {
+		Ggx::anisotropic(roughness, roughness)
+	}
+
+	// Different roughness along the tangent (u) and the bitangent (v), like brushed metal
+	pub fn anisotropic(roughness_u: Float, roughness_v: Float) -> Ggx {
+		let alpha = |roughness: Float| roughness.clamp(0.0, 1.0).powi(2);
+		let (alpha_x, alpha_y) = (alpha(roughness_u), alpha(roughness_v));
+
+		if alpha_x == 0.0 && alpha_y == 0.0 {
+			return Ggx { alpha_x, alpha_y };
+		}
+
+		Ggx {
+			alpha_x: alpha_x.max(MIN_ALPHA),
+			alpha_y: alpha_y.max(MIN_ALPHA),
+		}
+	}
+
+	pub fn is_smooth(&self) -> bool {
+		self.alpha_x == 0.0 && self.alpha_y == 0.0
+	}
+
+	pub fn is_isotropic(&self) -> bool {
+		self.alpha_x == self.alpha_y
+	}
+
+	// Normal distribution function of the microfacet normal `h`, in the shading frame
+	fn d(&self, h: Vec3f) -> Float {
+		let x = h.x / self.alpha_x;
+		let y = h.y / self.alpha_y;
+		let t = x * x + y * y + h.z * h.z;
+		1.0 / (PI as Float * self.alpha_x * self.alpha_y * t * t)
+	}
+
+	// Smith's auxiliary function, in the shading frame
+	fn lambda(&self, w: Vec3f) -> Float {
+		let x = self.alpha_x * w.x;
+		let y = self.alpha_y * w.y;
+		let alpha2_tan2 = (x * x + y * y) / (w.z * w.z);
+		((1.0 + alpha2_tan2).sqrt() - 1.0) / 2.0
+	}
+
+	// Reflects a ray travelling along `direction` off a microfacet of a surface with the given
+	// normal, which has to face the ray. `tangent` is a unit vector perpendicular to the normal
+	// that orients anisotropic roughness. None if the ray comes from below the surface or the
+	// reflection goes below it.
+	pub fn sample(
+		&self,
+		direction: Vec3f,
+		normal: Vec3f,
+		tangent: Option<Vec3f>,
+	) -> Option<MicrofacetSample> {
+		let frame = Frame::new(normal, tangent);
+		let wo = frame.to_local(-direction.unit());
+		if wo.z <= 0.0 {
+			return None;
+		}
+
+		let h = if self.is_smooth() {
+			Vec3f::new(0.0, 0.0, 1.0)
+		} else {
+			self.sample_visible_normal(wo)
+		};
+
+		let cos_theta = Vec3f::dot(&wo, &h);
+		let wi = h * 2.0 * cos_theta - wo;
+		if wi.z <= 0.0 {
+			return None;
+		}
+
+		let lambda_o = self.lambda(wo);
+		Some(MicrofacetSample {
+			direction: frame.to_world(wi),
+			cos_theta,
+			weight: (1.0 + lambda_o) / (1.0 + lambda_o + self.lambda(wi)),
+		})
+	}
+
+	fn sample_visible_normal(&self, wo: Vec3f) -> Vec3f {
+		// Stretch the view direction so the distribution becomes a hemisphere
+		let v = Vec3f::new(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z).unit();
+
+		let length2 = v.x * v.x + v.y * v.y;
+		let t1 = if length2 > 0.0 {
+			Vec3f::new(-v.y, v.x, 0.0) / length2.sqrt()
+		} else {
+			Vec3f::new(1.0, 0.0, 0.0)
+		};
+		let t2 = Vec3f::cross(&v, &t1);
+
+		// Uniform point on the disk, squashed onto the projection of the visible half
+		let r = random::float().sqrt();
+		let phi = 2.0 * PI as Float * random::float();
+		let p1 = r * phi.cos();
+		let s = 0.5 * (1.0 + v.z);
+		let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+
+		let n = t1 * p1 + t2 * p2 + v * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+
+		// Unstretch
+		Vec3f::new(self.alpha_x * n.x, self.alpha_y * n.y, n.z.max(1e-6)).unit()
+	}
+
+	// The distribution and shadowing of reflecting a ray along `direction` towards `scattered`,
+	// D G / (4 cos), and the cosine between the reflection and the microfacet normal for the
+	// Fresnel term. None for the perfect mirror, which reflects into a single direction.
+	pub fn eval(
+		&self,
+		direction: Vec3f,
+		normal: Vec3f,
+		tangent: Option<Vec3f>,
+		scattered: Vec3f,
+	) -> Option<(Float, Float)> {
+		if self.is_smooth() {
+			return None;
+		}
+
+		let frame = Frame::new(normal, tangent);
+		let wo = frame.to_local(-direction.unit());
+		let wi = frame.to_local(scattered.unit());
+		if wo.z <= 0.0 || wi.z <= 0.0 {
+			return Some((0.0, 1.0));
+		}
+
+		let h = (wo + wi).unit();
+		let g2 = 1.0 / (1.0 + self.lambda(wo) + self.lambda(wi));
+		Some((self.d(h) * g2 / (4.0 * wo.z), Vec3f::dot(&wo, &h)))
+	}
+
+	// Density per unit solid angle of sample returning `scattered` for a ray along
+	// `direction`, None for the perfect mirror
+	pub fn pdf(
+		&self,
+		direction: Vec3f,
+		normal: Vec3f,
+		tangent: Option<Vec3f>,
+		scattered: Vec3f,
+	) -> Option<Float> {
+		if self.is_smooth() {
+			return None;
+		}
+
+		let frame = Frame::new(normal, tangent);
+		let wo = frame.to_local(-direction.unit());
+		let wi = frame.to_local(scattered.unit());
+		if wo.z <= 0.0 || wi.z <= 0.0 {
+			return Some(0.0);
+		}
+
+		let h = (wo + wi).unit();
+		let g1 = 1.0 / (1.0 + self.lambda(wo));
+		Some(g1 * self.d(h) / (4.0 * wo.z))
+	}
+}
@@ -1,16 +1,141 @@
+mod clearcoat;
+mod conductor;
+mod dielectric;
 mod diffuse;
+mod holdout;
+mod isotropic;
+mod library;
 mod metal;
+mod microfacet;
+mod overrides;
 mod texture;
+mod thin_film;
 
+pub use self::clearcoat::*;
+pub use self::conductor::*;
+pub use self::dielectric::*;
 pub use self::diffuse::*;
+pub use self::holdout::*;
+pub use self::isotropic::*;
+pub use self::library::*;
 pub use self::metal::*;
+pub use self::microfacet::*;
+pub use self::overrides::*;
 pub use self::texture::*;
+pub use self::thin_film::*;
 
-use crate::geometry::{HitResult, Ray, Vec3f};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::geometry::{Float, HitResult, Ray, Vec3f};
+
+// Texture color at the hit, tinted by the vertex color of meshes that have them
+fn albedo(texture: &dyn Texture, hit_result: &HitResult) -> Vec3f {
+	let color = texture.value_at(hit_result);
+
+	match hit_result.color {
+		Some(tint) => color * tint,
+		None => color,
+	}
+}
+
+// Schlick's approximation of the Fresnel reflectance
+fn reflectance(cosine: Float, ratio: Float) -> Float {
+	let r0 = (1.0 - ratio) / (1.0 + ratio);
+	let r0 = r0 * r0;
+	r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
+}
+
+// The side of the surface the ray comes from
+fn facing(ray: &Ray, hit_result: &HitResult) -> Vec3f {
+	if Vec3f::dot(&ray.direction, &hit_result.normal) > 0.0 {
+		-hit_result.normal
+	} else {
+		hit_result.normal
+	}
+}
+
+// Unit tangent orienting anisotropic roughness around `normal`, optionally turned by the red
+// channel of `rotation` (0 to 1 is a full turn). None for isotropic distributions, which do
+// not care, and for surfaces without a usable tangent.
+fn tangent(
+	distribution: &Ggx,
+	rotation: &Option<Arc<dyn Texture>>,
+	hit_result: &HitResult,
+	normal: Vec3f,
+) -> Option<Vec3f> {
+	if distribution.is_isotropic() {
+		return None;
+	}
+
+	let tangent = hit_result.tangent?;
+	let tangent = tangent - normal * Vec3f::dot(&tangent, &normal);
+	if tangent.lengthsq() < 1e-12 {
+		return None;
+	}
+	let tangent = tangent.unit();
+
+	match rotation {
+		Some(rotation) => {
+			let angle = albedo(rotation.as_ref(), hit_result).x * 2.0 * PI as Float;
+			Some(tangent * angle.cos() + Vec3f::cross(&normal, &tangent) * angle.sin())
+		}
+		None => Some(tangent),
+	}
+}
+
+// What fills the inside of a dielectric, for tracking which one a path is in when they are nested
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Medium {
+	pub refraction_index: Float,
+	pub priority: u32,
+}
 
 pub trait Material: Send + Sync {
 	fn scatter(&self, ray: &Ray, hit_result: &HitResult) -> Option<(Vec3f, Ray)>;
-	fn emit(&self, _: f64, _: f64) -> Vec3f {
+	fn emit(&self, _: Float, _: Float) -> Vec3f {
 		Vec3f::new(0.0, 0.0, 0.0)
 	}
+	// Index of the light group that the emitted light is counted in
+	fn light_group(&self) -> Option<usize> {
+		None
+	}
+	// Density per unit solid angle of scattering a ray towards `direction`, None for materials
+	// that only scatter into a few discrete directions or not at all
+	fn pdf(&self, _: &Ray, _: &HitResult, _: Vec3f) -> Option<Float> {
+		None
+	}
+	// Fraction of the light arriving from `direction` that is scattered towards the ray, per unit
+	// solid angle and including the cosine at the surface, for lights sampled directly. None for
+	// materials that can only be sampled.
+	fn eval(&self, _: &Ray, _: &HitResult, _: Vec3f) -> Option<Vec3f> {
+		None
+	}
+	// The medium inside materials that refract, None for everything else
+	fn medium(&self) -> Option<Medium> {
+		None
+	}
+	// Scatters a ray at the surface of the material's medium, with the refraction index
+	// `outside` on the other side of the surface instead of air
+	fn scatter_between(&self, ray: &Ray, hit_result: &HitResult, _: Float) -> Option<(Vec3f, Ray)> {
+		self.scatter(ray, hit_result)
+	}
+	// Camera rays that hit a holdout leave their pixel transparent
+	fn is_holdout(&self) -> bool {
+		false
+	}
+	// Emitters, which material overrides leave alone
+	fn is_light(&self) -> bool {
+		false
+	}
+	// Phase functions of participating media, which scatter inside a volume rather than off a
+	// surface
+	fn is_phase_function(&self) -> bool {
+		false
+	}
+	// Lambertian surfaces, which reflect the light from every direction alike and so can take
+	// theirs from an irradiance cache
+	fn is_diffuse(&self) -> bool {
+		false
+	}
 }
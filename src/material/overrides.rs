@@ -0,0 +1,92 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::geometry::{Aabb3d, HitResult, Hittable, Interval, Ray, Vec3f};
+use crate::material::{Diffuse, Isotropic, Material, Metal, SolidColor};
+
+// Replacements for every material but the lights, for judging lighting and modeling apart
+// from shading
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MaterialOverride {
+	// Light gray diffuse
+	Clay,
+	// Mirror, for checking reflections of the surroundings
+	Chrome,
+}
+
+pub const OVERRIDES: &[&str] = &["clay", "chrome"];
+
+const CLAY: Vec3f = Vec3f {
+	x: 0.7,
+	y: 0.7,
+	z: 0.7,
+};
+const CHROME: Vec3f = Vec3f {
+	x: 0.9,
+	y: 0.9,
+	z: 0.9,
+};
+
+impl FromStr for MaterialOverride {
+	type Err = String;
+
+	fn from_str(value: &str) -> Result<Self, Self::Err> {
+		match value {
+			"clay" => Ok(MaterialOverride::Clay),
+			"chrome" => Ok(MaterialOverride::Chrome),
+			_ => Err(format!(
+				"unknown material override '{}', expected one of {}",
+				value,
+				OVERRIDES.join(", ")
+			)),
+		}
+	}
+}
+
+// Shows `object` with the material of the override on every surface that is not a light. Media
+// keep scattering like media, with the albedo of clay.
+pub struct Overridden {
+	object: Box<dyn Hittable>,
+	surface: Arc<dyn Material>,
+	medium: Arc<dyn Material>,
+}
+
+impl Overridden {
+	pub fn new(object: Box<dyn Hittable>, material: MaterialOverride) -> Overridden {
+		let surface: Arc<dyn Material> = match material {
+			MaterialOverride::Clay => Arc::new(Diffuse::new(Arc::new(SolidColor::new(CLAY)))),
+			MaterialOverride::Chrome => Arc::new(Metal::new(Arc::new(SolidColor::new(CHROME)))),
+		};
+
+		Overridden {
+			object,
+			surface,
+			medium: Arc::new(Isotropic::new(Arc::new(SolidColor::new(CLAY)))),
+		}
+	}
+}
+
+impl Hittable for Overridden {
+	fn hit(&self, interval: &Interval, ray: &Ray) -> Option<HitResult<'_>> {
+		let mut hit_result = self.object.hit(interval, ray)?;
+		if hit_result.material.is_phase_function() {
+			hit_result.material = self.medium.as_ref();
+		} else if !hit_result.material.is_light() {
+			hit_result.material = self.surface.as_ref();
+		}
+
+		Some(hit_result)
+	}
+
+	fn hit_any(&self, interval: &Interval, ray: &Ray) -> bool {
+		self.object.hit_any(interval, ray)
+	}
+
+	fn bounds(&self) -> &Aabb3d {
+		self.object.bounds()
+	}
+
+	fn is_bounded(&self) -> bool {
+		self.object.is_bounded()
+	}
+}
@@ -1,9 +1,17 @@
-use image::{DynamicImage, GenericImageView, Pixel};
+use std::sync::Arc;
 
-use crate::geometry::Vec3f;
+use image::{Rgb, RgbImage};
+
+use crate::error::{RendererError, Result};
+use crate::geometry::{Float, HitResult, Vec3f};
+use crate::stats;
 
 pub trait Texture: Send + Sync {
-	fn value(&self, u: f64, v: f64, point: &Vec3f) -> Vec3f;
+	fn value(&self, u: Float, v: Float, point: &Vec3f) -> Vec3f;
+	// Color at a hit on a surface, for textures that also need its normal
+	fn value_at(&self, hit_result: &HitResult) -> Vec3f {
+		self.value(hit_result.u, hit_result.v, &hit_result.point)
+	}
 }
 
 pub struct SolidColor {
@@ -17,7 +25,7 @@ impl SolidColor {
 }
 
 impl Texture for SolidColor {
-	fn value(&self, _: f64, _: f64, _: &Vec3f) -> Vec3f {
+	fn value(&self, _: Float, _: Float, _: &Vec3f) -> Vec3f {
 		self.value
 	}
 }
@@ -25,11 +33,11 @@ impl Texture for SolidColor {
 pub struct CheckerTexture {
 	pub even_color: Vec3f,
 	pub odd_color: Vec3f,
-	pub scale: f64,
+	pub scale: Float,
 }
 
 impl CheckerTexture {
-	pub fn new(even_color: Vec3f, odd_color: Vec3f, scale: f64) -> CheckerTexture {
+	pub fn new(even_color: Vec3f, odd_color: Vec3f, scale: Float) -> CheckerTexture {
 		CheckerTexture {
 			even_color,
 			odd_color,
@@ -39,9 +47,9 @@ impl CheckerTexture {
 }
 
 impl Texture for CheckerTexture {
-	fn value(&self, u: f64, v: f64, _: &Vec3f) -> Vec3f {
-		let ix = f64::round(u * self.scale) as i32;
-		let iy = f64::round(v * self.scale) as i32;
+	fn value(&self, u: Float, v: Float, _: &Vec3f) -> Vec3f {
+		let ix = Float::round(u * self.scale) as i32;
+		let iy = Float::round(v * self.scale) as i32;
 
 		if (ix + iy) % 2 == 0 {
 			self.even_color
@@ -49,45 +57,473 @@ impl Texture for CheckerTexture {
 			self.odd_color
 		}
 	}
+
+	// Averaged over the box around the footprint, so that far away squares blend into gray
+	// instead of flickering
+	fn value_at(&self, hit_result: &HitResult) -> Vec3f {
+		let Some([a, b]) = hit_result.footprint else {
+			return self.value(hit_result.u, hit_result.v, &hit_result.point);
+		};
+		let width = [(a.u.abs() + b.u.abs()), (a.v.abs() + b.v.abs())].map(|w| w * self.scale);
+		if width[0] < 1e-6 || width[1] < 1e-6 {
+			return self.value(hit_result.u, hit_result.v, &hit_result.point);
+		}
+
+		// Integral of the square wave that is 1 on even and -1 on odd squares, over the width
+		let wave = |x: Float, width: Float| {
+			let triangle = |x: Float| ((x * 0.5).rem_euclid(1.0) - 0.5).abs();
+			2.0 * (triangle(x - 0.5 * width) - triangle(x + 0.5 * width)) / width
+		};
+		let x = wave(hit_result.u * self.scale + 0.5, width[0]);
+		let y = wave(hit_result.v * self.scale + 0.5, width[1]);
+
+		let odd = 0.5 - 0.5 * x * y;
+		self.even_color + (self.odd_color - self.even_color) * odd
+	}
 }
 
+// Footprints longer than this many times their width are covered by as many samples as this
+const MAX_ANISOTROPY: Float = 8.0;
+
+// Image with a pyramid of mipmaps, every level half the size of the one before. Lookups without
+// a footprint read the nearest pixel of the full image, while ray cones average over the
+// footprint by taking up to MAX_ANISOTROPY trilinear samples along its long axis.
 pub struct ImageTexture {
-	data: DynamicImage,
+	levels: Vec<RgbImage>,
 	width: u32,
 	height: u32,
 }
 
+// Halves an image with a box filter, odd rows and columns are folded into the last pixel
+fn downsample(image: &RgbImage) -> RgbImage {
+	let (width, height) = ((image.width() / 2).max(1), (image.height() / 2).max(1));
+	RgbImage::from_fn(width, height, |x, y| {
+		let mut sum = [0u32; 3];
+		let mut count = 0;
+		for sy in y * 2..(y * 2 + 2).min(image.height()) {
+			for sx in x * 2..(x * 2 + 2).min(image.width()) {
+				let pixel = image.get_pixel(sx, sy);
+				(0..3).for_each(|c| sum[c] += pixel[c] as u32);
+				count += 1;
+			}
+		}
+		Rgb(sum.map(|c| ((c + count / 2) / count) as u8))
+	})
+}
+
 impl ImageTexture {
-	pub fn new(path: &str) -> ImageTexture {
-		let data = image::open(path).unwrap();
+	pub fn new(path: &str) -> Result<ImageTexture> {
+		let data = image::open(path).map_err(|source| RendererError::Image {
+			path: path.to_string(),
+			source,
+		})?;
 		let width = data.width();
 		let height = data.height();
 
-		ImageTexture {
-			data,
+		let mut levels = vec![data.to_rgb8()];
+		while let Some(last) = levels.last().filter(|l| l.width() > 1 || l.height() > 1) {
+			levels.push(downsample(last));
+		}
+
+		stats::add_texture(levels.iter().map(|level| level.as_raw().len()).sum());
+
+		Ok(ImageTexture {
+			levels,
 			width,
 			height,
+		})
+	}
+
+	// Pixel at integer coordinates of a level, repeating the image and with v growing upwards
+	fn texel(level: &RgbImage, x: i64, y: i64) -> Vec3f {
+		let x = x.rem_euclid(level.width() as i64) as u32;
+		let y = y.rem_euclid(level.height() as i64) as u32;
+		let pixel = level.get_pixel(x, level.height() - 1 - y);
+
+		Vec3f::new(
+			pixel[0] as Float / 255.0,
+			pixel[1] as Float / 255.0,
+			pixel[2] as Float / 255.0,
+		)
+	}
+
+	fn bilinear(level: &RgbImage, u: Float, v: Float) -> Vec3f {
+		let x = u * level.width() as Float - 0.5;
+		let y = v * level.height() as Float - 0.5;
+		let (x0, y0) = (x.floor(), y.floor());
+		let (fx, fy) = (x - x0, y - y0);
+		let (x0, y0) = (x0 as i64, y0 as i64);
+
+		let lerp = |a: Vec3f, b: Vec3f, t: Float| a + (b - a) * t;
+		lerp(
+			lerp(
+				ImageTexture::texel(level, x0, y0),
+				ImageTexture::texel(level, x0 + 1, y0),
+				fx,
+			),
+			lerp(
+				ImageTexture::texel(level, x0, y0 + 1),
+				ImageTexture::texel(level, x0 + 1, y0 + 1),
+				fx,
+			),
+			fy,
+		)
+	}
+
+	// Blends the two levels around a fractional level
+	fn trilinear(&self, level: Float, u: Float, v: Float) -> Vec3f {
+		let lower = level.floor() as usize;
+		let upper = (lower + 1).min(self.levels.len() - 1);
+		let t = level - lower as Float;
+
+		let a = ImageTexture::bilinear(&self.levels[lower], u, v);
+		if t == 0.0 || upper == lower {
+			return a;
 		}
+		a + (ImageTexture::bilinear(&self.levels[upper], u, v) - a) * t
 	}
 }
 
 impl Texture for ImageTexture {
-	fn value(&self, u: f64, v: f64, _: &Vec3f) -> Vec3f {
-		let mut px = f64::round(u * (self.width as f64 - 1.0)) as i32;
-		let mut py = f64::round(v * (self.height as f64 - 1.0)) as i32;
+	fn value(&self, u: Float, v: Float, _: &Vec3f) -> Vec3f {
+		let px = Float::round(u * (self.width as Float - 1.0)) as i64;
+		let py = Float::round(v * (self.height as Float - 1.0)) as i64;
 
-		px = px.rem_euclid(self.width as i32);
-		py = py.rem_euclid(self.height as i32);
+		ImageTexture::texel(&self.levels[0], px, py)
+	}
 
-		let pixel = self
-			.data
-			.get_pixel(px as u32, self.height - 1 - py as u32)
-			.to_rgb();
+	fn value_at(&self, hit_result: &HitResult) -> Vec3f {
+		let (u, v) = (hit_result.u, hit_result.v);
+		let Some(footprint) = hit_result.footprint else {
+			return self.value(u, v, &hit_result.point);
+		};
 
-		Vec3f::new(
-			pixel[0] as f64 / 255.0,
-			pixel[1] as f64 / 255.0,
-			pixel[2] as f64 / 255.0,
-		)
+		// Lengths of the footprint's axes in pixels of the full image
+		let [a, b] = footprint.map(|axis| {
+			let (x, y) = (axis.u * self.width as Float, axis.v * self.height as Float);
+			(axis, (x * x + y * y).sqrt())
+		});
+		let ((major, major_length), (_, minor_length)) = if a.1 >= b.1 { (a, b) } else { (b, a) };
+
+		let samples = (major_length / minor_length.max(1e-12))
+			.min(MAX_ANISOTROPY)
+			.ceil()
+			.max(1.0);
+		let width = (major_length / samples).max(minor_length);
+		let level = width
+			.max(1e-12)
+			.log2()
+			.clamp(0.0, (self.levels.len() - 1) as Float);
+
+		let mut sum = Vec3f::new(0.0, 0.0, 0.0);
+		for i in 0..samples as usize {
+			let offset = (i as Float + 0.5) / samples - 0.5;
+			sum += self.trilinear(level, u + major.u * offset, v + major.v * offset);
+		}
+		sum / samples
+	}
+}
+
+#[derive(Copy, Clone)]
+pub enum WorleyFeature {
+	// Distance to the nearest feature point, round cells
+	F1,
+	// Distance to the second nearest, bulging cells like hammered metal
+	F2,
+	// Difference of the two, zero along the borders between cells like cracks
+	F2MinusF1,
+}
+
+// Cellular noise after Worley, one random feature point in every unit cell of space scaled by
+// `scale`. The value is a gray level of the chosen distance, about 0 to 1.
+pub struct WorleyTexture {
+	pub feature: WorleyFeature,
+	pub scale: Float,
+}
+
+impl WorleyTexture {
+	pub fn new(feature: WorleyFeature, scale: Float) -> WorleyTexture {
+		WorleyTexture { feature, scale }
+	}
+}
+
+// Integer hash of a cell and one of its axes, mapped to 0 to 1
+fn cell_random(cell: [i32; 3], axis: u32) -> Float {
+	let mut h = (cell[0] as u32).wrapping_mul(0x8da6b343)
+		^ (cell[1] as u32).wrapping_mul(0xd8163841)
+		^ (cell[2] as u32).wrapping_mul(0xcb1ab31f)
+		^ axis.wrapping_mul(0x165667b1);
+	h ^= h >> 15;
+	h = h.wrapping_mul(0x2c1b3c6d);
+	h ^= h >> 12;
+	h = h.wrapping_mul(0x297a2d39);
+	h ^= h >> 15;
+	(h >> 8) as Float / (1u32 << 24) as Float
+}
+
+impl Texture for WorleyTexture {
+	fn value(&self, _: Float, _: Float, point: &Vec3f) -> Vec3f {
+		let p = *point * self.scale;
+		let base = [p.x.floor() as i32, p.y.floor() as i32, p.z.floor() as i32];
+
+		let (mut f1, mut f2) = (Float::MAX, Float::MAX);
+		for dx in -1..=1 {
+			for dy in -1..=1 {
+				for dz in -1..=1 {
+					let cell = [base[0] + dx, base[1] + dy, base[2] + dz];
+					let feature = Vec3f::new(
+						cell[0] as Float + cell_random(cell, 0),
+						cell[1] as Float + cell_random(cell, 1),
+						cell[2] as Float + cell_random(cell, 2),
+					);
+					let distance = (feature - p).lengthsq();
+					if distance < f1 {
+						f2 = f1;
+						f1 = distance;
+					} else if distance < f2 {
+						f2 = distance;
+					}
+				}
+			}
+		}
+
+		let value = match self.feature {
+			WorleyFeature::F1 => f1.sqrt(),
+			WorleyFeature::F2 => f2.sqrt(),
+			WorleyFeature::F2MinusF1 => f2.sqrt() - f1.sqrt(),
+		};
+		Vec3f::new(value, value, value)
+	}
+}
+
+// The scalar a ramp maps to colors
+pub enum RampDriver {
+	U,
+	V,
+	// The y coordinate of the point
+	Height,
+	// Luminance of another texture
+	Texture(Arc<dyn Texture>),
+}
+
+// Maps its driver through color stops, interpolating linearly between them and keeping the
+// color of the first and last stop beyond them
+pub struct RampTexture {
+	driver: RampDriver,
+	stops: Vec<(Float, Vec3f)>,
+}
+
+impl RampTexture {
+	pub fn new(driver: RampDriver, mut stops: Vec<(Float, Vec3f)>) -> RampTexture {
+		stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+		RampTexture { driver, stops }
+	}
+}
+
+impl RampTexture {
+	// `lookup` reads a driver texture, the same way that the ramp itself is looked up
+	fn map(
+		&self,
+		u: Float,
+		v: Float,
+		point: &Vec3f,
+		lookup: impl Fn(&dyn Texture) -> Vec3f,
+	) -> Vec3f {
+		let x = match &self.driver {
+			RampDriver::U => u,
+			RampDriver::V => v,
+			RampDriver::Height => point.y,
+			RampDriver::Texture(texture) => {
+				let color = lookup(texture.as_ref());
+				0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+			}
+		};
+
+		let next = self.stops.partition_point(|&(position, _)| position <= x);
+		match (self.stops.get(next.wrapping_sub(1)), self.stops.get(next)) {
+			(Some(&(a, from)), Some(&(b, to))) => from + (to - from) * ((x - a) / (b - a)),
+			(Some(&(_, color)), None) | (None, Some(&(_, color))) => color,
+			(None, None) => Vec3f::new(0.0, 0.0, 0.0),
+		}
+	}
+}
+
+impl Texture for RampTexture {
+	fn value(&self, u: Float, v: Float, point: &Vec3f) -> Vec3f {
+		self.map(u, v, point, |texture| texture.value(u, v, point))
+	}
+
+	fn value_at(&self, hit_result: &HitResult) -> Vec3f {
+		self.map(hit_result.u, hit_result.v, &hit_result.point, |texture| {
+			texture.value_at(hit_result)
+		})
+	}
+}
+
+// Textures combined from others, for layering procedural looks. Lerp blends from `a` to `b`
+// channel by channel by the color of `factor`, invert turns every channel c into 1 - c.
+pub enum CompositeTexture {
+	Multiply(Arc<dyn Texture>, Arc<dyn Texture>),
+	Add(Arc<dyn Texture>, Arc<dyn Texture>),
+	Lerp {
+		a: Arc<dyn Texture>,
+		b: Arc<dyn Texture>,
+		factor: Arc<dyn Texture>,
+	},
+	Invert(Arc<dyn Texture>),
+}
+
+impl CompositeTexture {
+	fn combine(&self, lookup: impl Fn(&dyn Texture) -> Vec3f) -> Vec3f {
+		match self {
+			CompositeTexture::Multiply(a, b) => lookup(a.as_ref()) * lookup(b.as_ref()),
+			CompositeTexture::Add(a, b) => lookup(a.as_ref()) + lookup(b.as_ref()),
+			CompositeTexture::Lerp { a, b, factor } => {
+				let (a, b) = (lookup(a.as_ref()), lookup(b.as_ref()));
+				a + (b - a) * lookup(factor.as_ref())
+			}
+			CompositeTexture::Invert(texture) => {
+				Vec3f::new(1.0, 1.0, 1.0) - lookup(texture.as_ref())
+			}
+		}
+	}
+}
+
+impl Texture for CompositeTexture {
+	fn value(&self, u: Float, v: Float, point: &Vec3f) -> Vec3f {
+		self.combine(|texture| texture.value(u, v, point))
+	}
+
+	fn value_at(&self, hit_result: &HitResult) -> Vec3f {
+		self.combine(|texture| texture.value_at(hit_result))
+	}
+}
+
+// Projects a texture along the three axes of space and blends the projections by how much the
+// normal faces each axis, for meshes without usable UVs. The texture repeats every 1 / `scale`
+// units, and a higher `sharpness` narrows the seams where the projections blend.
+pub struct TriplanarTexture {
+	texture: Arc<dyn Texture>,
+	scale: Float,
+	sharpness: Float,
+}
+
+impl TriplanarTexture {
+	pub fn new(texture: Arc<dyn Texture>, scale: Float, sharpness: Float) -> TriplanarTexture {
+		TriplanarTexture {
+			texture,
+			scale,
+			sharpness,
+		}
+	}
+
+	fn project(&self, point: &Vec3f, normal: &Vec3f) -> Vec3f {
+		let p = *point * self.scale;
+		let weights = normal.abs();
+		let [wx, wy, wz] = [weights.x, weights.y, weights.z].map(|w| w.powf(self.sharpness));
+		let total = wx + wy + wz;
+		if total == 0.0 {
+			return self.texture.value(p.x, p.z, point);
+		}
+
+		(self.texture.value(p.z, p.y, point) * wx
+			+ self.texture.value(p.x, p.z, point) * wy
+			+ self.texture.value(p.x, p.y, point) * wz)
+			/ total
+	}
+}
+
+impl Texture for TriplanarTexture {
+	// Without a normal only the projection from above is left
+	fn value(&self, _: Float, _: Float, point: &Vec3f) -> Vec3f {
+		self.project(point, &Vec3f::new(0.0, 1.0, 0.0))
+	}
+
+	fn value_at(&self, hit_result: &HitResult) -> Vec3f {
+		self.project(&hit_result.point, &hit_result.normal)
+	}
+}
+
+// Digits 0 to 9 in a 3x5 pixel font, the rows from the top with the leftmost pixel in the
+// highest of the three bits
+const DIGITS: [[u8; 5]; 10] = [
+	[7, 5, 5, 5, 7],
+	[2, 6, 2, 2, 7],
+	[7, 1, 7, 4, 7],
+	[7, 1, 3, 1, 7],
+	[5, 5, 7, 1, 1],
+	[7, 4, 7, 1, 7],
+	[7, 4, 7, 5, 7],
+	[7, 1, 1, 1, 1],
+	[7, 5, 7, 5, 7],
+	[7, 5, 7, 1, 7],
+];
+
+// Generated grid for checking UVs: a quarter of the 0 to 1 square each in red, green, blue and
+// yellow, brighter and darker cells alternating, thin lines between them and the number of every
+// cell written in it, counting from 0 at the bottom left along the rows. UVs outside 0 to 1 repeat.
+pub struct UvGridTexture {
+	pub cells: u32,
+}
+
+impl UvGridTexture {
+	pub fn new(cells: u32) -> UvGridTexture {
+		UvGridTexture {
+			cells: cells.max(1),
+		}
+	}
+
+	// Whether the number of the cell covers a point of it, both from 0 to 1
+	fn label(number: u32, x: Float, y: Float) -> bool {
+		let digits: Vec<u32> = number
+			.to_string()
+			.bytes()
+			.map(|b| (b - b'0') as u32)
+			.collect();
+
+		// Digits of 3x5 pixels with a gap of one, in a box half the cell high around the middle
+		let columns = digits.len() as Float * 4.0 - 1.0;
+		let pixel = Float::min(0.5 / 5.0, 0.8 / columns);
+		let column = ((x - 0.5) / pixel + columns / 2.0).floor();
+		let row = ((0.5 - y) / pixel + 2.5).floor();
+		if column < 0.0 || column >= columns || !(0.0..5.0).contains(&row) {
+			return false;
+		}
+
+		let (digit, bit) = (column as usize / 4, column as usize % 4);
+		bit < 3 && DIGITS[digits[digit] as usize][row as usize] & (4 >> bit) != 0
+	}
+}
+
+impl Texture for UvGridTexture {
+	fn value(&self, u: Float, v: Float, _: &Vec3f) -> Vec3f {
+		let (u, v) = (u.rem_euclid(1.0), v.rem_euclid(1.0));
+		let cells = self.cells as Float;
+		let (x, y) = (u * cells, v * cells);
+		let (column, row) = (
+			(x as u32).min(self.cells - 1),
+			(y as u32).min(self.cells - 1),
+		);
+		let (x, y) = (x - column as Float, y - row as Float);
+
+		// Lines between the cells
+		if !(0.03..=0.97).contains(&x) || !(0.03..=0.97).contains(&y) {
+			return Vec3f::new(0.05, 0.05, 0.05);
+		}
+		if UvGridTexture::label(row * self.cells + column, x, y) {
+			return Vec3f::new(1.0, 1.0, 1.0);
+		}
+
+		let quadrant = match (u < 0.5, v < 0.5) {
+			(true, true) => Vec3f::new(0.8, 0.15, 0.1),
+			(false, true) => Vec3f::new(0.15, 0.7, 0.15),
+			(true, false) => Vec3f::new(0.15, 0.3, 0.85),
+			(false, false) => Vec3f::new(0.85, 0.75, 0.1),
+		};
+		if (column + row) % 2 == 0 {
+			quadrant
+		} else {
+			quadrant * 0.55
+		}
 	}
 }
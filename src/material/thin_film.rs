@@ -0,0 +1,142 @@
+use std::f64::consts::PI;
+use std::ops::{Add, Div, Mul, Sub};
+
+use crate::geometry::{Float, Vec3f};
+
+// Wavelengths in nm that the red, green and blue channels are evaluated at
+const WAVELENGTHS: [Float; 3] = [650.0, 532.0, 450.0];
+
+// Thin transparent film on a surface, e.g. oil on water or a soap bubble. Light reflected at the
+// top and bottom of the film interferes, which tints the reflection by wavelength, film
+// thickness and viewing angle.
+#[derive(Debug, Clone, Copy)]
+pub struct ThinFilm {
+	// In nanometers
+	pub thickness: Float,
+	pub refraction_index: Float,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Complex {
+	re: Float,
+	im: Float,
+}
+
+impl Complex {
+	const fn new(re: Float, im: Float) -> Complex {
+		Complex { re, im }
+	}
+
+	fn norm2(self) -> Float {
+		self.re * self.re + self.im * self.im
+	}
+
+	// e^(i self), which decays for a complex phase
+	fn exp_i(self) -> Complex {
+		let (sin, cos) = self.re.sin_cos();
+		let decay = (-self.im).exp();
+		Complex::new(cos * decay, sin * decay)
+	}
+
+	// Principal square root
+	fn sqrt(self) -> Complex {
+		let length = self.norm2().sqrt();
+		let re = ((length + self.re) / 2.0).max(0.0).sqrt();
+		let im = ((length - self.re) / 2.0).max(0.0).sqrt();
+		Complex::new(re, if self.im < 0.0 { -im } else { im })
+	}
+}
+
+impl Add for Complex {
+	type Output = Complex;
+
+	fn add(self, rhs: Complex) -> Complex {
+		Complex::new(self.re + rhs.re, self.im + rhs.im)
+	}
+}
+
+impl Sub for Complex {
+	type Output = Complex;
+
+	fn sub(self, rhs: Complex) -> Complex {
+		Complex::new(self.re - rhs.re, self.im - rhs.im)
+	}
+}
+
+impl Mul for Complex {
+	type Output = Complex;
+
+	fn mul(self, rhs: Complex) -> Complex {
+		Complex::new(
+			self.re * rhs.re - self.im * rhs.im,
+			self.re * rhs.im + self.im * rhs.re,
+		)
+	}
+}
+
+impl Div for Complex {
+	type Output = Complex;
+
+	fn div(self, rhs: Complex) -> Complex {
+		let norm2 = rhs.norm2();
+		Complex::new(
+			(self.re * rhs.re + self.im * rhs.im) / norm2,
+			(self.im * rhs.re - self.re * rhs.im) / norm2,
+		)
+	}
+}
+
+// Cosine of the angle in a medium with index `n`, from Snell's law with `sin2` the squared sine
+// times the squared index in the medium the light comes from
+fn cosine(sin2: Complex, n: Complex) -> Complex {
+	(Complex::new(1.0, 0.0) - sin2 / (n * n)).sqrt()
+}
+
+// Fresnel amplitude coefficients for s and p polarized light going from medium i into j
+fn amplitudes(ni: Complex, cos_i: Complex, nj: Complex, cos_j: Complex) -> (Complex, Complex) {
+	let s = (ni * cos_i - nj * cos_j) / (ni * cos_i + nj * cos_j);
+	let p = (nj * cos_i - ni * cos_j) / (nj * cos_i + ni * cos_j);
+	(s, p)
+}
+
+impl ThinFilm {
+	// Reflectance from outside (air) at the given cosine for every channel, of the film on top
+	// of a substrate with the complex index of refraction eta + ik
+	pub fn reflectance(&self, cos_theta: Float, eta: Vec3f, k: Vec3f) -> Vec3f {
+		let substrate = [
+			Complex::new(eta.x, k.x),
+			Complex::new(eta.y, k.y),
+			Complex::new(eta.z, k.z),
+		];
+		let [r, g, b] =
+			std::array::from_fn(|i| self.channel(cos_theta, substrate[i], WAVELENGTHS[i]));
+
+		Vec3f::new(r, g, b)
+	}
+
+	fn channel(&self, cos_theta: Float, substrate: Complex, wavelength: Float) -> Float {
+		let air = Complex::new(1.0, 0.0);
+		let film = Complex::new(self.refraction_index, 0.0);
+
+		// Grazing light divides 0 by 0 in the amplitudes
+		let cos0 = Complex::new(cos_theta.clamp(1e-4, 1.0), 0.0);
+		let sin2 = Complex::new(1.0 - cos0.re * cos0.re, 0.0);
+		let cos1 = cosine(sin2, film);
+		let cos2 = cosine(sin2, substrate);
+
+		let (s01, p01) = amplitudes(air, cos0, film, cos1);
+		let (s12, p12) = amplitudes(film, cos1, substrate, cos2);
+
+		// Phase difference of one round trip through the film, the light only tunnels through
+		// when it is totally reflected inside
+		let phase = 4.0 * PI as Float * self.thickness * self.refraction_index / wavelength;
+		let shift = (cos1 * Complex::new(phase, 0.0)).exp_i();
+
+		let airy = |r01: Complex, r12: Complex| {
+			let r12 = r12 * shift;
+			((r01 + r12) / (Complex::new(1.0, 0.0) + r01 * r12)).norm2()
+		};
+
+		((airy(s01, s12) + airy(p01, p12)) / 2.0).clamp(0.0, 1.0)
+	}
+}
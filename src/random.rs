@@ -0,0 +1,58 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::geometry::Float;
+
+// Each thread gets its own generator, seeded from a shared counter so no OS entropy source
+// is needed (wasm32 has none without extra glue)
+static NEXT_SEED: AtomicU64 = AtomicU64::new(0);
+
+// SplitMix64, whose whole state is a single u64 in a Cell. Sampling draws several numbers per
+// bounce, and unlike a RefCell'd rand generator this needs no borrow and inlines to a handful
+// of instructions.
+const GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+fn mix(mut z: u64) -> u64 {
+	z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+	z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+	z ^ (z >> 31)
+}
+
+thread_local! {
+	static STATE: Cell<u64> = Cell::new(mix(NEXT_SEED.fetch_add(1, Ordering::Relaxed)));
+}
+
+// Reseeds the calling thread's generator, used to make renders reproducible. Neighboring seeds
+// give unrelated sequences.
+pub fn seed(seed: u64) {
+	STATE.with(|state| state.set(mix(seed)));
+}
+
+// Uniform over all u64
+#[inline]
+pub fn u64() -> u64 {
+	STATE.with(|state| {
+		let next = state.get().wrapping_add(GAMMA);
+		state.set(next);
+		mix(next)
+	})
+}
+
+// Uniform in [0, 1), with all the precision of the mantissa
+#[inline]
+pub fn float() -> Float {
+	let bits = Float::MANTISSA_DIGITS;
+	(u64() >> (64 - bits)) as Float / (1u64 << bits) as Float
+}
+
+// Uniform in [min, max)
+#[inline]
+pub fn range(min: Float, max: Float) -> Float {
+	min + (max - min) * float()
+}
+
+// Uniform in [0, n), the bias of the multiply is far below anything a render could show
+#[inline]
+pub fn index(n: usize) -> usize {
+	((u64() as u128 * n as u128) >> 64) as usize
+}
@@ -0,0 +1,949 @@
+use image::{ImageBuffer, Rgb, RgbImage, Rgba, RgbaImage};
+use std::num::NonZeroUsize;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::camera::Camera;
+use crate::dither::Dither;
+use crate::effects::Effects;
+use crate::error::{RendererError, Result};
+use crate::film::{Film, FilmTile};
+use crate::filter::Filter;
+use crate::geometry::{Float, HittableGroup, Vec3f};
+use crate::integrator::{heat, Integrator, Sample, MAX_LIGHT_GROUPS};
+use crate::lut::Lut;
+use crate::random;
+use crate::stats;
+
+// Called every time a worker finishes a row, or after every pass for the renders that add one
+// sample per pixel at a time
+pub type ProgressCallback = Arc<dyn Fn(Progress) + Send + Sync>;
+
+// `completed` out of `total` rows for render, passes for render_progressive and milliseconds of
+// the budget for render_for, along with the samples taken and the rays traced per second since
+// the render started. The ray rate counts the rays of every render running in the process.
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+	pub completed: u32,
+	pub total: u32,
+	pub samples: u64,
+	pub rays_per_second: f64,
+}
+
+// Stops a render from another thread, checked before every pixel. The render then returns
+// RendererError::Cancelled. Clones share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+	cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+	pub fn new() -> CancelToken {
+		CancelToken::default()
+	}
+
+	pub fn cancel(&self) {
+		self.cancelled.store(true, Ordering::Relaxed);
+	}
+
+	pub fn is_cancelled(&self) -> bool {
+		self.cancelled.load(Ordering::Relaxed)
+	}
+}
+
+fn is_cancelled(cancel: Option<&CancelToken>) -> bool {
+	cancel.is_some_and(CancelToken::is_cancelled)
+}
+
+// The rays traced per second since the render started
+#[derive(Debug, Clone, Copy)]
+struct Rate {
+	start: Instant,
+	rays: u64,
+}
+
+impl Rate {
+	fn start() -> Rate {
+		Rate {
+			start: Instant::now(),
+			rays: stats::rays(),
+		}
+	}
+
+	fn rays_per_second(&self) -> f64 {
+		(stats::rays() - self.rays) as f64 / self.start.elapsed().as_secs_f64().max(1e-9)
+	}
+}
+
+// How the workers of a render report finished rows and learn that it was cancelled
+#[derive(Clone)]
+struct Reporting {
+	progress: ProgressCallback,
+	rate: Rate,
+	cancel: Option<CancelToken>,
+}
+
+// Called with a finished part of the image and its exposed colors row by row, for live previews.
+// render sends every row as soon as it is done, render_for the whole region after every pass.
+pub type PreviewCallback = Arc<dyn Fn(Region, &[Vec3f]) + Send + Sync>;
+
+// A preview with the gain that turns the averages of a row into its colors
+#[derive(Clone)]
+struct Preview {
+	callback: PreviewCallback,
+	gain: Vec3f,
+}
+
+// Sub-rectangle of the image in pixels, everything outside of it is left black
+#[derive(Debug, Clone, Copy)]
+pub struct Region {
+	pub x: u32,
+	pub y: u32,
+	pub width: u32,
+	pub height: u32,
+}
+
+impl Region {
+	pub fn full(width: u32, height: u32) -> Region {
+		Region {
+			x: 0,
+			y: 0,
+			width,
+			height,
+		}
+	}
+
+	// Shrinks the region so it lies within an image of the given size
+	pub fn clamp(&self, width: u32, height: u32) -> Region {
+		let x = self.x.min(width);
+		let y = self.y.min(height);
+
+		Region {
+			x,
+			y,
+			width: self.width.min(width - x),
+			height: self.height.min(height - y),
+		}
+	}
+}
+
+// Parses "x,y,w,h"
+impl FromStr for Region {
+	type Err = String;
+
+	fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+		let parts: Vec<u32> = value
+			.split(',')
+			.map(|part| part.trim().parse::<u32>())
+			.collect::<std::result::Result<_, _>>()
+			.map_err(|_| format!("invalid region '{}', expected x,y,w,h", value))?;
+
+		match parts.as_slice() {
+			&[x, y, width, height] => Ok(Region {
+				x,
+				y,
+				width,
+				height,
+			}),
+			_ => Err(format!("invalid region '{}', expected x,y,w,h", value)),
+		}
+	}
+}
+
+// Shift of the whole sample pattern within the pixels, for accumulating frames TAA style. A
+// fixed offset is the same for every frame, the Halton one moves on with each frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleOffset {
+	Fixed(Float, Float),
+	Halton,
+}
+
+impl SampleOffset {
+	pub fn for_frame(self, frame: u32) -> (Float, Float) {
+		match self {
+			SampleOffset::Fixed(x, y) => (x, y),
+			SampleOffset::Halton => halton_offset(frame),
+		}
+	}
+}
+
+// Parses "x,y" in pixels or "halton"
+impl FromStr for SampleOffset {
+	type Err = String;
+
+	fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+		if value == "halton" {
+			return Ok(SampleOffset::Halton);
+		}
+
+		let error = || format!("invalid sample offset '{}', expected x,y or halton", value);
+		let parts: Vec<Float> = value
+			.split(',')
+			.map(|part| part.trim().parse::<Float>())
+			.collect::<std::result::Result<_, _>>()
+			.map_err(|_| error())?;
+
+		match parts.as_slice() {
+			&[x, y] if x.is_finite() && y.is_finite() => Ok(SampleOffset::Fixed(x, y)),
+			_ => Err(error()),
+		}
+	}
+}
+
+fn radical_inverse(mut index: u32, base: u32) -> Float {
+	let (mut result, mut scale) = (0.0, 1.0);
+	while index > 0 {
+		scale /= base as Float;
+		result += (index % base) as Float * scale;
+		index /= base;
+	}
+	result
+}
+
+// The offset of `frame` in (-0.5, 0.5) pixels from the Halton sequence in bases 2 and 3, which
+// spreads consecutive frames evenly over the pixel. Frame 0 gets the first point after the
+// origin, so none of them repeats the unshifted pattern.
+pub fn halton_offset(frame: u32) -> (Float, Float) {
+	let index = frame.wrapping_add(1);
+	(
+		radical_inverse(index, 2) - 0.5,
+		radical_inverse(index, 3) - 0.5,
+	)
+}
+
+// What the render is saved as. AOVs and light groups go into a single EXR together with the
+// render when there is more than one, or always with Exr.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+	Png,
+	// Float colors and alpha, before tone mapping and quantization
+	Exr,
+}
+
+pub const FORMATS: &[&str] = &["png", "exr"];
+
+impl FromStr for OutputFormat {
+	type Err = String;
+
+	fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+		match value {
+			"png" => Ok(OutputFormat::Png),
+			"exr" => Ok(OutputFormat::Exr),
+			_ => Err(format!(
+				"unknown format '{}', expected one of {}",
+				value,
+				FORMATS.join(", ")
+			)),
+		}
+	}
+}
+
+// The passes written besides the render and its light groups
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AovSettings {
+	// Average bounce count of every pixel
+	pub depth: bool,
+	// The objects seen in every pixel
+	pub ids: bool,
+	// False color exposure zones and a luminance histogram, always as PNGs
+	pub exposure: bool,
+}
+
+// How a scene is rendered and saved, everything but the scene, its camera and the integrator.
+// The default has the quality of Preset::Final.
+#[derive(Debug, Clone, Copy)]
+pub struct RenderSettings {
+	pub width: u32,
+	pub height: u32,
+	// Factor for the resolution and the region, see scaled
+	pub scale: Float,
+	// Only this part of the image is rendered, the rest stays black
+	pub region: Option<Region>,
+	pub samples: usize,
+	// Bounces of the path tracer unless a scene file gives its own
+	pub max_depth: u32,
+	// Capped at MAX_THREADS_PER_CORE per core and at the number of tiles
+	pub threads: usize,
+	// Rows a worker takes at a time
+	pub tile_size: u32,
+	// Seeds the calling thread's generator before rendering, None carries on with its state
+	pub seed: Option<u64>,
+	// Samples brighter than this in any channel are scaled down to it, which trades a little
+	// energy for fewer fireflies
+	pub clamp: Option<Float>,
+	pub format: OutputFormat,
+	pub aovs: AovSettings,
+	// How samples are weighted into the pixels around them
+	pub filter: Filter,
+	// In pixels, moves where the camera rays go through the image but not the pixels that their
+	// samples count for
+	pub sample_offset: (Float, Float),
+}
+
+impl Default for RenderSettings {
+	fn default() -> Self {
+		RenderSettings {
+			width: 768,
+			height: 768,
+			scale: 1.0,
+			region: None,
+			samples: 444,
+			max_depth: 5,
+			threads: available_threads(),
+			tile_size: 8,
+			seed: None,
+			clamp: None,
+			format: OutputFormat::Png,
+			aovs: AovSettings::default(),
+			filter: Filter::Box,
+			sample_offset: (0.0, 0.0),
+		}
+	}
+}
+
+// Quality levels, each one sets the samples, bounces, resolution scale and clamp together and
+// leaves the rest of the settings alone
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Preset {
+	// For framing and blocking out lights, fast but noisy, darker and at half the resolution
+	Draft,
+	// Close to the final image in a fraction of the time, with the brightest fireflies clamped
+	Medium,
+	Final,
+}
+
+pub const PRESETS: &[&str] = &["draft", "medium", "final"];
+
+impl FromStr for Preset {
+	type Err = String;
+
+	fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+		match value {
+			"draft" => Ok(Preset::Draft),
+			"medium" => Ok(Preset::Medium),
+			"final" => Ok(Preset::Final),
+			_ => Err(format!(
+				"unknown preset '{}', expected one of {}",
+				value,
+				PRESETS.join(", ")
+			)),
+		}
+	}
+}
+
+impl Preset {
+	pub fn apply(self, settings: RenderSettings) -> RenderSettings {
+		let (samples, max_depth, scale, clamp) = match self {
+			Preset::Draft => (16, 3, 0.5, Some(10.0)),
+			Preset::Medium => (128, 4, 1.0, Some(100.0)),
+			Preset::Final => (444, 5, 1.0, None),
+		};
+
+		RenderSettings {
+			samples,
+			max_depth,
+			scale,
+			clamp,
+			..settings
+		}
+	}
+}
+
+// Widest or tallest image that scaling up may give, larger factors are reduced to fit
+pub const MAX_SCALED_SIZE: u32 = 16384;
+
+impl RenderSettings {
+	// The settings at `scale` times their resolution, at least a pixel and when scaling up at most
+	// MAX_SCALED_SIZE on the longer side, and with a scale of 1. The camera has to be resized to
+	// match.
+	pub fn scaled(&self) -> RenderSettings {
+		let longest = self.width.max(self.height).max(1) as Float;
+		let factor = self
+			.scale
+			.min((MAX_SCALED_SIZE as Float / longest).max(1.0));
+		let scale = |value: u32| (value as Float * factor).round() as u32;
+		RenderSettings {
+			width: scale(self.width).max(1),
+			height: scale(self.height).max(1),
+			scale: 1.0,
+			region: self.region.map(|region| Region {
+				x: scale(region.x),
+				y: scale(region.y),
+				width: scale(region.width).max(1),
+				height: scale(region.height).max(1),
+			}),
+			..*self
+		}
+	}
+
+	// The region to render, the whole image without one
+	pub fn region(&self) -> Region {
+		self.region
+			.unwrap_or(Region::full(self.width, self.height))
+			.clamp(self.width, self.height)
+	}
+}
+
+// Workers beyond this many per core only add scheduling overhead
+pub const MAX_THREADS_PER_CORE: usize = 2;
+
+// The number of threads the machine can run at once, 1 if that cannot be found out
+pub fn available_threads() -> usize {
+	std::thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+// Number of objects whose coverage is kept per pixel for the ID pass
+pub const ID_RANKS: usize = 4;
+// What the workers of a pass share. They take tiles of `tile_size` rows in turn until none are
+// left.
+struct Pass {
+	world: Arc<HittableGroup>,
+	camera: Arc<Camera>,
+	integrator: Arc<dyn Integrator>,
+	region: Region,
+	samples: usize,
+	clamp: Option<Float>,
+	filter: Filter,
+	sample_offset: (Float, Float),
+	seed: u64,
+	tile_size: u32,
+	next_tile: AtomicU32,
+	completed: Mutex<u32>,
+	reporting: Reporting,
+	preview: Option<Preview>,
+}
+
+// Scales the sample down so that no channel is brighter than `clamp`, light groups alike
+fn clamp_sample(sample: &mut Sample, clamp: Float) {
+	let brightest = sample.color.x.max(sample.color.y).max(sample.color.z);
+	if brightest <= clamp {
+		return;
+	}
+
+	let scale = clamp / brightest;
+	sample.color *= scale;
+	for group in sample.groups.iter_mut() {
+		*group *= scale;
+	}
+}
+
+// Renders the rows from `row_start` to `row_end` into a tile of the film
+fn render_rows(pass: &Pass, row_start: u32, row_end: u32) -> FilmTile {
+	let region = pass.region;
+	let camera = &pass.camera;
+	let group_count = pass.integrator.light_groups().len().min(MAX_LIGHT_GROUPS);
+	let mut tile = FilmTile::new(region, row_start, row_end, pass.filter, group_count);
+
+	for row in row_start..row_end {
+		// Every row gets its own seed, so the image does not depend on how rows are split up
+		random::seed(pass.seed.wrapping_add(row as u64));
+
+		let y = row;
+		for x in region.x..region.x + region.width {
+			// What was rendered so far is thrown away anyway
+			if is_cancelled(pass.reporting.cancel.as_ref()) {
+				return tile;
+			}
+
+			let bounces = stats::pending_bounces();
+			for _ in 0..pass.samples {
+				let image_x = x as Float + random::float();
+				let image_y = y as Float + random::float();
+
+				// Pixels outside of the projection are black but opaque
+				let (offset_x, offset_y) = pass.sample_offset;
+				let Some(ray) = camera.ray_at(image_x + offset_x, image_y + offset_y) else {
+					let black = Sample::opaque(Vec3f::new(0.0, 0.0, 0.0));
+					tile.add_sample(image_x, image_y, &black);
+					continue;
+				};
+
+				let mut sample = pass.integrator.li(
+					&ray,
+					&pass.world,
+					camera.background,
+					camera.spread(),
+					camera.differentials(&ray),
+				);
+				if let Some(clamp) = pass.clamp {
+					clamp_sample(&mut sample, clamp);
+				}
+				tile.add_sample(image_x, image_y, &sample);
+			}
+			tile.set_bounces(x, y, stats::pending_bounces() - bounces);
+		}
+
+		stats::flush((region.width as usize * pass.samples) as u64);
+
+		if let Some(preview) = &pass.preview {
+			let colors: Vec<Vec3f> = (region.x..region.x + region.width)
+				.map(|x| tile.color(x, row) * preview.gain)
+				.collect();
+			let row = Region {
+				y: row,
+				height: 1,
+				..region
+			};
+			(preview.callback)(row, &colors);
+		}
+
+		{
+			let mut counter = pass.completed.lock().unwrap();
+			*counter += 1;
+			(pass.reporting.progress)(Progress {
+				completed: *counter,
+				total: region.height,
+				samples: *counter as u64 * region.width as u64 * pass.samples as u64,
+				rays_per_second: pass.reporting.rate.rays_per_second(),
+			});
+		}
+	}
+
+	tile
+}
+
+// Renders tiles until there are none left
+fn work(pass: &Pass) -> Vec<FilmTile> {
+	let mut tiles = Vec::new();
+
+	loop {
+		let tile = pass.next_tile.fetch_add(1, Ordering::Relaxed);
+		let start = pass.region.y + tile * pass.tile_size;
+		let end = (start + pass.tile_size).min(pass.region.y + pass.region.height);
+		if start >= end || is_cancelled(pass.reporting.cancel.as_ref()) {
+			return tiles;
+		}
+
+		tiles.push(render_rows(pass, start, end));
+	}
+}
+
+// Renders `samples` samples for every pixel in the region and adds them to `film`. The seeds of
+// the workers are drawn from the calling thread's generator, see random::seed. Fails with
+// RendererError::Cancelled if the render was cancelled before all rows were done.
+#[allow(clippy::too_many_arguments)]
+fn render_pass(
+	world: &Arc<HittableGroup>,
+	camera: &Arc<Camera>,
+	integrator: &Arc<dyn Integrator>,
+	settings: &RenderSettings,
+	region: Region,
+	samples: usize,
+	reporting: &Reporting,
+	preview: Option<&Preview>,
+	film: &mut Film,
+) -> Result<()> {
+	let tile_size = settings.tile_size.max(1);
+	let tiles = region.height.div_ceil(tile_size) as usize;
+	let threads = settings
+		.threads
+		.min(available_threads() * MAX_THREADS_PER_CORE)
+		.min(tiles)
+		.max(1);
+
+	let pass = Arc::new(Pass {
+		world: Arc::clone(world),
+		camera: Arc::clone(camera),
+		integrator: Arc::clone(integrator),
+		region,
+		samples,
+		clamp: settings.clamp,
+		filter: film.filter(),
+		sample_offset: settings.sample_offset,
+		seed: random::u64(),
+		tile_size,
+		next_tile: AtomicU32::new(0),
+		completed: Mutex::new(0),
+		reporting: reporting.clone(),
+		preview: preview.cloned(),
+	});
+
+	let handles: Vec<JoinHandle<Vec<FilmTile>>> = (0..threads)
+		.map(|_| {
+			let pass = Arc::clone(&pass);
+			std::thread::spawn(move || work(&pass))
+		})
+		.collect();
+
+	let tiles: Vec<Vec<FilmTile>> = handles
+		.into_iter()
+		.map(|handle| handle.join().map_err(|_| RendererError::WorkerPanicked))
+		.collect::<Result<_>>()?;
+
+	if is_cancelled(reporting.cancel.as_ref()) {
+		return Err(RendererError::Cancelled);
+	}
+
+	for tile in tiles.iter().flatten() {
+		film.merge(tile);
+	}
+
+	Ok(())
+}
+
+// Adjustments applied to the averaged samples before they are quantized
+#[derive(Debug, Clone)]
+pub struct PostProcess {
+	// In stops, every +1 doubles the brightness
+	pub exposure: Float,
+	// Color temperature in Kelvin of the light that should come out white, None leaves the
+	// colors as rendered
+	pub white_balance: Option<Float>,
+	// Noise against banding when the colors are cut down to 8 bit
+	pub dither: Option<Dither>,
+	// Bloom, vignette and chromatic aberration of the beauty image, light groups are left as
+	// they are so that they still add up to the light of the scene
+	pub effects: Effects,
+	// Grade of the 8 bit images, looked up with the clamped colors, the float colors stay
+	// ungraded
+	pub lut: Option<Arc<Lut>>,
+}
+
+impl Default for PostProcess {
+	fn default() -> Self {
+		PostProcess {
+			exposure: 0.0,
+			white_balance: None,
+			dither: None,
+			effects: Effects::default(),
+			lut: None,
+		}
+	}
+}
+
+// Approximate color of a black body at the given temperature, after Tanner Helland's fit
+fn kelvin_to_rgb(kelvin: Float) -> Vec3f {
+	let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+
+	let r = if t <= 66.0 {
+		255.0
+	} else {
+		329.69873 * (t - 60.0).powf(-0.13320476)
+	};
+	let g = if t <= 66.0 {
+		99.4708 * t.ln() - 161.11957
+	} else {
+		288.12217 * (t - 60.0).powf(-0.07551485)
+	};
+	let b = if t >= 66.0 {
+		255.0
+	} else if t <= 19.0 {
+		0.0
+	} else {
+		138.51773 * (t - 10.0).ln() - 305.0448
+	};
+
+	Vec3f::new(
+		r.clamp(1.0, 255.0) / 255.0,
+		g.clamp(1.0, 255.0) / 255.0,
+		b.clamp(1.0, 255.0) / 255.0,
+	)
+}
+
+impl PostProcess {
+	// Exposure and white balance together, per channel
+	pub fn gain(&self) -> Vec3f {
+		let exposure = Float::powf(2.0, self.exposure);
+		let balance = match self.white_balance {
+			// Relative to daylight, so 6500K leaves the image untouched
+			Some(kelvin) => {
+				let daylight = kelvin_to_rgb(6500.0);
+				let light = kelvin_to_rgb(kelvin);
+				Vec3f::new(
+					daylight.x / light.x,
+					daylight.y / light.y,
+					daylight.z / light.z,
+				)
+			}
+			None => Vec3f::new(1.0, 1.0, 1.0),
+		};
+
+		balance * exposure
+	}
+}
+
+// The light that came from the emitters of one light group
+pub struct LightGroup {
+	pub name: String,
+	pub image: RgbImage,
+	pub colors: Vec<Vec3f>,
+}
+
+// The developed image plus the coverage and the average number of bounces per sample of every
+// pixel, row by row. `colors` are the exposed colors with the effects applied, before they were
+// clamped and quantized to 8 bit. The colors of partially covered pixels are premultiplied by their
+// alpha. Every light group of the integrator gets an image of the light that came from its
+// emitters. `ids` holds the objects seen in each pixel with the fraction of samples that hit them,
+// most covered first and padded with (0, 0.0).
+pub struct RenderOutput {
+	pub image: RgbImage,
+	pub colors: Vec<Vec3f>,
+	pub light_groups: Vec<LightGroup>,
+	pub samples: usize,
+	// Samples left out for a NaN or an infinity, the pixels they were meant for average the rest
+	pub rejected: u64,
+	pub alpha: Vec<Float>,
+	pub depth: Vec<Float>,
+	pub ids: Vec<[(u32, Float); ID_RANKS]>,
+}
+
+impl RenderOutput {
+	// False if a holdout cut into any pixel
+	pub fn is_opaque(&self) -> bool {
+		self.alpha.iter().all(|&alpha| alpha >= 1.0)
+	}
+
+	// The image with straight (not premultiplied) alpha, as PNG expects
+	pub fn rgba_image(&self) -> RgbaImage {
+		let mut buffer: RgbaImage = ImageBuffer::new(self.image.width(), self.image.height());
+
+		for ((pixel, rgb), &alpha) in buffer
+			.pixels_mut()
+			.zip(self.image.pixels())
+			.zip(&self.alpha)
+		{
+			let unpremultiply = |value: u8| {
+				if alpha > 0.0 {
+					(value as Float / alpha).min(255.0) as u8
+				} else {
+					0
+				}
+			};
+
+			*pixel = Rgba([
+				unpremultiply(rgb[0]),
+				unpremultiply(rgb[1]),
+				unpremultiply(rgb[2]),
+				(alpha * 255.0) as u8,
+			]);
+		}
+
+		buffer
+	}
+
+	// Heat map of the average path depth, from blue at 0 to red at the deepest pixel in the image
+	pub fn depth_image(&self) -> RgbImage {
+		let max = self.max_depth().max(1.0);
+		let mut buffer: RgbImage = ImageBuffer::new(self.image.width(), self.image.height());
+
+		for (pixel, depth) in buffer.pixels_mut().zip(&self.depth) {
+			let color = heat(depth / max);
+
+			*pixel = Rgb([
+				(color.x * 255.0) as u8,
+				(color.y * 255.0) as u8,
+				(color.z * 255.0) as u8,
+			]);
+		}
+
+		buffer
+	}
+
+	// Deepest average of any pixel
+	pub fn max_depth(&self) -> Float {
+		self.depth.iter().cloned().fold(0.0, Float::max)
+	}
+
+	// Every object in a color made from its ID, weighted by how much of the pixel it covers.
+	// Pixels that only see the background are black.
+	pub fn id_image(&self) -> RgbImage {
+		let mut buffer: RgbImage = ImageBuffer::new(self.image.width(), self.image.height());
+
+		for (pixel, ids) in buffer.pixels_mut().zip(&self.ids) {
+			let mut color = Vec3f::new(0.0, 0.0, 0.0);
+			for &(id, coverage) in ids {
+				let [r, g, b, _] = id.to_le_bytes();
+				color += Vec3f::new(r as Float, g as Float, b as Float) * coverage;
+			}
+
+			*pixel = Rgb([color.x as u8, color.y as u8, color.z as u8]);
+		}
+
+		buffer
+	}
+}
+
+// Renders `settings.samples` samples for every pixel of the region of the settings, seeding the
+// calling thread's generator first if they have a seed
+#[allow(clippy::too_many_arguments)]
+pub fn render(
+	world: Arc<HittableGroup>,
+	camera: Arc<Camera>,
+	integrator: Arc<dyn Integrator>,
+	settings: &RenderSettings,
+	post: &PostProcess,
+	progress: ProgressCallback,
+	preview: Option<PreviewCallback>,
+	cancel: Option<&CancelToken>,
+) -> Result<RenderOutput> {
+	let (width, height) = (settings.width, settings.height);
+	let region = settings.region();
+	if let Some(seed) = settings.seed {
+		random::seed(seed);
+	}
+	let reporting = Reporting {
+		progress,
+		rate: Rate::start(),
+		cancel: cancel.cloned(),
+	};
+	let preview = preview.map(|callback| Preview {
+		callback,
+		gain: post.gain(),
+	});
+
+	let mut film = Film::new(
+		width,
+		height,
+		settings.filter,
+		integrator.light_groups().len().min(MAX_LIGHT_GROUPS),
+	);
+	render_pass(
+		&world,
+		&camera,
+		&integrator,
+		settings,
+		region,
+		settings.samples,
+		&reporting,
+		preview.as_ref(),
+		&mut film,
+	)?;
+
+	Ok(film.develop(integrator.light_groups(), settings.samples, post))
+}
+
+// Adds one sample per pixel at a time until `done`, told the number of passes so far, returns
+// true. The preview gets the whole region after every pass.
+#[allow(clippy::too_many_arguments)]
+fn accumulate(
+	world: &Arc<HittableGroup>,
+	camera: &Arc<Camera>,
+	integrator: &Arc<dyn Integrator>,
+	settings: &RenderSettings,
+	post: &PostProcess,
+	preview: Option<&PreviewCallback>,
+	cancel: Option<&CancelToken>,
+	mut done: impl FnMut(usize) -> bool,
+) -> Result<(Film, usize)> {
+	let region = settings.region();
+	if let Some(seed) = settings.seed {
+		random::seed(seed);
+	}
+	let quiet = Reporting {
+		progress: Arc::new(|_| {}),
+		rate: Rate::start(),
+		cancel: cancel.cloned(),
+	};
+
+	let mut film = Film::new(
+		settings.width,
+		settings.height,
+		settings.filter,
+		integrator.light_groups().len().min(MAX_LIGHT_GROUPS),
+	);
+	let mut passes = 0;
+
+	loop {
+		render_pass(
+			world, camera, integrator, settings, region, 1, &quiet, None, &mut film,
+		)?;
+		passes += 1;
+
+		if let Some(preview) = preview {
+			let gain = post.gain();
+			let colors: Vec<Vec3f> = (region.y..region.y + region.height)
+				.flat_map(|y| (region.x..region.x + region.width).map(move |x| (x, y)))
+				.map(|(x, y)| film.color(x, y) * gain)
+				.collect();
+			preview(region, &colors);
+		}
+
+		if done(passes) {
+			return Ok((film, passes));
+		}
+	}
+}
+
+// Keeps adding one sample per pixel until the time budget runs out, the pass that is running
+// when the budget expires is still finished. Progress is reported in elapsed milliseconds.
+// The output records the number of samples per pixel it got, the samples of the settings are
+// ignored.
+#[allow(clippy::too_many_arguments)]
+pub fn render_for(
+	world: Arc<HittableGroup>,
+	camera: Arc<Camera>,
+	integrator: Arc<dyn Integrator>,
+	settings: &RenderSettings,
+	budget: Duration,
+	post: &PostProcess,
+	progress: ProgressCallback,
+	preview: Option<PreviewCallback>,
+	cancel: Option<&CancelToken>,
+) -> Result<RenderOutput> {
+	let region = settings.region();
+	let rate = Rate::start();
+	let total = budget.as_millis() as u32;
+
+	let (film, passes) = accumulate(
+		&world,
+		&camera,
+		&integrator,
+		settings,
+		post,
+		preview.as_ref(),
+		cancel,
+		|passes| {
+			progress(Progress {
+				completed: (rate.start.elapsed().as_millis() as u32).min(total),
+				total,
+				samples: passes as u64 * region.width as u64 * region.height as u64,
+				rays_per_second: rate.rays_per_second(),
+			});
+			rate.start.elapsed() >= budget
+		},
+	)?;
+
+	Ok(film.develop(integrator.light_groups(), passes, post))
+}
+
+// Adds `settings.samples` passes of one sample per pixel like render_for, with the number of
+// finished passes as progress, so that the preview refines over the whole image
+#[allow(clippy::too_many_arguments)]
+pub fn render_progressive(
+	world: Arc<HittableGroup>,
+	camera: Arc<Camera>,
+	integrator: Arc<dyn Integrator>,
+	settings: &RenderSettings,
+	post: &PostProcess,
+	progress: ProgressCallback,
+	preview: Option<PreviewCallback>,
+	cancel: Option<&CancelToken>,
+) -> Result<RenderOutput> {
+	let region = settings.region();
+	let samples = settings.samples;
+
+	let rate = Rate::start();
+	let (film, passes) = accumulate(
+		&world,
+		&camera,
+		&integrator,
+		settings,
+		post,
+		preview.as_ref(),
+		cancel,
+		|passes| {
+			progress(Progress {
+				completed: passes as u32,
+				total: samples as u32,
+				samples: passes as u64 * region.width as u64 * region.height as u64,
+				rays_per_second: rate.rays_per_second(),
+			});
+			passes >= samples
+		},
+	)?;
+
+	Ok(film.develop(integrator.light_groups(), passes, post))
+}
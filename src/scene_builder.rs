@@ -0,0 +1,58 @@
+use std::sync::Arc;
+
+use crate::camera::{Camera, CameraBuilder};
+use crate::geometry::{object_id, Bvh, Hittable, HittableGroup, Tagged};
+
+// Collects the objects and the camera of a scene put together in Rust, for code that would
+// otherwise fill a HittableGroup by hand. The objects end up in a single BVH.
+pub struct SceneBuilder {
+	objects: Vec<Arc<dyn Hittable>>,
+	camera: CameraBuilder,
+}
+
+impl Default for SceneBuilder {
+	fn default() -> Self {
+		SceneBuilder::new()
+	}
+}
+
+impl SceneBuilder {
+	pub fn new() -> SceneBuilder {
+		SceneBuilder {
+			objects: Vec::new(),
+			camera: Camera::builder(),
+		}
+	}
+
+	pub fn camera(mut self, camera: CameraBuilder) -> SceneBuilder {
+		self.camera = camera;
+		self
+	}
+
+	pub fn object(mut self, object: impl Hittable + 'static) -> SceneBuilder {
+		self.objects.push(Arc::new(object));
+		self
+	}
+
+	// The object gets the ID of its name, which the ID pass and raycasts report, see object_id
+	pub fn named_object(mut self, name: &str, object: impl Hittable + 'static) -> SceneBuilder {
+		self.objects
+			.push(Arc::new(Tagged::new(Box::new(object), object_id(name))));
+		self
+	}
+
+	pub fn build(self) -> (HittableGroup, Arc<Camera>) {
+		let mut world = HittableGroup::new();
+		let camera = self.build_into(&mut world);
+		(world, camera)
+	}
+
+	// Adds the objects to `world` and returns the camera, the way scenes::Scene functions do
+	pub fn build_into(self, world: &mut HittableGroup) -> Arc<Camera> {
+		if !self.objects.is_empty() {
+			world.add(Box::new(Bvh::from_primitives(self.objects)));
+		}
+
+		Arc::new(self.camera.build())
+	}
+}
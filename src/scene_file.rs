@@ -0,0 +1,2268 @@
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::animation::{CameraPath, Interpolation, Track, TransformPath};
+use crate::aperture::{Aperture, ApertureMask};
+use crate::assets::Assets;
+use crate::camera::{Camera, DepthOfField, FisheyeMapping, Lens, Projection, Stereo, StereoLayout};
+use crate::dither::Dither;
+use crate::effects::{Bloom, Effects};
+use crate::error::{RendererError, Result};
+use crate::filter::Filter;
+use crate::geometry::{
+	object_id, Block, Bvh, Csg, CurveBasis, Curves, Cylinder, DensityGrid, Displacement, Float,
+	Heightfield, Hittable, HittableGroup, Mat4, MeshOptions, Operation, Plane, PointCloud, Quad,
+	Solid, Sphere, SplatShape, Tagged, Transform, Vec3f, Volume,
+};
+use crate::integrator::{
+	solar_position, sun_direction, AmbientOcclusion, DirectLighting, Environment, Fog, Integrator,
+	IrradianceCache, LightTree, PathTracer, PointLight, Sky, Spot, MAX_LIGHT_GROUPS,
+};
+use crate::lut::Lut;
+use crate::material::{
+	CheckerTexture, Clearcoat, CompositeTexture, Conductor, Dielectric, Diffuse, DiffuseLight,
+	Holdout, Isotropic, Material, MaterialLibrary, Metal, RampDriver, RampTexture, SolidColor,
+	Texture, ThinFilm, TriplanarTexture, UvGridTexture, WorleyFeature, WorleyTexture, CONDUCTORS,
+};
+use crate::render::{PostProcess, Preset, RenderSettings};
+use crate::script;
+
+// TOML scene description, see scenes/ for examples
+#[derive(Deserialize, Serialize)]
+pub struct SceneFile {
+	// Extra directories to search for textures referenced by MTL files
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub texture_paths: Vec<String>,
+	#[serde(default)]
+	pub render: RenderConfig,
+	pub camera: CameraConfig,
+	// Replaces the camera's background and lights the scene with a sun
+	pub sky: Option<SkyConfig>,
+	// Replaces the camera's background with an image of the light around the scene
+	pub environment: Option<EnvironmentConfig>,
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub lights: Vec<LightConfig>,
+	// Materials that objects refer to by name, each built once and shared between them
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub materials: BTreeMap<String, MaterialConfig>,
+	#[serde(default)]
+	pub objects: Vec<ObjectConfig>,
+	// Generate further objects, run and replaced by the objects they emit, appended to `objects`,
+	// when the file is loaded
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub scripts: Vec<ScriptConfig>,
+}
+
+// A script from a file at `path` or the `source` written out in the scene file, with `params`
+// as variables. Its random numbers only depend on `seed`.
+#[derive(Deserialize, Serialize)]
+pub struct ScriptConfig {
+	pub path: Option<String>,
+	pub source: Option<String>,
+	#[serde(default)]
+	pub params: toml::Table,
+	#[serde(default)]
+	pub seed: u64,
+}
+
+// Sun and sky, the angular diameter of the sun in degrees sets how soft its shadows are
+#[derive(Deserialize, Serialize)]
+pub struct SkyConfig {
+	pub sun: SunConfig,
+	#[serde(default = "default_sun_diameter")]
+	pub angular_diameter: Float,
+	#[serde(default = "default_turbidity")]
+	pub turbidity: Float,
+	#[serde(default = "default_sun_intensity")]
+	pub sun_intensity: Float,
+	#[serde(default = "default_sky_intensity")]
+	pub sky_intensity: Float,
+}
+
+// An equirectangular image, usually .hdr or .exr, with its brightness scaled by `intensity` and
+// turned by `rotation` degrees about +y, in the same direction as the azimuth of the sun
+#[derive(Deserialize, Serialize)]
+pub struct EnvironmentConfig {
+	pub path: String,
+	#[serde(default = "default_intensity")]
+	pub intensity: Float,
+	#[serde(default)]
+	pub rotation: Float,
+}
+
+// Lights with no size that rays cannot hit, `intensity` times `color` is the light sent per unit
+// solid angle. A spot only lights the cone towards `look_at` that is `angle` degrees wide, the
+// outer `blend` of it fading out.
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LightConfig {
+	Point {
+		position: [Float; 3],
+		#[serde(default = "default_light_color")]
+		color: [Float; 3],
+		#[serde(default = "default_intensity")]
+		intensity: Float,
+	},
+	Spot {
+		position: [Float; 3],
+		look_at: [Float; 3],
+		angle: Float,
+		#[serde(default = "default_spot_blend")]
+		blend: Float,
+		#[serde(default = "default_light_color")]
+		color: [Float; 3],
+		#[serde(default = "default_intensity")]
+		intensity: Float,
+	},
+}
+
+fn default_light_color() -> [Float; 3] {
+	[1.0, 1.0, 1.0]
+}
+
+fn default_spot_blend() -> Float {
+	0.15
+}
+
+impl LightConfig {
+	fn build(&self) -> Result<PointLight> {
+		Ok(match self {
+			LightConfig::Point {
+				position,
+				color,
+				intensity,
+			} => PointLight::new(vec3f(position), vec3f(color) * *intensity),
+			LightConfig::Spot {
+				position,
+				look_at,
+				angle,
+				blend,
+				color,
+				intensity,
+			} => {
+				if !(*angle > 0.0 && *angle < 180.0) {
+					return Err(RendererError::Scene {
+						path: None,
+						message: format!(
+							"the angle of a spot is {} degrees, it has to be between 0 and 180",
+							angle
+						),
+					});
+				}
+				let spot = Spot::new(vec3f(look_at) - vec3f(position), *angle, *blend);
+				PointLight::new(vec3f(position), vec3f(color) * *intensity).with_spot(spot)
+			}
+		})
+	}
+}
+
+// Either the azimuth (clockwise from north, -z, towards east, +x) and elevation in degrees, or
+// where and when the sun is seen: latitude and longitude in degrees north and east, the date as
+// YYYY-MM-DD and the local time as HH:MM, `utc_offset` hours ahead of UTC
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum SunConfig {
+	Angles {
+		azimuth: Float,
+		elevation: Float,
+	},
+	Time {
+		latitude: Float,
+		longitude: Float,
+		date: String,
+		time: String,
+		#[serde(default)]
+		utc_offset: Float,
+	},
+}
+
+fn default_sun_diameter() -> Float {
+	0.53
+}
+
+fn default_turbidity() -> Float {
+	3.0
+}
+
+fn default_sun_intensity() -> Float {
+	4.0
+}
+
+fn default_sky_intensity() -> Float {
+	0.3
+}
+
+// Day of the year from 1 of a YYYY-MM-DD date
+fn day_of_year(date: &str) -> Option<u32> {
+	let mut parts = date.split('-').map(|part| part.parse::<u32>().ok());
+	let (year, month, day) = (parts.next()??, parts.next()??, parts.next()??);
+	if parts.next().is_some() || !(1..=12).contains(&month) {
+		return None;
+	}
+
+	let leap = year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+	let lengths = [
+		31,
+		if leap { 29 } else { 28 },
+		31,
+		30,
+		31,
+		30,
+		31,
+		31,
+		30,
+		31,
+		30,
+		31,
+	];
+	if day == 0 || day > lengths[month as usize - 1] {
+		return None;
+	}
+	Some(lengths[..month as usize - 1].iter().sum::<u32>() + day)
+}
+
+// Hours of an HH:MM or HH:MM:SS time
+fn hours(time: &str) -> Option<Float> {
+	let parts: Vec<Float> = time
+		.split(':')
+		.map(|part| part.parse::<Float>().ok())
+		.collect::<Option<_>>()?;
+	match parts.as_slice() {
+		[h, m] => Some(h + m / 60.0),
+		[h, m, s] => Some(h + m / 60.0 + s / 3600.0),
+		_ => None,
+	}
+}
+
+impl SkyConfig {
+	pub fn build(&self) -> Result<Sky> {
+		let (azimuth, elevation) = match &self.sun {
+			SunConfig::Angles { azimuth, elevation } => (*azimuth, *elevation),
+			SunConfig::Time {
+				latitude,
+				longitude,
+				date,
+				time,
+				utc_offset,
+			} => {
+				let invalid = |message: String| RendererError::Scene {
+					path: None,
+					message,
+				};
+				let day = day_of_year(date).ok_or_else(|| {
+					invalid(format!("invalid date {}, expected YYYY-MM-DD", date))
+				})?;
+				let hours = hours(time)
+					.ok_or_else(|| invalid(format!("invalid time {}, expected HH:MM", time)))?;
+				let (azimuth, elevation) =
+					solar_position(*latitude, *longitude, day, hours - utc_offset);
+				log::info!(
+					"Sun at {:.1} degrees azimuth, {:.1} elevation",
+					azimuth,
+					elevation
+				);
+				(azimuth, elevation)
+			}
+		};
+
+		Ok(Sky::new(
+			sun_direction(azimuth, elevation),
+			self.angular_diameter,
+			self.turbidity,
+			self.sun_intensity,
+			self.sky_intensity,
+		))
+	}
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct RenderConfig {
+	pub width: u32,
+	pub height: u32,
+	pub samples: usize,
+	// Reconstruction filter, the box filter of the render settings without one
+	pub filter: Option<FilterConfig>,
+	// One of render::PRESETS, replaces the samples and sets the bounces that the integrator
+	// leaves open
+	pub preset: Option<String>,
+	// Exposure in stops and the color temperature in Kelvin that should come out white
+	pub exposure: Float,
+	pub white_balance: Option<Float>,
+	pub dither: Option<DitherConfig>,
+	pub bloom: Option<BloomConfig>,
+	pub vignette: Float,
+	pub chromatic_aberration: Float,
+	// Path of a .cube file that grades the 8 bit images
+	pub lut: Option<String>,
+	pub integrator: IntegratorConfig,
+	pub fog: Option<FogConfig>,
+}
+
+// Exponential fog, `density` is the fraction of light lost per unit of distance for thin fog
+#[derive(Deserialize, Serialize)]
+pub struct FogConfig {
+	pub density: Float,
+	pub color: [Float; 3],
+	#[serde(default = "default_max_distance")]
+	pub max_distance: Float,
+}
+
+impl FogConfig {
+	fn build(&self) -> Fog {
+		Fog {
+			density: self.density,
+			color: vec3f(&self.color),
+			max_distance: self.max_distance,
+		}
+	}
+}
+
+// Glow around everything brighter than `threshold`, blurred over `radius` as a fraction of the
+// image height
+#[derive(Deserialize, Serialize)]
+pub struct BloomConfig {
+	#[serde(default = "default_bloom_threshold")]
+	pub threshold: Float,
+	#[serde(default = "default_bloom_intensity")]
+	pub intensity: Float,
+	#[serde(default = "default_bloom_radius")]
+	pub radius: Float,
+}
+
+impl BloomConfig {
+	fn build(&self) -> Bloom {
+		Bloom {
+			threshold: self.threshold,
+			intensity: self.intensity,
+			radius: self.radius,
+		}
+	}
+}
+
+fn default_bloom_threshold() -> Float {
+	1.0
+}
+
+fn default_bloom_intensity() -> Float {
+	0.1
+}
+
+fn default_bloom_radius() -> Float {
+	0.02
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum IntegratorConfig {
+	// Without a max_depth that of the render settings
+	Path {
+		max_depth: Option<u32>,
+		irradiance_cache: Option<IrradianceCacheConfig>,
+	},
+	Ao {
+		#[serde(default = "default_max_distance")]
+		max_distance: Float,
+	},
+	Direct,
+}
+
+impl Default for IntegratorConfig {
+	fn default() -> Self {
+		IntegratorConfig::Path {
+			max_depth: None,
+			irradiance_cache: None,
+		}
+	}
+}
+
+// Diffuse light interpolated between records of `rays` paths each, placed more densely the
+// lower the `accuracy`
+#[derive(Deserialize, Serialize)]
+pub struct IrradianceCacheConfig {
+	#[serde(default = "default_cache_accuracy")]
+	pub accuracy: Float,
+	#[serde(default = "default_cache_rays")]
+	pub rays: u32,
+}
+
+fn default_cache_accuracy() -> Float {
+	0.2
+}
+
+fn default_cache_rays() -> u32 {
+	1024
+}
+
+fn default_max_distance() -> Float {
+	Float::MAX
+}
+
+impl IntegratorConfig {
+	// Only the path tracer separates light groups, ambient occlusion ignores fog and all lights
+	// but the emitters
+	pub fn build(
+		&self,
+		settings: &RenderSettings,
+		light_groups: Vec<String>,
+		fog: Option<Fog>,
+		sky: Option<Sky>,
+		environment: Option<Environment>,
+		lights: Vec<PointLight>,
+	) -> Arc<dyn Integrator> {
+		match *self {
+			IntegratorConfig::Path {
+				max_depth,
+				ref irradiance_cache,
+			} => {
+				let mut path = PathTracer::new(max_depth.unwrap_or(settings.max_depth))
+					.with_light_groups(light_groups)
+					.with_lights(lights);
+				if let Some(cache) = irradiance_cache {
+					path = path.with_cache(IrradianceCache::new(cache.accuracy, cache.rays));
+				}
+				path.fog = fog;
+				path.sky = sky;
+				path.environment = environment;
+				Arc::new(path)
+			}
+			IntegratorConfig::Ao { max_distance } => Arc::new(AmbientOcclusion::new(max_distance)),
+			IntegratorConfig::Direct => Arc::new(DirectLighting {
+				fog,
+				sky,
+				environment,
+				lights: LightTree::new(lights),
+			}),
+		}
+	}
+}
+
+impl Default for RenderConfig {
+	fn default() -> Self {
+		RenderConfig {
+			width: 384,
+			height: 384,
+			samples: 64,
+			filter: None,
+			preset: None,
+			exposure: 0.0,
+			white_balance: None,
+			dither: None,
+			bloom: None,
+			vignette: 0.0,
+			chromatic_aberration: 0.0,
+			lut: None,
+			integrator: IntegratorConfig::default(),
+			fog: None,
+		}
+	}
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct CameraConfig {
+	pub position: [Float; 3],
+	pub look_at: [Float; 3],
+	// Either a vertical field of view in degrees or the photographic settings of a lens
+	pub fov: Option<Float>,
+	pub lens: Option<LensConfig>,
+	#[serde(default)]
+	pub background: [Float; 3],
+	#[serde(default)]
+	pub projection: ProjectionConfig,
+	pub depth_of_field: Option<DepthOfFieldConfig>,
+	pub stereo: Option<StereoConfig>,
+	// Keyframes of a camera move, which make the scene an animation of as many frames as it takes
+	// to reach the last key
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub keys: Vec<CameraKeyConfig>,
+	#[serde(default)]
+	pub interpolation: InterpolationConfig,
+}
+
+// The camera at `frame`, any of the settings left out are interpolated between the other keys
+#[derive(Deserialize, Serialize)]
+pub struct CameraKeyConfig {
+	pub frame: u32,
+	pub position: Option<[Float; 3]>,
+	pub look_at: Option<[Float; 3]>,
+	pub fov: Option<Float>,
+}
+
+#[derive(Deserialize, Serialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum InterpolationConfig {
+	Linear,
+	#[default]
+	Spline,
+}
+
+// Defaults to a full frame sensor at f/16, 1/100s and ISO 100, which leaves the exposure as is
+#[derive(Deserialize, Serialize)]
+pub struct LensConfig {
+	pub focal_length: Float,
+	#[serde(default = "default_sensor_height")]
+	pub sensor_height: Float,
+	#[serde(default = "default_f_stop")]
+	pub f_stop: Float,
+	#[serde(default = "default_shutter")]
+	pub shutter: Float,
+	#[serde(default = "default_iso")]
+	pub iso: Float,
+}
+
+fn default_sensor_height() -> Float {
+	24.0
+}
+
+fn default_f_stop() -> Float {
+	16.0
+}
+
+fn default_shutter() -> Float {
+	0.01
+}
+
+fn default_iso() -> Float {
+	100.0
+}
+
+impl LensConfig {
+	fn build(&self) -> Lens {
+		Lens {
+			focal_length: self.focal_length,
+			sensor_height: self.sensor_height,
+			f_stop: self.f_stop,
+			shutter: self.shutter,
+			iso: self.iso,
+		}
+	}
+}
+
+// In focus at the look-at point unless `focus_distance` is given. The opening is round, has
+// `blades` blades turned by `rotation` degrees, or the shape of the `mask` image.
+#[derive(Deserialize, Serialize)]
+pub struct DepthOfFieldConfig {
+	pub focus_distance: Option<Float>,
+	pub aperture: Float,
+	pub blades: Option<u32>,
+	#[serde(default)]
+	pub rotation: Float,
+	pub mask: Option<String>,
+}
+
+impl DepthOfFieldConfig {
+	fn build(&self, position: Vec3f, look_at: Vec3f) -> Result<DepthOfField> {
+		let error = |message: &str| RendererError::Scene {
+			path: None,
+			message: message.to_string(),
+		};
+		if self.aperture < 0.0 {
+			return Err(error("the aperture of the camera cannot be negative"));
+		}
+
+		let shape = match (self.blades, &self.mask) {
+			(None, None) => Aperture::Circle,
+			(Some(blades), None) if blades >= 3 => Aperture::Polygon {
+				blades,
+				rotation: self.rotation,
+			},
+			(Some(_), None) => return Err(error("an aperture needs at least 3 blades")),
+			(None, Some(path)) => Aperture::Mask(Arc::new(ApertureMask::load(path)?)),
+			(Some(_), Some(_)) => {
+				return Err(error("an aperture has either blades or a mask, not both"))
+			}
+		};
+
+		Ok(DepthOfField {
+			focus_distance: self
+				.focus_distance
+				.unwrap_or_else(|| (look_at - position).length()),
+			aperture: self.aperture,
+			shape,
+		})
+	}
+}
+
+// Both eyes share the image of the render settings, side by side unless `layout` says otherwise
+#[derive(Deserialize, Serialize)]
+pub struct StereoConfig {
+	pub interocular: Float,
+	pub convergence: Option<Float>,
+	#[serde(default)]
+	pub layout: StereoLayoutConfig,
+}
+
+#[derive(Deserialize, Serialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum StereoLayoutConfig {
+	#[default]
+	SideBySide,
+	TopBottom,
+}
+
+impl StereoConfig {
+	fn build(&self) -> Result<Stereo> {
+		if self
+			.convergence
+			.is_some_and(|convergence| convergence <= 0.0)
+		{
+			return Err(RendererError::Scene {
+				path: None,
+				message: "the convergence distance of the eyes must be positive".to_string(),
+			});
+		}
+
+		Ok(Stereo {
+			interocular: self.interocular,
+			convergence: self.convergence,
+			layout: match self.layout {
+				StereoLayoutConfig::SideBySide => StereoLayout::SideBySide,
+				StereoLayoutConfig::TopBottom => StereoLayout::TopBottom,
+			},
+		})
+	}
+}
+
+#[derive(Deserialize, Serialize, Default)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ProjectionConfig {
+	#[default]
+	Perspective,
+	Fisheye {
+		#[serde(default)]
+		mapping: FisheyeMappingConfig,
+		fov: Float,
+	},
+	Equirectangular,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum DitherConfig {
+	Ordered,
+	BlueNoise,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterConfig {
+	Box,
+	Tent,
+	Gaussian,
+	Mitchell,
+}
+
+#[derive(Deserialize, Serialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum FisheyeMappingConfig {
+	#[default]
+	Equidistant,
+	Equisolid,
+}
+
+#[derive(Deserialize, Serialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum CurveBasisConfig {
+	#[default]
+	CatmullRom,
+	Bezier,
+}
+
+#[derive(Deserialize, Serialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SplatShapeConfig {
+	#[default]
+	Disk,
+	Sphere,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationConfig {
+	Union,
+	Intersection,
+	// Cuts `b` out of `a`
+	Difference,
+}
+
+// Closed shapes for CSG, the block spans two opposite corners and the cylinder stands on the
+// center of its bottom cap along y
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SolidConfig {
+	Sphere {
+		center: [Float; 3],
+		radius: Float,
+		material: MaterialConfig,
+	},
+	Block {
+		min: [Float; 3],
+		max: [Float; 3],
+		material: MaterialConfig,
+	},
+	Cylinder {
+		base: [Float; 3],
+		radius: Float,
+		height: Float,
+		material: MaterialConfig,
+	},
+	Csg {
+		operation: OperationConfig,
+		a: Box<SolidConfig>,
+		b: Box<SolidConfig>,
+	},
+}
+
+impl SolidConfig {
+	fn materials(&self) -> Vec<&MaterialConfig> {
+		match self {
+			SolidConfig::Sphere { material, .. }
+			| SolidConfig::Block { material, .. }
+			| SolidConfig::Cylinder { material, .. } => vec![material],
+			SolidConfig::Csg { a, b, .. } => {
+				let mut materials = a.materials();
+				materials.extend(b.materials());
+				materials
+			}
+		}
+	}
+
+	fn build(
+		&self,
+		groups: &[String],
+		library: &MaterialLibrary,
+		assets: &Assets,
+	) -> Result<Box<dyn Solid>> {
+		Ok(match self {
+			SolidConfig::Sphere {
+				center,
+				radius,
+				material,
+			} => Box::new(Sphere::new(
+				vec3f(center),
+				*radius,
+				material.build(groups, library, assets)?,
+			)),
+			SolidConfig::Block { min, max, material } => Box::new(Block::new(
+				vec3f(min),
+				vec3f(max),
+				material.build(groups, library, assets)?,
+			)),
+			SolidConfig::Cylinder {
+				base,
+				radius,
+				height,
+				material,
+			} => Box::new(Cylinder::new(
+				vec3f(base),
+				*radius,
+				*height,
+				material.build(groups, library, assets)?,
+			)),
+			SolidConfig::Csg { operation, a, b } => {
+				Box::new(csg(*operation, a, b, groups, library, assets)?)
+			}
+		})
+	}
+}
+
+fn csg(
+	operation: OperationConfig,
+	a: &SolidConfig,
+	b: &SolidConfig,
+	groups: &[String],
+	library: &MaterialLibrary,
+	assets: &Assets,
+) -> Result<Csg> {
+	let operation = match operation {
+		OperationConfig::Union => Operation::Union,
+		OperationConfig::Intersection => Operation::Intersection,
+		OperationConfig::Difference => Operation::Difference,
+	};
+
+	Ok(Csg::new(
+		operation,
+		a.build(groups, library, assets)?,
+		b.build(groups, library, assets)?,
+	))
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ObjectConfig {
+	Sphere {
+		center: [Float; 3],
+		radius: Float,
+		material: MaterialConfig,
+		#[serde(default)]
+		name: Option<String>,
+		transform: Option<TransformConfig>,
+	},
+	Plane {
+		xbasis: [Float; 3],
+		ybasis: [Float; 3],
+		point: [Float; 3],
+		#[serde(default = "default_uv_scale")]
+		uv_scale: Float,
+		material: MaterialConfig,
+		#[serde(default)]
+		name: Option<String>,
+		transform: Option<TransformConfig>,
+	},
+	// Bounded parallelogram spanned by u and v from the corner, facing along u x v
+	Quad {
+		corner: [Float; 3],
+		u: [Float; 3],
+		v: [Float; 3],
+		material: MaterialConfig,
+		#[serde(default)]
+		name: Option<String>,
+		transform: Option<TransformConfig>,
+	},
+	// Terrain from a grayscale image spanning `size` from the corner, its rows along z and white
+	// at `size[1]` above the corner
+	Heightfield {
+		path: String,
+		corner: [Float; 3],
+		size: [Float; 3],
+		material: MaterialConfig,
+		#[serde(default)]
+		name: Option<String>,
+		transform: Option<TransformConfig>,
+	},
+	// Thin tubes along each list of points, tapering from `radius` to `tip_radius`
+	Curves {
+		#[serde(default)]
+		basis: CurveBasisConfig,
+		curves: Vec<Vec<[Float; 3]>>,
+		radius: Float,
+		tip_radius: Option<Float>,
+		#[serde(default = "default_curve_subdivisions")]
+		subdivisions: u32,
+		material: MaterialConfig,
+		#[serde(default)]
+		name: Option<String>,
+		transform: Option<TransformConfig>,
+	},
+	// Points of a PLY file drawn as splats of `radius`, tinted by their colors
+	PointCloud {
+		path: String,
+		radius: Float,
+		#[serde(default)]
+		shape: SplatShapeConfig,
+		material: MaterialConfig,
+		#[serde(default)]
+		name: Option<String>,
+		transform: Option<TransformConfig>,
+	},
+	// Smoke or clouds from a grid of a NanoVDB file, the first float grid unless `grid` names
+	// one. The grid fills the box from the corner spanning `size`, `density` scales its values
+	// to the extinction per unit length.
+	Volume {
+		path: String,
+		grid: Option<String>,
+		corner: [Float; 3],
+		size: [Float; 3],
+		#[serde(default = "default_density")]
+		density: Float,
+		material: MaterialConfig,
+		#[serde(default)]
+		name: Option<String>,
+		transform: Option<TransformConfig>,
+	},
+	// Boolean combination of two solids, which can be CSG operations themselves
+	Csg {
+		operation: OperationConfig,
+		a: SolidConfig,
+		b: SolidConfig,
+		#[serde(default)]
+		name: Option<String>,
+		transform: Option<TransformConfig>,
+	},
+	// Without a material the mesh uses the materials from its MTL file. `weld` merges vertices
+	// closer than that distance, `smooth_normals` is the angle in degrees below which edges are
+	// shaded smooth. `motion` lists OBJ files of the same triangles later in the shutter interval,
+	// spaced evenly up to the time it closes, that the vertices move through for motion blur.
+	Mesh {
+		path: String,
+		#[serde(default, skip_serializing_if = "Vec::is_empty")]
+		motion: Vec<String>,
+		material: Option<MaterialConfig>,
+		weld: Option<Float>,
+		#[serde(default)]
+		smooth_subdivisions: u32,
+		displacement: Option<DisplacementConfig>,
+		smooth_normals: Option<Float>,
+		#[serde(default)]
+		name: Option<String>,
+		transform: Option<TransformConfig>,
+	},
+	// `object` placed many times, every instance shares its geometry and BVH and only adds its
+	// own transform. There is one instance for each of `instances`, plus those of `grid` and
+	// `scatter`, and the transform of the whole moves them all.
+	Instances {
+		object: Box<ObjectConfig>,
+		#[serde(default, skip_serializing_if = "Vec::is_empty")]
+		instances: Vec<TransformConfig>,
+		grid: Option<GridConfig>,
+		scatter: Option<ScatterConfig>,
+		#[serde(default)]
+		name: Option<String>,
+		transform: Option<TransformConfig>,
+	},
+}
+
+impl ObjectConfig {
+	// The name the object's ID is hashed from, unnamed objects are named after their position in
+	// the file
+	pub fn name(&self, index: usize) -> String {
+		let name = match self {
+			ObjectConfig::Sphere { name, .. }
+			| ObjectConfig::Plane { name, .. }
+			| ObjectConfig::Quad { name, .. }
+			| ObjectConfig::Heightfield { name, .. }
+			| ObjectConfig::Curves { name, .. }
+			| ObjectConfig::PointCloud { name, .. }
+			| ObjectConfig::Volume { name, .. }
+			| ObjectConfig::Csg { name, .. }
+			| ObjectConfig::Mesh { name, .. }
+			| ObjectConfig::Instances { name, .. } => name,
+		};
+
+		name.clone().unwrap_or_else(|| format!("object_{}", index))
+	}
+
+	pub fn transform(&self) -> Option<&TransformConfig> {
+		match self {
+			ObjectConfig::Sphere { transform, .. }
+			| ObjectConfig::Plane { transform, .. }
+			| ObjectConfig::Quad { transform, .. }
+			| ObjectConfig::Heightfield { transform, .. }
+			| ObjectConfig::Curves { transform, .. }
+			| ObjectConfig::PointCloud { transform, .. }
+			| ObjectConfig::Volume { transform, .. }
+			| ObjectConfig::Csg { transform, .. }
+			| ObjectConfig::Mesh { transform, .. }
+			| ObjectConfig::Instances { transform, .. } => transform.as_ref(),
+		}
+	}
+
+	// The transform of the object and those inside it, of its instances and what they place
+	fn transforms(&self) -> Vec<&TransformConfig> {
+		let mut transforms: Vec<&TransformConfig> = self.transform().into_iter().collect();
+		if let ObjectConfig::Instances {
+			object, instances, ..
+		} = self
+		{
+			transforms.extend(instances);
+			transforms.extend(object.transforms());
+		}
+		transforms
+	}
+
+	fn materials(&self) -> Vec<&MaterialConfig> {
+		match self {
+			ObjectConfig::Sphere { material, .. }
+			| ObjectConfig::Plane { material, .. }
+			| ObjectConfig::Quad { material, .. }
+			| ObjectConfig::Heightfield { material, .. }
+			| ObjectConfig::Curves { material, .. }
+			| ObjectConfig::PointCloud { material, .. }
+			| ObjectConfig::Volume { material, .. } => vec![material],
+			ObjectConfig::Csg { a, b, .. } => {
+				let mut materials = a.materials();
+				materials.extend(b.materials());
+				materials
+			}
+			ObjectConfig::Mesh { material, .. } => material.iter().collect(),
+			ObjectConfig::Instances { object, .. } => object.materials(),
+		}
+	}
+
+	// Whether the object has meshes with motion samples
+	fn deforms(&self) -> bool {
+		match self {
+			ObjectConfig::Mesh { motion, .. } => !motion.is_empty(),
+			ObjectConfig::Instances { object, .. } => object.deforms(),
+			_ => false,
+		}
+	}
+}
+
+// `count` instances along x, y and z, `spacing` apart and centered on the origin
+#[derive(Deserialize, Serialize)]
+pub struct GridConfig {
+	pub count: [u32; 3],
+	pub spacing: [Float; 3],
+}
+
+impl GridConfig {
+	fn matrices(&self) -> Vec<Mat4> {
+		let [nx, ny, nz] = self.count;
+		let offset = |axis: usize, index: u32| {
+			(index as Float - (self.count[axis] as Float - 1.0) / 2.0) * self.spacing[axis]
+		};
+
+		(0..nz)
+			.flat_map(|z| (0..ny).flat_map(move |y| (0..nx).map(move |x| [x, y, z])))
+			.map(|[x, y, z]| Mat4::translate(Vec3f::new(offset(0, x), offset(1, y), offset(2, z))))
+			.collect()
+	}
+}
+
+// `count` instances at random points of the box from `min` to `max`, each turned by a random
+// angle of up to `rotate` degrees about x, y and z and scaled by a random factor from `scale`.
+// The same `seed` scatters them the same way on every build.
+#[derive(Deserialize, Serialize)]
+pub struct ScatterConfig {
+	pub count: u32,
+	pub min: [Float; 3],
+	pub max: [Float; 3],
+	#[serde(default)]
+	pub rotate: [Float; 3],
+	#[serde(default = "default_scatter_scale")]
+	pub scale: [Float; 2],
+	#[serde(default)]
+	pub seed: u64,
+}
+
+fn default_scatter_scale() -> [Float; 2] {
+	[1.0, 1.0]
+}
+
+impl ScatterConfig {
+	fn matrices(&self) -> Result<Vec<Mat4>> {
+		let [min_scale, max_scale] = self.scale;
+		if min_scale <= 0.0 || max_scale < min_scale {
+			return Err(RendererError::Scene {
+				path: None,
+				message: "the scale of a scatter must be a positive range from low to high"
+					.to_string(),
+			});
+		}
+
+		// Its own generator, which leaves the one of the thread to the render
+		let mut rng = SmallRng::seed_from_u64(self.seed);
+		let mut range = |min: Float, max: Float| min + (max - min) * rng.gen::<Float>();
+		let (min, max) = (vec3f(&self.min), vec3f(&self.max));
+		let rotate = vec3f(&self.rotate);
+
+		Ok((0..self.count)
+			.map(|_| {
+				let position = Vec3f::new(
+					range(min.x, max.x),
+					range(min.y, max.y),
+					range(min.z, max.z),
+				);
+				let angles = Vec3f::new(
+					range(0.0, rotate.x),
+					range(0.0, rotate.y),
+					range(0.0, rotate.z),
+				);
+				let scale = range(min_scale, max_scale);
+
+				Mat4::translate(position)
+					* Mat4::rotate(Vec3f::new(0.0, 0.0, 1.0), angles.z)
+					* Mat4::rotate(Vec3f::new(0.0, 1.0, 0.0), angles.y)
+					* Mat4::rotate(Vec3f::new(1.0, 0.0, 0.0), angles.x)
+					* Mat4::scale(Vec3f::new(scale, scale, scale))
+			})
+			.collect())
+	}
+}
+
+fn transformed(object: Box<dyn Hittable>, matrix: Mat4, name: &str) -> Result<Transform> {
+	Transform::new(object, matrix).ok_or_else(|| RendererError::Scene {
+		path: None,
+		message: format!(
+			"the transform of {} flattens it, its scale cannot be 0",
+			name
+		),
+	})
+}
+
+// Scales, rotates (degrees around x, y and z in that order) and moves an object, see
+// TransformPath. `keys` animate any of them, which are interpolated like the keys of the camera
+// and keep the values given here without any.
+#[derive(Deserialize, Serialize)]
+pub struct TransformConfig {
+	#[serde(default)]
+	pub translate: [Float; 3],
+	#[serde(default)]
+	pub rotate: [Float; 3],
+	#[serde(default = "default_scale")]
+	pub scale: [Float; 3],
+	#[serde(default)]
+	pub pivot: [Float; 3],
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub keys: Vec<TransformKeyConfig>,
+	#[serde(default)]
+	pub interpolation: InterpolationConfig,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TransformKeyConfig {
+	pub frame: u32,
+	pub translate: Option<[Float; 3]>,
+	pub rotate: Option<[Float; 3]>,
+	pub scale: Option<[Float; 3]>,
+}
+
+fn default_scale() -> [Float; 3] {
+	[1.0, 1.0, 1.0]
+}
+
+impl TransformConfig {
+	pub fn path(&self) -> Result<TransformPath> {
+		check_key_order(self.keys.iter().map(|key| key.frame))?;
+
+		let interpolation = self.interpolation.build();
+		let track = |value: fn(&TransformKeyConfig) -> Option<&[Float; 3]>, fixed: &[Float; 3]| {
+			let track = track(
+				&self.keys,
+				|key| Some((key.frame, vec3f(value(key)?))),
+				interpolation,
+			);
+			if track.keys.is_empty() {
+				Track {
+					keys: vec![(0.0, vec3f(fixed))],
+					interpolation,
+				}
+			} else {
+				track
+			}
+		};
+
+		Ok(TransformPath {
+			translate: track(|key| key.translate.as_ref(), &self.translate),
+			rotate: track(|key| key.rotate.as_ref(), &self.rotate),
+			scale: track(|key| key.scale.as_ref(), &self.scale),
+			pivot: vec3f(&self.pivot),
+		})
+	}
+}
+
+impl InterpolationConfig {
+	fn build(self) -> Interpolation {
+		match self {
+			InterpolationConfig::Linear => Interpolation::Linear,
+			InterpolationConfig::Spline => Interpolation::Spline,
+		}
+	}
+}
+
+// Keys have to be sorted by frame with no frame twice
+fn check_key_order(frames: impl Iterator<Item = u32>) -> Result<()> {
+	let frames: Vec<u32> = frames.collect();
+	match frames.windows(2).find(|pair| pair[1] <= pair[0]) {
+		Some(pair) => Err(RendererError::Scene {
+			path: None,
+			message: format!(
+				"keys must be in order of their frames, frame {} comes after {}",
+				pair[1], pair[0]
+			),
+		}),
+		None => Ok(()),
+	}
+}
+
+// The values of the keys that set one, at their frames
+fn track<K, T>(
+	keys: &[K],
+	value: impl Fn(&K) -> Option<(u32, T)>,
+	interpolation: Interpolation,
+) -> Track<T> {
+	Track {
+		keys: keys
+			.iter()
+			.filter_map(|key| value(key).map(|(frame, value)| (frame as Float, value)))
+			.collect(),
+		interpolation,
+	}
+}
+
+// A material is either written out as a table or refers to one of the scene's `materials` by
+// its name, as a plain string
+#[derive(Deserialize, Serialize)]
+#[serde(remote = "Self", tag = "type", rename_all = "snake_case")]
+pub enum MaterialConfig {
+	Diffuse {
+		texture: TextureConfig,
+	},
+	// Phase function for volumes, the texture is the fraction of light scattered
+	Isotropic {
+		texture: TextureConfig,
+	},
+	// Roughness from 0 for a mirror to 1. With `roughness_v` the roughness is `roughness` along
+	// the surface tangent and `roughness_v` across it, turned around the normal by the red channel
+	// of `tangent_rotation` (1 is a full turn).
+	Metal {
+		texture: TextureConfig,
+		#[serde(default)]
+		roughness: Float,
+		roughness_v: Option<Float>,
+		tangent_rotation: Option<TextureConfig>,
+	},
+	// Either one of the conductor presets (gold, copper, aluminum, silver) or the complex index
+	// of refraction as `eta` and `k` for red, green and blue
+	Conductor {
+		preset: Option<String>,
+		eta: Option<[Float; 3]>,
+		k: Option<[Float; 3]>,
+		#[serde(default)]
+		roughness: Float,
+		roughness_v: Option<Float>,
+		tangent_rotation: Option<TextureConfig>,
+		film: Option<ThinFilmConfig>,
+	},
+	// Clear lacquer over the `base` material, 1.5 is a typical refraction index for the coat
+	Clearcoat {
+		base: Box<MaterialConfig>,
+		#[serde(default = "default_coat_index")]
+		refraction_index: Float,
+		#[serde(default)]
+		roughness: Float,
+	},
+	// Lights with a `group` are also rendered into an image of their own. The texture is looked
+	// up at the UVs of the hit and scaled by `intensity`, for glowing images on meshes. A
+	// `temperature` in Kelvin gives the light the color of a black body, tinting the texture if
+	// there is one.
+	Light {
+		texture: Option<TextureConfig>,
+		temperature: Option<Float>,
+		#[serde(default = "default_intensity")]
+		intensity: Float,
+		group: Option<String>,
+	},
+	// Nested dielectrics with a higher `priority` take the place of others where they overlap
+	Dielectric {
+		refraction_index: Float,
+		film: Option<ThinFilmConfig>,
+		#[serde(default)]
+		priority: u32,
+	},
+	Holdout,
+	#[serde(skip)]
+	Named {
+		name: String,
+	},
+}
+
+impl<'de> Deserialize<'de> for MaterialConfig {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+		match toml::Value::deserialize(deserializer)? {
+			toml::Value::String(name) => Ok(MaterialConfig::Named { name }),
+			value => MaterialConfig::deserialize(value).map_err(de::Error::custom),
+		}
+	}
+}
+
+impl Serialize for MaterialConfig {
+	fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+		match self {
+			MaterialConfig::Named { name } => serializer.serialize_str(name),
+			material => MaterialConfig::serialize(material, serializer),
+		}
+	}
+}
+
+// Heightmap read from the red channel of `texture`, 1 moves a vertex `scale` along its normal.
+// Every subdivision splits each triangle into four.
+#[derive(Deserialize, Serialize)]
+pub struct DisplacementConfig {
+	texture: TextureConfig,
+	scale: Float,
+	#[serde(default = "default_subdivisions")]
+	subdivisions: u32,
+}
+
+// Already 4^8 = 65536 triangles for each one of the mesh
+const MAX_SUBDIVISIONS: u32 = 8;
+
+fn check_subdivisions(kind: &str, subdivisions: u32) -> Result<()> {
+	if subdivisions > MAX_SUBDIVISIONS {
+		return Err(RendererError::Scene {
+			path: None,
+			message: format!(
+				"at most {} {} subdivisions are supported, got {}",
+				MAX_SUBDIVISIONS, kind, subdivisions
+			),
+		});
+	}
+
+	Ok(())
+}
+
+fn mesh_options(
+	weld: Option<Float>,
+	subdivisions: u32,
+	displacement: &Option<DisplacementConfig>,
+	smooth_normals: Option<Float>,
+	assets: &Assets,
+) -> Result<MeshOptions> {
+	check_subdivisions("smooth", subdivisions)?;
+
+	if weld.is_some_and(|epsilon| epsilon <= 0.0) {
+		return Err(RendererError::Scene {
+			path: None,
+			message: "the weld distance has to be positive".to_string(),
+		});
+	}
+
+	Ok(MeshOptions {
+		weld,
+		subdivisions,
+		displacement: displacement.as_ref().map(|d| d.build(assets)).transpose()?,
+		smooth_normals,
+	})
+}
+
+impl DisplacementConfig {
+	fn build(&self, assets: &Assets) -> Result<Displacement> {
+		check_subdivisions("displacement", self.subdivisions)?;
+
+		Ok(Displacement {
+			texture: self.texture.build(assets)?,
+			scale: self.scale,
+			subdivisions: self.subdivisions,
+		})
+	}
+}
+
+// Film `thickness` in nanometers, a few hundred give the strongest colors
+#[derive(Deserialize, Serialize)]
+pub struct ThinFilmConfig {
+	thickness: Float,
+	refraction_index: Float,
+}
+
+impl ThinFilmConfig {
+	fn build(&self) -> ThinFilm {
+		ThinFilm {
+			thickness: self.thickness,
+			refraction_index: self.refraction_index,
+		}
+	}
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TextureConfig {
+	Solid {
+		color: [Float; 3],
+	},
+	Checker {
+		even: [Float; 3],
+		odd: [Float; 3],
+		scale: Float,
+	},
+	Image {
+		path: String,
+	},
+	// Cellular noise in space, `scale` cells per unit
+	Worley {
+		#[serde(default)]
+		feature: WorleyFeatureConfig,
+		scale: Float,
+	},
+	// Colors of the stops at the value of the driver, blended in between
+	Ramp {
+		driver: RampDriverConfig,
+		stops: Vec<RampStopConfig>,
+	},
+	Multiply {
+		a: Box<TextureConfig>,
+		b: Box<TextureConfig>,
+	},
+	Add {
+		a: Box<TextureConfig>,
+		b: Box<TextureConfig>,
+	},
+	// From `a` at a black `factor` to `b` at white
+	Lerp {
+		a: Box<TextureConfig>,
+		b: Box<TextureConfig>,
+		factor: Box<TextureConfig>,
+	},
+	Invert {
+		texture: Box<TextureConfig>,
+	},
+	// Numbered grid for checking UVs, `cells` across each direction
+	UvGrid {
+		#[serde(default = "default_grid_cells")]
+		cells: u32,
+	},
+	// `texture` projected along the axes with UVs of `scale` per unit, blended by the normal
+	Triplanar {
+		texture: Box<TextureConfig>,
+		#[serde(default = "default_uv_scale")]
+		scale: Float,
+		#[serde(default = "default_sharpness")]
+		sharpness: Float,
+	},
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RampDriverConfig {
+	U,
+	V,
+	Height,
+	Texture(Box<TextureConfig>),
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct RampStopConfig {
+	position: Float,
+	color: [Float; 3],
+}
+
+#[derive(Deserialize, Serialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum WorleyFeatureConfig {
+	#[default]
+	F1,
+	F2,
+	F2MinusF1,
+}
+
+fn default_uv_scale() -> Float {
+	1.0
+}
+
+fn default_grid_cells() -> u32 {
+	8
+}
+
+fn default_sharpness() -> Float {
+	4.0
+}
+
+fn default_intensity() -> Float {
+	1.0
+}
+
+fn default_coat_index() -> Float {
+	1.5
+}
+
+fn default_subdivisions() -> u32 {
+	3
+}
+
+fn default_curve_subdivisions() -> u32 {
+	8
+}
+
+fn default_density() -> Float {
+	1.0
+}
+
+fn vec3f(value: &[Float; 3]) -> Vec3f {
+	Vec3f::new(value[0], value[1], value[2])
+}
+
+impl SceneFile {
+	pub fn parse(text: &str) -> Result<SceneFile> {
+		let scene: SceneFile = toml::from_str(text).map_err(|err| RendererError::Scene {
+			path: None,
+			message: err.to_string(),
+		})?;
+		scene.generate()
+	}
+
+	pub fn load(path: &str) -> Result<SceneFile> {
+		let text = std::fs::read_to_string(path).map_err(|source| RendererError::Io {
+			path: path.to_string(),
+			source,
+		})?;
+
+		let scene: SceneFile = toml::from_str(&text).map_err(|err| RendererError::Scene {
+			path: Some(path.to_string()),
+			message: err.to_string(),
+		})?;
+		scene.generate()
+	}
+
+	// Runs the scripts and adds the objects they emit
+	fn generate(mut self) -> Result<SceneFile> {
+		for script in std::mem::take(&mut self.scripts) {
+			let (name, source) = match (&script.path, &script.source) {
+				(Some(path), None) => {
+					let source =
+						std::fs::read_to_string(path).map_err(|source| RendererError::Io {
+							path: path.clone(),
+							source,
+						})?;
+					(path.as_str(), source)
+				}
+				(None, Some(source)) => ("script", source.clone()),
+				_ => {
+					return Err(RendererError::Scene {
+						path: None,
+						message: "a script needs either a path or a source".to_string(),
+					})
+				}
+			};
+
+			for object in script::run(name, &source, &script.params, script.seed)? {
+				let object =
+					object
+						.try_into()
+						.map_err(|err: toml::de::Error| RendererError::Scene {
+							path: Some(name.to_string()),
+							message: format!("emitted object: {}", err.message()),
+						})?;
+				self.objects.push(object);
+			}
+		}
+
+		Ok(self)
+	}
+
+	// The scene file with every object written out, including the ones scripts generated, for
+	// editing by hand. Loading it again gives the same scene.
+	pub fn to_toml(&self) -> Result<String> {
+		toml::to_string(self).map_err(|err| RendererError::Scene {
+			path: None,
+			message: err.to_string(),
+		})
+	}
+
+	// Names of the light groups in the order they first appear
+	pub fn light_groups(&self) -> Result<Vec<String>> {
+		let mut groups: Vec<String> = Vec::new();
+
+		let library = self.materials.values().collect();
+		let objects = self.objects.iter().map(ObjectConfig::materials);
+
+		for materials in std::iter::once(library).chain(objects) {
+			for material in materials {
+				if let MaterialConfig::Light {
+					group: Some(group), ..
+				} = material
+				{
+					if !groups.contains(group) {
+						groups.push(group.clone());
+					}
+				}
+			}
+		}
+
+		if groups.len() > MAX_LIGHT_GROUPS {
+			return Err(RendererError::Scene {
+				path: None,
+				message: format!("at most {} light groups are supported", MAX_LIGHT_GROUPS),
+			});
+		}
+
+		Ok(groups)
+	}
+
+	// Builds the named materials, each one after the material it refers to
+	fn material_library(&self, groups: &[String], assets: &Assets) -> Result<MaterialLibrary> {
+		let mut library = MaterialLibrary::new();
+		let mut pending: Vec<(&String, &MaterialConfig)> = self.materials.iter().collect();
+
+		while !pending.is_empty() {
+			let (ready, waiting): (Vec<_>, Vec<_>) =
+				pending.into_iter().partition(|(_, material)| {
+					material
+						.reference()
+						.is_none_or(|name| library.contains(name))
+				});
+
+			if ready.is_empty() {
+				let (name, material) = waiting[0];
+				let reference = material.reference().unwrap_or_default();
+				let message = if self.materials.contains_key(reference) {
+					format!(
+						"material '{}' refers to itself through '{}'",
+						name, reference
+					)
+				} else {
+					format!(
+						"material '{}' refers to unknown material '{}'",
+						name, reference
+					)
+				};
+				return Err(RendererError::Scene {
+					path: None,
+					message,
+				});
+			}
+
+			for (name, material) in ready {
+				library.add(name, material.build(groups, &library, assets)?);
+			}
+			pending = waiting;
+		}
+
+		Ok(library)
+	}
+
+	// Frames of the animation, enough to reach the last key of the camera or of any object. None
+	// for scenes without keys.
+	pub fn frames(&self) -> Option<u32> {
+		let camera = self.camera.keys.iter().map(|key| key.frame);
+		let objects = self
+			.objects
+			.iter()
+			.flat_map(ObjectConfig::transforms)
+			.flat_map(|transform| transform.keys.iter().map(|key| key.frame));
+
+		camera.chain(objects).max().map(|last| last + 1)
+	}
+
+	// Whether any object moves, in which case every frame needs a build of its own
+	pub fn has_animated_objects(&self) -> bool {
+		self.objects
+			.iter()
+			.flat_map(ObjectConfig::transforms)
+			.any(|transform| !transform.keys.is_empty())
+	}
+
+	// The camera move of the keys of the camera over the frames of the animation, None for scenes
+	// without keys
+	pub fn camera_path(&self) -> Result<Option<CameraPath>> {
+		let Some(frames) = self.frames() else {
+			return Ok(None);
+		};
+		let keys = &self.camera.keys;
+		check_key_order(keys.iter().map(|key| key.frame))?;
+
+		let interpolation = self.camera.interpolation.build();
+		let vector = |value: fn(&CameraKeyConfig) -> Option<&[Float; 3]>| {
+			track(
+				keys,
+				|key| Some((key.frame, vec3f(value(key)?))),
+				interpolation,
+			)
+		};
+
+		Ok(Some(CameraPath {
+			position: vector(|key| key.position.as_ref()),
+			look_at: vector(|key| key.look_at.as_ref()),
+			fov: track(keys, |key| Some((key.frame, key.fov?)), interpolation),
+			frames,
+		}))
+	}
+
+	// Names of the objects, in the order of the file, see ObjectConfig::name
+	pub fn object_names(&self) -> Vec<String> {
+		self.objects
+			.iter()
+			.enumerate()
+			.map(|(index, object)| object.name(index))
+			.collect()
+	}
+
+	// `settings` are those the scene is rendered with, see SceneFile::settings
+	pub fn integrator(&self, settings: &RenderSettings) -> Result<Arc<dyn Integrator>> {
+		let fog = self.render.fog.as_ref().map(FogConfig::build);
+		let sky = self.sky.as_ref().map(SkyConfig::build).transpose()?;
+		if sky.is_some() && self.environment.is_some() {
+			return Err(RendererError::Scene {
+				path: None,
+				message: "a scene can have either a sky or an environment, not both".to_string(),
+			});
+		}
+		let environment = self
+			.environment
+			.as_ref()
+			.map(|environment| {
+				Environment::load(
+					&environment.path,
+					environment.intensity,
+					environment.rotation,
+				)
+			})
+			.transpose()?;
+		let lights = self
+			.lights
+			.iter()
+			.map(LightConfig::build)
+			.collect::<Result<Vec<_>>>()?;
+
+		Ok(self.render.integrator.build(
+			settings,
+			self.light_groups()?,
+			fog,
+			sky,
+			environment,
+			lights,
+		))
+	}
+
+	// `base` with the resolution of the scene and either its samples or the quality of its preset
+	pub fn settings(&self, base: RenderSettings) -> Result<RenderSettings> {
+		let settings = RenderSettings {
+			width: self.render.width,
+			height: self.render.height,
+			samples: self.render.samples,
+			filter: self
+				.render
+				.filter
+				.map_or(base.filter, |filter| match filter {
+					FilterConfig::Box => Filter::Box,
+					FilterConfig::Tent => Filter::Tent,
+					FilterConfig::Gaussian => Filter::Gaussian,
+					FilterConfig::Mitchell => Filter::Mitchell,
+				}),
+			..base
+		};
+
+		let Some(name) = &self.render.preset else {
+			return Ok(settings);
+		};
+		let preset: Preset = name.parse().map_err(|message| RendererError::Scene {
+			path: None,
+			message,
+		})?;
+		Ok(preset.apply(settings))
+	}
+
+	// The exposure of the render settings plus that of the camera lens, if there is one
+	pub fn post_process(&self) -> Result<PostProcess> {
+		let lens = self
+			.camera
+			.lens
+			.as_ref()
+			.map_or(0.0, |lens| lens.build().exposure());
+
+		Ok(PostProcess {
+			exposure: self.render.exposure + lens,
+			white_balance: self.render.white_balance,
+			dither: self.render.dither.map(|dither| match dither {
+				DitherConfig::Ordered => Dither::Ordered,
+				DitherConfig::BlueNoise => Dither::BlueNoise,
+			}),
+			effects: Effects {
+				bloom: self.render.bloom.as_ref().map(BloomConfig::build),
+				vignette: self.render.vignette,
+				chromatic_aberration: self.render.chromatic_aberration,
+			},
+			lut: match &self.render.lut {
+				Some(path) => Some(Arc::new(Lut::load(path)?)),
+				None => None,
+			},
+		})
+	}
+
+	pub fn build(&self) -> Result<(HittableGroup, Arc<Camera>)> {
+		self.build_with(&Assets::default())
+	}
+
+	// Loads images and meshes through `assets`, which may still hold them from an earlier build
+	pub fn build_with(&self, assets: &Assets) -> Result<(HittableGroup, Arc<Camera>)> {
+		self.build_frame(assets, 0)
+	}
+
+	// With the objects placed as they are at `frame` of the animation
+	pub fn build_frame(&self, assets: &Assets, frame: u32) -> Result<(HittableGroup, Arc<Camera>)> {
+		let background = vec3f(&self.camera.background);
+		let position = vec3f(&self.camera.position);
+		let look_at = vec3f(&self.camera.look_at);
+		let (width, height) = (self.render.width, self.render.height);
+
+		let camera = match (self.camera.fov, &self.camera.lens) {
+			(Some(fov), None) => Camera::new(background, position, look_at, fov, width, height),
+			(None, Some(lens)) => {
+				Camera::from_lens(background, position, look_at, &lens.build(), width, height)
+			}
+			_ => {
+				return Err(RendererError::Scene {
+					path: None,
+					message: "the camera needs either a fov or a lens".to_string(),
+				})
+			}
+		};
+		let mut camera = camera.with_projection(self.camera.projection.build());
+		if let Some(depth_of_field) = &self.camera.depth_of_field {
+			camera = camera.with_depth_of_field(depth_of_field.build(position, look_at)?);
+		}
+		if let Some(stereo) = &self.camera.stereo {
+			camera = camera.with_stereo(stereo.build()?);
+		}
+		// Rays only need a time when something moves over the shutter interval
+		if self.objects.iter().any(ObjectConfig::deforms) {
+			camera = camera.with_motion_blur();
+		}
+		let camera = Arc::new(camera);
+
+		let groups = self.light_groups()?;
+		let library = self.material_library(&groups, assets)?;
+		let mut objects = Vec::with_capacity(self.objects.len());
+
+		for (index, object) in self.objects.iter().enumerate() {
+			let hittable = self.build_object(
+				object,
+				&object.name(index),
+				frame,
+				&groups,
+				&library,
+				assets,
+			)?;
+
+			objects.push(Tagged::new(hittable, object_id(&object.name(index))));
+		}
+
+		// Scripts and instances can emit thousands of objects, the BVH keeps planes and other
+		// unbounded objects out of its nodes
+		let mut world = HittableGroup::new();
+		if !objects.is_empty() {
+			world.add(Box::new(Bvh::from_primitives(objects)));
+		}
+
+		Ok((world, camera))
+	}
+
+	fn build_object(
+		&self,
+		object: &ObjectConfig,
+		name: &str,
+		frame: u32,
+		groups: &[String],
+		library: &MaterialLibrary,
+		assets: &Assets,
+	) -> Result<Box<dyn Hittable>> {
+		let mut hittable: Box<dyn Hittable> = match object {
+			ObjectConfig::Sphere {
+				center,
+				radius,
+				material,
+				..
+			} => Box::new(Sphere::new(
+				vec3f(center),
+				*radius,
+				material.build(groups, library, assets)?,
+			)),
+			ObjectConfig::Plane {
+				xbasis,
+				ybasis,
+				point,
+				uv_scale,
+				material,
+				..
+			} => Box::new(
+				Plane::new(
+					vec3f(xbasis),
+					vec3f(ybasis),
+					vec3f(point),
+					material.build(groups, library, assets)?,
+				)
+				.with_uv_scale(*uv_scale),
+			),
+			ObjectConfig::Quad {
+				corner,
+				u,
+				v,
+				material,
+				..
+			} => Box::new(Quad::new(
+				vec3f(corner),
+				vec3f(u),
+				vec3f(v),
+				material.build(groups, library, assets)?,
+			)),
+			ObjectConfig::Curves {
+				basis,
+				curves,
+				radius,
+				tip_radius,
+				subdivisions,
+				material,
+				..
+			} => {
+				let curves: Vec<Vec<Vec3f>> = curves
+					.iter()
+					.map(|points| points.iter().map(vec3f).collect())
+					.collect();
+				let basis = match basis {
+					CurveBasisConfig::CatmullRom => CurveBasis::CatmullRom,
+					CurveBasisConfig::Bezier => CurveBasis::Bezier,
+				};
+
+				Box::new(Curves::new(
+					&curves,
+					basis,
+					*subdivisions,
+					*radius,
+					tip_radius.unwrap_or(*radius),
+					material.build(groups, library, assets)?,
+				)?)
+			}
+			ObjectConfig::PointCloud {
+				path,
+				radius,
+				shape,
+				material,
+				..
+			} => {
+				let shape = match shape {
+					SplatShapeConfig::Disk => SplatShape::Disk,
+					SplatShapeConfig::Sphere => SplatShape::Sphere,
+				};
+
+				Box::new(PointCloud::load(
+					path,
+					*radius,
+					shape,
+					material.build(groups, library, assets)?,
+				)?)
+			}
+			ObjectConfig::Volume {
+				path,
+				grid,
+				corner,
+				size,
+				density,
+				material,
+				..
+			} => Box::new(Volume::new(
+				DensityGrid::load(path, grid.as_deref())?,
+				vec3f(corner),
+				vec3f(size),
+				*density,
+				material.build(groups, library, assets)?,
+			)),
+			ObjectConfig::Csg {
+				operation, a, b, ..
+			} => Box::new(csg(*operation, a, b, groups, library, assets)?),
+			ObjectConfig::Heightfield {
+				path,
+				corner,
+				size,
+				material,
+				..
+			} => Box::new(Heightfield::new(
+				path,
+				vec3f(corner),
+				vec3f(size),
+				material.build(groups, library, assets)?,
+			)?),
+			ObjectConfig::Mesh {
+				path,
+				motion,
+				material: Some(material),
+				weld,
+				smooth_subdivisions,
+				displacement,
+				smooth_normals,
+				..
+			} => {
+				let material = material.build(groups, library, assets)?;
+				let options = mesh_options(
+					*weld,
+					*smooth_subdivisions,
+					displacement,
+					*smooth_normals,
+					assets,
+				)?;
+
+				if motion.is_empty() {
+					assets.mesh_with_material(path, material, &options)?
+				} else {
+					assets.moving_mesh_with_material(path, motion, material, &options)?
+				}
+			}
+			ObjectConfig::Mesh {
+				path,
+				motion,
+				material: None,
+				weld,
+				smooth_subdivisions,
+				displacement,
+				smooth_normals,
+				..
+			} => {
+				let fallback = Arc::new(Diffuse::new(Arc::new(SolidColor::new(Vec3f::new(
+					0.8, 0.8, 0.8,
+				)))));
+				let texture_paths: Vec<PathBuf> =
+					self.texture_paths.iter().map(PathBuf::from).collect();
+
+				let options = mesh_options(
+					*weld,
+					*smooth_subdivisions,
+					displacement,
+					*smooth_normals,
+					assets,
+				)?;
+
+				if motion.is_empty() {
+					assets.mesh_with_materials(path, fallback, &texture_paths, &options)?
+				} else {
+					assets.moving_mesh_with_materials(
+						path,
+						motion,
+						fallback,
+						&texture_paths,
+						&options,
+					)?
+				}
+			}
+			ObjectConfig::Instances {
+				object: instanced,
+				instances,
+				grid,
+				scatter,
+				..
+			} => {
+				// Built once and shared by every instance, which only adds its transform
+				let shared: Arc<dyn Hittable> =
+					Arc::from(self.build_object(instanced, name, frame, groups, library, assets)?);
+
+				let mut matrices = instances
+					.iter()
+					.map(|transform| Ok(transform.path()?.matrix(frame as Float)))
+					.collect::<Result<Vec<Mat4>>>()?;
+				if let Some(grid) = grid {
+					matrices.extend(grid.matrices());
+				}
+				if let Some(scatter) = scatter {
+					matrices.extend(scatter.matrices()?);
+				}
+				if matrices.is_empty() {
+					return Err(RendererError::Scene {
+						path: None,
+						message: format!(
+							"{} has no instances, it needs instances, a grid or a scatter",
+							name
+						),
+					});
+				}
+
+				let placed = matrices
+					.into_iter()
+					.map(|matrix| {
+						let instance = transformed(Box::new(Arc::clone(&shared)), matrix, name)?;
+						Ok(Arc::new(instance) as Arc<dyn Hittable>)
+					})
+					.collect::<Result<Vec<_>>>()?;
+				Box::new(Bvh::new(&placed))
+			}
+		};
+
+		if let Some(transform) = object.transform() {
+			let matrix = transform.path()?.matrix(frame as Float);
+			hittable = Box::new(transformed(hittable, matrix, name)?);
+		}
+
+		Ok(hittable)
+	}
+}
+
+impl ProjectionConfig {
+	fn build(&self) -> Projection {
+		match self {
+			ProjectionConfig::Perspective => Projection::Perspective,
+			ProjectionConfig::Fisheye { mapping, fov } => Projection::Fisheye {
+				mapping: match mapping {
+					FisheyeMappingConfig::Equidistant => FisheyeMapping::Equidistant,
+					FisheyeMappingConfig::Equisolid => FisheyeMapping::Equisolid,
+				},
+				fov: *fov,
+			},
+			ProjectionConfig::Equirectangular => Projection::Equirectangular,
+		}
+	}
+}
+
+impl MaterialConfig {
+	// The named material this one is or is built on
+	fn reference(&self) -> Option<&str> {
+		match self {
+			MaterialConfig::Named { name } => Some(name),
+			MaterialConfig::Clearcoat { base, .. } => base.reference(),
+			_ => None,
+		}
+	}
+
+	fn build(
+		&self,
+		groups: &[String],
+		library: &MaterialLibrary,
+		assets: &Assets,
+	) -> Result<Arc<dyn Material>> {
+		Ok(match self {
+			MaterialConfig::Diffuse { texture } => Arc::new(Diffuse::new(texture.build(assets)?)),
+			MaterialConfig::Isotropic { texture } => {
+				Arc::new(Isotropic::new(texture.build(assets)?))
+			}
+			MaterialConfig::Metal {
+				texture,
+				roughness,
+				roughness_v,
+				tangent_rotation,
+			} => {
+				let mut metal = Metal::new(texture.build(assets)?)
+					.with_anisotropic_roughness(*roughness, roughness_v.unwrap_or(*roughness));
+				if let Some(rotation) = tangent_rotation {
+					metal = metal.with_tangent_rotation(rotation.build(assets)?);
+				}
+				Arc::new(metal)
+			}
+			MaterialConfig::Conductor {
+				preset,
+				eta,
+				k,
+				roughness,
+				roughness_v,
+				tangent_rotation,
+				film,
+			} => {
+				let conductor = match (preset, eta, k) {
+					(Some(name), None, None) => Conductor::preset(name).ok_or_else(|| {
+						let names: Vec<&str> =
+							CONDUCTORS.iter().map(|(name, _, _)| *name).collect();
+						RendererError::Scene {
+							path: None,
+							message: format!(
+								"unknown conductor '{}', expected one of {}",
+								name,
+								names.join(", ")
+							),
+						}
+					})?,
+					(None, Some(eta), Some(k)) => Conductor::new(vec3f(eta), vec3f(k)),
+					_ => {
+						return Err(RendererError::Scene {
+							path: None,
+							message: "a conductor needs either a preset or both eta and k"
+								.to_string(),
+						})
+					}
+				};
+				let mut conductor = conductor
+					.with_anisotropic_roughness(*roughness, roughness_v.unwrap_or(*roughness));
+				if let Some(rotation) = tangent_rotation {
+					conductor = conductor.with_tangent_rotation(rotation.build(assets)?);
+				}
+				match film {
+					Some(film) => Arc::new(conductor.with_thin_film(film.build())),
+					None => Arc::new(conductor),
+				}
+			}
+			MaterialConfig::Clearcoat {
+				base,
+				refraction_index,
+				roughness,
+			} => Arc::new(
+				Clearcoat::new(base.build(groups, library, assets)?, *refraction_index)
+					.with_roughness(*roughness),
+			),
+			MaterialConfig::Light {
+				texture,
+				temperature,
+				intensity,
+				group,
+			} => {
+				let texture = match (texture, temperature) {
+					(Some(texture), _) => texture.build(assets)?,
+					(None, Some(_)) => Arc::new(SolidColor::new(Vec3f::new(1.0, 1.0, 1.0))),
+					(None, None) => {
+						return Err(RendererError::Scene {
+							path: None,
+							message: "a light needs a texture or a temperature".to_string(),
+						})
+					}
+				};
+				let mut light = DiffuseLight::new(texture).with_intensity(*intensity);
+				if let Some(temperature) = temperature {
+					light = light.with_temperature(*temperature);
+				}
+				match group
+					.as_ref()
+					.and_then(|group| groups.iter().position(|g| g == group))
+				{
+					Some(index) => Arc::new(light.with_group(index)),
+					None => Arc::new(light),
+				}
+			}
+			MaterialConfig::Dielectric {
+				refraction_index,
+				film,
+				priority,
+			} => {
+				let dielectric = Dielectric::new(*refraction_index).with_priority(*priority);
+				match film {
+					Some(film) => Arc::new(dielectric.with_thin_film(film.build())),
+					None => Arc::new(dielectric),
+				}
+			}
+			MaterialConfig::Holdout => Arc::new(Holdout),
+			MaterialConfig::Named { name } => library.get(name)?,
+		})
+	}
+}
+
+impl TextureConfig {
+	fn build(&self, assets: &Assets) -> Result<Arc<dyn Texture>> {
+		Ok(match self {
+			TextureConfig::Solid { color } => Arc::new(SolidColor::new(vec3f(color))),
+			TextureConfig::Checker { even, odd, scale } => {
+				Arc::new(CheckerTexture::new(vec3f(even), vec3f(odd), *scale))
+			}
+			TextureConfig::Image { path } => assets.image(Path::new(path))?,
+			TextureConfig::Worley { feature, scale } => Arc::new(WorleyTexture::new(
+				match feature {
+					WorleyFeatureConfig::F1 => WorleyFeature::F1,
+					WorleyFeatureConfig::F2 => WorleyFeature::F2,
+					WorleyFeatureConfig::F2MinusF1 => WorleyFeature::F2MinusF1,
+				},
+				*scale,
+			)),
+			TextureConfig::Ramp { driver, stops } => {
+				if stops.is_empty() {
+					return Err(RendererError::Scene {
+						path: None,
+						message: "a ramp needs at least one stop".to_string(),
+					});
+				}
+				let driver = match driver {
+					RampDriverConfig::U => RampDriver::U,
+					RampDriverConfig::V => RampDriver::V,
+					RampDriverConfig::Height => RampDriver::Height,
+					RampDriverConfig::Texture(texture) => {
+						RampDriver::Texture(texture.build(assets)?)
+					}
+				};
+				let stops = stops
+					.iter()
+					.map(|stop| (stop.position, vec3f(&stop.color)))
+					.collect();
+				Arc::new(RampTexture::new(driver, stops))
+			}
+			TextureConfig::Multiply { a, b } => Arc::new(CompositeTexture::Multiply(
+				a.build(assets)?,
+				b.build(assets)?,
+			)),
+			TextureConfig::Add { a, b } => {
+				Arc::new(CompositeTexture::Add(a.build(assets)?, b.build(assets)?))
+			}
+			TextureConfig::Lerp { a, b, factor } => Arc::new(CompositeTexture::Lerp {
+				a: a.build(assets)?,
+				b: b.build(assets)?,
+				factor: factor.build(assets)?,
+			}),
+			TextureConfig::Invert { texture } => {
+				Arc::new(CompositeTexture::Invert(texture.build(assets)?))
+			}
+			TextureConfig::UvGrid { cells } => Arc::new(UvGridTexture::new(*cells)),
+			TextureConfig::Triplanar {
+				texture,
+				scale,
+				sharpness,
+			} => Arc::new(TriplanarTexture::new(
+				texture.build(assets)?,
+				*scale,
+				*sharpness,
+			)),
+		})
+	}
+}
@@ -0,0 +1,585 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::camera::Camera;
+use crate::error::Result;
+use crate::geometry::{
+	cuboid, load_mesh, Bvh, Float, Hittable, HittableGroup, MeshOptions, Plane, Quad, RotateY,
+	Sphere, Translate, Vec3f,
+};
+use crate::material::{
+	CheckerTexture, Dielectric, Diffuse, DiffuseLight, ImageTexture, Material, MaterialLibrary,
+	Metal, SolidColor, Texture,
+};
+use crate::random;
+use crate::scene_builder::SceneBuilder;
+use crate::scene_file::{
+	CameraConfig, InterpolationConfig, MaterialConfig, ObjectConfig, ProjectionConfig,
+	RenderConfig, SceneFile, TextureConfig,
+};
+
+// Builds a scene into the group and returns its camera for the given image size
+pub type Scene = fn(&mut HittableGroup, u32, u32) -> Result<Arc<Camera>>;
+
+// Describes a scene as a scene file for the given image size
+pub type SceneDescription = fn(u32, u32) -> SceneFile;
+
+// Built-in scenes by name, the first one is the default
+pub const SCENES: &[(&str, Scene)] = &[
+	("tank", scene_tank),
+	("ant", scene_ant),
+	("cube", scene_cube),
+	("spheres", scene_spheres),
+	("cornell", scene_cornell),
+	("random_spheres", scene_random_spheres),
+];
+
+// Built-in scenes that can also be written out as scene files, for the same image size
+pub const SCENE_FILES: &[(&str, SceneDescription)] =
+	&[("random_spheres", scene_file_random_spheres)];
+
+pub fn find(name: &str) -> Option<Scene> {
+	SCENES
+		.iter()
+		.find(|(scene, _)| *scene == name)
+		.map(|(_, scene)| *scene)
+}
+
+// Materials shared by the model scenes
+fn studio_materials() -> MaterialLibrary {
+	MaterialLibrary::new()
+		.with_material(
+			"gray_metal",
+			Arc::new(Metal::new(Arc::new(SolidColor::new(Vec3f::new(
+				0.8, 0.8, 0.8,
+			))))),
+		)
+		.with_material(
+			"purple_diffuse",
+			Arc::new(Diffuse::new(Arc::new(SolidColor::new(Vec3f::new(
+				0.98, 0.70, 0.65,
+			))))),
+		)
+		.with_material(
+			"white_light",
+			Arc::new(DiffuseLight::new(Arc::new(SolidColor::new(Vec3f::new(
+				10.0, 10.0, 10.0,
+			))))),
+		)
+}
+
+pub const BACKGROUND: Vec3f = Vec3f {
+	x: 0.0,
+	y: 0.0,
+	z: 0.0,
+};
+
+#[allow(unused_variables)]
+pub fn scene_cube(scene: &mut HittableGroup, width: u32, height: u32) -> Result<Arc<Camera>> {
+	let camera = Arc::new(Camera::new(
+		BACKGROUND,
+		Vec3f::new(0.0, 1.0, 0.0),
+		Vec3f::new(0.0, 0.0, -5.0),
+		70.0,
+		width,
+		height,
+	));
+
+	let plane_texture = Arc::new(CheckerTexture::new(
+		Vec3f::new(1.0, 1.0, 1.0),
+		Vec3f::new(0.7, 0.0, 0.0),
+		1.0,
+	));
+
+	let plane_diffuse = Arc::new(Diffuse::new(plane_texture));
+
+	let materials = studio_materials();
+
+	let ant_texture: Arc<dyn Texture> = Arc::new(ImageTexture::new("models/IS.png")?);
+	let ant_diffuse = Arc::new(Diffuse::new(Arc::clone(&ant_texture)));
+	let ant_metal = Arc::new(Metal::new(ant_texture));
+
+	let ant = load_mesh("models/cube.obj", ant_metal, &MeshOptions::default())?;
+
+	scene.add(Box::new(ant));
+
+	scene.add(Box::new(Plane::new(
+		Vec3f::new(1.0, 0.0, 0.0),
+		Vec3f::new(0.0, 0.0, -1.0),
+		Vec3f::new(0.0, -0.1, 0.0),
+		plane_diffuse,
+	)));
+
+	/*
+	scene.add(Box::new(Sphere::new(
+		Vec3f::new(0.0, 1.5, -5.0),
+		1.0,
+		materials.get("gray_metal")?,
+	)));
+
+	 */
+	Ok(camera)
+}
+
+#[allow(unused_variables)]
+pub fn scene_tank(scene: &mut HittableGroup, width: u32, height: u32) -> Result<Arc<Camera>> {
+	let camera = Arc::new(Camera::new(
+		BACKGROUND,
+		Vec3f::new(-1.0, 6.0, 20.0),
+		Vec3f::new(0.0, 0.0, -5.0),
+		70.0,
+		width,
+		height,
+	));
+
+	let plane_texture = Arc::new(CheckerTexture::new(
+		Vec3f::new(0.75, 0.75, 0.75),
+		Vec3f::new(103.0 / 255.0, 197.0 / 255.0, 211.0 / 255.0),
+		0.5,
+	));
+
+	let plane_diffuse = Arc::new(Diffuse::new(plane_texture));
+
+	let materials = studio_materials();
+
+	let ant_texture: Arc<dyn Texture> = Arc::new(ImageTexture::new("models/IS.png")?);
+	let ant_diffuse = Arc::new(Diffuse::new(Arc::clone(&ant_texture)));
+	let ant_metal = Arc::new(Metal::new(ant_texture));
+
+	let ant = load_mesh("models/IS.obj", ant_diffuse, &MeshOptions::default())?;
+
+	scene.add(Box::new(ant));
+
+	scene.add(Box::new(Plane::new(
+		Vec3f::new(1.0, 0.0, 0.0),
+		Vec3f::new(0.0, 0.0, -1.0),
+		Vec3f::new(0.0, -0.1, 0.0),
+		plane_diffuse,
+	)));
+
+	scene.add(Box::new(Sphere::new(
+		Vec3f::new(1.0, 25.0, 10.0),
+		7.5,
+		materials.get("white_light")?,
+	)));
+
+	Ok(camera)
+}
+
+#[allow(unused_variables)]
+pub fn scene_ant(scene: &mut HittableGroup, width: u32, height: u32) -> Result<Arc<Camera>> {
+	let camera = Arc::new(Camera::new(
+		BACKGROUND,
+		Vec3f::new(0.0, 2.0, 0.0),
+		Vec3f::new(0.0, 0.0, -5.0),
+		90.0,
+		width,
+		height,
+	));
+
+	let plane_texture = Arc::new(CheckerTexture::new(
+		Vec3f::new(1.0, 1.0, 1.0),
+		Vec3f::new(0.7, 0.0, 0.0),
+		1.0,
+	));
+
+	let plane_diffuse = Arc::new(Diffuse::new(plane_texture));
+
+	let materials = studio_materials();
+
+	let ant_texture: Arc<dyn Texture> = Arc::new(ImageTexture::new("models/ant.png")?);
+	let ant_diffuse = Arc::new(Diffuse::new(Arc::clone(&ant_texture)));
+	let ant_metal = Arc::new(Metal::new(ant_texture));
+
+	let ant = load_mesh(
+		"models/ant.obj",
+		materials.get("purple_diffuse")?,
+		&MeshOptions::default(),
+	)?;
+
+	scene.add(Box::new(ant));
+
+	scene.add(Box::new(Plane::new(
+		Vec3f::new(1.0, 0.0, 0.0),
+		Vec3f::new(0.0, 0.0, -1.0),
+		Vec3f::new(0.0, -0.1, 0.0),
+		plane_diffuse,
+	)));
+
+	scene.add(Box::new(Sphere::new(
+		Vec3f::new(1.0, 4.5, -3.5),
+		2.0,
+		materials.get("white_light")?,
+	)));
+
+	Ok(camera)
+}
+
+#[allow(unused_variables)]
+pub fn scene_spheres(scene: &mut HittableGroup, width: u32, height: u32) -> Result<Arc<Camera>> {
+	let camera = Arc::new(Camera::new(
+		BACKGROUND,
+		Vec3f::new(0.0, 0.0, 0.0),
+		Vec3f::new(0.0, 0.0, -1.0),
+		70.0,
+		width,
+		height,
+	));
+
+	let plane_texture = Arc::new(CheckerTexture::new(
+		Vec3f::new(1.0, 1.0, 1.0),
+		Vec3f::new(0.7, 0.0, 0.0),
+		10.0,
+	));
+
+	let plane_diffuse = Arc::new(Diffuse::new(plane_texture));
+
+	// A dimmer light than the other scenes', close to the spheres
+	let materials = studio_materials().with_material(
+		"white_light",
+		Arc::new(DiffuseLight::new(Arc::new(SolidColor::new(Vec3f::new(
+			1.0, 1.0, 1.0,
+		))))),
+	);
+
+	scene.add(Box::new(Plane::new(
+		Vec3f::new(1.0, 0.0, 0.0),
+		Vec3f::new(0.0, 0.0, -1.0),
+		Vec3f::new(0.0, -0.1, 0.0),
+		plane_diffuse,
+	)));
+
+	scene.add(Box::new(Sphere::new(
+		Vec3f::new(-0.4, 0.35, -1.0),
+		0.2,
+		materials.get("gray_metal")?,
+	)));
+
+	scene.add(Box::new(Sphere::new(
+		Vec3f::new(0.25, 0.15, -1.0),
+		0.2,
+		materials.get("white_light")?,
+	)));
+
+	Ok(camera)
+}
+
+// The standard Cornell box, 555 units on each side with the open side facing the camera
+pub fn scene_cornell(scene: &mut HittableGroup, width: u32, height: u32) -> Result<Arc<Camera>> {
+	let camera = Camera::builder()
+		.background(BACKGROUND)
+		.position(Vec3f::new(278.0, 278.0, -800.0))
+		.look_at(Vec3f::new(278.0, 278.0, 0.0))
+		.fov(40.0)
+		.size(width, height);
+
+	let red = Arc::new(Diffuse::new(Arc::new(SolidColor::new(Vec3f::new(
+		0.65, 0.05, 0.05,
+	)))));
+	let white = Arc::new(Diffuse::new(Arc::new(SolidColor::new(Vec3f::new(
+		0.73, 0.73, 0.73,
+	)))));
+	let green = Arc::new(Diffuse::new(Arc::new(SolidColor::new(Vec3f::new(
+		0.12, 0.45, 0.15,
+	)))));
+	let light = Arc::new(DiffuseLight::new(Arc::new(SolidColor::new(Vec3f::new(
+		15.0, 15.0, 15.0,
+	)))));
+
+	let tall = cuboid(
+		Vec3f::new(0.0, 0.0, 0.0),
+		Vec3f::new(165.0, 330.0, 165.0),
+		white.clone(),
+	);
+	let short = cuboid(
+		Vec3f::new(0.0, 0.0, 0.0),
+		Vec3f::new(165.0, 165.0, 165.0),
+		white.clone(),
+	);
+
+	// Quads are one-sided, so every wall faces into the box
+	let camera = SceneBuilder::new()
+		.camera(camera)
+		.named_object(
+			"green_wall",
+			Quad::new(
+				Vec3f::new(555.0, 0.0, 0.0),
+				Vec3f::new(0.0, 0.0, 555.0),
+				Vec3f::new(0.0, 555.0, 0.0),
+				green,
+			),
+		)
+		.named_object(
+			"red_wall",
+			Quad::new(
+				Vec3f::new(0.0, 0.0, 0.0),
+				Vec3f::new(0.0, 555.0, 0.0),
+				Vec3f::new(0.0, 0.0, 555.0),
+				red,
+			),
+		)
+		.named_object(
+			"light",
+			Quad::new(
+				Vec3f::new(343.0, 554.0, 332.0),
+				Vec3f::new(-130.0, 0.0, 0.0),
+				Vec3f::new(0.0, 0.0, -105.0),
+				light,
+			),
+		)
+		.named_object(
+			"floor",
+			Quad::new(
+				Vec3f::new(0.0, 0.0, 0.0),
+				Vec3f::new(0.0, 0.0, 555.0),
+				Vec3f::new(555.0, 0.0, 0.0),
+				white.clone(),
+			),
+		)
+		.named_object(
+			"ceiling",
+			Quad::new(
+				Vec3f::new(555.0, 555.0, 555.0),
+				Vec3f::new(-555.0, 0.0, 0.0),
+				Vec3f::new(0.0, 0.0, -555.0),
+				white.clone(),
+			),
+		)
+		.named_object(
+			"back_wall",
+			Quad::new(
+				Vec3f::new(0.0, 0.0, 555.0),
+				Vec3f::new(0.0, 555.0, 0.0),
+				Vec3f::new(555.0, 0.0, 0.0),
+				white,
+			),
+		)
+		.named_object(
+			"tall_block",
+			Translate::new(
+				Box::new(RotateY::new(Box::new(tall), 15.0)),
+				Vec3f::new(265.0, 0.0, 295.0),
+			),
+		)
+		.named_object(
+			"short_block",
+			Translate::new(
+				Box::new(RotateY::new(Box::new(short), -18.0)),
+				Vec3f::new(130.0, 0.0, 65.0),
+			),
+		)
+		.build_into(scene);
+
+	Ok(camera)
+}
+
+fn random_color(min: Float, max: Float) -> Vec3f {
+	Vec3f::new(
+		random::range(min, max),
+		random::range(min, max),
+		random::range(min, max),
+	)
+}
+
+// Material of a sphere of the random field
+enum FieldMaterial {
+	Diffuse(Vec3f),
+	Metal(Vec3f),
+	Glass,
+}
+
+// Centers, radii and materials of hundreds of small random spheres around three large ones,
+// drawn from the calling thread's generator
+fn random_field() -> Vec<(Vec3f, Float, FieldMaterial)> {
+	let mut spheres = Vec::new();
+
+	for a in -11..11 {
+		for b in -11..11 {
+			let center = Vec3f::new(
+				a as Float + 0.9 * random::float(),
+				0.2,
+				b as Float + 0.9 * random::float(),
+			);
+
+			// Keep clear of the large metal sphere
+			if (center - Vec3f::new(4.0, 0.2, 0.0)).length() <= 0.9 {
+				continue;
+			}
+
+			let choice = random::float();
+			let material = if choice < 0.8 {
+				FieldMaterial::Diffuse(random_color(0.0, 1.0) * random_color(0.0, 1.0))
+			} else if choice < 0.95 {
+				FieldMaterial::Metal(random_color(0.5, 1.0))
+			} else {
+				FieldMaterial::Glass
+			};
+
+			spheres.push((center, 0.2, material));
+		}
+	}
+
+	spheres.push((Vec3f::new(0.0, 1.0, 0.0), 1.0, FieldMaterial::Glass));
+	spheres.push((
+		Vec3f::new(-4.0, 1.0, 0.0),
+		1.0,
+		FieldMaterial::Diffuse(Vec3f::new(0.4, 0.2, 0.1)),
+	));
+	spheres.push((
+		Vec3f::new(4.0, 1.0, 0.0),
+		1.0,
+		FieldMaterial::Metal(Vec3f::new(0.7, 0.6, 0.5)),
+	));
+	spheres
+}
+
+const FIELD_BACKGROUND: Vec3f = Vec3f {
+	x: 0.7,
+	y: 0.8,
+	z: 1.0,
+};
+const FIELD_POSITION: Vec3f = Vec3f {
+	x: 13.0,
+	y: 2.0,
+	z: 3.0,
+};
+const FIELD_FOV: Float = 20.0;
+const FIELD_EVEN: Vec3f = Vec3f {
+	x: 0.2,
+	y: 0.3,
+	z: 0.1,
+};
+const FIELD_ODD: Vec3f = Vec3f {
+	x: 0.9,
+	y: 0.9,
+	z: 0.9,
+};
+
+// The final scene of "Ray Tracing in One Weekend". The field is generated with the calling
+// thread's generator, see random::seed.
+pub fn scene_random_spheres(
+	scene: &mut HittableGroup,
+	width: u32,
+	height: u32,
+) -> Result<Arc<Camera>> {
+	let camera = Arc::new(Camera::new(
+		FIELD_BACKGROUND,
+		FIELD_POSITION,
+		Vec3f::new(0.0, 0.0, 0.0),
+		FIELD_FOV,
+		width,
+		height,
+	));
+
+	let ground_texture = Arc::new(CheckerTexture::new(FIELD_EVEN, FIELD_ODD, 1.0));
+
+	// Everything goes into one BVH, which keeps the unbounded ground plane out of its nodes
+	let mut objects: Vec<Arc<dyn Hittable>> = vec![Arc::new(Plane::new(
+		Vec3f::new(1.0, 0.0, 0.0),
+		Vec3f::new(0.0, 0.0, -1.0),
+		Vec3f::new(0.0, 0.0, 0.0),
+		Arc::new(Diffuse::new(ground_texture)),
+	))];
+
+	let glass: Arc<dyn Material> = Arc::new(Dielectric::new(1.5));
+
+	for (center, radius, material) in random_field() {
+		let material: Arc<dyn Material> = match material {
+			FieldMaterial::Diffuse(albedo) => {
+				Arc::new(Diffuse::new(Arc::new(SolidColor::new(albedo))))
+			}
+			FieldMaterial::Metal(albedo) => Arc::new(Metal::new(Arc::new(SolidColor::new(albedo)))),
+			FieldMaterial::Glass => Arc::clone(&glass),
+		};
+		objects.push(Arc::new(Sphere::new(center, radius, material)));
+	}
+
+	scene.add(Box::new(Bvh::new(&objects)));
+
+	Ok(camera)
+}
+
+fn array(v: Vec3f) -> [Float; 3] {
+	[v.x, v.y, v.z]
+}
+
+fn solid(color: Vec3f) -> TextureConfig {
+	TextureConfig::Solid {
+		color: array(color),
+	}
+}
+
+// scene_random_spheres as a scene file
+pub fn scene_file_random_spheres(width: u32, height: u32) -> SceneFile {
+	let ground = ObjectConfig::Plane {
+		xbasis: [1.0, 0.0, 0.0],
+		ybasis: [0.0, 0.0, -1.0],
+		point: [0.0, 0.0, 0.0],
+		uv_scale: 1.0,
+		material: MaterialConfig::Diffuse {
+			texture: TextureConfig::Checker {
+				even: array(FIELD_EVEN),
+				odd: array(FIELD_ODD),
+				scale: 1.0,
+			},
+		},
+		name: None,
+		transform: None,
+	};
+
+	let glass = MaterialConfig::Dielectric {
+		refraction_index: 1.5,
+		film: None,
+		priority: 0,
+	};
+
+	let spheres = random_field()
+		.into_iter()
+		.map(|(center, radius, material)| ObjectConfig::Sphere {
+			center: array(center),
+			radius,
+			material: match material {
+				FieldMaterial::Diffuse(albedo) => MaterialConfig::Diffuse {
+					texture: solid(albedo),
+				},
+				FieldMaterial::Metal(albedo) => MaterialConfig::Metal {
+					texture: solid(albedo),
+					roughness: 0.0,
+					roughness_v: None,
+					tangent_rotation: None,
+				},
+				FieldMaterial::Glass => MaterialConfig::Named {
+					name: "glass".to_string(),
+				},
+			},
+			name: None,
+			transform: None,
+		});
+
+	SceneFile {
+		texture_paths: Vec::new(),
+		render: RenderConfig {
+			width,
+			height,
+			..RenderConfig::default()
+		},
+		camera: CameraConfig {
+			position: array(FIELD_POSITION),
+			look_at: [0.0, 0.0, 0.0],
+			fov: Some(FIELD_FOV),
+			lens: None,
+			background: array(FIELD_BACKGROUND),
+			projection: ProjectionConfig::default(),
+			depth_of_field: None,
+			stereo: None,
+			keys: Vec::new(),
+			interpolation: InterpolationConfig::default(),
+		},
+		sky: None,
+		environment: None,
+		lights: Vec::new(),
+		materials: BTreeMap::from([("glass".to_string(), glass)]),
+		objects: std::iter::once(ground).chain(spheres).collect(),
+		scripts: Vec::new(),
+	}
+}
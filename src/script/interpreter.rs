@@ -0,0 +1,903 @@
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use crate::script::parser::{
+	Access, Expr, Function, Iterable, Kind, Statement, Target, MAX_NESTING,
+};
+use crate::script::ScriptError;
+
+// Statements and loop iterations a script may run, so that an endless loop fails the scene
+// rather than hanging it
+const MAX_STEPS: u64 = 100_000_000;
+const MAX_CALL_DEPTH: usize = 64;
+// Expressions and blocks being evaluated at once, across all function calls. Each function body
+// is limited to MAX_NESTING by the parser, but recursion stacks them up.
+const MAX_EVAL_DEPTH: usize = 4 * MAX_NESTING;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+	Unit,
+	Bool(bool),
+	Int(i64),
+	Float(f64),
+	Str(String),
+	Array(Vec<Value>),
+	Map(BTreeMap<String, Value>),
+}
+
+impl Value {
+	fn type_name(&self) -> &'static str {
+		match self {
+			Value::Unit => "nothing",
+			Value::Bool(_) => "a bool",
+			Value::Int(_) => "an integer",
+			Value::Float(_) => "a float",
+			Value::Str(_) => "a string",
+			Value::Array(_) => "an array",
+			Value::Map(_) => "a map",
+		}
+	}
+
+	fn number(&self) -> Option<f64> {
+		match *self {
+			Value::Int(value) => Some(value as f64),
+			Value::Float(value) => Some(value),
+			_ => None,
+		}
+	}
+}
+
+impl fmt::Display for Value {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Value::Unit => write!(f, "()"),
+			Value::Bool(value) => write!(f, "{}", value),
+			Value::Int(value) => write!(f, "{}", value),
+			Value::Float(value) => write!(f, "{}", value),
+			Value::Str(text) => write!(f, "{}", text),
+			Value::Array(items) => {
+				let items: Vec<String> = items.iter().map(Value::to_string).collect();
+				write!(f, "[{}]", items.join(", "))
+			}
+			Value::Map(entries) => {
+				let entries: Vec<String> = entries
+					.iter()
+					.map(|(key, value)| format!("{}: {}", key, value))
+					.collect();
+				write!(f, "#{{{}}}", entries.join(", "))
+			}
+		}
+	}
+}
+
+// How a block was left
+enum Flow {
+	Normal,
+	Break,
+	Continue,
+	Return(Value),
+}
+
+pub struct Interpreter {
+	functions: HashMap<String, Function>,
+	// Read by every function, but never assigned to
+	globals: HashMap<String, Value>,
+	pub objects: Vec<Value>,
+	rng: SmallRng,
+	steps: u64,
+	depth: usize,
+	nesting: usize,
+	line: usize,
+}
+
+fn expect_args(name: &str, args: &[Value], count: usize) -> Result<(), String> {
+	if args.len() != count {
+		return Err(format!(
+			"{} takes {} argument{}, got {}",
+			name,
+			count,
+			if count == 1 { "" } else { "s" },
+			args.len()
+		));
+	}
+	Ok(())
+}
+
+fn numbers<const N: usize>(name: &str, args: &[Value]) -> Result<[f64; N], String> {
+	expect_args(name, args, N)?;
+	let mut numbers = [0.0; N];
+	for (number, arg) in numbers.iter_mut().zip(args) {
+		*number = arg
+			.number()
+			.ok_or_else(|| format!("{} expects numbers, got {}", name, arg.type_name()))?;
+	}
+	Ok(numbers)
+}
+
+fn integer(value: &Value, what: &str) -> Result<i64, String> {
+	match *value {
+		Value::Int(value) => Ok(value),
+		_ => Err(format!(
+			"{} must be an integer, not {}",
+			what,
+			value.type_name()
+		)),
+	}
+}
+
+fn arithmetic(operator: &str, left: Value, right: Value) -> Result<Value, String> {
+	match (operator, &left, &right) {
+		("+", Value::Str(_), _) | ("+", _, Value::Str(_)) => {
+			return Ok(Value::Str(format!("{}{}", left, right)))
+		}
+		("+", Value::Array(_), Value::Array(_)) => {
+			let (Value::Array(mut left), Value::Array(right)) = (left, right) else {
+				unreachable!()
+			};
+			left.extend(right);
+			return Ok(Value::Array(left));
+		}
+		_ => {}
+	}
+
+	if let (Value::Int(a), Value::Int(b)) = (&left, &right) {
+		let result = match operator {
+			"+" => a.checked_add(*b),
+			"-" => a.checked_sub(*b),
+			"*" => a.checked_mul(*b),
+			"%" if *b == 0 => return Err(String::from("remainder of a division by zero")),
+			"%" => a.checked_rem_euclid(*b),
+			// Integers are divided like floats, `i / n` is rarely meant to round down
+			_ => None,
+		};
+		if operator != "/" {
+			return result
+				.map(Value::Int)
+				.ok_or_else(|| String::from("integer overflow"));
+		}
+	}
+
+	let (Some(a), Some(b)) = (left.number(), right.number()) else {
+		return Err(format!(
+			"cannot apply '{}' to {} and {}",
+			operator,
+			left.type_name(),
+			right.type_name()
+		));
+	};
+	Ok(Value::Float(match operator {
+		"+" => a + b,
+		"-" => a - b,
+		"*" => a * b,
+		"/" => a / b,
+		_ => a.rem_euclid(b),
+	}))
+}
+
+fn compare(operator: &str, left: &Value, right: &Value) -> Result<Value, String> {
+	if let (Some(a), Some(b)) = (left.number(), right.number()) {
+		return Ok(Value::Bool(match operator {
+			"==" => a == b,
+			"!=" => a != b,
+			"<" => a < b,
+			"<=" => a <= b,
+			">" => a > b,
+			_ => a >= b,
+		}));
+	}
+
+	match operator {
+		"==" => Ok(Value::Bool(left == right)),
+		"!=" => Ok(Value::Bool(left != right)),
+		_ => match (left, right) {
+			(Value::Str(a), Value::Str(b)) => Ok(Value::Bool(match operator {
+				"<" => a < b,
+				"<=" => a <= b,
+				">" => a > b,
+				_ => a >= b,
+			})),
+			_ => Err(format!(
+				"cannot compare {} and {}",
+				left.type_name(),
+				right.type_name()
+			)),
+		},
+	}
+}
+
+// The element or field `access` refers to within `value`, for assignments
+fn element<'a>(value: &'a mut Value, access: &Value) -> Result<&'a mut Value, String> {
+	match (value, access) {
+		(Value::Array(items), Value::Int(index)) => {
+			let len = items.len();
+			usize::try_from(*index)
+				.ok()
+				.and_then(|index| items.get_mut(index))
+				.ok_or_else(|| format!("index {} is out of bounds for {} elements", index, len))
+		}
+		(Value::Map(entries), Value::Str(key)) => {
+			Ok(entries.entry(key.clone()).or_insert(Value::Unit))
+		}
+		(value, access) => Err(format!(
+			"cannot index {} with {}",
+			value.type_name(),
+			access.type_name()
+		)),
+	}
+}
+
+fn index(value: Value, access: &Value) -> Result<Value, String> {
+	match (value, access) {
+		(Value::Array(mut items), Value::Int(index)) => {
+			let len = items.len();
+			usize::try_from(*index)
+				.ok()
+				.filter(|&index| index < len)
+				.map(|index| items.swap_remove(index))
+				.ok_or_else(|| format!("index {} is out of bounds for {} elements", index, len))
+		}
+		(Value::Map(mut entries), Value::Str(key)) => entries
+			.remove(key)
+			.ok_or_else(|| format!("the map has no key '{}'", key)),
+		(value, access) => Err(format!(
+			"cannot index {} with {}",
+			value.type_name(),
+			access.type_name()
+		)),
+	}
+}
+
+impl Interpreter {
+	pub fn new(globals: HashMap<String, Value>, seed: u64) -> Interpreter {
+		Interpreter {
+			functions: HashMap::new(),
+			globals,
+			objects: Vec::new(),
+			rng: SmallRng::seed_from_u64(seed),
+			steps: 0,
+			depth: 0,
+			nesting: 0,
+			line: 0,
+		}
+	}
+
+	fn error(&self, message: String) -> ScriptError {
+		ScriptError {
+			line: self.line,
+			message,
+		}
+	}
+
+	fn nested<T>(
+		&mut self,
+		run: impl FnOnce(&mut Interpreter) -> Result<T, ScriptError>,
+	) -> Result<T, ScriptError> {
+		if self.nesting == MAX_EVAL_DEPTH {
+			return Err(self.error(format!(
+				"expressions and blocks can only be nested {} deep, counting function calls",
+				MAX_EVAL_DEPTH
+			)));
+		}
+		self.nesting += 1;
+		let result = run(self);
+		self.nesting -= 1;
+		result
+	}
+
+	fn step(&mut self) -> Result<(), ScriptError> {
+		self.steps += 1;
+		if self.steps > MAX_STEPS {
+			return Err(self.error(format!(
+				"the script did not finish within {} steps",
+				MAX_STEPS
+			)));
+		}
+		Ok(())
+	}
+
+	// Runs the top level of a script. Functions can be called before they are defined.
+	pub fn run(&mut self, statements: &[Statement]) -> Result<(), ScriptError> {
+		for statement in statements {
+			if let Kind::Function(name, function) = &statement.kind {
+				self.functions.insert(name.clone(), function.clone());
+			}
+		}
+
+		let mut scopes = vec![HashMap::new()];
+		match self.block(statements, &mut scopes)? {
+			Flow::Normal | Flow::Return(_) => Ok(()),
+			Flow::Break | Flow::Continue => {
+				Err(self.error(String::from("break and continue only work in loops")))
+			}
+		}
+	}
+
+	fn block(
+		&mut self,
+		statements: &[Statement],
+		scopes: &mut Vec<HashMap<String, Value>>,
+	) -> Result<Flow, ScriptError> {
+		self.nested(|interpreter| interpreter.statements(statements, scopes))
+	}
+
+	fn statements(
+		&mut self,
+		statements: &[Statement],
+		scopes: &mut Vec<HashMap<String, Value>>,
+	) -> Result<Flow, ScriptError> {
+		scopes.push(HashMap::new());
+		let mut flow = Flow::Normal;
+		for statement in statements {
+			flow = self.statement(statement, scopes)?;
+			if !matches!(flow, Flow::Normal) {
+				break;
+			}
+		}
+		scopes.pop();
+		Ok(flow)
+	}
+
+	fn statement(
+		&mut self,
+		statement: &Statement,
+		scopes: &mut Vec<HashMap<String, Value>>,
+	) -> Result<Flow, ScriptError> {
+		self.line = statement.line;
+		self.step()?;
+
+		match &statement.kind {
+			Kind::Let(name, value) => {
+				let value = self.eval(value, scopes)?;
+				scopes.last_mut().unwrap().insert(name.clone(), value);
+			}
+			Kind::Assign(target, operator, value) => {
+				let value = self.eval(value, scopes)?;
+				self.assign(target, *operator, value, scopes)?;
+			}
+			Kind::If(branches, otherwise) => {
+				for (condition, body) in branches {
+					if self.condition(condition, scopes)? {
+						return self.block(body, scopes);
+					}
+				}
+				return self.block(otherwise, scopes);
+			}
+			Kind::For(name, iterable, body) => {
+				let items: Box<dyn Iterator<Item = Value>> = match iterable {
+					Iterable::Range {
+						start,
+						end,
+						inclusive,
+					} => {
+						let start = self.eval(start, scopes)?;
+						let end = self.eval(end, scopes)?;
+						let start =
+							integer(&start, "the start of a range").map_err(|m| self.error(m))?;
+						let end = integer(&end, "the end of a range").map_err(|m| self.error(m))?;
+						if *inclusive {
+							Box::new((start..=end).map(Value::Int))
+						} else {
+							Box::new((start..end).map(Value::Int))
+						}
+					}
+					Iterable::Array(array) => match self.eval(array, scopes)? {
+						Value::Array(items) => Box::new(items.into_iter()),
+						other => {
+							return Err(self.error(format!(
+								"for loops go over ranges or arrays, not {}",
+								other.type_name()
+							)))
+						}
+					},
+				};
+
+				for item in items {
+					self.step()?;
+					scopes.push(HashMap::from([(name.clone(), item)]));
+					let flow = self.block(body, scopes);
+					scopes.pop();
+					match flow? {
+						Flow::Break => break,
+						Flow::Return(value) => return Ok(Flow::Return(value)),
+						Flow::Normal | Flow::Continue => {}
+					}
+				}
+			}
+			Kind::While(condition, body) => {
+				while self.condition(condition, scopes)? {
+					self.step()?;
+					match self.block(body, scopes)? {
+						Flow::Break => break,
+						Flow::Return(value) => return Ok(Flow::Return(value)),
+						Flow::Normal | Flow::Continue => {}
+					}
+				}
+			}
+			Kind::Break => return Ok(Flow::Break),
+			Kind::Continue => return Ok(Flow::Continue),
+			Kind::Return(value) => return Ok(Flow::Return(self.eval(value, scopes)?)),
+			Kind::Function(..) if self.depth > 0 || scopes.len() > 2 => {
+				return Err(self.error(String::from(
+					"functions can only be defined at the top level",
+				)))
+			}
+			Kind::Function(..) => {}
+			Kind::Expr(expr) => {
+				self.eval(expr, scopes)?;
+			}
+		}
+
+		Ok(Flow::Normal)
+	}
+
+	fn condition(
+		&mut self,
+		condition: &Expr,
+		scopes: &mut Vec<HashMap<String, Value>>,
+	) -> Result<bool, ScriptError> {
+		match self.eval(condition, scopes)? {
+			Value::Bool(value) => Ok(value),
+			other => Err(self.error(format!(
+				"conditions must be bools, not {}",
+				other.type_name()
+			))),
+		}
+	}
+
+	fn assign(
+		&mut self,
+		target: &Target,
+		operator: Option<&'static str>,
+		value: Value,
+		scopes: &mut Vec<HashMap<String, Value>>,
+	) -> Result<(), ScriptError> {
+		let mut path = Vec::new();
+		for access in &target.path {
+			path.push(match access {
+				Access::Index(index) => self.eval(index, scopes)?,
+				Access::Field(field) => Value::Str(field.clone()),
+			});
+		}
+
+		let Some(variable) = scopes
+			.iter_mut()
+			.rev()
+			.find_map(|scope| scope.get_mut(&target.name))
+		else {
+			return Err(self.error(format!(
+				"unknown variable '{}', declare it with let first",
+				target.name
+			)));
+		};
+
+		let mut place = variable;
+		for access in &path {
+			place = element(place, access).map_err(|message| ScriptError {
+				line: self.line,
+				message,
+			})?;
+		}
+
+		*place = match operator {
+			Some(operator) => arithmetic(operator, std::mem::replace(place, Value::Unit), value)
+				.map_err(|message| ScriptError {
+					line: self.line,
+					message,
+				})?,
+			None => value,
+		};
+		Ok(())
+	}
+
+	fn eval(
+		&mut self,
+		expr: &Expr,
+		scopes: &mut Vec<HashMap<String, Value>>,
+	) -> Result<Value, ScriptError> {
+		self.nested(|interpreter| interpreter.evaluate(expr, scopes))
+	}
+
+	fn evaluate(
+		&mut self,
+		expr: &Expr,
+		scopes: &mut Vec<HashMap<String, Value>>,
+	) -> Result<Value, ScriptError> {
+		let value = match expr {
+			Expr::Unit => Value::Unit,
+			Expr::Bool(value) => Value::Bool(*value),
+			Expr::Int(value) => Value::Int(*value),
+			Expr::Float(value) => Value::Float(*value),
+			Expr::Str(text) => Value::Str(text.clone()),
+			Expr::Array(items) => Value::Array(
+				items
+					.iter()
+					.map(|item| self.eval(item, scopes))
+					.collect::<Result<_, _>>()?,
+			),
+			Expr::Map(entries) => {
+				let mut map = BTreeMap::new();
+				for (key, value) in entries {
+					map.insert(key.clone(), self.eval(value, scopes)?);
+				}
+				Value::Map(map)
+			}
+			Expr::Var(name) => scopes
+				.iter()
+				.rev()
+				.find_map(|scope| scope.get(name))
+				.or_else(|| self.globals.get(name))
+				.cloned()
+				.or_else(|| (name == "PI").then_some(Value::Float(std::f64::consts::PI)))
+				.ok_or_else(|| self.error(format!("unknown variable '{}'", name)))?,
+			Expr::Call(name, args) => {
+				let args: Vec<Value> = args
+					.iter()
+					.map(|arg| self.eval(arg, scopes))
+					.collect::<Result<_, _>>()?;
+				self.call(name, args)?
+			}
+			Expr::Index(value, access) => {
+				let value = self.eval(value, scopes)?;
+				let access = self.eval(access, scopes)?;
+				index(value, &access).map_err(|message| self.error(message))?
+			}
+			Expr::Field(value, field) => {
+				let value = self.eval(value, scopes)?;
+				index(value, &Value::Str(field.clone())).map_err(|message| self.error(message))?
+			}
+			Expr::Unary(operator, value) => match (*operator, self.eval(value, scopes)?) {
+				("-", Value::Int(value)) => Value::Int(
+					value
+						.checked_neg()
+						.ok_or_else(|| self.error(String::from("integer overflow")))?,
+				),
+				("-", Value::Float(value)) => Value::Float(-value),
+				("!", Value::Bool(value)) => Value::Bool(!value),
+				(operator, value) => {
+					return Err(self.error(format!(
+						"cannot apply '{}' to {}",
+						operator,
+						value.type_name()
+					)))
+				}
+			},
+			Expr::Binary(operator @ ("&&" | "||"), left, right) => {
+				let left = self.condition(left, scopes)?;
+				// The right side is only evaluated if it decides the result
+				if left == (*operator == "||") {
+					Value::Bool(left)
+				} else {
+					Value::Bool(self.condition(right, scopes)?)
+				}
+			}
+			Expr::Binary(operator, left, right) => {
+				let left = self.eval(left, scopes)?;
+				let right = self.eval(right, scopes)?;
+				match *operator {
+					"==" | "!=" | "<" | "<=" | ">" | ">=" => compare(operator, &left, &right),
+					_ => arithmetic(operator, left, right),
+				}
+				.map_err(|message| self.error(message))?
+			}
+		};
+		Ok(value)
+	}
+
+	fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value, ScriptError> {
+		if let Some(function) = self.functions.get(name).cloned() {
+			if args.len() != function.parameters.len() {
+				return Err(self.error(format!(
+					"{} takes {} arguments, got {}",
+					name,
+					function.parameters.len(),
+					args.len()
+				)));
+			}
+			if self.depth == MAX_CALL_DEPTH {
+				return Err(self.error(format!(
+					"functions can only be nested {} calls deep",
+					MAX_CALL_DEPTH
+				)));
+			}
+
+			// Functions only see their arguments and the globals
+			let line = self.line;
+			self.depth += 1;
+			let arguments = function.parameters.iter().cloned().zip(args).collect();
+			let flow = self.block(&function.body, &mut vec![arguments]);
+			self.depth -= 1;
+			self.line = line;
+
+			return match flow? {
+				Flow::Return(value) => Ok(value),
+				Flow::Normal => Ok(Value::Unit),
+				Flow::Break | Flow::Continue => {
+					Err(self.error(String::from("break and continue only work in loops")))
+				}
+			};
+		}
+
+		self.builtin(name, args)
+			.map_err(|message| self.error(message))
+	}
+
+	fn builtin(&mut self, name: &str, args: Vec<Value>) -> Result<Value, String> {
+		let float = |value: f64| Ok(Value::Float(value));
+		match name {
+			"emit" => {
+				expect_args(name, &args, 1)?;
+				let Some(object @ Value::Map(_)) = args.into_iter().next() else {
+					return Err(String::from("emit expects a map describing an object"));
+				};
+				self.objects.push(object);
+				Ok(Value::Unit)
+			}
+			"print" => {
+				let text: Vec<String> = args.iter().map(Value::to_string).collect();
+				log::info!("{}", text.join(" "));
+				Ok(Value::Unit)
+			}
+			"rand" => match args.len() {
+				0 => float(self.rng.gen::<f64>()),
+				_ => {
+					let [min, max] = numbers(name, &args)?;
+					float(min + (max - min) * self.rng.gen::<f64>())
+				}
+			},
+			"rand_int" => {
+				expect_args(name, &args, 2)?;
+				let min = integer(&args[0], "the minimum of rand_int")?;
+				let max = integer(&args[1], "the maximum of rand_int")?;
+				if min > max {
+					return Err(String::from(
+						"rand_int needs a minimum no larger than the maximum",
+					));
+				}
+				Ok(Value::Int(self.rng.gen_range(min..=max)))
+			}
+			"len" => {
+				expect_args(name, &args, 1)?;
+				match &args[0] {
+					Value::Array(items) => Ok(Value::Int(items.len() as i64)),
+					Value::Map(entries) => Ok(Value::Int(entries.len() as i64)),
+					Value::Str(text) => Ok(Value::Int(text.chars().count() as i64)),
+					other => Err(format!("{} has no length", other.type_name())),
+				}
+			}
+			"int" => {
+				let [value] = numbers(name, &args)?;
+				Ok(Value::Int(value as i64))
+			}
+			"float" => {
+				let [value] = numbers(name, &args)?;
+				float(value)
+			}
+			"floor" | "ceil" | "round" | "abs" | "sqrt" | "sin" | "cos" | "tan" | "exp" | "ln" => {
+				let [value] = numbers(name, &args)?;
+				float(match name {
+					"floor" => value.floor(),
+					"ceil" => value.ceil(),
+					"round" => value.round(),
+					"abs" => value.abs(),
+					"sqrt" => value.sqrt(),
+					"sin" => value.to_radians().sin(),
+					"cos" => value.to_radians().cos(),
+					"tan" => value.to_radians().tan(),
+					"exp" => value.exp(),
+					_ => value.ln(),
+				})
+			}
+			"atan2" | "pow" | "min" | "max" => {
+				let [a, b] = numbers(name, &args)?;
+				float(match name {
+					"atan2" => a.atan2(b).to_degrees(),
+					"pow" => a.powf(b),
+					"min" => a.min(b),
+					_ => a.max(b),
+				})
+			}
+			"clamp" | "lerp" => {
+				let [a, b, c] = numbers(name, &args)?;
+				float(match name {
+					"clamp" => a.clamp(b, c.max(b)),
+					_ => a + (b - a) * c,
+				})
+			}
+			_ => Err(format!("unknown function '{}'", name)),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::script::{lexer, parser, to_toml};
+
+	fn run(source: &str) -> Result<Interpreter, ScriptError> {
+		let statements = parser::parse(lexer::tokenize(source)?)?;
+		let mut interpreter = Interpreter::new(HashMap::new(), 0);
+		interpreter.run(&statements)?;
+		Ok(interpreter)
+	}
+
+	// The values the script emitted with `emit(#{ value: ... })`
+	fn emitted(source: &str) -> Vec<Value> {
+		let interpreter =
+			run(source).unwrap_or_else(|err| panic!("line {}: {}", err.line, err.message));
+		interpreter
+			.objects
+			.into_iter()
+			.map(|object| match object {
+				Value::Map(mut entries) => entries.remove("value").unwrap(),
+				_ => unreachable!(),
+			})
+			.collect()
+	}
+
+	fn error(source: &str) -> String {
+		match run(source) {
+			Ok(_) => panic!("the script ran without an error"),
+			Err(err) => err.message,
+		}
+	}
+
+	#[test]
+	fn arithmetic() {
+		assert_eq!(
+			emitted(
+				"emit(#{ value: 1 + 2 * 3 }); emit(#{ value: 7 / 2 }); \
+				 emit(#{ value: -7 % 3 }); emit(#{ value: \"a\" + 1 });"
+			),
+			[
+				Value::Int(7),
+				Value::Float(3.5),
+				Value::Int(2),
+				Value::Str(String::from("a1")),
+			]
+		);
+	}
+
+	#[test]
+	fn integer_overflow() {
+		assert_eq!(
+			error("let a = 9223372036854775807 + 1;"),
+			"integer overflow"
+		);
+		assert_eq!(
+			error("let a = -9223372036854775807 - 1; let b = a % -1;"),
+			"integer overflow"
+		);
+		assert_eq!(
+			error("let a = -9223372036854775807 - 1; let b = -a;"),
+			"integer overflow"
+		);
+		assert_eq!(error("let a = 1 % 0;"), "remainder of a division by zero");
+	}
+
+	#[test]
+	fn scoping() {
+		assert_eq!(
+			emitted("let a = 1; if true { let a = 2; emit(#{ value: a }); } emit(#{ value: a });"),
+			[Value::Int(2), Value::Int(1)]
+		);
+		assert_eq!(
+			error("if true { let a = 1; } emit(#{ value: a });"),
+			"unknown variable 'a'"
+		);
+		// Functions only see their arguments and the globals, not the caller's variables
+		assert_eq!(
+			error("fn f() { return a; } let a = 1; f();"),
+			"unknown variable 'a'"
+		);
+	}
+
+	#[test]
+	fn assignments_copy() {
+		assert_eq!(
+			emitted(
+				"let a = [1, #{ x: 2 }]; let b = a; b[0] = 3; b[1].x += 1; \
+				 emit(#{ value: a }); emit(#{ value: b });"
+			),
+			[
+				Value::Array(vec![
+					Value::Int(1),
+					Value::Map(BTreeMap::from([(String::from("x"), Value::Int(2))])),
+				]),
+				Value::Array(vec![
+					Value::Int(3),
+					Value::Map(BTreeMap::from([(String::from("x"), Value::Int(3))])),
+				]),
+			]
+		);
+	}
+
+	#[test]
+	fn break_and_continue() {
+		assert_eq!(
+			emitted(
+				"for i in 0..10 { if i % 2 == 0 { continue; } if i > 5 { break; } \
+				 emit(#{ value: i }); }"
+			),
+			[Value::Int(1), Value::Int(3), Value::Int(5)]
+		);
+		assert_eq!(
+			emitted("let i = 0; while true { i += 1; if i == 3 { break; } } emit(#{ value: i });"),
+			[Value::Int(3)]
+		);
+		assert_eq!(error("break;"), "break and continue only work in loops");
+	}
+
+	#[test]
+	fn step_limit() {
+		let statements = parser::parse(lexer::tokenize("while true {}").unwrap()).unwrap();
+		let mut interpreter = Interpreter::new(HashMap::new(), 0);
+		interpreter.steps = MAX_STEPS - 1000;
+		let err = interpreter.run(&statements).err().unwrap();
+		assert!(err.message.contains("did not finish"), "{}", err.message);
+	}
+
+	#[test]
+	fn nesting_limits() {
+		let nested = |depth: usize| format!("let a = {}1{};", "(".repeat(depth), ")".repeat(depth));
+		assert!(run(&nested(MAX_NESTING - 10)).is_ok());
+		assert!(error(&nested(100_000)).contains("nested"));
+
+		let chained = format!("let a = 1{};", " + 1".repeat(100_000));
+		assert!(error(&chained).contains("nested"));
+
+		let blocks = format!("{}{}", "if true { ".repeat(100_000), "}".repeat(100_000));
+		assert!(error(&blocks).contains("nested"));
+
+		assert!(error("fn f(n) { return f(n + 1); } f(0);").contains("deep"));
+	}
+
+	// The deepest scripts the limits allow still fit in the 2 MiB stack of a server thread
+	#[test]
+	fn deepest_scripts_fit_in_a_thread_stack() {
+		let source = format!(
+			"fn f(n) {{ if n > 0 {{ return {}f(n - 1){}; }} return 0; }} f(1000);",
+			"(".repeat(MAX_NESTING - 8),
+			")".repeat(MAX_NESTING - 8)
+		);
+		let result = std::thread::Builder::new()
+			.stack_size(2 * 1024 * 1024)
+			.spawn(move || run(&source).err().map(|err| err.message))
+			.unwrap()
+			.join()
+			.unwrap();
+		assert!(result.unwrap().contains("deep"));
+	}
+
+	#[test]
+	fn emitted_objects_convert_to_toml() {
+		let interpreter = run(
+			"for i in 0..2 { emit(#{ type: \"sphere\", center: [i, 0.5, 0], radius: 0.5, \
+			 visible: true }); }",
+		)
+		.ok()
+		.unwrap();
+		let objects: Vec<toml::Value> = interpreter
+			.objects
+			.into_iter()
+			.map(|object| to_toml(object).unwrap())
+			.collect();
+
+		assert_eq!(
+			objects[1],
+			toml::Value::Table(
+				toml::from_str(
+					"type = \"sphere\"\ncenter = [1, 0.5, 0]\nradius = 0.5\nvisible = true"
+				)
+				.unwrap()
+			)
+		);
+		assert!(to_toml(Value::Map(BTreeMap::from([(
+			String::from("a"),
+			Value::Unit
+		)])))
+		.is_err());
+		assert_eq!(error("emit(1);"), "emit expects a map describing an object");
+	}
+}
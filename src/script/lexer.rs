@@ -0,0 +1,134 @@
+use crate::script::ScriptError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+	Int(i64),
+	Float(f64),
+	Str(String),
+	// Names and keywords
+	Ident(String),
+	Symbol(&'static str),
+	End,
+}
+
+// Longer symbols first, so that `..=` is not read as `..` followed by `=`
+const SYMBOLS: [&str; 32] = [
+	"..=", "..", "==", "!=", "<=", ">=", "&&", "||", "+=", "-=", "*=", "/=", "#{", "+", "-", "*",
+	"/", "%", "<", ">", "=", "!", "(", ")", "[", "]", "{", "}", ",", ";", ":", ".",
+];
+
+// Splits the source into tokens with the line each starts on, ending with Token::End
+pub fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, ScriptError> {
+	let chars: Vec<char> = source.chars().collect();
+	let mut tokens = Vec::new();
+	let mut line = 1;
+	let mut i = 0;
+
+	while i < chars.len() {
+		let c = chars[i];
+		if c == '\n' {
+			line += 1;
+			i += 1;
+		} else if c.is_whitespace() {
+			i += 1;
+		} else if c == '/' && chars.get(i + 1) == Some(&'/') {
+			while i < chars.len() && chars[i] != '\n' {
+				i += 1;
+			}
+		} else if c.is_ascii_digit() {
+			let start = i;
+			let mut float = false;
+			while i < chars.len() && chars[i].is_ascii_digit() {
+				i += 1;
+			}
+			// A dot only continues the number if a digit follows, `0..10` is a range
+			if chars.get(i) == Some(&'.') && chars.get(i + 1).is_some_and(char::is_ascii_digit) {
+				float = true;
+				i += 1;
+				while i < chars.len() && chars[i].is_ascii_digit() {
+					i += 1;
+				}
+			}
+			if matches!(chars.get(i), Some('e' | 'E')) {
+				let sign = matches!(chars.get(i + 1), Some('+' | '-')) as usize;
+				if chars.get(i + 1 + sign).is_some_and(char::is_ascii_digit) {
+					float = true;
+					i += 1 + sign;
+					while i < chars.len() && chars[i].is_ascii_digit() {
+						i += 1;
+					}
+				}
+			}
+
+			let text: String = chars[start..i].iter().collect();
+			let token = if float {
+				text.parse().map(Token::Float).ok()
+			} else {
+				text.parse().map(Token::Int).ok()
+			};
+			let token = token.ok_or_else(|| ScriptError {
+				line,
+				message: format!("invalid number {}", text),
+			})?;
+			tokens.push((token, line));
+		} else if c.is_alphabetic() || c == '_' {
+			let start = i;
+			while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+				i += 1;
+			}
+			tokens.push((Token::Ident(chars[start..i].iter().collect()), line));
+		} else if c == '"' {
+			let start = line;
+			let mut text = String::new();
+			i += 1;
+			loop {
+				match chars.get(i) {
+					None => {
+						return Err(ScriptError {
+							line: start,
+							message: String::from("unterminated string"),
+						})
+					}
+					Some('"') => break,
+					Some('\\') => {
+						text.push(match chars.get(i + 1) {
+							Some('n') => '\n',
+							Some('t') => '\t',
+							Some(&other) => other,
+							None => '\\',
+						});
+						i += 2;
+						continue;
+					}
+					Some(&other) => {
+						if other == '\n' {
+							line += 1;
+						}
+						text.push(other);
+					}
+				}
+				i += 1;
+			}
+			i += 1;
+			tokens.push((Token::Str(text), start));
+		} else {
+			let symbol = SYMBOLS.iter().find(|symbol| {
+				symbol
+					.chars()
+					.enumerate()
+					.all(|(offset, s)| chars.get(i + offset) == Some(&s))
+			});
+			let Some(&symbol) = symbol else {
+				return Err(ScriptError {
+					line,
+					message: format!("unexpected character '{}'", c),
+				});
+			};
+			tokens.push((Token::Symbol(symbol), line));
+			i += symbol.len();
+		}
+	}
+
+	tokens.push((Token::End, line));
+	Ok(tokens)
+}
@@ -0,0 +1,85 @@
+mod interpreter;
+mod lexer;
+mod parser;
+
+use std::collections::HashMap;
+
+use crate::error::{RendererError, Result};
+use crate::script::interpreter::{Interpreter, Value};
+
+// A small scripting language for generating scene objects, see the README
+#[derive(Debug)]
+pub struct ScriptError {
+	pub line: usize,
+	pub message: String,
+}
+
+fn from_toml(value: &toml::Value) -> Value {
+	match value {
+		toml::Value::String(text) => Value::Str(text.clone()),
+		toml::Value::Integer(value) => Value::Int(*value),
+		toml::Value::Float(value) => Value::Float(*value),
+		toml::Value::Boolean(value) => Value::Bool(*value),
+		toml::Value::Datetime(value) => Value::Str(value.to_string()),
+		toml::Value::Array(items) => Value::Array(items.iter().map(from_toml).collect()),
+		toml::Value::Table(entries) => Value::Map(
+			entries
+				.iter()
+				.map(|(key, value)| (key.clone(), from_toml(value)))
+				.collect(),
+		),
+	}
+}
+
+fn to_toml(value: Value) -> std::result::Result<toml::Value, String> {
+	Ok(match value {
+		Value::Unit => return Err(String::from("objects cannot contain nothing, ()")),
+		Value::Bool(value) => toml::Value::Boolean(value),
+		Value::Int(value) => toml::Value::Integer(value),
+		Value::Float(value) => toml::Value::Float(value),
+		Value::Str(text) => toml::Value::String(text),
+		Value::Array(items) => toml::Value::Array(
+			items
+				.into_iter()
+				.map(to_toml)
+				.collect::<std::result::Result<_, _>>()?,
+		),
+		Value::Map(entries) => toml::Value::Table(
+			entries
+				.into_iter()
+				.map(|(key, value)| Ok((key, to_toml(value)?)))
+				.collect::<std::result::Result<_, String>>()?,
+		),
+	})
+}
+
+// Runs a script with `params` as read-only variables and returns the objects it emitted as TOML
+// tables, in the order they were emitted. `name` is the file the errors are reported for. The
+// random numbers of the script only depend on `seed`.
+pub fn run(name: &str, source: &str, params: &toml::Table, seed: u64) -> Result<Vec<toml::Value>> {
+	let error = |err: ScriptError| RendererError::Scene {
+		path: Some(name.to_string()),
+		message: format!("line {}: {}", err.line, err.message),
+	};
+
+	let statements = parser::parse(lexer::tokenize(source).map_err(error)?).map_err(error)?;
+	let globals: HashMap<String, Value> = params
+		.iter()
+		.map(|(key, value)| (key.clone(), from_toml(value)))
+		.collect();
+
+	let mut interpreter = Interpreter::new(globals, seed);
+	interpreter.run(&statements).map_err(error)?;
+
+	interpreter
+		.objects
+		.into_iter()
+		.enumerate()
+		.map(|(index, object)| {
+			to_toml(object).map_err(|message| RendererError::Scene {
+				path: Some(name.to_string()),
+				message: format!("emitted object {}: {}", index, message),
+			})
+		})
+		.collect()
+}
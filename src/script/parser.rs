@@ -0,0 +1,477 @@
+use crate::script::lexer::Token;
+use crate::script::ScriptError;
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+	Unit,
+	Bool(bool),
+	Int(i64),
+	Float(f64),
+	Str(String),
+	Array(Vec<Expr>),
+	Map(Vec<(String, Expr)>),
+	Var(String),
+	Call(String, Vec<Expr>),
+	Index(Box<Expr>, Box<Expr>),
+	Field(Box<Expr>, String),
+	Unary(&'static str, Box<Expr>),
+	Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+// Where an assignment stores its value: a variable, or an element or field within one
+#[derive(Debug, Clone)]
+pub enum Access {
+	Index(Expr),
+	Field(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Target {
+	pub name: String,
+	pub path: Vec<Access>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Iterable {
+	Range {
+		start: Expr,
+		end: Expr,
+		inclusive: bool,
+	},
+	Array(Expr),
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+	pub parameters: Vec<String>,
+	pub body: Vec<Statement>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Kind {
+	Let(String, Expr),
+	// The operator of a compound assignment like `+=`, None for `=`
+	Assign(Target, Option<&'static str>, Expr),
+	If(Vec<(Expr, Vec<Statement>)>, Vec<Statement>),
+	For(String, Iterable, Vec<Statement>),
+	While(Expr, Vec<Statement>),
+	Break,
+	Continue,
+	Return(Expr),
+	Function(String, Function),
+	Expr(Expr),
+}
+
+#[derive(Debug, Clone)]
+pub struct Statement {
+	pub kind: Kind,
+	pub line: usize,
+}
+
+const KEYWORDS: [&str; 12] = [
+	"let", "if", "else", "for", "in", "while", "break", "continue", "return", "fn", "true", "false",
+];
+
+// Binary operators from the loosest to the tightest binding
+const PRECEDENCE: [&[&str]; 5] = [
+	&["||"],
+	&["&&"],
+	&["==", "!=", "<", "<=", ">", ">="],
+	&["+", "-"],
+	&["*", "/", "%"],
+];
+
+// Parentheses, blocks and chains of operators a script may nest, so that parsing and running it
+// stays well within the stack of a server thread
+pub const MAX_NESTING: usize = 128;
+
+struct Parser {
+	tokens: Vec<(Token, usize)>,
+	position: usize,
+	depth: usize,
+}
+
+impl Parser {
+	fn peek(&self) -> &Token {
+		&self.tokens[self.position].0
+	}
+
+	fn line(&self) -> usize {
+		self.tokens[self.position].1
+	}
+
+	fn advance(&mut self) -> Token {
+		let token = self.tokens[self.position].0.clone();
+		if token != Token::End {
+			self.position += 1;
+		}
+		token
+	}
+
+	fn error<T>(&self, message: String) -> Result<T, ScriptError> {
+		Err(ScriptError {
+			line: self.line(),
+			message,
+		})
+	}
+
+	fn unexpected<T>(&self, expected: &str) -> Result<T, ScriptError> {
+		let found = match self.peek() {
+			Token::Int(value) => value.to_string(),
+			Token::Float(value) => value.to_string(),
+			Token::Str(text) => format!("\"{}\"", text),
+			Token::Ident(name) => name.clone(),
+			Token::Symbol(symbol) => format!("'{}'", symbol),
+			Token::End => String::from("the end of the script"),
+		};
+		self.error(format!("expected {}, found {}", expected, found))
+	}
+
+	fn is_symbol(&self, symbol: &str) -> bool {
+		matches!(self.peek(), Token::Symbol(s) if *s == symbol)
+	}
+
+	fn is_keyword(&self, keyword: &str) -> bool {
+		matches!(self.peek(), Token::Ident(name) if name == keyword)
+	}
+
+	fn eat(&mut self, symbol: &str) -> bool {
+		let found = self.is_symbol(symbol);
+		if found {
+			self.advance();
+		}
+		found
+	}
+
+	fn expect(&mut self, symbol: &str) -> Result<(), ScriptError> {
+		if !self.eat(symbol) {
+			return self.unexpected(&format!("'{}'", symbol));
+		}
+		Ok(())
+	}
+
+	fn name(&mut self) -> Result<String, ScriptError> {
+		match self.peek() {
+			Token::Ident(name) if !KEYWORDS.contains(&name.as_str()) => {
+				let name = name.clone();
+				self.advance();
+				Ok(name)
+			}
+			_ => self.unexpected("a name"),
+		}
+	}
+
+	fn enter(&mut self) -> Result<(), ScriptError> {
+		if self.depth == MAX_NESTING {
+			return self.error(format!(
+				"expressions and blocks can only be nested {} deep",
+				MAX_NESTING
+			));
+		}
+		self.depth += 1;
+		Ok(())
+	}
+
+	fn nested<T>(
+		&mut self,
+		parse: impl FnOnce(&mut Parser) -> Result<T, ScriptError>,
+	) -> Result<T, ScriptError> {
+		self.enter()?;
+		let result = parse(self);
+		self.depth -= 1;
+		result
+	}
+
+	fn block(&mut self) -> Result<Vec<Statement>, ScriptError> {
+		self.nested(Parser::statements)
+	}
+
+	fn statements(&mut self) -> Result<Vec<Statement>, ScriptError> {
+		self.expect("{")?;
+		let mut statements = Vec::new();
+		while !self.eat("}") {
+			if *self.peek() == Token::End {
+				return self.unexpected("'}'");
+			}
+			if let Some(statement) = self.statement()? {
+				statements.push(statement);
+			}
+		}
+		Ok(statements)
+	}
+
+	// None for an empty statement, a lone `;`
+	fn statement(&mut self) -> Result<Option<Statement>, ScriptError> {
+		let line = self.line();
+		if self.eat(";") {
+			return Ok(None);
+		}
+
+		let keyword = match self.peek() {
+			Token::Ident(name) => name.clone(),
+			_ => String::new(),
+		};
+		let kind = match keyword.as_str() {
+			"let" => {
+				self.advance();
+				let name = self.name()?;
+				self.expect("=")?;
+				let value = self.expression()?;
+				self.expect(";")?;
+				Kind::Let(name, value)
+			}
+			"if" => {
+				let mut branches = Vec::new();
+				let mut otherwise = Vec::new();
+				loop {
+					self.advance();
+					let condition = self.expression()?;
+					branches.push((condition, self.block()?));
+					if !self.is_keyword("else") {
+						break;
+					}
+					self.advance();
+					if !self.is_keyword("if") {
+						otherwise = self.block()?;
+						break;
+					}
+				}
+				Kind::If(branches, otherwise)
+			}
+			"for" => {
+				self.advance();
+				let name = self.name()?;
+				if !self.is_keyword("in") {
+					return self.unexpected("'in'");
+				}
+				self.advance();
+				let start = self.expression()?;
+				let iterable = if self.is_symbol("..") || self.is_symbol("..=") {
+					let inclusive = self.advance() == Token::Symbol("..=");
+					Iterable::Range {
+						start,
+						end: self.expression()?,
+						inclusive,
+					}
+				} else {
+					Iterable::Array(start)
+				};
+				Kind::For(name, iterable, self.block()?)
+			}
+			"while" => {
+				self.advance();
+				let condition = self.expression()?;
+				Kind::While(condition, self.block()?)
+			}
+			"break" | "continue" => {
+				self.advance();
+				self.expect(";")?;
+				if keyword == "break" {
+					Kind::Break
+				} else {
+					Kind::Continue
+				}
+			}
+			"return" => {
+				self.advance();
+				let value = if self.is_symbol(";") {
+					Expr::Unit
+				} else {
+					self.expression()?
+				};
+				self.expect(";")?;
+				Kind::Return(value)
+			}
+			"fn" => {
+				self.advance();
+				let name = self.name()?;
+				self.expect("(")?;
+				let mut parameters = Vec::new();
+				while !self.eat(")") {
+					parameters.push(self.name()?);
+					if !self.is_symbol(")") {
+						self.expect(",")?;
+					}
+				}
+				let body = self.block()?;
+				Kind::Function(name, Function { parameters, body })
+			}
+			_ => {
+				let expr = self.expression()?;
+				let operator: Option<Option<&'static str>> = match *self.peek() {
+					Token::Symbol("=") => Some(None),
+					Token::Symbol(symbol @ ("+=" | "-=" | "*=" | "/=")) => Some(Some(&symbol[..1])),
+					_ => None,
+				};
+				let kind = match operator {
+					Some(operator) => {
+						self.advance();
+						let target = target(expr).ok_or_else(|| ScriptError {
+							line,
+							message: String::from(
+								"only variables, elements and fields can be assigned to",
+							),
+						})?;
+						Kind::Assign(target, operator, self.expression()?)
+					}
+					None => Kind::Expr(expr),
+				};
+				self.expect(";")?;
+				kind
+			}
+		};
+
+		Ok(Some(Statement { kind, line }))
+	}
+
+	fn expression(&mut self) -> Result<Expr, ScriptError> {
+		self.nested(|parser| parser.binary(0))
+	}
+
+	fn binary(&mut self, level: usize) -> Result<Expr, ScriptError> {
+		if level == PRECEDENCE.len() {
+			return self.unary();
+		}
+
+		// Every operator in a chain nests the expression on its left one deeper
+		let depth = self.depth;
+		let mut left = self.binary(level + 1)?;
+		while let Token::Symbol(symbol) = *self.peek() {
+			if !PRECEDENCE[level].contains(&symbol) {
+				break;
+			}
+			self.enter()?;
+			self.advance();
+			let right = self.binary(level + 1)?;
+			left = Expr::Binary(symbol, Box::new(left), Box::new(right));
+		}
+		self.depth = depth;
+		Ok(left)
+	}
+
+	fn unary(&mut self) -> Result<Expr, ScriptError> {
+		for operator in ["-", "!"] {
+			if self.eat(operator) {
+				return Ok(Expr::Unary(operator, Box::new(self.nested(Parser::unary)?)));
+			}
+		}
+		self.postfix()
+	}
+
+	fn postfix(&mut self) -> Result<Expr, ScriptError> {
+		let depth = self.depth;
+		let mut expr = self.primary()?;
+		loop {
+			if self.is_symbol("[") || self.is_symbol(".") {
+				self.enter()?;
+			}
+			if self.eat("[") {
+				let index = self.expression()?;
+				self.expect("]")?;
+				expr = Expr::Index(Box::new(expr), Box::new(index));
+			} else if self.eat(".") {
+				expr = Expr::Field(Box::new(expr), self.name()?);
+			} else {
+				self.depth = depth;
+				return Ok(expr);
+			}
+		}
+	}
+
+	// Comma separated expressions up to `end`, which may follow a trailing comma
+	fn list(&mut self, end: &str) -> Result<Vec<Expr>, ScriptError> {
+		let mut items = Vec::new();
+		while !self.eat(end) {
+			items.push(self.expression()?);
+			if !self.is_symbol(end) {
+				self.expect(",")?;
+			}
+		}
+		Ok(items)
+	}
+
+	fn primary(&mut self) -> Result<Expr, ScriptError> {
+		let expr = match self.peek().clone() {
+			Token::Int(value) => Expr::Int(value),
+			Token::Float(value) => Expr::Float(value),
+			Token::Str(text) => Expr::Str(text),
+			Token::Ident(name) if name == "true" => Expr::Bool(true),
+			Token::Ident(name) if name == "false" => Expr::Bool(false),
+			Token::Symbol("(") => {
+				self.advance();
+				let expr = self.expression()?;
+				self.expect(")")?;
+				return Ok(expr);
+			}
+			Token::Symbol("[") => {
+				self.advance();
+				return Ok(Expr::Array(self.list("]")?));
+			}
+			Token::Symbol("#{") => {
+				self.advance();
+				let mut entries = Vec::new();
+				while !self.eat("}") {
+					let key = match self.peek().clone() {
+						Token::Str(key) => {
+							self.advance();
+							key
+						}
+						_ => self.name()?,
+					};
+					self.expect(":")?;
+					entries.push((key, self.expression()?));
+					if !self.is_symbol("}") {
+						self.expect(",")?;
+					}
+				}
+				return Ok(Expr::Map(entries));
+			}
+			Token::Ident(_) => {
+				let name = self.name()?;
+				if self.eat("(") {
+					return Ok(Expr::Call(name, self.list(")")?));
+				}
+				return Ok(Expr::Var(name));
+			}
+			_ => return self.unexpected("an expression"),
+		};
+		self.advance();
+		Ok(expr)
+	}
+}
+
+fn target(expr: Expr) -> Option<Target> {
+	match expr {
+		Expr::Var(name) => Some(Target {
+			name,
+			path: Vec::new(),
+		}),
+		Expr::Index(inner, index) => {
+			let mut target = target(*inner)?;
+			target.path.push(Access::Index(*index));
+			Some(target)
+		}
+		Expr::Field(inner, field) => {
+			let mut target = target(*inner)?;
+			target.path.push(Access::Field(field));
+			Some(target)
+		}
+		_ => None,
+	}
+}
+
+pub fn parse(tokens: Vec<(Token, usize)>) -> Result<Vec<Statement>, ScriptError> {
+	let mut parser = Parser {
+		tokens,
+		position: 0,
+		depth: 0,
+	};
+
+	let mut statements = Vec::new();
+	while *parser.peek() != Token::End {
+		if let Some(statement) = parser.statement()? {
+			statements.push(statement);
+		}
+	}
+	Ok(statements)
+}
@@ -0,0 +1,400 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, BufReader, Cursor, Read, Take, Write};
+use std::net::{TcpListener, TcpStream};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use image::ImageOutputFormat;
+
+use raytracer::error::{RendererError, Result};
+use raytracer::render::{render, Progress, RenderSettings};
+use raytracer::scene_file::SceneFile;
+
+// HTTP API:
+//   POST /jobs            submit a TOML scene file as the request body, returns the job id
+//   GET  /jobs            status of every job
+//   GET  /jobs/{id}       status and progress of a single job
+//   GET  /jobs/{id}/image the finished render as PNG
+
+enum JobStatus {
+	Queued,
+	Rendering,
+	Finished(Vec<u8>),
+	Failed(String),
+}
+
+struct Job {
+	status: JobStatus,
+	completed_rows: Arc<AtomicU32>,
+	// The rows of the scaled render region, known once the render reports its first progress
+	total_rows: Arc<AtomicU32>,
+}
+
+impl Job {
+	fn is_done(&self) -> bool {
+		matches!(self.status, JobStatus::Finished(_) | JobStatus::Failed(_))
+	}
+
+	fn to_json(&self, id: usize) -> String {
+		let total = self.total_rows.load(Ordering::Relaxed).max(1);
+		let progress = self.completed_rows.load(Ordering::Relaxed) as f32 / total as f32;
+
+		let status = match &self.status {
+			JobStatus::Queued => String::from("\"queued\""),
+			JobStatus::Rendering => String::from("\"rendering\""),
+			JobStatus::Finished(_) => String::from("\"finished\""),
+			JobStatus::Failed(error) => format!("\"failed\",\"error\":{}", json_string(error)),
+		};
+
+		format!(
+			"{{\"id\":{},\"status\":{},\"progress\":{:.3}}}",
+			id, status, progress
+		)
+	}
+}
+
+// Jobs by id. Ids count up from 0 and are never reused, also after old jobs were dropped.
+#[derive(Default)]
+struct JobList {
+	jobs: BTreeMap<usize, Job>,
+	next_id: usize,
+}
+
+impl JobList {
+	fn push(&mut self, job: Job) -> usize {
+		let id = self.next_id;
+		self.jobs.insert(id, job);
+		self.next_id += 1;
+		id
+	}
+
+	// Drops the oldest finished or failed jobs with their images beyond MAX_DONE_JOBS
+	fn prune(&mut self) {
+		let done: Vec<usize> = self
+			.jobs
+			.iter()
+			.filter(|(_, job)| job.is_done())
+			.map(|(&id, _)| id)
+			.collect();
+
+		for id in done.iter().take(done.len().saturating_sub(MAX_DONE_JOBS)) {
+			self.jobs.remove(id);
+		}
+	}
+}
+
+type Jobs = Arc<Mutex<JobList>>;
+
+// Scene files are a few KB, anything much larger is refused before it is read into memory
+const MAX_BODY_SIZE: u64 = 16 * 1024 * 1024;
+// The request line and the headers together
+const MAX_HEADER_SIZE: u64 = 64 * 1024;
+// Clients that send nothing for this long are dropped, so idle connections do not hold threads
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+// Finished and failed jobs kept around for their status and image
+const MAX_DONE_JOBS: usize = 64;
+// Largest image a job may render, after its scale
+const MAX_PIXELS: u64 = 8192 * 8192;
+
+struct Request {
+	method: String,
+	path: String,
+	body: Vec<u8>,
+}
+
+struct Response {
+	status: &'static str,
+	content_type: &'static str,
+	body: Vec<u8>,
+}
+
+impl Response {
+	fn json(status: &'static str, body: String) -> Response {
+		Response {
+			status,
+			content_type: "application/json",
+			body: body.into_bytes(),
+		}
+	}
+
+	fn error(status: &'static str, message: &str) -> Response {
+		Response::json(status, format!("{{\"error\":{}}}", json_string(message)))
+	}
+}
+
+fn json_string(value: &str) -> String {
+	let mut escaped = String::from("\"");
+
+	for c in value.chars() {
+		match c {
+			'"' => escaped.push_str("\\\""),
+			'\\' => escaped.push_str("\\\\"),
+			'\n' => escaped.push_str("\\n"),
+			c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+			c => escaped.push(c),
+		}
+	}
+
+	escaped.push('"');
+	escaped
+}
+
+// Reads a line of the request head. A line without its newline was cut off by the end of the
+// stream or by the limit on the size of the head.
+fn read_line(
+	reader: &mut BufReader<Take<&TcpStream>>,
+	line: &mut String,
+) -> std::result::Result<(), Response> {
+	match reader.read_line(line) {
+		Ok(_) if line.ends_with('\n') => Ok(()),
+		Ok(_) if reader.get_ref().limit() == 0 => Err(Response::error(
+			"431 Request Header Fields Too Large",
+			"the request line and headers are limited to 64 KiB",
+		)),
+		_ => Err(Response::error("400 Bad Request", "malformed request")),
+	}
+}
+
+fn read_request(stream: &TcpStream) -> std::result::Result<Request, Response> {
+	let malformed = || Response::error("400 Bad Request", "malformed request");
+	let mut reader = BufReader::new(stream.take(MAX_HEADER_SIZE));
+
+	let mut request_line = String::new();
+	read_line(&mut reader, &mut request_line)?;
+
+	let mut parts = request_line.split_whitespace();
+	let method = parts.next().ok_or_else(malformed)?.to_string();
+	let path = parts.next().ok_or_else(malformed)?.to_string();
+
+	let mut headers = HashMap::new();
+	loop {
+		let mut line = String::new();
+		read_line(&mut reader, &mut line)?;
+
+		let line = line.trim_end();
+		if line.is_empty() {
+			break;
+		}
+
+		if let Some((name, value)) = line.split_once(':') {
+			headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+		}
+	}
+
+	let length: u64 = headers
+		.get("content-length")
+		.and_then(|length| length.parse().ok())
+		.unwrap_or(0);
+
+	if length > MAX_BODY_SIZE {
+		return Err(Response::error(
+			"413 Payload Too Large",
+			"scene files are limited to 16 MiB",
+		));
+	}
+
+	// Part of the body may already be buffered, the limit only counts what is still unread
+	reader.get_mut().set_limit(length);
+	let mut body = Vec::new();
+	reader
+		.take(length)
+		.read_to_end(&mut body)
+		.map_err(|_| malformed())?;
+
+	if body.len() as u64 != length {
+		return Err(malformed());
+	}
+
+	Ok(Request { method, path, body })
+}
+
+fn write_response(mut stream: TcpStream, response: Response) {
+	let header = format!(
+		"HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+		response.status,
+		response.content_type,
+		response.body.len()
+	);
+
+	// The client may already have hung up, there is nobody left to report the error to
+	let _ = stream
+		.write_all(header.as_bytes())
+		.and_then(|_| stream.write_all(&response.body));
+}
+
+fn submit(jobs: &Jobs, queue: &Sender<(usize, SceneFile)>, body: &[u8]) -> Response {
+	let text = match std::str::from_utf8(body) {
+		Ok(text) => text,
+		Err(_) => return Response::error("400 Bad Request", "scene file is not valid UTF-8"),
+	};
+
+	let scene = match SceneFile::parse(text) {
+		Ok(scene) => scene,
+		Err(err) => return Response::error("400 Bad Request", &err.to_string()),
+	};
+
+	let id = jobs.lock().unwrap().push(Job {
+		status: JobStatus::Queued,
+		completed_rows: Arc::new(AtomicU32::new(0)),
+		total_rows: Arc::new(AtomicU32::new(scene.render.height)),
+	});
+
+	queue.send((id, scene)).unwrap();
+
+	Response::json("201 Created", format!("{{\"id\":{}}}", id))
+}
+
+fn route(jobs: &Jobs, queue: &Sender<(usize, SceneFile)>, request: Request) -> Response {
+	let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+
+	match (request.method.as_str(), segments.as_slice()) {
+		("POST", ["jobs"]) => submit(jobs, queue, &request.body),
+		("GET", ["jobs"]) => {
+			let jobs = jobs.lock().unwrap();
+			let list: Vec<String> = jobs.jobs.iter().map(|(&id, job)| job.to_json(id)).collect();
+			Response::json("200 OK", format!("[{}]", list.join(",")))
+		}
+		("GET", ["jobs", id]) => {
+			let jobs = jobs.lock().unwrap();
+			let status = id
+				.parse::<usize>()
+				.ok()
+				.and_then(|id| jobs.jobs.get(&id).map(|job| job.to_json(id)));
+
+			match status {
+				Some(status) => Response::json("200 OK", status),
+				None => Response::error("404 Not Found", "no such job"),
+			}
+		}
+		("GET", ["jobs", id, "image"]) => {
+			let jobs = jobs.lock().unwrap();
+			match id.parse::<usize>().ok().and_then(|id| jobs.jobs.get(&id)) {
+				Some(Job {
+					status: JobStatus::Finished(png),
+					..
+				}) => Response {
+					status: "200 OK",
+					content_type: "image/png",
+					body: png.clone(),
+				},
+				Some(_) => Response::error("409 Conflict", "job has not finished"),
+				None => Response::error("404 Not Found", "no such job"),
+			}
+		}
+		_ => Response::error("404 Not Found", "unknown endpoint"),
+	}
+}
+
+fn set_status(jobs: &Jobs, id: usize, status: JobStatus) {
+	let mut jobs = jobs.lock().unwrap();
+	if let Some(job) = jobs.jobs.get_mut(&id) {
+		job.status = status;
+	}
+	jobs.prune();
+}
+
+// Jobs are rendered one at a time, each one using every worker thread
+fn run_job(jobs: &Jobs, id: usize, scene: SceneFile, base: RenderSettings) {
+	set_status(jobs, id, JobStatus::Rendering);
+
+	let (completed_rows, total_rows) = {
+		let jobs = jobs.lock().unwrap();
+		let job = &jobs.jobs[&id];
+		(Arc::clone(&job.completed_rows), Arc::clone(&job.total_rows))
+	};
+
+	let result = catch_unwind(AssertUnwindSafe(|| -> Result<Vec<u8>> {
+		let settings = scene.settings(base)?.scaled();
+		if settings.width as u64 * settings.height as u64 > MAX_PIXELS {
+			return Err(RendererError::Scene {
+				path: None,
+				message: format!(
+					"{}x{} is larger than the {} pixels the server renders",
+					settings.width, settings.height, MAX_PIXELS
+				),
+			});
+		}
+
+		let (world, camera) = scene.build()?;
+		let output = render(
+			Arc::new(world),
+			Arc::new(camera.resized(settings.width, settings.height)),
+			scene.integrator(&settings)?,
+			&settings,
+			&scene.post_process()?,
+			Arc::new(move |progress: Progress| {
+				total_rows.store(progress.total, Ordering::Relaxed);
+				completed_rows.store(progress.completed, Ordering::Relaxed);
+			}),
+			None,
+			None,
+		)?;
+
+		let mut png = Vec::new();
+		let mut cursor = Cursor::new(&mut png);
+		let written = if output.is_opaque() {
+			output.image.write_to(&mut cursor, ImageOutputFormat::Png)
+		} else {
+			output
+				.rgba_image()
+				.write_to(&mut cursor, ImageOutputFormat::Png)
+		};
+
+		written.map_err(|source| RendererError::Image {
+			path: String::from("<response>"),
+			source,
+		})?;
+
+		Ok(png)
+	}));
+
+	let status = match result {
+		Ok(Ok(png)) => JobStatus::Finished(png),
+		Ok(Err(err)) => JobStatus::Failed(err.to_string()),
+		Err(_) => JobStatus::Failed(String::from("render panicked, see the server log")),
+	};
+
+	set_status(jobs, id, status);
+}
+
+// Jobs are rendered with `base` and the render settings of their scene files
+pub fn serve(address: &str, base: RenderSettings) -> Result<()> {
+	let listener = TcpListener::bind(address).map_err(|source| RendererError::Io {
+		path: address.to_string(),
+		source,
+	})?;
+	log::info!("Listening on {}", address);
+
+	let jobs: Jobs = Arc::new(Mutex::new(JobList::default()));
+	let (queue, pending) = channel::<(usize, SceneFile)>();
+
+	let worker_jobs = Arc::clone(&jobs);
+	std::thread::spawn(move || {
+		for (id, scene) in pending {
+			run_job(&worker_jobs, id, scene, base);
+		}
+	});
+
+	for stream in listener.incoming().flatten() {
+		let jobs = Arc::clone(&jobs);
+		let queue = queue.clone();
+
+		std::thread::spawn(move || {
+			if stream.set_read_timeout(Some(READ_TIMEOUT)).is_err() {
+				return;
+			}
+
+			let response = match read_request(&stream) {
+				Ok(request) => route(&jobs, &queue, request),
+				Err(response) => response,
+			};
+
+			write_response(stream, response);
+		});
+	}
+
+	Ok(())
+}
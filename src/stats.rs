@@ -0,0 +1,151 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// Ray counters are kept per thread and only added to the shared totals once a worker finishes
+// a row, so the hot path never touches a contended cache line
+thread_local! {
+	static RAYS: Cell<u64> = const { Cell::new(0) };
+	static BOUNCES: Cell<u64> = const { Cell::new(0) };
+	static NODES: Cell<u64> = const { Cell::new(0) };
+	static PRIMITIVES: Cell<u64> = const { Cell::new(0) };
+}
+
+static TOTAL_RAYS: AtomicU64 = AtomicU64::new(0);
+static TOTAL_BOUNCES: AtomicU64 = AtomicU64::new(0);
+static TOTAL_PATHS: AtomicU64 = AtomicU64::new(0);
+static TRIANGLES: AtomicU64 = AtomicU64::new(0);
+static BVH_BUILD_NANOS: AtomicU64 = AtomicU64::new(0);
+static BVH_NODES: AtomicU64 = AtomicU64::new(0);
+static GEOMETRY_BYTES: AtomicU64 = AtomicU64::new(0);
+static TEXTURE_BYTES: AtomicU64 = AtomicU64::new(0);
+
+pub fn count_ray() {
+	RAYS.with(|rays| rays.set(rays.get() + 1));
+}
+
+pub fn count_bounce() {
+	BOUNCES.with(|bounces| bounces.set(bounces.get() + 1));
+}
+
+// Bounces counted on the calling thread since its last flush
+pub fn pending_bounces() -> u64 {
+	BOUNCES.with(|bounces| bounces.get())
+}
+
+pub fn count_node() {
+	NODES.with(|nodes| nodes.set(nodes.get() + 1));
+}
+
+pub fn count_primitive() {
+	PRIMITIVES.with(|primitives| primitives.set(primitives.get() + 1));
+}
+
+// BVH nodes and primitives tested on the calling thread so far. These are never flushed, the
+// heatmap view takes the difference around a single ray.
+pub fn traversal() -> (u64, u64) {
+	(
+		NODES.with(|nodes| nodes.get()),
+		PRIMITIVES.with(|primitives| primitives.get()),
+	)
+}
+
+// Adds the calling thread's counters to the totals, `paths` is the number of camera rays it
+// started since the last flush
+pub fn flush(paths: u64) {
+	TOTAL_RAYS.fetch_add(RAYS.with(|rays| rays.replace(0)), Ordering::Relaxed);
+	TOTAL_BOUNCES.fetch_add(
+		BOUNCES.with(|bounces| bounces.replace(0)),
+		Ordering::Relaxed,
+	);
+	TOTAL_PATHS.fetch_add(paths, Ordering::Relaxed);
+}
+
+// Rays traced on all threads, as far as they were flushed
+pub fn rays() -> u64 {
+	TOTAL_RAYS.load(Ordering::Relaxed)
+}
+
+pub fn add_mesh(triangles: usize, bvh_build: Duration) {
+	TRIANGLES.fetch_add(triangles as u64, Ordering::Relaxed);
+	BVH_BUILD_NANOS.fetch_add(bvh_build.as_nanos() as u64, Ordering::Relaxed);
+}
+
+// Nodes of an acceleration structure and the bytes it takes up with the primitives it keeps
+pub fn add_bvh(nodes: usize, bytes: usize) {
+	BVH_NODES.fetch_add(nodes as u64, Ordering::Relaxed);
+	GEOMETRY_BYTES.fetch_add(bytes as u64, Ordering::Relaxed);
+}
+
+// Geometry data outside of BVHs, like voxel grids
+pub fn add_geometry(bytes: usize) {
+	GEOMETRY_BYTES.fetch_add(bytes as u64, Ordering::Relaxed);
+}
+
+pub fn add_texture(bytes: usize) {
+	TEXTURE_BYTES.fetch_add(bytes as u64, Ordering::Relaxed);
+}
+
+pub struct RenderStats {
+	pub triangles: u64,
+	pub bvh_build: Duration,
+	pub bvh_nodes: u64,
+	pub geometry_bytes: u64,
+	pub texture_bytes: u64,
+	pub render_time: Duration,
+	pub rays: u64,
+	pub paths: u64,
+	pub bounces: u64,
+}
+
+impl RenderStats {
+	// Everything counted so far in this process, `render_time` is measured by the caller
+	pub fn collect(render_time: Duration) -> RenderStats {
+		RenderStats {
+			triangles: TRIANGLES.load(Ordering::Relaxed),
+			bvh_build: Duration::from_nanos(BVH_BUILD_NANOS.load(Ordering::Relaxed)),
+			bvh_nodes: BVH_NODES.load(Ordering::Relaxed),
+			geometry_bytes: GEOMETRY_BYTES.load(Ordering::Relaxed),
+			texture_bytes: TEXTURE_BYTES.load(Ordering::Relaxed),
+			render_time,
+			rays: TOTAL_RAYS.load(Ordering::Relaxed),
+			paths: TOTAL_PATHS.load(Ordering::Relaxed),
+			bounces: TOTAL_BOUNCES.load(Ordering::Relaxed),
+		}
+	}
+
+	pub fn rays_per_second(&self) -> f64 {
+		self.rays as f64 / self.render_time.as_secs_f64().max(1e-9)
+	}
+
+	pub fn average_depth(&self) -> f64 {
+		self.bounces as f64 / self.paths.max(1) as f64
+	}
+
+	// Estimate of what the scene keeps in memory, without the image being rendered and the
+	// objects not counted anywhere, which are small next to meshes and textures
+	pub fn memory_bytes(&self) -> u64 {
+		self.geometry_bytes + self.texture_bytes
+	}
+
+	pub fn to_json(&self) -> String {
+		format!(
+			concat!(
+				"{{\"triangles\":{},\"bvh_build_ms\":{:.3},\"bvh_nodes\":{},",
+				"\"geometry_bytes\":{},\"texture_bytes\":{},\"memory_bytes\":{},\"render_ms\":{:.3},",
+				"\"rays\":{},\"paths\":{},\"rays_per_second\":{:.0},\"average_depth\":{:.3}}}\n"
+			),
+			self.triangles,
+			self.bvh_build.as_secs_f64() * 1000.0,
+			self.bvh_nodes,
+			self.geometry_bytes,
+			self.texture_bytes,
+			self.memory_bytes(),
+			self.render_time.as_secs_f64() * 1000.0,
+			self.rays,
+			self.paths,
+			self.rays_per_second(),
+			self.average_depth(),
+		)
+	}
+}
@@ -0,0 +1,114 @@
+use std::io::{self, Write};
+use std::net::TcpStream;
+use std::sync::Mutex;
+
+use raytracer::geometry::Vec3f;
+use raytracer::render::Region;
+
+// Packet types of the tev IPC protocol
+const CREATE_IMAGE: u8 = 4;
+const UPDATE_IMAGE: u8 = 5;
+
+const CHANNELS: [&str; 3] = ["R", "G", "B"];
+
+// Streams parts of the render to a running tev (https://github.com/Tom94/tev) over TCP. Every
+// packet is its length as u32 including the length itself, a type byte and the payload, all
+// little endian with null terminated strings.
+pub struct TevClient {
+	name: String,
+	// Dropped after the first failed write, so a closed viewer does not stop the render
+	stream: Mutex<Option<TcpStream>>,
+}
+
+struct Packet {
+	bytes: Vec<u8>,
+}
+
+impl Packet {
+	fn new(kind: u8) -> Packet {
+		let mut bytes = vec![0; 4];
+		bytes.push(kind);
+		Packet { bytes }
+	}
+
+	fn bool(mut self, value: bool) -> Packet {
+		self.bytes.push(value as u8);
+		self
+	}
+
+	fn string(mut self, value: &str) -> Packet {
+		self.bytes.extend_from_slice(value.as_bytes());
+		self.bytes.push(0);
+		self
+	}
+
+	fn int(mut self, value: u32) -> Packet {
+		self.bytes.extend_from_slice(&(value as i32).to_le_bytes());
+		self
+	}
+
+	fn float(mut self, value: f32) -> Packet {
+		self.bytes.extend_from_slice(&value.to_le_bytes());
+		self
+	}
+
+	fn finish(mut self) -> Vec<u8> {
+		let length = self.bytes.len() as u32;
+		self.bytes[..4].copy_from_slice(&length.to_le_bytes());
+		self.bytes
+	}
+}
+
+impl TevClient {
+	// Connects to tev at `address` (tev listens on 127.0.0.1:14158 by default) and opens an
+	// empty image called `name`
+	pub fn connect(address: &str, name: &str, width: u32, height: u32) -> io::Result<TevClient> {
+		let mut stream = TcpStream::connect(address)?;
+
+		let mut packet = Packet::new(CREATE_IMAGE)
+			.bool(true)
+			.string(name)
+			.int(width)
+			.int(height)
+			.int(CHANNELS.len() as u32);
+		for channel in CHANNELS {
+			packet = packet.string(channel);
+		}
+		stream.write_all(&packet.finish())?;
+
+		Ok(TevClient {
+			name: name.to_string(),
+			stream: Mutex::new(Some(stream)),
+		})
+	}
+
+	// Replaces `region` of the image with `colors`, given row by row
+	#[allow(clippy::unnecessary_cast)]
+	pub fn update(&self, region: Region, colors: &[Vec3f]) {
+		let mut stream = self.stream.lock().unwrap();
+		let Some(connection) = stream.as_mut() else {
+			return;
+		};
+
+		for (index, channel) in CHANNELS.iter().enumerate() {
+			let mut packet = Packet::new(UPDATE_IMAGE)
+				.bool(false)
+				.string(&self.name)
+				.string(channel)
+				.int(region.x)
+				.int(region.y)
+				.int(region.width)
+				.int(region.height);
+			for color in colors {
+				let value = [color.x, color.y, color.z][index];
+				packet = packet.float(value as f32);
+			}
+
+			if let Err(err) = connection.write_all(&packet.finish()) {
+				log::warn!("Lost the connection to tev, stopping the preview: {}", err);
+				*stream = None;
+				return;
+			}
+		}
+	}
+}
@@ -0,0 +1,51 @@
+use image::RgbImage;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+// Streams raw RGB frames into an ffmpeg process, so animations don't need intermediate PNGs
+pub struct VideoWriter {
+	ffmpeg: Child,
+	stdin: ChildStdin,
+}
+
+impl VideoWriter {
+	pub fn new(path: &str, width: u32, height: u32, fps: u32) -> io::Result<VideoWriter> {
+		let codec: &[&str] = match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+			Some("webm") => &["-c:v", "libvpx-vp9", "-b:v", "0", "-crf", "30"],
+			_ => &["-c:v", "libx264", "-pix_fmt", "yuv420p", "-crf", "18"],
+		};
+
+		let mut ffmpeg = Command::new("ffmpeg")
+			.args(["-y", "-loglevel", "error"])
+			.args(["-f", "rawvideo", "-pix_fmt", "rgb24"])
+			.args(["-s", &format!("{}x{}", width, height)])
+			.args(["-r", &fps.to_string()])
+			.args(["-i", "-"])
+			.args(codec)
+			.arg(path)
+			.stdin(Stdio::piped())
+			.spawn()?;
+
+		let stdin = ffmpeg.stdin.take().unwrap();
+
+		Ok(VideoWriter { ffmpeg, stdin })
+	}
+
+	pub fn write_frame(&mut self, frame: &RgbImage) -> io::Result<()> {
+		self.stdin.write_all(frame.as_raw())
+	}
+
+	// Closes the pipe so ffmpeg can finalize the file, then waits for it to exit
+	pub fn finish(self) -> io::Result<()> {
+		let VideoWriter { mut ffmpeg, stdin } = self;
+		drop(stdin);
+
+		let status = ffmpeg.wait()?;
+		if status.success() {
+			Ok(())
+		} else {
+			Err(io::Error::other(format!("ffmpeg exited with {}", status)))
+		}
+	}
+}
@@ -0,0 +1,100 @@
+use std::cell::RefCell;
+use std::sync::Arc;
+
+use crate::camera::Camera;
+use crate::geometry::{Float, HittableGroup, Vec3f};
+use crate::integrator::{Integrator, PathTracer};
+use crate::random;
+use crate::scenes::scene_spheres;
+
+// The browser has no threads or file system, so the demo renders the procedural sphere scene
+// one sample per pixel per call and lets the page redraw the canvas between passes
+struct Preview {
+	world: HittableGroup,
+	camera: Arc<Camera>,
+	integrator: PathTracer,
+	width: u32,
+	height: u32,
+	passes: u32,
+	accumulated: Vec<Vec3f>,
+	pixels: Vec<u8>,
+}
+
+thread_local! {
+	static PREVIEW: RefCell<Option<Preview>> = const { RefCell::new(None) };
+}
+
+#[no_mangle]
+pub extern "C" fn init(width: u32, height: u32, seed: u32) {
+	random::seed(seed as u64);
+
+	let mut world = HittableGroup::new();
+	// The sphere scene loads no files, so this only fails if that changes
+	let Ok(camera) = scene_spheres(&mut world, width, height) else {
+		return;
+	};
+	let count = (width * height) as usize;
+
+	PREVIEW.with(|preview| {
+		*preview.borrow_mut() = Some(Preview {
+			world,
+			camera,
+			integrator: PathTracer::default(),
+			width,
+			height,
+			passes: 0,
+			accumulated: vec![Vec3f::new(0.0, 0.0, 0.0); count],
+			pixels: vec![255; count * 4],
+		});
+	});
+}
+
+// Adds one sample to every pixel and returns the number of completed passes
+#[no_mangle]
+pub extern "C" fn render_pass() -> u32 {
+	PREVIEW.with(|preview| {
+		let mut preview = preview.borrow_mut();
+		let Some(preview) = preview.as_mut() else {
+			return 0;
+		};
+
+		preview.passes += 1;
+		let scale = 1.0 / preview.passes as Float;
+
+		for y in 0..preview.height {
+			for x in 0..preview.width {
+				let index = (y * preview.width + x) as usize;
+
+				if let Some(ray) = preview.camera.intial_ray(x, y) {
+					let value = preview
+						.integrator
+						.li(
+							&ray,
+							&preview.world,
+							preview.camera.background,
+							preview.camera.spread(),
+							preview.camera.differentials(&ray),
+						)
+						.color;
+					preview.accumulated[index] += value;
+				}
+
+				let color = preview.accumulated[index] * scale;
+				preview.pixels[index * 4] = (color.x * 255.0) as u8;
+				preview.pixels[index * 4 + 1] = (color.y * 255.0) as u8;
+				preview.pixels[index * 4 + 2] = (color.z * 255.0) as u8;
+			}
+		}
+
+		preview.passes
+	})
+}
+
+// RGBA8 pixels of the current preview, valid until the next call to init
+#[no_mangle]
+pub extern "C" fn pixels() -> *const u8 {
+	PREVIEW.with(|preview| match preview.borrow().as_ref() {
+		Some(preview) => preview.pixels.as_ptr(),
+		None => std::ptr::null(),
+	})
+}
@@ -0,0 +1,190 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use raytracer::assets::Assets;
+use raytracer::error::{RendererError, Result};
+use raytracer::render::{render_progressive, CancelToken, PreviewCallback};
+use raytracer::scene_file::SceneFile;
+
+use crate::cli::Options;
+use crate::{
+	connect_tev, override_materials, post_process, render_path, render_settings, save_all,
+	update_progress, warn_rejected,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+fn modified(path: &str) -> Option<SystemTime> {
+	std::fs::metadata(path)
+		.and_then(|metadata| metadata.modified())
+		.ok()
+}
+
+// The script files a scene file runs, read without loading the scene so that they are also
+// watched while one of them fails
+fn script_paths(path: &str) -> Vec<String> {
+	let Some(scene) = std::fs::read_to_string(path)
+		.ok()
+		.and_then(|text| text.parse::<toml::Table>().ok())
+	else {
+		return Vec::new();
+	};
+
+	let scripts = scene.get("scripts").and_then(toml::Value::as_array);
+	scripts
+		.into_iter()
+		.flatten()
+		.filter_map(|script| script.get("path")?.as_str().map(String::from))
+		.collect()
+}
+
+// The scene file and its scripts as they were when they were last loaded, and the assets the
+// scene loaded
+struct Watched<'a> {
+	files: Vec<(String, Option<SystemTime>)>,
+	assets: &'a Assets,
+}
+
+impl Watched<'_> {
+	fn new<'a>(path: &str, assets: &'a Assets) -> Watched<'a> {
+		let mut paths = vec![path.to_string()];
+		paths.extend(script_paths(path));
+		Watched {
+			files: paths
+				.into_iter()
+				.map(|path| {
+					let time = modified(&path);
+					(path, time)
+				})
+				.collect(),
+			assets,
+		}
+	}
+
+	fn changed(&self) -> bool {
+		self.files
+			.iter()
+			.any(|(path, time)| modified(path) != *time)
+			|| !self.assets.changed().is_empty()
+	}
+}
+
+// The tev connection, made again when the size of the image changes
+struct Tev {
+	width: u32,
+	height: u32,
+	callback: PreviewCallback,
+}
+
+// Loads, builds and renders the scene file once. Returns false if it was changed before the
+// render finished.
+fn render_once(
+	options: &Options,
+	path: &str,
+	watched: &Watched,
+	tev: &mut Option<Tev>,
+) -> Result<bool> {
+	let scene_file = SceneFile::load(path)?;
+	let (scene, camera) = scene_file.build_with(watched.assets)?;
+	let scene = override_materials(options, scene);
+	let settings = render_settings(options, Some(&scene_file))?.scaled();
+	let camera = Arc::new(camera.resized(settings.width, settings.height));
+	let (width, height) = (settings.width, settings.height);
+	let integrator = match &options.integrator {
+		Some(integrator) => Arc::clone(integrator),
+		None => scene_file.integrator(&settings)?,
+	};
+
+	let preview = match &options.tev {
+		Some(address) => {
+			if !tev
+				.as_ref()
+				.is_some_and(|tev| tev.width == width && tev.height == height)
+			{
+				*tev = Some(Tev {
+					width,
+					height,
+					callback: connect_tev(address, width, height)?,
+				});
+			}
+			tev.as_ref().map(|tev| Arc::clone(&tev.callback))
+		}
+		None => None,
+	};
+
+	// Loaded before the watcher starts, which only stops once the render has finished
+	let post = post_process(options, Some(&scene_file))?;
+
+	println!("Rendering...");
+	let start = Instant::now();
+	let cancel = CancelToken::new();
+	let finished = AtomicBool::new(false);
+	let output = std::thread::scope(|scope| {
+		// Stops the render as soon as anything changes
+		scope.spawn(|| {
+			while !finished.load(Ordering::Relaxed) {
+				if watched.changed() {
+					cancel.cancel();
+					return;
+				}
+				std::thread::sleep(POLL_INTERVAL);
+			}
+		});
+
+		let output = render_progressive(
+			Arc::new(scene),
+			camera,
+			integrator,
+			&settings,
+			&post,
+			Arc::new(update_progress),
+			preview,
+			Some(&cancel),
+		);
+		finished.store(true, Ordering::Relaxed);
+		output
+	});
+	println!();
+
+	let output = match output {
+		Err(RendererError::Cancelled) => return Ok(false),
+		output => output?,
+	};
+	warn_rejected(&output);
+	log::info!("Rendered in {:.1?}", start.elapsed());
+	save_all(&settings, &output, &scene_file.object_names(), render_path)?;
+	Ok(true)
+}
+
+// Renders the scene file, then waits for it, its scripts or any of the images and meshes it uses
+// to be saved and starts over, also when that happens halfway through a render. The assets are
+// kept between renders, so only the files that changed are loaded again and editing materials in
+// the scene file reuses the BVHs of its meshes. Errors in the scene are logged and wait for the
+// next change. Runs until the process is killed.
+pub fn watch(options: &Options) -> Result<()> {
+	let path = options.scene_file.as_deref().unwrap_or_default();
+	let assets = Assets::default();
+	let mut tev = None;
+
+	loop {
+		for file in assets.refresh() {
+			log::info!("{} changed", file.display());
+		}
+
+		let watched = Watched::new(path, &assets);
+		match render_once(options, path, &watched, &mut tev) {
+			Ok(false) => {
+				log::info!("Changes found, starting over");
+				continue;
+			}
+			Ok(true) => {}
+			Err(err) => log::error!("{}", err),
+		}
+
+		log::info!("Waiting for changes to {} or its assets", path);
+		while !watched.changed() {
+			std::thread::sleep(POLL_INTERVAL);
+		}
+	}
+}
@@ -0,0 +1,124 @@
+// Renders small scenes with a fixed seed and compares them against the references in
+// tests/golden. After an intentional change to the output, regenerate them with
+//   UPDATE_GOLDEN=1 cargo test --test golden
+use image::RgbImage;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use raytracer::geometry::HittableGroup;
+use raytracer::integrator::PathTracer;
+use raytracer::random;
+use raytracer::render::{render, PostProcess, RenderSettings};
+use raytracer::scenes::{scene_cornell, scene_random_spheres, scene_spheres, scene_tank, Scene};
+
+const SIZE: u32 = 64;
+const SAMPLES: usize = 64;
+const THREADS: usize = 4;
+const BLOCK: u32 = 8;
+
+fn render_scene(scene: Scene) -> RgbImage {
+	random::seed(1);
+
+	let mut world = HittableGroup::new();
+	let camera = scene(&mut world, SIZE, SIZE).unwrap();
+
+	render(
+		Arc::new(world),
+		camera,
+		Arc::new(PathTracer::default()),
+		&RenderSettings {
+			width: SIZE,
+			height: SIZE,
+			samples: SAMPLES,
+			threads: THREADS,
+			..RenderSettings::default()
+		},
+		&PostProcess::default(),
+		Arc::new(|_| {}),
+		None,
+		None,
+	)
+	.unwrap()
+	.image
+}
+
+// Averages BLOCK x BLOCK pixel blocks, so the comparison sees shading and geometry rather than
+// the sample noise of individual pixels
+fn downsample(image: &RgbImage) -> Vec<f64> {
+	let (width, height) = image.dimensions();
+	let mut blocks = vec![0.0; ((width / BLOCK) * (height / BLOCK) * 3) as usize];
+
+	for (x, y, pixel) in image.enumerate_pixels() {
+		let block = ((y / BLOCK) * (width / BLOCK) + x / BLOCK) as usize * 3;
+		for channel in 0..3 {
+			blocks[block + channel] += pixel[channel] as f64 / 255.0 / (BLOCK * BLOCK) as f64;
+		}
+	}
+
+	blocks
+}
+
+// Root mean square error of the downsampled images, in 0..1
+fn rmse(a: &RgbImage, b: &RgbImage) -> f64 {
+	let a = downsample(a);
+	let b = downsample(b);
+	let sum: f64 = a.iter().zip(&b).map(|(a, b)| (a - b).powi(2)).sum();
+
+	(sum / a.len() as f64).sqrt()
+}
+
+// The thresholds sit above the difference between two seeds, which also covers the paths that
+// change between feature builds (f32, simd), with some margin for the noisier scenes
+fn check(name: &str, scene: Scene, threshold: f64) {
+	let image = render_scene(scene);
+	let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+		.join("tests/golden")
+		.join(format!("{}.png", name));
+
+	if std::env::var_os("UPDATE_GOLDEN").is_some() {
+		image.save(&path).unwrap();
+		return;
+	}
+
+	let reference = image::open(&path)
+		.unwrap_or_else(|err| panic!("{}: {}, run with UPDATE_GOLDEN=1", path.display(), err))
+		.to_rgb8();
+	assert_eq!(image.dimensions(), reference.dimensions());
+
+	let error = rmse(&image, &reference);
+	if error > threshold {
+		let actual = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.png", name));
+		image.save(&actual).unwrap();
+		panic!(
+			"{} differs from the reference (rmse {:.4} > {}), see {}",
+			name,
+			error,
+			threshold,
+			actual.display()
+		);
+	}
+}
+
+#[test]
+fn spheres() {
+	check("spheres", scene_spheres, 0.01);
+}
+
+#[test]
+fn tank() {
+	check("tank", scene_tank, 0.05);
+}
+
+#[test]
+fn cornell() {
+	check("cornell", scene_cornell, 0.03);
+}
+
+#[test]
+#[cfg_attr(
+	feature = "f32",
+	ignore = "the sphere field comes out differently in single precision"
+)]
+fn random_spheres() {
+	check("random_spheres", scene_random_spheres, 0.01);
+}